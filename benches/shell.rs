@@ -0,0 +1,128 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Benchmarks for the hot paths of the shell: parsing, word expansion, globbing
+//! and structural regular expressions on big buffers.
+//!
+//! Run them with `cargo bench`. The numbers of the last measured baseline are in
+//! `benches/README.md`.
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rwsh::parser::sre::CompleteCommand;
+use rwsh::shell::{parse_code, Config, Shell};
+use rwsh::sre::{run_command, Buffer};
+use rwsh::tests::common::new_composed_address;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+
+/// A bit of everything the parser knows about, repeated to make a big script.
+const SCRIPT_CHUNK: &str = r#"
+# a comment
+let a = [ x y z ]
+let m <= [ key value ]
+fn f {
+	if (test $1 -gt 1) echo "big $1" else echo small
+	return 0
+}
+while (let -t $i '<' 10) {
+	echo $i "$a[1]" '$quoted' $(basename ~/file) >/dev/null
+	let i += 1
+}
+switch $a
+/x/ echo x
+/y/ echo y
+end
+cat file.txt |> ,x/foo/ c/bar/ |> ,p | grep -v baz && echo ok || echo fail
+"#;
+
+fn parsing(c: &mut Criterion) {
+    let script = SCRIPT_CHUNK.repeat(1000);
+    c.bench_function("parse 18k-line script", |b| {
+        b.iter(|| parse_code(&script).unwrap())
+    });
+}
+
+fn expansion(c: &mut Criterion) {
+    let mut shell = Shell::new_embedded(Config::default()).expect("can't create the shell");
+    shell
+        .eval("let a = [ x y z ]; let s = ''; let HOME = /home/user")
+        .unwrap();
+    let code = r#"let i = 0
+while (let -t $i '<' 1000) {
+	let s = [ "$i-$a[1]-$HOME/x" ~/y $a ]
+	let i += 1
+}"#;
+    c.bench_function("expand words in a 1000-iteration loop", |b| {
+        b.iter(|| assert_eq!(shell.eval(code), Ok(0)))
+    });
+}
+
+/// Makes a directory with `n` files, half of them matching `*.txt`.
+fn glob_dir(n: usize) -> io::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("rwsh-bench-glob-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    for i in 0..n {
+        let ext = if i % 2 == 0 { "txt" } else { "log" };
+        File::create(dir.join(format!("file{}.{}", i, ext)))?;
+    }
+    Ok(dir)
+}
+
+fn globbing(c: &mut Criterion) {
+    let dir = glob_dir(10_000).unwrap();
+    let mut shell = Shell::new_embedded(Config::default()).expect("can't create the shell");
+    let code = format!("let files = [ {}/*.txt ]", dir.display());
+    c.bench_function("glob 10k-file directory", |b| {
+        b.iter(|| assert_eq!(shell.eval(&code), Ok(0)))
+    });
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn command(
+    name: char,
+    string_args: &[&str],
+    command_args: Vec<CompleteCommand>,
+) -> CompleteCommand {
+    CompleteCommand {
+        address: Default::default(),
+        name,
+        string_args: string_args.iter().map(|s| s.to_string()).collect(),
+        command_args,
+        original_address: String::new(),
+    }
+}
+
+fn sre(c: &mut Criterion) {
+    // about 4 MiB
+    let text = "the quick brown fox jumps over the lazy dog\n".repeat(100_000);
+    let mut x = command('x', &["fox"], vec![command('c', &["cat"], vec![])]);
+    x.address = new_composed_address(",");
+    c.bench_function("x/fox/ c/cat/ on a 4 MiB buffer", |b| {
+        b.iter_batched(
+            || Buffer::new(text.as_bytes()).unwrap(),
+            |mut buf| run_command(x.clone(), &mut buf, None, &mut io::sink()).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = parsing, expansion, globbing, sre
+}
+criterion_main!(benches);
@@ -10,7 +10,11 @@ temp="$(mktemp /tmp/rwsh.XXXX)"
 trap finish EXIT
 for example in *.rwsh; do
     echo $example
-    ../target/debug/rwsh "$example" > "$temp"
+    args=""
+    if [ -f "${example%.rwsh}.args" ]; then
+        args="$(cat "${example%.rwsh}.args")"
+    fi
+    ../target/debug/rwsh "$example" $args > "$temp"
     if ! diff "$temp" "${example%.rwsh}.out" > /dev/null; then
         echo "[Wrong] $example"
         diff "$temp" "${example%.rwsh}.out"
@@ -0,0 +1,29 @@
+/*
+ * The C interface of rwsh. Build the shared library with
+ * `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+ *
+ * Strings are NUL-terminated UTF-8. The strings returned by rwsh_get_var
+ * and rwsh_parse belong to the caller, who frees them with rwsh_string_free.
+ */
+#ifndef RWSH_H
+#define RWSH_H
+
+typedef struct RwshShell RwshShell;
+
+/* Creates a shell that runs only the code given to rwsh_eval, or returns NULL. */
+RwshShell *rwsh_new(void);
+void rwsh_free(RwshShell *sh);
+
+/* Runs the code and returns the status of its last command, or -1 on error. */
+int rwsh_eval(RwshShell *sh, const char *code);
+/* The error of the last rwsh_eval, or NULL. Valid until the next evaluation. */
+const char *rwsh_error(const RwshShell *sh);
+
+/* The value of a variable, like "name" or "name[1]", or NULL if it isn't set. */
+char *rwsh_get_var(const RwshShell *sh, const char *name);
+/* The syntax tree of the code, as printed by rwsh -n, or NULL if it can't be parsed. */
+char *rwsh_parse(const char *code);
+
+void rwsh_string_free(char *s);
+
+#endif
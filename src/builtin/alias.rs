@@ -0,0 +1,66 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::print_paged;
+use crate::msg;
+use crate::shell::Context;
+use std::io::Write;
+
+/// Defines an alias, or lists them all if no arguments are given.
+///
+/// The first word of a command is replaced by the words of its alias.
+/// An alias can start with its own name to refer to the command it hides.
+pub fn alias(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    match args.len() {
+        1 => {
+            let mut names = ctx.state.aliases.keys().collect::<Vec<_>>();
+            names.sort();
+            let mut listing = String::new();
+            for name in names {
+                listing += &format!("{} {}\n", name, ctx.state.aliases[name].join(" "));
+            }
+            print_paged(ctx, &listing);
+            0
+        }
+        2 => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("alias.usage"));
+            2
+        }
+        _ => {
+            ctx.state.aliases.insert(
+                args[1].to_owned(),
+                args[2..].iter().map(|s| (*s).to_owned()).collect(),
+            );
+            0
+        }
+    }
+}
+
+pub fn unalias(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    if args.len() < 2 {
+        let _ = writeln!(ctx.stderr, "{}", msg!("unalias.usage"));
+        return 2;
+    }
+    let mut ret = 0;
+    for name in &args[1..] {
+        if ctx.state.aliases.remove(*name).is_none() {
+            let _ = writeln!(ctx.stderr, "{}", msg!("unalias.not-found", name));
+            ret = 1;
+        }
+    }
+    ret
+}
@@ -0,0 +1,78 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::debug::Breakpoint;
+use crate::shell::Context;
+use getopts::Options;
+use std::io::Write;
+
+pub fn breakpoint(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag(
+        "d",
+        "",
+        "delete the breakpoints, or all of them if none are given",
+    );
+    opts.optflag("l", "", "list the breakpoints");
+
+    macro_rules! err {
+        ($reason:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("breakpoint.error", $reason));
+            let _ = write!(ctx.stderr, "{}", opts.usage(&msg!("breakpoint.usage")));
+            return 2;
+        }};
+    }
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => err!(e),
+    };
+    if matches.opt_present("l") {
+        if !matches.free.is_empty() || matches.opt_present("d") {
+            err!(msg!("breakpoint.list-only"));
+        }
+        for b in &ctx.state.breakpoints {
+            let _ = writeln!(ctx.stdout, "breakpoint {}", b);
+        }
+        return 0;
+    }
+
+    if matches.opt_present("d") {
+        if matches.free.is_empty() {
+            ctx.state.breakpoints.clear();
+            return 0;
+        }
+        let mut status = 0;
+        for arg in &matches.free {
+            if !ctx.state.breakpoints.remove(&Breakpoint::new(arg)) {
+                let _ = writeln!(ctx.stderr, "{}", msg!("breakpoint.not-found", arg));
+                status = 1;
+            }
+        }
+        status
+    } else if matches.free.is_empty() {
+        // pause before the next command
+        ctx.state.config.debug = true;
+        0
+    } else {
+        for arg in &matches.free {
+            ctx.state.breakpoints.insert(Breakpoint::new(arg));
+        }
+        0
+    }
+}
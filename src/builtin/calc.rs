@@ -15,18 +15,24 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use super::r#let::eval_arith;
+use crate::msg;
 use crate::shell::Context;
-use calc::eval;
+use std::io::Write;
 
-pub fn calc(_ctx: &mut Context, args: Vec<&str>) -> i32 {
+/// Evaluates its arguments, joined by spaces, as an [arithmetic expression](../../util/arith/index.html),
+/// like `let` does.
+pub fn calc(ctx: &mut Context, args: Vec<&str>) -> i32 {
     let mut args = args.into_iter();
     args.next(); // skip name
 
     let code = args.map(String::from).collect::<Vec<String>>().join(" ");
-    match eval(&code) {
-        Ok(val) => println!("{}", val),
+    match eval_arith(ctx, &code) {
+        Ok(val) => {
+            let _ = writeln!(ctx.stdout, "{}", val);
+        }
         Err(err) => {
-            eprintln!("{}", err);
+            let _ = writeln!(ctx.stderr, "{}", msg!("calc.error", err));
             return 1;
         }
     }
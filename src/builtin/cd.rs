@@ -15,21 +15,148 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
-use crate::shell::Context;
+use super::eval::eval;
+use crate::msg;
+use crate::shell::{Context, Key};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
 
-pub fn cd(_ctx: &mut Context, args: Vec<&str>) -> i32 {
-    let mut dir;
-    let home = dirs::home_dir().unwrap();
-    if let Some(arg) = args.get(1) {
-        dir = std::path::PathBuf::new();
-        dir.push(arg);
-    } else {
-        dir = home;
-    }
-    if let Err(error) = std::env::set_current_dir(dir) {
-        eprintln!("cd: {}", error);
-        1
-    } else {
-        0
+pub fn cd(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    // the directory is printed when it isn't the one given
+    let (dir, show) = match args.get(1) {
+        // `cd -` goes back to the previous directory
+        Some(&"-") => match ctx.state.get_var(Key::Var("OLDPWD")) {
+            Some(old) => (PathBuf::from(old.to_string()), true),
+            None => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("cd.no-oldpwd"));
+                return 1;
+            }
+        },
+        Some(arg) => find_dir(ctx, arg),
+        None => (dirs::home_dir().unwrap(), false),
+    };
+    match ctx.state.chdir(&dir) {
+        Err(error) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("cd.error", error));
+            1
+        }
+        Ok(old) => {
+            if show {
+                let _ = writeln!(ctx.stdout, "{}", dir.display());
+            }
+            after_cd(ctx, old)
+        }
     }
 }
+
+/// Finds where `cd arg` goes: a directory named `arg` under one of those in `$CDPATH`,
+/// `arg` itself, or, with the `cdfuzzy` option, the only directory here whose name starts with `arg`.
+/// Also tells whether the directory found differs from `arg`.
+fn find_dir(ctx: &mut Context, arg: &str) -> (PathBuf, bool) {
+    let path = PathBuf::from(arg);
+    // like in other shells, paths starting with `/`, `.` or `..` are taken as they are
+    match path.components().next() {
+        Some(Component::Normal(_)) => {}
+        _ => return (path, false),
+    }
+    if let Some(cdpath) = ctx.state.get_var(Key::Var("CDPATH")) {
+        for entry in cdpath.value.array().iter().flat_map(|e| e.split(':')) {
+            // an empty entry stands for the current directory
+            let candidate = Path::new(entry).join(&path);
+            if candidate.is_dir() {
+                return (candidate, !entry.is_empty());
+            }
+        }
+    }
+    if path.is_dir() || !ctx.state.config.cd_fuzzy || path.components().count() > 1 {
+        return (path, false);
+    }
+    let mut matches = std::fs::read_dir(".")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|e| e.path().is_dir() && e.file_name().to_string_lossy().starts_with(arg));
+    match (matches.next(), matches.next()) {
+        (Some(only), None) => (PathBuf::from(only.file_name()), true),
+        _ => (path, false),
+    }
+}
+
+/// Runs the hooks enabled by the `autopushd` and `autols` options after a directory change.
+/// `old` is pushed on the directory stack, so `pushd` and `popd` pass `None`.
+fn after_cd(ctx: &mut Context, old: Option<PathBuf>) -> i32 {
+    if ctx.state.config.autopushd {
+        if let Some(old) = old {
+            ctx.state.dir_stack.push(old);
+        }
+    }
+    if ctx.state.config.autols {
+        // Go through eval so that aliases and functions named `ls` are honoured.
+        return eval(ctx, vec!["eval", "ls"]);
+    }
+    0
+}
+
+/// Saves the current directory on the stack and changes to the given one.
+/// Without an argument, swaps the current directory with the one on top of the stack.
+pub fn pushd(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let dir = match args.get(1) {
+        Some(arg) => PathBuf::from(arg),
+        None => match ctx.state.dir_stack.pop() {
+            Some(dir) => dir,
+            None => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("pushd.no-other-directory"));
+                return 1;
+            }
+        },
+    };
+    match ctx.state.chdir(&dir) {
+        Err(error) => {
+            if args.len() < 2 {
+                ctx.state.dir_stack.push(dir);
+            }
+            let _ = writeln!(ctx.stderr, "{}", msg!("pushd.error", error));
+            1
+        }
+        Ok(old) => {
+            ctx.state.dir_stack.extend(old);
+            dirs(ctx, vec!["dirs"]);
+            after_cd(ctx, None)
+        }
+    }
+}
+
+/// Changes to the directory on top of the stack, removing it.
+pub fn popd(ctx: &mut Context, _args: Vec<&str>) -> i32 {
+    let dir = match ctx.state.dir_stack.last() {
+        Some(dir) => dir.clone(),
+        None => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("popd.empty"));
+            return 1;
+        }
+    };
+    if let Err(error) = ctx.state.chdir(&dir) {
+        let _ = writeln!(ctx.stderr, "{}", msg!("popd.error", error));
+        return 1;
+    }
+    ctx.state.dir_stack.pop();
+    dirs(ctx, vec!["dirs"]);
+    after_cd(ctx, None)
+}
+
+pub fn dirs(ctx: &mut Context, _args: Vec<&str>) -> i32 {
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(error) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("cd.error", error));
+            return 1;
+        }
+    };
+    let mut line = cwd.display().to_string();
+    for dir in ctx.state.dir_stack.iter().rev() {
+        line.push(' ');
+        line.push_str(&dir.display().to_string());
+    }
+    let _ = writeln!(ctx.stdout, "{}", line);
+    0
+}
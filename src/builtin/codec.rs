@@ -0,0 +1,134 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::Context;
+use crate::util::{digest, encoding};
+use getopts::{Options, ParsingStyle};
+use std::io::{self, Read, Write};
+
+/// Returns the strings given as arguments, or all of the standard input if there are none.
+fn inputs(strings: &[String]) -> Result<Vec<Vec<u8>>, String> {
+    if !strings.is_empty() {
+        return Ok(strings.iter().map(|s| s.as_bytes().to_vec()).collect());
+    }
+    let mut buf = Vec::new();
+    io::stdin()
+        .read_to_end(&mut buf)
+        .map_err(|e| msg!("encoding.read-error", e))?;
+    Ok(vec![buf])
+}
+
+/// Prints the digest of each argument, or of the standard input, in hexadecimal.
+///
+/// `hash sha256|md5 [string...]`
+pub fn hash(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("hash.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("hash.usage"));
+            return 2;
+        }};
+    }
+    let digest: fn(&[u8]) -> Vec<u8> = match args.get(1) {
+        Some(&"sha256") => |data| digest::sha256(data).to_vec(),
+        Some(&"md5") => |data| digest::md5(data).to_vec(),
+        Some(name) => usage!(msg!("hash.unknown-algorithm", name)),
+        None => usage!(msg!("hash.missing-algorithm")),
+    };
+    let strings = args[2..]
+        .iter()
+        .map(|s| (*s).to_owned())
+        .collect::<Vec<_>>();
+    match inputs(&strings) {
+        Ok(inputs) => {
+            for input in inputs {
+                let _ = writeln!(ctx.stdout, "{}", encoding::hex(&digest(&input)));
+            }
+            0
+        }
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("hash.error", e));
+            1
+        }
+    }
+}
+
+/// Encodes each argument, or the standard input, printing a line for each.
+/// With `-d`, decodes them instead and writes the bytes as they are.
+fn code(
+    ctx: &mut Context,
+    args: Vec<&str>,
+    name: &str,
+    encode: fn(&[u8]) -> String,
+    decode: fn(&str) -> Result<Vec<u8>, String>,
+) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag("d", "", "decode instead of encoding");
+    // the strings may start with a dash
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    let error = |ctx: &mut Context, e: String| {
+        let _ = writeln!(ctx.stderr, "{}", msg!("encoding.error", name, e));
+    };
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => {
+            error(ctx, e.to_string());
+            let _ = writeln!(ctx.stderr, "{}", msg!("encoding.usage", name));
+            return 2;
+        }
+    };
+    let inputs = match inputs(&m.free) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            error(ctx, e);
+            return 1;
+        }
+    };
+    for input in inputs {
+        if !m.opt_present("d") {
+            let _ = writeln!(ctx.stdout, "{}", encode(&input));
+            continue;
+        }
+        match decode(&String::from_utf8_lossy(&input)) {
+            Ok(data) => {
+                let _ = ctx.stdout.write_all(&data);
+            }
+            Err(e) => {
+                error(ctx, e);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+/// `base64 [-d] [string...]`
+pub fn base64(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    code(
+        ctx,
+        args,
+        "base64",
+        encoding::base64,
+        encoding::base64_decode,
+    )
+}
+
+/// `hex [-d] [string...]`
+pub fn hex(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    code(ctx, args, "hex", encoding::hex, encoding::hex_decode)
+}
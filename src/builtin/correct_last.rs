@@ -0,0 +1,51 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{Context, Key, State};
+use std::io::Write;
+
+/// Returns the line with the last parse error and the position of the error in it,
+/// as a character index.
+pub fn last_parse_error(state: &State) -> Option<(String, usize)> {
+    let source = state.get_var(Key::Var("PARSE_ERROR_SOURCE"))?;
+    let col = state.get_var(Key::Var("PARSE_ERROR_COL"))?;
+    // the column is the one of the last character read
+    let cursor = col
+        .to_string()
+        .parse::<usize>()
+        .unwrap_or(0)
+        .saturating_sub(1);
+    Some((source.to_string(), cursor))
+}
+
+/// Puts the line with the last parse error in the next prompt,
+/// with the cursor at the position of the error.
+///
+/// The line editors do the same on Alt-E, while the line is being edited.
+pub fn correct_last(ctx: &mut Context, _args: Vec<&str>) -> i32 {
+    match last_parse_error(ctx.state) {
+        Some((source, cursor)) => {
+            ctx.state.parser.borrow().set_initial_line(source, cursor);
+            0
+        }
+        None => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("correct-last.no-error"));
+            1
+        }
+    }
+}
@@ -0,0 +1,301 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::Context;
+use chrono::{DateTime, Days, Local, Months, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use getopts::{Options, ParsingStyle};
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::io::Write;
+
+/// Reads a point in time: `now`, seconds since the epoch (`@1571234567` or just the
+/// number), RFC 3339 (`2019-10-16T14:02:47+03:00`), or a date with an optional time,
+/// like `2019-10-16 14:02`, taken in the time zone `tz`.
+fn parse_time<Tz: TimeZone>(tz: &Tz, s: &str) -> Result<DateTime<Tz>, String> {
+    let bad = || msg!("date.bad-time", s);
+    if s == "now" {
+        return Ok(Utc::now().with_timezone(tz));
+    }
+    if let Ok(secs) = s.trim_start_matches('@').parse::<i64>() {
+        return tz.timestamp_opt(secs, 0).single().ok_or_else(bad);
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(tz));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(bad)?;
+    // a time skipped by daylight saving doesn't exist, a repeated one is taken the first time
+    tz.from_local_datetime(&naive).earliest().ok_or_else(bad)
+}
+
+#[derive(Debug, Default, PartialEq)]
+/// A duration, split in the parts that calendars make uneven.
+struct Span {
+    months: i64,
+    days: i64,
+    seconds: i64,
+}
+
+impl Span {
+    /// Reads a duration like `1d12h`, `-2w` or `+1y6mo`. The units are `s`, `m`, `h`,
+    /// `d`, `w`, `mo` and `y`, and the sign goes for the whole duration.
+    fn parse(s: &str) -> Result<Span, String> {
+        let bad = || msg!("date.bad-duration", s);
+        let (sign, mut rest) = match s.chars().next() {
+            Some('-') => (-1, &s[1..]),
+            Some('+') => (1, &s[1..]),
+            _ => (1, s),
+        };
+        if rest.is_empty() {
+            return Err(bad());
+        }
+        let mut span = Span::default();
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(bad)?;
+            let n: i64 = rest[..digits].parse().map_err(|_| bad())?;
+            rest = &rest[digits..];
+            let unit = rest
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let n = n.checked_mul(sign).ok_or_else(bad)?;
+            let (field, mult) = match &rest[..unit] {
+                "s" => (&mut span.seconds, 1),
+                "m" => (&mut span.seconds, 60),
+                "h" => (&mut span.seconds, 60 * 60),
+                "d" => (&mut span.days, 1),
+                "w" => (&mut span.days, 7),
+                "mo" => (&mut span.months, 1),
+                "y" => (&mut span.months, 12),
+                _ => return Err(bad()),
+            };
+            *field = n
+                .checked_mul(mult)
+                .and_then(|n| field.checked_add(n))
+                .ok_or_else(bad)?;
+            rest = &rest[unit..];
+        }
+        Ok(span)
+    }
+
+    fn add(&mut self, other: Span) -> Option<()> {
+        self.months = self.months.checked_add(other.months)?;
+        self.days = self.days.checked_add(other.days)?;
+        self.seconds = self.seconds.checked_add(other.seconds)?;
+        Some(())
+    }
+
+    /// Moves the time by the span. Months and days follow the calendar, so a day can
+    /// be 23 hours long when the clocks change, and a month after January 31 is the
+    /// last day of February.
+    fn apply<Tz: TimeZone>(&self, t: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let months = u32::try_from(self.months.unsigned_abs()).ok()?;
+        let t = if self.months < 0 {
+            t.checked_sub_months(Months::new(months))?
+        } else {
+            t.checked_add_months(Months::new(months))?
+        };
+        let days = Days::new(self.days.unsigned_abs());
+        let t = if self.days < 0 {
+            t.checked_sub_days(days)?
+        } else {
+            t.checked_add_days(days)?
+        };
+        if self.seconds.checked_abs()? > i64::MAX / 1000 {
+            return None;
+        }
+        t.checked_add_signed(chrono::Duration::seconds(self.seconds))
+    }
+}
+
+/// Formats the time with a strftime format, or returns `None` if the format is wrong.
+fn format<Tz: TimeZone>(t: &DateTime<Tz>, f: &str) -> Option<String>
+where
+    Tz::Offset: Display,
+{
+    let mut s = String::new();
+    fmt::Write::write_fmt(&mut s, format_args!("{}", t.format(f))).ok()?;
+    Some(s)
+}
+
+fn add<Tz: TimeZone>(tz: &Tz, time: &str, durations: &[String]) -> Result<DateTime<Tz>, String> {
+    let t = parse_time(tz, time)?;
+    let mut span = Span::default();
+    for d in durations {
+        span.add(Span::parse(d)?)
+            .ok_or_else(|| msg!("date.bad-duration", d))?;
+    }
+    span.apply(t).ok_or_else(|| msg!("date.out-of-range"))
+}
+
+/// Adds durations to a time, and prints the result in seconds since the epoch, or
+/// formatted.
+///
+/// `date-add [-u] [-f format] time [duration...]`
+pub fn date_add(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    // negative durations are not options
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    opts.optflag("u", "", "use UTC instead of the local time zone");
+    opts.optopt("f", "", "print the time with a strftime format", "FORMAT");
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("date-add.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("date-add.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let (time, durations) = match m.free.split_first() {
+        Some(x) => x,
+        None => usage!(msg!("date-add.missing-time")),
+    };
+    let f = m.opt_str("f");
+    let out = if m.opt_present("u") {
+        add(&Utc, time, durations).and_then(|t| print(&t, f.as_deref()))
+    } else {
+        add(&Local, time, durations).and_then(|t| print(&t, f.as_deref()))
+    };
+    match out {
+        Ok(s) => {
+            if writeln!(ctx.stdout, "{}", s).is_err() {
+                return 1;
+            }
+            0
+        }
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("date-add.error", e));
+            1
+        }
+    }
+}
+
+fn print<Tz: TimeZone>(t: &DateTime<Tz>, f: Option<&str>) -> Result<String, String>
+where
+    Tz::Offset: Display,
+{
+    match f {
+        Some(f) => format(t, f).ok_or_else(|| msg!("date.bad-format", f)),
+        None => Ok(t.timestamp().to_string()),
+    }
+}
+
+/// Prints the time from the first time to the second, in seconds or in the given unit,
+/// rounded towards zero.
+///
+/// `date-diff [-u] [-i unit] from to`
+pub fn date_diff(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag("u", "", "use UTC instead of the local time zone");
+    opts.optopt("i", "", "print the difference in s, m, h, d or w", "UNIT");
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("date-diff.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("date-diff.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let unit = match m.opt_str("i").as_deref() {
+        None | Some("s") => 1,
+        Some("m") => 60,
+        Some("h") => 60 * 60,
+        Some("d") => 24 * 60 * 60,
+        Some("w") => 7 * 24 * 60 * 60,
+        Some(u) => usage!(msg!("date-diff.unknown-unit", u)),
+    };
+    let (from, to) = match m.free.as_slice() {
+        [from, to] => (from, to),
+        [_, _, extra, ..] => usage!(msg!("date-diff.unexpected-argument", extra)),
+        _ => usage!(msg!("date-diff.missing-time")),
+    };
+    let utc = m.opt_present("u");
+    let seconds = |t: &str| {
+        if utc {
+            parse_time(&Utc, t).map(|t| t.timestamp())
+        } else {
+            parse_time(&Local, t).map(|t| t.timestamp())
+        }
+    };
+    match seconds(to).and_then(|to| seconds(from).map(|from| to - from)) {
+        Ok(s) => {
+            if writeln!(ctx.stdout, "{}", s / unit).is_err() {
+                return 1;
+            }
+            0
+        }
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("date-diff.error", e));
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> i64 {
+        parse_time(&Utc, s).unwrap().timestamp()
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(utc("@86400"), 86400);
+        assert_eq!(utc("1970-01-02"), 86400);
+        assert_eq!(utc("1970-01-02 00:01"), 86460);
+        assert_eq!(utc("1970-01-02T03:00:00+03:00"), 86400);
+        assert!(parse_time(&Utc, "yesterday").is_err());
+    }
+
+    #[test]
+    fn spans() {
+        let span = |months, days, seconds| Span {
+            months,
+            days,
+            seconds,
+        };
+        assert_eq!(Span::parse("1d12h"), Ok(span(0, 1, 12 * 3600)));
+        assert_eq!(Span::parse("-2w3s"), Ok(span(0, -14, -3)));
+        assert_eq!(Span::parse("+1y6mo"), Ok(span(18, 0, 0)));
+        assert!(Span::parse("3").is_err());
+        assert!(Span::parse("1x").is_err());
+        assert!(Span::parse("-").is_err());
+    }
+
+    #[test]
+    fn calendar() {
+        let t = parse_time(&Utc, "2019-01-31").unwrap();
+        let t = Span::parse("1mo").unwrap().apply(t).unwrap();
+        assert_eq!(t, parse_time(&Utc, "2019-02-28").unwrap());
+        let t = Span::parse("-1d1h").unwrap().apply(t).unwrap();
+        assert_eq!(t, parse_time(&Utc, "2019-02-26 23:00").unwrap());
+    }
+}
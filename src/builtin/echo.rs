@@ -0,0 +1,127 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::parser::escape;
+use crate::shell::Context;
+use std::io::Write;
+
+fn push_char(v: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    v.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// Interprets the backslash escapes in a string.
+/// Octal and hexadecimal escapes produce raw bytes.
+///
+/// The second element is `false` if `\c` was found, meaning that
+/// no more output should be produced.
+pub fn unescape(s: &str) -> (Vec<u8>, bool) {
+    let mut r = Vec::with_capacity(s.len());
+    let mut it = s.chars().peekable();
+    while let Some(c) = it.next() {
+        if c != '\\' {
+            push_char(&mut r, c);
+            continue;
+        }
+        match it.next() {
+            None => r.push(b'\\'),
+            Some('c') => return (r, false),
+            Some('e') => r.push(b'\x1b'),
+            Some('f') => r.push(b'\x0c'),
+            Some('r') => r.push(b'\r'),
+            Some('v') => r.push(b'\x0b'),
+            Some('0') => {
+                let mut n = 0;
+                for _ in 0..3 {
+                    match it.peek().and_then(|c| c.to_digit(8)) {
+                        Some(d) => n = n * 8 + d,
+                        None => break,
+                    }
+                    it.next();
+                }
+                r.push(n as u8);
+            }
+            Some('x') => {
+                let mut n = 0;
+                let mut len = 0;
+                while len < 2 {
+                    match it.peek().and_then(|c| c.to_digit(16)) {
+                        Some(d) => n = n * 16 + d,
+                        None => break,
+                    }
+                    it.next();
+                    len += 1;
+                }
+                if len == 0 {
+                    r.extend_from_slice(b"\\x");
+                } else {
+                    r.push(n as u8);
+                }
+            }
+            Some(c @ 'n') | Some(c @ 't') | Some(c @ 'a') | Some(c @ 'b') | Some(c @ '\\') => {
+                push_char(&mut r, escape(c))
+            }
+            Some(c) => {
+                r.push(b'\\');
+                push_char(&mut r, c);
+            }
+        }
+    }
+    (r, true)
+}
+
+pub fn echo(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut i = 1;
+    // options are only recognized before the first argument that isn't one
+    while i < args.len() {
+        let arg = args[i];
+        if arg.len() < 2
+            || !arg.starts_with('-')
+            || !arg[1..].chars().all(|c| c == 'n' || c == 'e' || c == 'E')
+        {
+            break;
+        }
+        for c in arg[1..].chars() {
+            match c {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        i += 1;
+    }
+
+    let s = args[i..].join(" ");
+    let mut s = if escapes {
+        let (unescaped, go_on) = unescape(&s);
+        newline = newline && go_on;
+        unescaped
+    } else {
+        s.into_bytes()
+    };
+    if newline {
+        s.push(b'\n');
+    }
+    if let Err(e) = ctx.stdout.write_all(&s) {
+        let _ = writeln!(ctx.stderr, "{}", msg!("echo.write-error", e));
+        return 1;
+    }
+    0
+}
@@ -0,0 +1,79 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{self, Context};
+use crate::util::sh_quote;
+use std::io::Write;
+
+/// Runs the body, then prints the exported variables it added (`+`), removed (`-`)
+/// and changed (`~`), sorted by name. The status is the one of the body.
+///
+/// `env-diff { body }`
+pub fn env_diff(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("env-diff.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("env-diff.usage"));
+            return 2;
+        }};
+    }
+    let code = match &args[1..] {
+        [code] => code,
+        [] => usage!(msg!("env-diff.missing-body")),
+        [_, arg, ..] => usage!(msg!("env-diff.unexpected-argument", arg)),
+    };
+    let body = match shell::parse_code(code) {
+        Ok(body) => body,
+        Err(e) => usage!(e),
+    };
+
+    let before = ctx.state.exported_vars.clone();
+    let mut status = 0;
+    for prog in &body {
+        status = match ctx.run_program(prog.clone()) {
+            Ok(status) => status,
+            Err(e) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("env-diff.error", e));
+                1
+            }
+        };
+        if ctx.state.exit != -1 {
+            break;
+        }
+    }
+
+    let after = &ctx.state.exported_vars;
+    let mut names = before.keys().chain(after.keys()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let _ = match (before.get(name), after.get(name)) {
+            (None, Some(new)) => writeln!(ctx.stdout, "+ {}={}", name, sh_quote(new)),
+            (Some(old), None) => writeln!(ctx.stdout, "- {}={}", name, sh_quote(old)),
+            (Some(old), Some(new)) if old != new => writeln!(
+                ctx.stdout,
+                "~ {}={} -> {}",
+                name,
+                sh_quote(old),
+                sh_quote(new)
+            ),
+            _ => Ok(()),
+        };
+    }
+    status
+}
@@ -16,9 +16,9 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 use crate::parser::Parser;
-use crate::shell::{self, Context};
+use crate::shell::Context;
 use crate::util::{BufReadChars, FileLineReader};
-use std::io::Cursor;
+use std::io::{Cursor, Write};
 
 pub fn eval(ctx: &mut Context, args: Vec<&str>) -> i32 {
     let mut args = args.into_iter();
@@ -35,15 +35,15 @@ pub fn eval(ctx: &mut Context, args: Vec<&str>) -> i32 {
         if prog.0.is_empty() {
             return 0;
         }
-        match shell::run_program(prog, ctx.state) {
-            Ok(status) => status.0,
+        match ctx.run_program(prog) {
+            Ok(status) => status,
             Err(error) => {
-                eprintln!("{}", error);
+                let _ = writeln!(ctx.stderr, "{}", error);
                 1
             }
         }
     } else {
-        eprintln!("{}", prog.err().unwrap());
+        let _ = writeln!(ctx.stderr, "{}", prog.err().unwrap());
         1
     }
 }
@@ -0,0 +1,145 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{self, Context};
+use getopts::{Options, ParsingStyle};
+use nix::poll::poll;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Parses a duration like `5s`, `1.5m`, `200ms` or `1h`. A bare number is in seconds.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let unit_start = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(unit_start);
+    let scale = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    let secs = n.parse::<f64>().ok()? * scale;
+    if secs <= 0.0 {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+/// Waits until `deadline`, running the traps of the signals that arrive meanwhile.
+/// Returns `false` if a trap made the shell exit.
+fn wait_until(ctx: &mut Context, deadline: Instant) -> bool {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        // round up, so that it doesn't wake up just before the deadline
+        let ms = (deadline - now).as_micros().div_ceil(1000);
+        // a signal interrupts the wait
+        let _ = poll(&mut [], ms.min(i32::MAX as u128) as i32);
+        ctx.state.run_pending_traps();
+        if ctx.state.exit != -1 {
+            return false;
+        }
+    }
+}
+
+/// Runs the body over and over, once per interval, until it calls `exit`.
+/// The interval is counted from the start of each run, so the runs don't drift;
+/// a run that takes longer than that is followed right away by the next one.
+///
+/// `every [-n count] duration { body }`
+pub fn every(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optopt("n", "", "stop after this many runs", "COUNT");
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("every.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("every.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let mut left = match m.opt_str("n").map(|n| n.parse::<usize>()) {
+        None => None,
+        Some(Ok(n)) if n > 0 => Some(n),
+        Some(_) => usage!(msg!("every.bad-count", m.opt_str("n").unwrap())),
+    };
+    let (interval, code) = match &m.free[..] {
+        [interval, code] => (interval, code),
+        _ => usage!(msg!("every.missing-args")),
+    };
+    let interval = match parse_duration(interval) {
+        Some(interval) => interval,
+        None => usage!(msg!("every.bad-duration", interval)),
+    };
+    let body = match shell::parse_code(code) {
+        Ok(body) => body,
+        Err(e) => usage!(e),
+    };
+
+    let mut status = 0;
+    let mut next = Instant::now();
+    loop {
+        for prog in &body {
+            status = match ctx.run_program(prog.clone()) {
+                Ok(status) => status,
+                Err(e) => {
+                    let _ = writeln!(ctx.stderr, "{}", msg!("every.error", e));
+                    1
+                }
+            };
+            if ctx.state.exit != -1 {
+                return status;
+            }
+        }
+        if let Some(n) = left.as_mut() {
+            *n -= 1;
+            if *n == 0 {
+                return status;
+            }
+        }
+        next = (next + interval).max(Instant::now());
+        if !wait_until(ctx, next) {
+            return status;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_duration;
+    use std::time::Duration;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("1.5m"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("200ms"), Some(Duration::from_millis(200)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("-1s"), None);
+        assert_eq!(parse_duration("5d"), None);
+        assert_eq!(parse_duration("s"), None);
+    }
+}
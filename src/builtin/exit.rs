@@ -15,11 +15,13 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use crate::msg;
 use crate::shell::Context;
+use std::io::Write;
 
 pub fn exit(ctx: &mut Context, args: Vec<&str>) -> i32 {
     if args.len() > 2 {
-        eprintln!("exit: Usage:\nexit [code]");
+        let _ = writeln!(ctx.stderr, "{}", msg!("exit.usage"));
         return 1;
     }
 
@@ -27,7 +29,7 @@ pub fn exit(ctx: &mut Context, args: Vec<&str>) -> i32 {
         match args[1].parse::<i32>() {
             Ok(i) => ctx.state.exit = i,
             Err(_) => {
-                eprintln!("exit: exit code not an integer");
+                let _ = writeln!(ctx.stderr, "{}", msg!("exit.not-integer"));
                 return 1;
             }
         }
@@ -0,0 +1,285 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::r#type::{kind, Kind};
+use crate::msg;
+use crate::parser::{self, Command, Node, Program, RawWord, WordParameterBracket};
+use crate::shell::{self, Context, Key};
+use crate::task::expand_tilde;
+use std::io::Write;
+use std::ops::Deref;
+
+/// What a word turns into, found without running anything.
+#[derive(Default)]
+struct DryRun {
+    /// The word after expansion.
+    text: String,
+    /// The word as a glob pattern, with the expanded parts escaped.
+    pattern: String,
+    /// Whether unquoted parts of the word have glob characters.
+    should_glob: bool,
+    /// Whether parameters or a `~` were expanded.
+    expanded: bool,
+    /// Whether the word has a command substitution, which isn't run.
+    substituted: bool,
+}
+
+impl DryRun {
+    fn new(ctx: &mut Context, word: &parser::Word) -> DryRun {
+        let mut dry = DryRun::default();
+        dry.walk(ctx, word, false, true);
+        dry
+    }
+
+    fn push_expanded(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.pattern.push_str(&glob::Pattern::escape(s));
+        self.expanded = true;
+    }
+
+    fn walk(&mut self, ctx: &mut Context, word: &parser::Word, quoted: bool, tilde: bool) {
+        match word.borrow().deref() {
+            RawWord::String(s, single_quoted) => {
+                if *single_quoted || quoted {
+                    self.text.push_str(s);
+                    self.pattern.push_str(&glob::Pattern::escape(s));
+                    return;
+                }
+                let mut s = s.clone();
+                if tilde && s.starts_with('~') && expand_tilde(&mut s).is_ok() {
+                    self.expanded = true;
+                }
+                self.should_glob = self.should_glob || s.contains(|c| "*?[".contains(c));
+                self.text.push_str(&s);
+                self.pattern.push_str(&s);
+            }
+            RawWord::Parameter(param) => {
+                let val = ctx.get_parameter_value(match &param.bracket {
+                    WordParameterBracket::None => Key::Var(&param.name),
+                    WordParameterBracket::Index(index) => Key::Index(&param.name, *index),
+                    WordParameterBracket::Key(field) => Key::Field(&param.name, field),
+                });
+                self.push_expanded(&val.map(|v| v.to_string()).unwrap_or_default());
+            }
+            RawWord::List(words, double_quoted) => {
+                for (i, w) in words.iter().enumerate() {
+                    let quoted = quoted || *double_quoted;
+                    self.walk(ctx, w, quoted, tilde && !quoted && i == 0);
+                }
+            }
+            RawWord::Pattern(words) => {
+                for w in words {
+                    self.walk(ctx, w, true, false);
+                }
+            }
+            RawWord::Command(_) => self.substituted = true,
+            RawWord::Expansion(var) => self.push_expanded(&var.to_string()),
+        }
+    }
+}
+
+/// Writes a word back the way it could have been typed.
+fn source(word: &parser::Word) -> String {
+    match word.borrow().deref() {
+        RawWord::String(s, false) => s.clone(),
+        RawWord::String(s, true) => format!("'{}'", s),
+        RawWord::Parameter(param) => match &param.bracket {
+            WordParameterBracket::None => format!("${}", param.name),
+            WordParameterBracket::Index(index) => format!("${}[{}]", param.name, index),
+            WordParameterBracket::Key(field) => format!("${}[{}]", param.name, field),
+        },
+        RawWord::List(words, false) => words.iter().map(source).collect(),
+        RawWord::List(words, true) => {
+            format!("\"{}\"", words.iter().map(source).collect::<String>())
+        }
+        RawWord::Pattern(words) => format!("/{}/", words.iter().map(source).collect::<String>()),
+        RawWord::Command(_) => "$(...)".to_owned(),
+        RawWord::Expansion(var) => var.to_string(),
+    }
+}
+
+/// Tells how an argument will be expanded.
+fn describe_word(ctx: &mut Context, word: &parser::Word) -> String {
+    let src = source(word);
+    let dry = DryRun::new(ctx, word);
+    if dry.substituted {
+        return msg!("explain.substitution", src);
+    }
+    if dry.should_glob {
+        let matches = glob::glob(&dry.pattern)
+            .map(|g| {
+                g.filter_map(Result::ok)
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if matches.is_empty() {
+            msg!("explain.glob-none", src)
+        } else {
+            msg!("explain.glob", src, matches.join(" "))
+        }
+    } else if dry.expanded {
+        msg!("explain.expands", src, dry.text)
+    } else {
+        msg!("explain.literal", src)
+    }
+}
+
+/// Tells what a command name will run.
+fn describe_name(ctx: &mut Context, word: &parser::Word) -> String {
+    let src = source(word);
+    let dry = DryRun::new(ctx, word);
+    if dry.substituted {
+        return msg!("explain.substitution", src);
+    }
+    let label = if dry.expanded {
+        msg!("explain.expanded-name", src, dry.text)
+    } else {
+        src
+    };
+    match kind(ctx, &dry.text) {
+        Some(Kind::Alias(words)) => msg!("explain.alias", label, words.join(" ")),
+        Some(Kind::Function) => msg!("explain.function", label),
+        Some(Kind::Builtin) => msg!("explain.builtin", label),
+        Some(Kind::File(path)) => msg!("explain.file", label, path),
+        None => msg!("explain.not-found", label),
+    }
+}
+
+fn explain_program(ctx: &mut Context, prog: &Program, lines: &mut Vec<String>) {
+    for cl in &prog.0 {
+        explain_node(ctx, &cl.0, lines);
+    }
+}
+
+fn explain_node(ctx: &mut Context, node: &Node, lines: &mut Vec<String>) {
+    match node {
+        Node::Pipeline(p) => {
+            for cmd in &p.0 {
+                explain_command(ctx, cmd, lines);
+            }
+        }
+        Node::BinOp(_, left, right) => {
+            explain_node(ctx, left, lines);
+            explain_node(ctx, right, lines);
+        }
+    }
+}
+
+fn explain_arms(ctx: &mut Context, arms: &[(parser::Word, Program)], lines: &mut Vec<String>) {
+    for (pattern, prog) in arms {
+        lines.push(msg!("explain.pattern", source(pattern)));
+        explain_program(ctx, prog, lines);
+    }
+}
+
+fn explain_command(ctx: &mut Context, cmd: &Command, lines: &mut Vec<String>) {
+    match cmd {
+        Command::SimpleCommand(sc) => {
+            for (name, value) in &sc.2 {
+                let dry = DryRun::new(ctx, value);
+                lines.push(if dry.substituted {
+                    msg!("explain.assignment-substitution", name, source(value))
+                } else {
+                    msg!("explain.assignment", name, source(value), dry.text)
+                });
+            }
+            lines.push(describe_name(ctx, &sc.0));
+            for word in &sc.1 {
+                lines.push(describe_word(ctx, word));
+            }
+        }
+        Command::SREProgram(_) => lines.push(msg!("explain.sre")),
+        Command::BraceGroup(cls, redirections) => {
+            for cl in cls {
+                explain_node(ctx, &cl.0, lines);
+            }
+            for redirection in redirections {
+                if let Some(word) = redirection.1.word() {
+                    lines.push(describe_word(ctx, word));
+                }
+            }
+        }
+        Command::IfConstruct(cond, body) | Command::WhileConstruct(cond, body, _) => {
+            let name = if let Command::IfConstruct(..) = cmd {
+                "if"
+            } else {
+                "while"
+            };
+            lines.push(msg!("explain.keyword", name));
+            explain_program(ctx, cond, lines);
+            explain_program(ctx, body, lines);
+        }
+        Command::ElseConstruct(body) => {
+            lines.push(msg!("explain.keyword", "else"));
+            explain_program(ctx, body, lines);
+        }
+        Command::SwitchConstruct(word, arms) => {
+            lines.push(msg!("explain.keyword", "switch"));
+            lines.push(describe_word(ctx, word));
+            explain_arms(ctx, arms, lines);
+        }
+        Command::MatchConstruct(src, arms) => {
+            lines.push(msg!("explain.keyword", "match"));
+            match src {
+                parser::MatchSource::Stdin => {}
+                parser::MatchSource::Text(word) | parser::MatchSource::File(word) => {
+                    lines.push(describe_word(ctx, word))
+                }
+            }
+            explain_arms(ctx, arms, lines);
+        }
+        Command::NotConstruct(p) | Command::TimeConstruct(p) => {
+            let name = if let Command::NotConstruct(_) = cmd {
+                "!"
+            } else {
+                "time"
+            };
+            lines.push(msg!("explain.keyword", name));
+            for cmd in &p.0 {
+                explain_command(ctx, cmd, lines);
+            }
+        }
+        Command::FunctionDefinition(name, _) => {
+            lines.push(msg!("explain.keyword", "fn"));
+            lines.push(msg!("explain.definition", name));
+        }
+    }
+}
+
+/// Prints how each word of a command line would be classified and expanded, without running it.
+pub fn explain(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    if args.len() < 2 {
+        let _ = writeln!(ctx.stderr, "{}", msg!("explain.usage"));
+        return 2;
+    }
+    let progs = match shell::parse_code(&args[1..].join(" ")) {
+        Ok(progs) => progs,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("explain.error", e));
+            return 1;
+        }
+    };
+    let mut lines = Vec::new();
+    for prog in &progs {
+        explain_program(ctx, prog, &mut lines);
+    }
+    for line in lines {
+        let _ = writeln!(ctx.stdout, "{}", line);
+    }
+    0
+}
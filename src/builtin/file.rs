@@ -0,0 +1,190 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{check_var_name, Context, Key, Var, VarValue};
+use crate::util::encoding;
+use getopts::{Options, ParsingStyle};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+
+/// How the text of a variable or of an argument stands for the bytes of a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Utf8,
+    Base64,
+    Hex,
+}
+
+impl Encoding {
+    fn parse(name: Option<String>) -> Result<Encoding, String> {
+        match name.as_deref() {
+            None | Some("utf8") => Ok(Encoding::Utf8),
+            Some("base64") => Ok(Encoding::Base64),
+            Some("hex") => Ok(Encoding::Hex),
+            Some(name) => Err(msg!("file.unknown-encoding", name)),
+        }
+    }
+
+    /// Turns the bytes into text. Bytes that are not UTF-8 can only be read encoded.
+    fn encode(self, path: &str, data: Vec<u8>) -> Result<String, String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(data).map_err(|_| msg!("file.not-utf8", path)),
+            Encoding::Base64 => Ok(encoding::base64(&data)),
+            Encoding::Hex => Ok(encoding::hex(&data)),
+        }
+    }
+
+    fn decode(self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Utf8 => Ok(data),
+            Encoding::Base64 => encoding::base64_decode(&String::from_utf8_lossy(&data)),
+            Encoding::Hex => encoding::hex_decode(&String::from_utf8_lossy(&data)),
+        }
+    }
+}
+
+/// Reads a whole file into a variable, or prints it, without starting `cat`.
+/// The contents are kept as they are, with the last newline too.
+///
+/// `read-file [-e utf8|base64|hex] [-o var] path`, where `-v` is the same as `-o`
+pub fn read_file(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optopt(
+        "e",
+        "",
+        "encode the contents: utf8, base64 or hex",
+        "ENCODING",
+    );
+    opts.optopt("o", "", "store the contents in a variable", "VAR");
+    opts.optopt("v", "", "the same as -o", "VAR");
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("read-file.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("read-file.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let path = match m.free.as_slice() {
+        [path] => path,
+        [] => usage!(msg!("read-file.missing-path")),
+        [_, extra, ..] => usage!(msg!("read-file.unexpected-argument", extra)),
+    };
+    let enc = match Encoding::parse(m.opt_str("e")) {
+        Ok(enc) => enc,
+        Err(e) => usage!(e),
+    };
+    let var = m.opt_str("o").or_else(|| m.opt_str("v"));
+    if let Some(Err(e)) = var.as_ref().map(|name| check_var_name(name)) {
+        usage!(e);
+    }
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            let _ = writeln!(
+                ctx.stderr,
+                "{}",
+                msg!("read-file.error", format!("{}: {}", path, e))
+            );
+            return 1;
+        }
+    };
+    let done = match (var, enc) {
+        // printed as it is, the file doesn't have to be text
+        (None, Encoding::Utf8) => ctx.stdout.write_all(&data).map_err(|e| e.to_string()),
+        (None, enc) => enc
+            .encode(path, data)
+            .and_then(|text| writeln!(ctx.stdout, "{}", text).map_err(|e| e.to_string())),
+        (Some(name), enc) => enc.encode(path, data).map(|text| {
+            let var = Var::new(name.clone(), VarValue::Array(vec![text]));
+            ctx.state.set_var(Key::Var(&name), var, false);
+        }),
+    };
+    match done {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("read-file.error", e));
+            1
+        }
+    }
+}
+
+/// Writes the arguments, separated by blanks, or the standard input if there are none,
+/// to a file, without starting `cat` or `tee`. Nothing is added at the end, not even a
+/// newline. With `-e`, the data is decoded first.
+///
+/// `write-file [-a] [-e utf8|base64|hex] path [string...]`
+pub fn write_file(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    // the strings may start with a dash
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    opts.optflag(
+        "a",
+        "append",
+        "add to the end of the file instead of replacing it",
+    );
+    opts.optopt("e", "", "decode the data: utf8, base64 or hex", "ENCODING");
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("write-file.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("write-file.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let (path, strings) = match m.free.split_first() {
+        Some(x) => x,
+        None => usage!(msg!("write-file.missing-path")),
+    };
+    let enc = match Encoding::parse(m.opt_str("e")) {
+        Ok(enc) => enc,
+        Err(e) => usage!(e),
+    };
+    let data = if strings.is_empty() {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map(|_| data)
+            .map_err(|e| msg!("encoding.read-error", e))
+    } else {
+        Ok(strings.join(" ").into_bytes())
+    };
+    let done = data.and_then(|data| enc.decode(data)).and_then(|data| {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(m.opt_present("a"))
+            .truncate(!m.opt_present("a"))
+            .open(path)
+            .and_then(|mut f| f.write_all(&data))
+            .map_err(|e| format!("{}: {}", path, e))
+    });
+    match done {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("write-file.error", e));
+            1
+        }
+    }
+}
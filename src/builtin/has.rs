@@ -0,0 +1,81 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::r#type::kind;
+use super::version::features;
+use super::{get_builtin, set};
+use crate::msg;
+use crate::parser::KEYWORDS;
+use crate::shell::Context;
+use getopts::Options;
+use std::io::Write;
+
+/// Tells whether `name` is something this shell has, of the kinds that were asked for.
+fn has_name(ctx: &mut Context, name: &str, kinds: &[&str]) -> bool {
+    kinds.iter().any(|k| match *k {
+        "b" => get_builtin(name).is_some(),
+        "c" => kind(ctx, name).is_some(),
+        "f" => features().iter().any(|f| f == name),
+        "k" => KEYWORDS.iter().any(|k| k.name == name),
+        "o" => set::is_option(name),
+        _ => false,
+    })
+}
+
+/// Succeeds if the shell has everything that is named: a builtin (`-b`), a command that
+/// can be run (`-c`), a feature that it was built with (`-f`), a keyword (`-k`) or an
+/// option of `set` (`-o`), or, without any of these, any of them.
+///
+/// Scripts use it to guard what needs a newer shell, instead of looking at the version.
+pub fn has(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag("b", "", "look only for builtins");
+    opts.optflag(
+        "c",
+        "",
+        "look only for commands: aliases, functions, builtins and files",
+    );
+    opts.optflag("f", "", "look only for features the shell was built with");
+    opts.optflag("k", "", "look only for keywords");
+    opts.optflag("o", "", "look only for options of set");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("has.error", e));
+            let _ = write!(ctx.stderr, "{}", opts.usage(&msg!("has.usage")));
+            return 2;
+        }
+    };
+    if matches.free.is_empty() {
+        let _ = write!(ctx.stderr, "{}", opts.usage(&msg!("has.usage")));
+        return 2;
+    }
+    let all = ["b", "c", "f", "k", "o"];
+    let mut kinds = all
+        .iter()
+        .copied()
+        .filter(|k| matches.opt_present(k))
+        .collect::<Vec<_>>();
+    if kinds.is_empty() {
+        kinds = all.to_vec();
+    }
+    if matches.free.iter().all(|name| has_name(ctx, name, &kinds)) {
+        0
+    } else {
+        1
+    }
+}
@@ -15,20 +15,66 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
-use crate::shell::{Context, Key, Var, VarValue};
+use crate::msg;
+use crate::shell::{Context, Key, Var};
+use crate::util::TextUnit;
+use getopts::{Matches, Options};
+use std::io::Write;
 
+/// Adds the options that select a text unit.
+pub fn unit_options(opts: &mut Options) {
+    opts.optflag("g", "", "count grapheme clusters");
+    opts.optflag("c", "", "count characters");
+    opts.optflag("b", "", "count bytes");
+}
+
+/// Returns the text unit selected by the options, if any.
+pub fn unit_from_matches(m: &Matches) -> Result<Option<TextUnit>, String> {
+    let units = [
+        ("g", TextUnit::Graphemes),
+        ("c", TextUnit::Chars),
+        ("b", TextUnit::Bytes),
+    ];
+    let mut selected = units.iter().filter(|(o, _)| m.opt_present(o));
+    let unit = selected.next().map(|(_, u)| *u);
+    if selected.next().is_some() {
+        return Err(msg!("len.too-many-units"));
+    }
+    Ok(unit)
+}
+
+/// Prints the number of elements of a variable,
+/// or the length of its value in the selected unit.
 pub fn len(ctx: &mut Context, args: Vec<&str>) -> i32 {
-    if args.len() != 2 {
-        eprintln!("Usage: len variable");
+    let mut opts = Options::new();
+    unit_options(&mut opts);
+    let (matches, unit) = match opts
+        .parse(&args[1..])
+        .map_err(|e| e.to_string())
+        .and_then(|m| unit_from_matches(&m).map(|u| (m, u)))
+    {
+        Ok(x) => x,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("len.error", e));
+            let _ = write!(ctx.stderr, "{}", opts.usage(&msg!("len.usage")));
+            return 2;
+        }
+    };
+    if matches.free.len() != 1 {
+        let _ = write!(ctx.stderr, "{}", opts.usage(&msg!("len.usage")));
         return 2;
     }
-    match ctx
+    let name = &matches.free[0];
+    let arr = ctx
         .state
-        .get_var(Key::Var(args[1]))
-        .unwrap_or(Var::empty(args[1].to_owned()))
+        .get_var(Key::Var(name))
+        .unwrap_or(Var::empty(name.to_owned()))
         .value
-    {
-        VarValue::Array(arr) => println!("{}", arr.len()),
-    }
+        .array();
+    let n = match unit {
+        None => arr.len(),
+        Some(unit) => unit.count(&arr.join(" ")),
+    };
+    let _ = writeln!(ctx.stdout, "{}", n);
     0
 }
@@ -14,27 +14,65 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use super::print_paged;
+use crate::msg;
 use crate::shell::Context;
-use crate::shell::{Key, Var, VarValue};
-use getopts::Options;
+use crate::shell::{sorted_entries, Key, Var, VarValue};
+use crate::util::arith::{self, Number};
+use crate::util::{json_string, porcelain_escape, FdWriter};
+use getopts::{Options, ParsingStyle};
+use std::collections::HashMap;
+use std::io::Write;
 
 fn is_special_var(s: &str) -> bool {
-    s == "" || s == "?"
+    // the empty string is made of digits too
+    s == "?" || s == "#" || s == "@" || s == "*" || s.bytes().all(|b| b.is_ascii_digit())
 }
 
-fn print_usage(program: &str, opts: Options) {
-    let brief = format!(
-        "Usage: {} [options] key1 key2 ... keyN = value1 value 2 ... valueN\n       {0} [options] -e key",
+fn print_usage(err: &mut FdWriter, program: &str, opts: Options) {
+    let brief = msg!(
+        "let.usage",
+        program,
+        program,
+        program,
+        program,
+        program,
         program
     );
-    eprint!("{}", opts.usage(&brief));
+    let _ = write!(err, "{}", opts.usage(&brief));
 }
 
-#[derive(Copy, Clone)]
+/// Evaluates an arithmetic expression, looking up the variables in the context.
+/// Booleans count as 1 and 0, and null as 0.
+pub fn eval_arith(ctx: &Context, expr: &str) -> Result<Number, String> {
+    arith::eval(expr, &|name| {
+        ctx.state
+            .get_var(Key::Var(name))
+            .map(|var| match var.value {
+                VarValue::Bool(b) => (b as i32).to_string(),
+                VarValue::Null => "0".to_owned(),
+                _ => var.to_string(),
+            })
+    })
+}
+
+/// Reads the value given to `let --bool`: `true`, `false`, or an arithmetic expression
+/// that is true if it isn't zero.
+fn to_bool(ctx: &Context, val: &Value) -> Result<bool, String> {
+    match val {
+        Value::String("true") => Ok(true),
+        Value::String("false") => Ok(false),
+        Value::String(s) => eval_arith(ctx, s).map(|n| n.is_true()),
+        Value::Array(_) => Err(msg!("let.array-on-bool")),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
 enum OperatorType {
     None,
     Arithmetic,
     Array,
+    Map,
 }
 
 #[derive(Copy, Clone)]
@@ -56,6 +94,12 @@ macro_rules! op {
             typ: OperatorType::Array,
         }
     };
+    ($op:expr, k) => {
+        Operator {
+            op: $op,
+            typ: OperatorType::Map,
+        }
+    };
 }
 
 // keep sorted!
@@ -67,10 +111,12 @@ static OPERATORS: &'static [Operator] = &[
     op!("-=", m),
     op!("/=", m),
     op!("::=", a),
+    op!("<=", k),
     Operator {
         op: "=",
         typ: OperatorType::None,
     },
+    op!(">=", k),
 ];
 
 fn get_operator(s: &str) -> Option<Operator> {
@@ -115,25 +161,25 @@ impl<'a> KVReader<'a> {
         }
     }
 
-    fn read_keys(&mut self) -> Result<Vec<Key<'a>>, &'static str> {
+    fn read_keys(&mut self) -> Result<Vec<Key<'a>>, String> {
         let mut keys = Vec::new();
         while self.i < self.args.len() {
             if let Some(op) = get_operator(&self.args[self.i]) {
                 self.op = Some(op);
                 break;
             }
-            keys.push(Key::new(&self.args[self.i]));
+            keys.push(Key::new(&self.args[self.i])?);
             self.i += 1;
         }
         if keys.is_empty() {
-            return Err("missing keys");
+            return Err(msg!("let.missing-keys"));
         }
         Ok(keys)
     }
 
     fn operator(&mut self) -> Result<Operator, &'static str> {
         if self.i == self.args.len() {
-            return Err("missing '=' operator");
+            return Err("let.missing-operator");
         }
         self.i += 1;
         Ok(self.op.unwrap())
@@ -147,7 +193,7 @@ impl<'a> KVReader<'a> {
             self.i += 1;
         }
         if vals.is_empty() {
-            return Err("missing values");
+            return Err("let.missing-values");
         }
         Ok((op, vals))
     }
@@ -173,67 +219,268 @@ impl<'a> KVReader<'a> {
             }
         }
         if in_array {
-            return Err("array literal left open");
+            return Err("let.array-open");
         }
         Ok((op, values))
     }
 }
 
+#[derive(Copy, Clone)]
+enum ListingFormat {
+    Human,
+    Porcelain,
+    Json,
+}
+
+/// Prints the variables, paging them if they are listed for people.
+fn list(ctx: &mut Context, format: ListingFormat, vars: Vec<Var>) {
+    if let ListingFormat::Human = format {
+        let mut listing = Vec::new();
+        print_listing(&mut listing, format, vars);
+        print_paged(ctx, &String::from_utf8_lossy(&listing));
+    } else {
+        print_listing(&mut ctx.stdout, format, vars);
+    }
+}
+
+/// Prints the variables, sorted by name.
+///
+/// The porcelain format has a line for each variable, made of tab-separated fields:
+/// the name and then every element. The JSON format is an object with
+/// the names as keys and arrays of elements as values.
+/// Associative arrays have a line for each key, named like `name[key]`,
+/// and are JSON objects.
+fn print_listing(out: &mut impl Write, format: ListingFormat, vars: Vec<Var>) {
+    match format {
+        ListingFormat::Human => {
+            for var in vars {
+                match &var.value {
+                    VarValue::Map(map) => {
+                        for (k, v) in sorted_entries(map) {
+                            let _ = writeln!(out, "{}[{}]={}", var.key, k, v);
+                        }
+                    }
+                    // null has no value at all, not even an empty one
+                    VarValue::Null => {
+                        let _ = writeln!(out, "{}", var.key);
+                    }
+                    _ => {
+                        let _ = writeln!(out, "{}={}", var.key, var);
+                    }
+                }
+            }
+        }
+        ListingFormat::Porcelain => {
+            for var in vars {
+                match var.value {
+                    VarValue::Map(map) => {
+                        for (k, v) in sorted_entries(&map) {
+                            let name = format!("{}[{}]", var.key, k);
+                            let _ = writeln!(
+                                out,
+                                "{}\t{}",
+                                porcelain_escape(&name),
+                                porcelain_escape(&v)
+                            );
+                        }
+                    }
+                    value => {
+                        let mut line = porcelain_escape(&var.key);
+                        for elem in &value.array() {
+                            line.push('\t');
+                            line.push_str(&porcelain_escape(elem));
+                        }
+                        let _ = writeln!(out, "{}", line);
+                    }
+                }
+            }
+        }
+        ListingFormat::Json => {
+            let fields = vars
+                .iter()
+                .map(|var| {
+                    let value = match &var.value {
+                        VarValue::Array(arr) => {
+                            let elems = arr.iter().map(|e| json_string(e)).collect::<Vec<_>>();
+                            format!("[{}]", elems.join(","))
+                        }
+                        VarValue::Map(map) => {
+                            let entries = sorted_entries(map)
+                                .iter()
+                                .map(|(k, v)| format!("{}:{}", json_string(k), json_string(v)))
+                                .collect::<Vec<_>>();
+                            format!("{{{}}}", entries.join(","))
+                        }
+                        VarValue::Bool(b) => b.to_string(),
+                        VarValue::Null => "null".to_owned(),
+                    };
+                    format!("{}:{}", json_string(&var.key), value)
+                })
+                .collect::<Vec<_>>();
+            let _ = writeln!(out, "{{{}}}", fields.join(","));
+        }
+    }
+}
+
 #[allow(clippy::collapsible_if)]
 pub fn r#let(ctx: &mut Context, args: Vec<&str>) -> i32 {
     let mut opts = Options::new();
     opts.optflag("x", "", "export variable");
     opts.optflag("e", "", "erase variable");
     opts.optflag("l", "", "create variable in the local scope");
+    opts.optflag(
+        "",
+        "fill",
+        "fill the gap with empty elements when setting an index past the end of an array",
+    );
+    opts.optflag(
+        "",
+        "porcelain",
+        "list variables in a machine-readable format",
+    );
+    opts.optflag("", "json", "list variables as a JSON object");
+    opts.optflag(
+        "",
+        "bool",
+        "assign true, false or the truth of an expression",
+    );
+    opts.optflag("", "null", "set variables that have no value");
+    opts.optopt(
+        "p",
+        "",
+        "format floating-point results with this many digits after the decimal point",
+        "DIGITS",
+    );
+    opts.optflag(
+        "t",
+        "",
+        "evaluate an arithmetic expression and succeed if it isn't zero",
+    );
+    // options only come before the keys, so that `-1` and `-=` aren't taken for options
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
 
     macro_rules! err {
         ($reason:expr) => {{
-            eprintln!("let: {}", $reason);
-            print_usage(args[0], opts);
+            let _ = writeln!(ctx.stderr, "{}", msg!("let.error", $reason));
+            print_usage(&mut ctx.stderr, args[0], opts);
             return 2;
         }};
     }
 
-    let matches = match opts.parse(args.iter()) {
+    let mut matches = match opts.parse(args[1..].iter()) {
         Ok(m) => m,
         Err(e) => err!(e),
     };
-    if (matches.opt_present("e") || matches.opt_present("l")) && matches.free.len() < 2 {
-        err!("not enough arguments");
+    matches.free.insert(0, args[0].to_owned());
+    if matches.opt_present("t") {
+        if matches.free.len() < 2 {
+            err!(msg!("let.not-enough-arguments"));
+        }
+        return match eval_arith(ctx, &matches.free[1..].join(" ")) {
+            Ok(n) => !n.is_true() as i32,
+            Err(e) => err!(e),
+        };
+    }
+    let precision = match matches.opt_get::<usize>("p") {
+        Ok(p) => p,
+        Err(_) => err!(msg!("arith.bad-precision")),
+    };
+    let is_bool = matches.opt_present("bool");
+    if (matches.opt_present("e") || matches.opt_present("l") || matches.opt_present("null"))
+        && matches.free.len() < 2
+    {
+        err!(msg!("let.not-enough-arguments"));
     }
 
     if matches.free.len() == 1 {
+        let format = if matches.opt_present("json") {
+            ListingFormat::Json
+        } else if matches.opt_present("porcelain") {
+            ListingFormat::Porcelain
+        } else {
+            ListingFormat::Human
+        };
         if matches.opt_present("x") {
-            for (k, v) in &ctx.state.exported_vars {
-                println!("{}={}", k, v);
-            }
+            let vars = ctx
+                .state
+                .exported_vars
+                .iter()
+                .map(|(k, v)| Var::new(k.clone(), VarValue::Array(vec![v.clone()])))
+                .collect();
+            list(ctx, format, vars);
         } else {
-            for k in ctx.state.vars.keys() {
-                println!("{}={}", k, ctx.state.get_var(Key::Var(k)).unwrap());
-            }
+            let mut keys = ctx.state.vars.keys().collect::<Vec<_>>();
+            keys.sort();
+            let vars = keys
+                .into_iter()
+                .filter_map(|k| ctx.state.get_var(Key::Var(k)))
+                .collect();
+            list(ctx, format, vars);
         }
         return 0;
+    } else if matches.opt_present("porcelain") || matches.opt_present("json") {
+        err!(msg!("let.listing-only"));
     }
 
     if is_special_var(&matches.free[1]) {
-        eprintln!("let: cannot change special variable");
+        let _ = writeln!(ctx.stderr, "{}", msg!("let.special-var"));
         return 1;
     }
 
+    if matches.opt_present("null") {
+        if is_bool || matches.opt_present("e") {
+            err!(msg!("let.null-only"));
+        }
+        for arg in &matches.free[1..] {
+            let name = match Key::new(arg) {
+                Ok(Key::Var(name)) => name,
+                Ok(_) => err!(msg!("let.use-whole")),
+                Err(e) => err!(e),
+            };
+            // null isn't exported, not even as the empty string
+            if matches.opt_present("x") {
+                ctx.state.unexport_var(name);
+            } else {
+                let var = Var::new(name.to_owned(), VarValue::Null);
+                ctx.state
+                    .set_var(Key::Var(name), var, matches.opt_present("l"));
+            }
+        }
+        return 0;
+    }
+
     let mut reader = KVReader::new(&matches.free);
     let keys = match reader.read_keys() {
         Ok(ks) => ks,
         Err(e) => err!(e),
     };
-    let (op, vals) = if !matches.opt_present("e") {
+    // a single key with several values is assigned an arithmetic expression
+    let joined;
+    let (op, vals, is_expr) = if !matches.opt_present("e") {
         match reader.read_values() {
-            Ok(vs) => {
-                if keys.len() != vs.1.len() {
-                    err!("number of keys doesn't match the number of values");
+            Ok((op, mut vals)) => {
+                let is_expr = keys.len() == 1
+                    && vals.len() > 1
+                    && (op.op == "=" || op.typ == OperatorType::Arithmetic);
+                if is_expr {
+                    let mut words = Vec::new();
+                    for val in &vals {
+                        match val {
+                            Value::String(s) => words.push(*s),
+                            Value::Array(_) => err!(msg!("let.array-on-number")),
+                        }
+                    }
+                    joined = words.join(" ");
+                    vals = vec![Value::String(&joined)];
+                } else if keys.len() != vals.len() {
+                    err!(msg!("let.keys-values-mismatch"));
+                }
+                if is_bool && op.op != "=" {
+                    err!(msg!("let.bool-operator"));
                 }
-                vs
+                (op, vals, is_expr)
             }
-            Err(e) => err!(e),
+            Err(e) => err!(msg!(e)),
         }
     } else {
         (
@@ -242,6 +489,7 @@ pub fn r#let(ctx: &mut Context, args: Vec<&str>) -> i32 {
                 typ: OperatorType::None,
             },
             vec![],
+            false,
         )
     };
     if matches.opt_present("x") {
@@ -249,7 +497,7 @@ pub fn r#let(ctx: &mut Context, args: Vec<&str>) -> i32 {
             for key in keys {
                 let key = match key {
                     Key::Var(name) => name,
-                    Key::Index(_, _) => err!("can only erase whole vars"),
+                    _ => err!(msg!("let.erase-whole")),
                 };
                 ctx.state.unexport_var(key);
             }
@@ -257,67 +505,90 @@ pub fn r#let(ctx: &mut Context, args: Vec<&str>) -> i32 {
             for (key, val) in keys.into_iter().zip(vals.into_iter()) {
                 let key = match key {
                     Key::Var(name) => name,
-                    Key::Index(_, _) => err!("can only use whole vars"),
+                    _ => err!(msg!("let.use-whole")),
+                };
+                // booleans are exported as the words `true` and `false`
+                let val = if is_bool {
+                    match to_bool(ctx, &val) {
+                        Ok(b) => b.to_string(),
+                        Err(e) => err!(e),
+                    }
+                } else {
+                    val.to_var(key.to_owned()).to_string()
                 };
-                let val = val.to_var(key.to_owned()).to_string();
                 ctx.state.export_var(key.to_owned(), val);
             }
         }
     } else {
         if matches.opt_present("e") {
             for key in keys {
-                let key = match key {
-                    Key::Var(name) => name,
-                    Key::Index(_, _) => err!("can only use whole vars"),
-                };
-                ctx.state.remove_var(key);
+                match key {
+                    Key::Var(name) => ctx.state.remove_var(name),
+                    Key::Index(name, index) => {
+                        if !ctx.state.remove_field(name, &index.to_string()) {
+                            err!(msg!("let.use-whole"));
+                        }
+                    }
+                    Key::Field(name, field) => {
+                        if !ctx.state.remove_field(name, field) {
+                            err!(msg!("let.not-a-map", name));
+                        }
+                    }
+                    Key::Append(_) => err!(msg!("let.use-whole")),
+                }
             }
         } else {
             for (key, val) in keys.into_iter().zip(vals.into_iter()) {
+                let key = match ctx.state.resolve_key(key, matches.opt_present("fill")) {
+                    Ok(key) => key,
+                    Err(e) => err!(e),
+                };
                 let left = ctx.state.get_var(key);
-                if left.is_none() && op.op != "=" {
-                    err!(format!("variable '{}' doesn't exist", key));
+                if left.is_none() && op.op != "=" && op.typ != OperatorType::Map {
+                    err!(msg!("let.no-such-var", key));
                 }
                 if op.op == "=" {
-                    ctx.state.set_var(
-                        key,
-                        val.to_var(key.name().to_owned()),
-                        matches.opt_present("l"),
-                    );
+                    let var = match val {
+                        _ if is_bool => match to_bool(ctx, &val) {
+                            Ok(b) => Var::new(key.name().to_owned(), VarValue::Bool(b)),
+                            Err(e) => err!(e),
+                        },
+                        Value::String(s) if is_expr => match eval_arith(ctx, s) {
+                            Ok(n) => Var::new(
+                                key.name().to_owned(),
+                                VarValue::Array(vec![n.format(precision)]),
+                            ),
+                            Err(e) => err!(e),
+                        },
+                        _ => val.to_var(key.name().to_owned()),
+                    };
+                    ctx.state.set_var(key, var, matches.opt_present("l"));
                     continue;
                 }
                 match op.typ {
                     OperatorType::None => {}
                     OperatorType::Arithmetic => {
                         let left = left.unwrap();
-                        macro_rules! to_int {
-                            ($expr:expr) => {{
-                                match $expr.parse::<i64>() {
-                                    Ok(i) => i,
-                                    Err(e) => err!(format!("'{}' is not a number: {}", $expr, e)),
-                                }
-                            }};
-                        }
                         let right = match val {
-                            Value::String(s) => match s.parse::<i64>() {
+                            Value::String(s) => match eval_arith(ctx, s) {
                                 Ok(i) => i,
-                                Err(e) => err!(format!("cannot use string on number: {}", e)),
+                                Err(e) => err!(e),
                             },
-                            Value::Array(_) => err!("cannot use array on number"),
+                            Value::Array(_) => err!(msg!("let.array-on-number")),
                         };
                         match left.value {
                             VarValue::Array(mut left) => {
                                 for v in &mut left {
-                                    let mut i = to_int!(v);
-                                    match op.op.as_bytes()[0] {
-                                        b'%' => i %= right,
-                                        b'*' => i *= right,
-                                        b'+' => i += right,
-                                        b'-' => i -= right,
-                                        b'/' => i /= right,
-                                        _ => panic!(),
+                                    let i = match Number::parse(v) {
+                                        Some(i) => i,
+                                        None => err!(msg!("let.not-a-number", v)),
+                                    };
+                                    // the operator without the `=`
+                                    let op = &op.op[..op.op.len() - 1];
+                                    match arith::apply(op, i, right) {
+                                        Ok(i) => *v = i.format(precision),
+                                        Err(e) => err!(e),
                                     }
-                                    *v = i.to_string();
                                 }
                                 ctx.state.set_var(
                                     key,
@@ -325,6 +596,8 @@ pub fn r#let(ctx: &mut Context, args: Vec<&str>) -> i32 {
                                     matches.opt_present("l"),
                                 );
                             }
+                            VarValue::Map(_) => err!(msg!("let.not-an-array", key)),
+                            _ => err!(msg!("let.typed", key)),
                         }
                     }
                     OperatorType::Array => {
@@ -354,7 +627,41 @@ pub fn r#let(ctx: &mut Context, args: Vec<&str>) -> i32 {
                                     matches.opt_present("l"),
                                 );
                             }
+                            VarValue::Map(_) => err!(msg!("let.not-an-array", key)),
+                            _ => err!(msg!("let.typed", key)),
+                        }
+                    }
+                    OperatorType::Map => {
+                        let name = match key {
+                            Key::Var(name) => name,
+                            _ => err!(msg!("let.use-whole")),
+                        };
+                        let right = match val {
+                            Value::String(s) => vec![s],
+                            Value::Array(arr) => arr,
+                        };
+                        let mut map = match left.map(|left| left.value) {
+                            None => HashMap::new(),
+                            Some(VarValue::Map(map)) => map,
+                            Some(_) => err!(msg!("let.not-a-map", name)),
+                        };
+                        if op.op == "<=" {
+                            if right.len() % 2 != 0 {
+                                err!(msg!("let.odd-pairs"));
+                            }
+                            for pair in right.chunks(2) {
+                                map.insert(pair[0].to_owned(), pair[1].to_owned());
+                            }
+                        } else {
+                            for k in right {
+                                map.remove(k);
+                            }
                         }
+                        ctx.state.set_var(
+                            key,
+                            Var::new(name.to_owned(), VarValue::Map(map)),
+                            matches.opt_present("l"),
+                        );
                     }
                 }
             }
@@ -0,0 +1,169 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::parser::Program;
+use crate::shell::{self, check_var_name, Context, Key, Var, VarValue};
+use crate::sys::{self, ForkResult};
+use crate::util::FdReader;
+use getopts::{Matches, Options, ParsingStyle};
+use std::io::{Read, Write};
+use std::process::exit;
+
+/// Runs the body in a subshell, with `$it` set to the element.
+/// Returns its exit status and, if `capture` is set, its output without the trailing newlines.
+fn run_body(
+    ctx: &mut Context,
+    body: &[Program],
+    elem: &str,
+    capture: bool,
+) -> Result<(i32, String), String> {
+    let pipe = if capture {
+        Some(sys::pipe().map_err(|e| msg!("map.pipe-error", e))?)
+    } else {
+        None
+    };
+    match sys::fork().map_err(|e| msg!("fork.error", e))? {
+        ForkResult::Child => {
+            let _ = ctx.redirect_child();
+            if let Some((read_end, write_end)) = pipe {
+                let _ = sys::close(read_end);
+                let _ = sys::move_fd(write_end, 1);
+            }
+            ctx.state.enter_subshell();
+            ctx.state.set_var(
+                Key::Var("it"),
+                Var::new("it".to_owned(), VarValue::Array(vec![elem.to_owned()])),
+                true,
+            );
+            let mut status = 0;
+            for prog in body {
+                status = match ctx.run_program(prog.clone()) {
+                    Ok(status) => status,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        1
+                    }
+                };
+                if ctx.state.exit != -1 {
+                    exit(ctx.state.exit);
+                }
+            }
+            exit(status);
+        }
+        ForkResult::Parent { child, .. } => {
+            let mut output = Vec::new();
+            if let Some((read_end, write_end)) = pipe {
+                let _ = sys::close(write_end);
+                let _ = FdReader(read_end).read_to_end(&mut output);
+                let _ = sys::close(read_end);
+            }
+            let status = sys::wait(child).unwrap_or(1);
+            let output = String::from_utf8_lossy(&output);
+            Ok((status, output.trim_end_matches('\n').to_owned()))
+        }
+    }
+}
+
+/// Parses the arguments shared by `map` and `filter`.
+/// Returns them, the elements and the parsed body.
+fn parse_args(args: &[&str]) -> Result<(Matches, Vec<String>, Vec<Program>), String> {
+    let mut opts = Options::new();
+    opts.optopt(
+        "v",
+        "",
+        "store the results in an array variable instead of printing them",
+        "VAR",
+    );
+    // the elements may start with a dash
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    let m = opts.parse(&args[1..]).map_err(|e| e.to_string())?;
+    if let Some(name) = m.opt_str("v") {
+        check_var_name(&name)?;
+    }
+    let (code, elems) = m
+        .free
+        .split_last()
+        .ok_or_else(|| msg!("map.missing-body"))?;
+    // parsed once, so that it can be run for every element
+    let body = shell::parse_code(code)?;
+    let elems = elems.to_vec();
+    Ok((m, elems, body))
+}
+
+/// Runs a body for every element, keeping the results that `keep` returns.
+fn iterate(
+    ctx: &mut Context,
+    args: Vec<&str>,
+    capture: bool,
+    keep: fn(String, i32, String) -> Option<String>,
+) -> i32 {
+    let (m, elems, body) = match parse_args(&args) {
+        Ok(x) => x,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("map.error", args[0], e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("map.usage", args[0]));
+            return 2;
+        }
+    };
+    let mut results = Vec::new();
+    for elem in elems {
+        match run_body(ctx, &body, &elem, capture) {
+            Ok((status, output)) => results.extend(keep(elem, status, output)),
+            Err(e) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("map.error", args[0], e));
+                return 1;
+            }
+        }
+    }
+    match m.opt_str("v") {
+        Some(name) => {
+            ctx.state.set_var(
+                Key::Var(&name),
+                Var::new(name.clone(), VarValue::Array(results)),
+                false,
+            );
+        }
+        None => {
+            for s in results {
+                if let Err(e) = writeln!(ctx.stdout, "{}", s) {
+                    let _ = writeln!(ctx.stderr, "{}", msg!("map.error", args[0], e));
+                    return 1;
+                }
+            }
+        }
+    }
+    0
+}
+
+/// Runs the body for every element, with the element in `$it`.
+/// The output of each run is an element of the result.
+pub fn map(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    iterate(ctx, args, true, |_, _, output| Some(output))
+}
+
+/// Runs the body for every element, with the element in `$it`.
+/// The elements for which it succeeds are kept.
+pub fn filter(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    iterate(ctx, args, false, |elem, status, _| {
+        if status == 0 {
+            Some(elem)
+        } else {
+            None
+        }
+    })
+}
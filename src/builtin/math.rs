@@ -0,0 +1,93 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::r#let::eval_arith;
+use crate::msg;
+use crate::shell::Context;
+use crate::util::arith::{self, Number};
+use getopts::{Options, ParsingStyle};
+use std::io::Write;
+
+/// Applies a function to numbers.
+fn apply(func: &str, args: &[Number]) -> Result<Number, String> {
+    let arity = match func {
+        "pow" => 2,
+        "abs" | "ceil" | "floor" | "round" | "sqrt" => 1,
+        _ => return Err(msg!("math.unknown-function", func)),
+    };
+    if args.len() != arity {
+        return Err(msg!("math.wrong-arguments", func, arity));
+    }
+    Ok(match (func, args[0]) {
+        ("pow", x) => arith::apply("**", x, args[1])?,
+        ("abs", Number::Int(i)) => {
+            Number::Int(i.checked_abs().ok_or_else(|| msg!("arith.overflow"))?)
+        }
+        ("abs", Number::Float(f)) => Number::Float(f.abs()),
+        ("sqrt", x) if x.as_f64() < 0.0 => return Err(msg!("math.negative-sqrt")),
+        ("sqrt", x) => Number::Float(x.as_f64().sqrt()),
+        (_, Number::Int(i)) => Number::Int(i),
+        ("ceil", Number::Float(f)) => Number::Float(f.ceil()),
+        ("floor", Number::Float(f)) => Number::Float(f.floor()),
+        (_, Number::Float(f)) => Number::Float(f.round()),
+    })
+}
+
+/// Applies a function to arithmetic expressions and prints the result.
+pub fn math(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optopt(
+        "p",
+        "",
+        "format floating-point results with this many digits after the decimal point",
+        "DIGITS",
+    );
+    // negative numbers aren't options
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    let usage = opts.usage(&msg!("math.usage"));
+    let result = opts
+        .parse(&args[1..])
+        .map_err(|e| e.to_string())
+        .and_then(|m| {
+            let precision = m
+                .opt_get::<usize>("p")
+                .map_err(|_| msg!("arith.bad-precision"))?;
+            let (func, exprs) = m
+                .free
+                .split_first()
+                .ok_or_else(|| msg!("math.missing-function"))?;
+            let nums = exprs
+                .iter()
+                .map(|e| eval_arith(ctx, e))
+                .collect::<Result<Vec<_>, _>>()?;
+            apply(func, &nums).map(|n| n.format(precision))
+        });
+    match result {
+        Ok(s) => {
+            if let Err(e) = writeln!(ctx.stdout, "{}", s) {
+                let _ = writeln!(ctx.stderr, "{}", msg!("math.error", e));
+                return 1;
+            }
+            0
+        }
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("math.error", e));
+            let _ = write!(ctx.stderr, "{}", usage);
+            2
+        }
+    }
+}
@@ -16,21 +16,99 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 use crate::shell::Context;
+use crate::util::pager;
+use std::io::Write;
 
+mod alias;
+mod breakpoint;
 mod calc;
 mod cd;
+mod codec;
+mod correct_last;
+mod date;
+mod echo;
+mod env_diff;
 mod eval;
+#[cfg(unix)]
+mod every;
 mod exit;
+mod explain;
+mod file;
+mod has;
 mod len;
 mod r#let;
+mod map;
+mod math;
+#[cfg(unix)]
+mod net;
+mod printf;
+#[cfg(unix)]
+mod progress;
+#[cfg(unix)]
+mod pty;
+mod quote;
+mod read;
+mod set;
+mod shift;
+mod sre;
+#[cfg(unix)]
+mod stat;
+mod string;
+mod substr;
+mod table;
+mod test;
+mod theme;
+mod trap;
 mod r#true;
+mod r#type;
+mod version;
+mod watch;
 use self::calc::calc;
-use cd::cd;
+use alias::{alias, unalias};
+use breakpoint::breakpoint;
+use cd::{cd, dirs, popd, pushd};
+use codec::{base64, hash, hex};
+use correct_last::correct_last;
+pub use correct_last::last_parse_error;
+use date::{date_add, date_diff};
+use echo::echo;
+use env_diff::env_diff;
 use eval::eval;
+#[cfg(unix)]
+use every::every;
 use exit::exit;
+use explain::explain;
+use file::{read_file, write_file};
+use has::has;
 use len::len;
+use map::{filter, map};
+use math::math;
+#[cfg(unix)]
+use net::net;
+use printf::printf;
+#[cfg(unix)]
+use progress::progress;
+#[cfg(unix)]
+use pty::pty;
+use quote::{quote, ssh_run};
 use r#let::r#let;
 use r#true::{r#false, r#true};
+use r#type::{r#type, rehash};
+use read::read;
+use set::set;
+use shift::shift;
+use sre::sre;
+#[cfg(unix)]
+use stat::stat;
+use string::str;
+use substr::substr;
+use table::table;
+use test::test;
+use theme::theme;
+use trap::trap;
+use version::version;
+pub use version::version_info;
+use watch::watch;
 
 /// A built-in command prototype.
 type BuiltinFunc = fn(&mut Context, Vec<&str>) -> i32;
@@ -55,23 +133,99 @@ macro_rules! b {
 }
 static BUILTINS: &'static [Builtin] = &[
     // keep sorted pls
+    Builtin {
+        name: "[",
+        func: test,
+    },
+    b!(alias),
+    b!(base64),
+    b!(breakpoint),
     b!(calc),
     b!(cd),
+    Builtin {
+        name: "correct-last",
+        func: correct_last,
+    },
+    Builtin {
+        name: "date-add",
+        func: date_add,
+    },
+    Builtin {
+        name: "date-diff",
+        func: date_diff,
+    },
+    b!(dirs),
+    b!(echo),
+    Builtin {
+        name: "env-diff",
+        func: env_diff,
+    },
     b!(eval),
+    #[cfg(unix)]
+    b!(every),
     b!(exit),
+    b!(explain),
     Builtin {
         name: "false",
         func: r#false,
     },
+    b!(filter),
+    b!(has),
+    b!(hash),
+    b!(hex),
     b!(len),
     Builtin {
         name: "let",
         func: r#let,
     },
+    b!(map),
+    b!(math),
+    #[cfg(unix)]
+    b!(net),
+    b!(popd),
+    b!(printf),
+    #[cfg(unix)]
+    b!(progress),
+    #[cfg(unix)]
+    b!(pty),
+    b!(pushd),
+    b!(quote),
+    b!(read),
+    Builtin {
+        name: "read-file",
+        func: read_file,
+    },
+    b!(rehash),
+    b!(set),
+    b!(shift),
+    b!(sre),
+    Builtin {
+        name: "ssh-run",
+        func: ssh_run,
+    },
+    #[cfg(unix)]
+    b!(stat),
+    b!(str),
+    b!(substr),
+    b!(table),
+    b!(test),
+    b!(theme),
+    b!(trap),
     Builtin {
         name: "true",
         func: r#true,
     },
+    Builtin {
+        name: "type",
+        func: r#type,
+    },
+    b!(unalias),
+    b!(version),
+    b!(watch),
+    Builtin {
+        name: "write-file",
+        func: write_file,
+    },
 ];
 
 /// Find a built-in function by name.
@@ -81,3 +235,18 @@ pub fn get_builtin(name: &str) -> Option<Builtin> {
         .ok()
         .map(|i| BUILTINS[i])
 }
+
+/// Writes the listing of a builtin, through the pager if it doesn't fit in the
+/// terminal, unless the `nopager` or the `plain` option is set.
+fn print_paged(ctx: &mut Context, text: &str) {
+    let _ = if ctx.state.config.no_pager || ctx.state.config.plain {
+        ctx.stdout.write_all(text.as_bytes())
+    } else {
+        pager::page_or_write(&mut ctx.stdout, text)
+    };
+}
+
+/// Returns the names of the built-in commands, sorted.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|b| b.name)
+}
@@ -0,0 +1,155 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::parser::Program;
+use crate::shell::{self, Context, Key, Var, VarValue};
+use crate::sys::net::{Address, Listener, Stream};
+use crate::sys::SavedFds;
+use crate::util::FdWriter;
+use getopts::Options;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+
+/// Connects to a server, or waits for clients, and runs the body with the
+/// connection as its standard input and output.
+///
+/// `net connect host port { body }` and `net connect -u path { body }` connect once.
+/// `net listen [host] port { body }` and `net listen -u path { body }` run the body
+/// for each client in turn, with its address in `$peer`, until `-n count` clients
+/// were served. The host to listen on defaults to `localhost`.
+pub fn net(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optopt("u", "", "use the Unix socket at this path", "PATH");
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("net.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("net.usage"));
+            return 2;
+        }};
+    }
+    let listen = match args.get(1) {
+        Some(&"connect") => false,
+        Some(&"listen") => {
+            opts.optopt("n", "", "stop after this many clients", "COUNT");
+            true
+        }
+        Some(cmd) => usage!(msg!("net.unknown-command", cmd)),
+        None => usage!(msg!("net.missing-args")),
+    };
+    let m = match opts.parse(&args[2..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let count = if listen { m.opt_str("n") } else { None };
+    let mut left = match count.as_ref().map(|n| n.parse::<usize>()) {
+        None => None,
+        Some(Ok(n)) if n > 0 => Some(n),
+        Some(_) => usage!(msg!("net.bad-count", count.unwrap())),
+    };
+    let (code, rest) = match m.free.split_last() {
+        Some(split) => split,
+        None => usage!(msg!("net.missing-args")),
+    };
+    let address = match (m.opt_str("u"), rest) {
+        (Some(path), []) => Ok(Address::Unix(PathBuf::from(path))),
+        (None, [port]) if listen => tcp_address("localhost", port),
+        (None, [host, port]) => tcp_address(host, port),
+        _ => Err(msg!("net.missing-args")),
+    };
+    let address = match address {
+        Ok(address) => address,
+        Err(e) => usage!(e),
+    };
+    let body = match shell::parse_code(code) {
+        Ok(body) => body,
+        Err(e) => usage!(e),
+    };
+    macro_rules! fail {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("net.error", $e));
+            return 1;
+        }};
+    }
+
+    if !listen {
+        return match Stream::connect(&address) {
+            Ok(stream) => run_connected(ctx, &body, &stream),
+            Err(e) => fail!(e),
+        };
+    }
+    let listener = match Listener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => fail!(e),
+    };
+    loop {
+        let stream = match listener.accept() {
+            Ok(stream) => stream,
+            Err(e) => fail!(e),
+        };
+        let peer = stream.peer();
+        ctx.state.set_var(
+            Key::Var("peer"),
+            Var::new("peer".to_owned(), VarValue::Array(vec![peer])),
+            false,
+        );
+        let status = run_connected(ctx, &body, &stream);
+        if ctx.state.exit != -1 {
+            return status;
+        }
+        if let Some(n) = left.as_mut() {
+            *n -= 1;
+            if *n == 0 {
+                return status;
+            }
+        }
+    }
+}
+
+fn tcp_address(host: &str, port: &str) -> Result<Address, String> {
+    port.parse()
+        .map(|port| Address::Tcp(host.to_owned(), port))
+        .map_err(|_| msg!("net.bad-port", port))
+}
+
+/// Runs the body with its standard input and output connected to the stream.
+fn run_connected(ctx: &mut Context, body: &[Program], stream: &Stream) -> i32 {
+    let mut saved = SavedFds::default();
+    let fd = stream.as_raw_fd();
+    if let Err(e) = saved.redirect(0, fd) {
+        let _ = writeln!(ctx.stderr, "{}", msg!("net.error", e));
+        return 1;
+    }
+    let stdout = std::mem::replace(&mut ctx.stdout, FdWriter(fd));
+    let mut status = 0;
+    for prog in body {
+        status = match ctx.run_program(prog.clone()) {
+            Ok(status) => status,
+            Err(e) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("net.error", e));
+                1
+            }
+        };
+        if ctx.state.exit != -1 {
+            break;
+        }
+    }
+    ctx.stdout = stdout;
+    saved.restore();
+    status
+}
@@ -0,0 +1,121 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::echo::unescape;
+use crate::msg;
+use crate::shell::Context;
+use std::io::Write;
+
+enum Piece {
+    Literal(String),
+    Directive(char),
+}
+
+fn parse_format(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut it = format.chars();
+    while let Some(c) = it.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        match it.next() {
+            Some('%') => literal.push('%'),
+            Some(d @ 's') | Some(d @ 'd') | Some(d @ 'x') => {
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(literal.clone()));
+                    literal.clear();
+                }
+                pieces.push(Piece::Directive(d));
+            }
+            Some(d) => return Err(msg!("printf.invalid-directive", d)),
+            None => return Err(msg!("printf.missing-directive")),
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    Ok(pieces)
+}
+
+fn parse_number(s: &str) -> Result<i64, String> {
+    if s.is_empty() {
+        return Ok(0);
+    }
+    s.parse::<i64>()
+        .map_err(|_| msg!("printf.invalid-number", s))
+}
+
+pub fn printf(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    if args.len() < 2 {
+        let _ = writeln!(ctx.stderr, "{}", msg!("printf.usage"));
+        return 2;
+    }
+    let pieces = match parse_format(args[1]) {
+        Ok(p) => p,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("printf.error", e));
+            return 1;
+        }
+    };
+    let has_directives = pieces.iter().any(|p| matches!(p, Piece::Directive(_)));
+
+    let mut ret = 0;
+    let mut out = Vec::new();
+    let mut rest = args[2..].iter();
+    // the format is reused as long as there are arguments left
+    'outer: loop {
+        for piece in &pieces {
+            match piece {
+                Piece::Literal(s) => {
+                    let (s, go_on) = unescape(s);
+                    out.extend_from_slice(&s);
+                    if !go_on {
+                        break 'outer;
+                    }
+                }
+                Piece::Directive(d) => {
+                    let arg = rest.next().cloned().unwrap_or("");
+                    let s = match d {
+                        's' => Ok(arg.to_owned()),
+                        'd' => parse_number(arg).map(|n| n.to_string()),
+                        'x' => parse_number(arg).map(|n| format!("{:x}", n)),
+                        _ => unreachable!(),
+                    };
+                    match s {
+                        Ok(s) => out.extend_from_slice(s.as_bytes()),
+                        Err(e) => {
+                            let _ = writeln!(ctx.stderr, "{}", msg!("printf.error", e));
+                            out.push(b'0');
+                            ret = 1;
+                        }
+                    }
+                }
+            }
+        }
+        if !has_directives || rest.len() == 0 {
+            break;
+        }
+    }
+
+    if let Err(e) = ctx.stdout.write_all(&out) {
+        let _ = writeln!(ctx.stderr, "{}", msg!("printf.write-error", e));
+        return 1;
+    }
+    ret
+}
@@ -0,0 +1,219 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::pty::window_size;
+use crate::shell::{Context, Relay};
+use crate::util::human_size;
+use getopts::Options;
+use nix::unistd;
+use std::io::{ErrorKind, Write};
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
+
+/// How often the bar is drawn again.
+const REDRAW: Duration = Duration::from_millis(100);
+
+/// The bar takes at most this many columns, not counting its brackets.
+const MAX_BAR: usize = 40;
+
+/// Parses a size in bytes, with an optional `K`, `M`, `G` or `T` suffix.
+fn parse_size(s: &str) -> Option<u64> {
+    let (digits, shift) = match s.char_indices().last()? {
+        (i, 'K') | (i, 'k') => (&s[..i], 10),
+        (i, 'M') | (i, 'm') => (&s[..i], 20),
+        (i, 'G') | (i, 'g') => (&s[..i], 30),
+        (i, 'T') | (i, 't') => (&s[..i], 40),
+        _ => (s, 0),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// Shows how much data went through, and how fast.
+struct Meter {
+    label: Option<String>,
+    total: Option<u64>,
+    done: u64,
+    start: Instant,
+    /// The terminal to draw the bar on, or `None` to only print a summary at the end.
+    tty: Option<RawFd>,
+}
+
+impl Meter {
+    /// Returns the line describing the progress, fitting in `columns`.
+    fn line(&self, columns: usize) -> String {
+        let secs = self.start.elapsed().as_secs_f64();
+        let rate = if secs > 0.0 {
+            (self.done as f64 / secs) as u64
+        } else {
+            0
+        };
+        let size = match self.total {
+            Some(total) => format!("{} / {}", human_size(self.done), human_size(total)),
+            None => human_size(self.done),
+        };
+        let counts = format!("{}  {}/s", size, human_size(rate));
+        let label = match &self.label {
+            Some(label) => format!("{} ", label),
+            None => String::new(),
+        };
+        let total = match self.total {
+            Some(total) => total,
+            None => return format!("{}{}", label, counts),
+        };
+        let ratio = if total == 0 {
+            1.0
+        } else {
+            (self.done as f64 / total as f64).min(1.0)
+        };
+        let percent = format!("{:3}%", (ratio * 100.0) as u64);
+        // the label, the brackets, the percentage, the counts and the spaces between them
+        let used = label.chars().count() + 2 + 1 + percent.len() + 2 + counts.len();
+        let bar = columns.saturating_sub(used).min(MAX_BAR);
+        if bar == 0 {
+            return format!("{}{}  {}", label, percent, counts);
+        }
+        let filled = (ratio * bar as f64) as usize;
+        format!(
+            "{}[{}{}] {}  {}",
+            label,
+            "#".repeat(filled),
+            " ".repeat(bar - filled),
+            percent,
+            counts
+        )
+    }
+
+    /// Draws the bar over the previous one.
+    fn draw(&self, stderr: &mut dyn Write) {
+        if let Some(fd) = self.tty {
+            let columns = match window_size(fd) {
+                Ok(size) if size.ws_col > 0 => size.ws_col as usize,
+                _ => 80,
+            };
+            let line = self.line(columns.saturating_sub(1));
+            let _ = write!(stderr, "\r{}\x1b[K", line);
+        }
+    }
+
+    /// Draws the bar for the last time, or prints the summary.
+    fn finish(&self, stderr: &mut dyn Write) {
+        if self.tty.is_some() {
+            self.draw(stderr);
+            let _ = writeln!(stderr);
+        } else {
+            let _ = writeln!(stderr, "{}", self.line(80));
+        }
+    }
+}
+
+/// Copies the standard input to the standard output, showing on the standard error
+/// how many bytes went through and how fast. With `-s size`, a bar shows how much of
+/// `size` bytes is done. If the standard error is not a terminal, only a summary is
+/// printed at the end.
+///
+/// `progress [-l label] [-s size]`
+pub fn progress(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optopt("l", "", "show this before the bar", "LABEL");
+    opts.optopt("s", "", "how many bytes to expect", "SIZE");
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("progress.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("progress.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    if let Some(arg) = m.free.first() {
+        usage!(msg!("progress.unexpected-argument", arg));
+    }
+    let total = match m.opt_str("s").map(|s| parse_size(&s)) {
+        None => None,
+        Some(Some(total)) => Some(total),
+        Some(None) => usage!(msg!("progress.bad-size", m.opt_str("s").unwrap())),
+    };
+    let stderr = ctx.stderr.0;
+    let mut meter = Meter {
+        label: m.opt_str("l"),
+        total,
+        done: 0,
+        start: Instant::now(),
+        tty: Some(stderr)
+            .filter(|&fd| !ctx.state.config.plain && unistd::isatty(fd).unwrap_or(false)),
+    };
+
+    let mut relay = Relay::new(0, ctx.stdout.0);
+    let mut drawn = Instant::now();
+    meter.draw(&mut ctx.stderr);
+    let status = loop {
+        let n = match relay.step() {
+            Ok(0) => break 0,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            // the reader is gone, like `head` after enough lines
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => break 1,
+            Err(e) => {
+                meter.finish(&mut ctx.stderr);
+                let _ = writeln!(ctx.stderr, "{}", msg!("progress.error", e));
+                return 1;
+            }
+        };
+        meter.done += n as u64;
+        if drawn.elapsed() >= REDRAW {
+            meter.draw(&mut ctx.stderr);
+            drawn = Instant::now();
+        }
+    };
+    meter.finish(&mut ctx.stderr);
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_size, Meter};
+    use std::time::Instant;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("2K"), Some(2048));
+        assert_eq!(parse_size("3m"), Some(3 << 20));
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_size("1.5G"), None);
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn bar() {
+        let meter = Meter {
+            label: Some("copy".to_owned()),
+            total: Some(4096),
+            done: 1024,
+            start: Instant::now(),
+            tty: None,
+        };
+        let line = meter.line(60);
+        assert!(line.starts_with("copy [##"));
+        assert!(line.contains("]  25%  1.0K / 4.0K  "));
+        assert!(line.chars().count() <= 60);
+        assert!(meter.line(10).starts_with("copy  25%"));
+    }
+}
@@ -0,0 +1,78 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::pty::{self, PtyFork};
+use crate::shell::Context;
+use crate::util::os2c;
+use nix::unistd;
+use std::ffi::OsStr;
+use std::io::Write;
+
+/// Runs an executable on a new pseudoterminal, passing the standard input to it
+/// and its output to the standard output, so that interactive programs work
+/// even when the shell isn't talking to a terminal.
+pub fn pty(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    if args.len() < 2 {
+        let _ = writeln!(ctx.stderr, "{}", msg!("pty.usage"));
+        return 2;
+    }
+    let path = match ctx.state.find_command(args[1]) {
+        Some(path) => path,
+        None => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("command.not-found", args[1]));
+            return 127;
+        }
+    };
+    let path = os2c(path.as_os_str());
+    let argv = args[1..]
+        .iter()
+        .map(|a| os2c(OsStr::new(a)))
+        .collect::<Vec<_>>();
+    let env = ctx
+        .state
+        .computed_exported_vars
+        .iter()
+        .map(|v| os2c(OsStr::new(v)))
+        .collect::<Vec<_>>();
+
+    // the new terminal is like the one the shell runs on, if any
+    let tty = [0, 1, 2]
+        .iter()
+        .cloned()
+        .find(|&fd| unistd::isatty(fd).unwrap_or(false))
+        .unwrap_or(0);
+    let pty = match pty::fork(tty) {
+        Ok(PtyFork::Child) => {
+            let e = unistd::execve(&path, &argv, &env).unwrap_err();
+            eprintln!("{}", msg!("exec.error", args[1], e));
+            std::process::exit(127);
+        }
+        Ok(PtyFork::Parent(pty)) => pty,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("pty.error", e));
+            return 1;
+        }
+    };
+    match pty::relay(&pty, 0, ctx.stdout.0, |_, _| {}) {
+        Ok(code) => code,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("pty.error", e));
+            1
+        }
+    }
+}
@@ -0,0 +1,68 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::parser::{Command, CommandList, Node, Pipeline, Program, RawWord, SimpleCommand, Word};
+use crate::shell::Context;
+use crate::util::sh_quote;
+use std::io::Write;
+
+/// Prints the arguments quoted for a POSIX shell, separated by spaces.
+///
+/// With a block argument, like `quote rwsh -c { echo $HOME }`, it prints
+/// a command that a remote shell can run as it is.
+pub fn quote(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let quoted = args[1..].iter().map(|s| sh_quote(s)).collect::<Vec<_>>();
+    if let Err(e) = writeln!(ctx.stdout, "{}", quoted.join(" ")) {
+        let _ = writeln!(ctx.stderr, "{}", msg!("quote.write-error", e));
+        return 1;
+    }
+    0
+}
+
+fn word(s: &str) -> Word {
+    RawWord::List(vec![RawWord::String(s.to_owned(), true).into()], false).into()
+}
+
+/// Runs a block on a remote host with rwsh, through `ssh`.
+///
+/// `ssh-run [ssh-option...] host { code }`
+pub fn ssh_run(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    if args.len() < 3 {
+        let _ = writeln!(ctx.stderr, "{}", msg!("ssh-run.usage"));
+        return 2;
+    }
+    let (code, ssh_args) = args[1..].split_last().unwrap();
+    let remote = ["rwsh", "-c", code]
+        .iter()
+        .map(|s| sh_quote(s))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut words = ssh_args.iter().map(|s| word(s)).collect::<Vec<_>>();
+    words.push(word(&remote));
+    let cmd = SimpleCommand(word("ssh"), words, vec![], 0);
+    let prog = Program(vec![CommandList(Node::Pipeline(Pipeline(vec![
+        Command::SimpleCommand(cmd),
+    ])))]);
+    match ctx.run_program(prog) {
+        Ok(status) => status,
+        Err(error) => {
+            let _ = writeln!(ctx.stderr, "{}", error);
+            1
+        }
+    }
+}
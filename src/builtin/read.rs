@@ -0,0 +1,109 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{check_var_name, Context, Key, Var, VarValue};
+use crate::sys;
+use crate::task::split_fields;
+use crate::util::FdWriter;
+use getopts::Options;
+use std::io::{self, Write};
+
+fn print_usage(err: &mut FdWriter, program: &str, opts: Options) {
+    let brief = msg!("read.usage", program);
+    let _ = write!(err, "{}", opts.usage(&brief));
+}
+
+/// Reads a line from the standard input, one byte at a time,
+/// so that the rest of the input is left for the next command.
+///
+/// Returns `None` if the end of file was reached before reading anything.
+fn read_line(raw: bool) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut escaped = false;
+    let mut buf = [0u8; 1];
+    loop {
+        if sys::read(0, &mut buf)? == 0 {
+            if line.is_empty() && !escaped {
+                return Ok(None);
+            }
+            break;
+        }
+        let c = buf[0];
+        if escaped {
+            escaped = false;
+            // a backslash before the newline continues the line
+            if c != b'\n' {
+                line.push(c);
+            }
+        } else if c == b'\\' && !raw {
+            escaped = true;
+        } else if c == b'\n' {
+            break;
+        } else {
+            line.push(c);
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+pub fn read(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag("r", "", "do not treat backslashes as escape characters");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("read.error", e));
+            print_usage(&mut ctx.stderr, args[0], opts);
+            return 2;
+        }
+    };
+    if matches.free.is_empty() {
+        let _ = writeln!(ctx.stderr, "{}", msg!("read.missing-vars"));
+        print_usage(&mut ctx.stderr, args[0], opts);
+        return 2;
+    }
+    if let Err(e) = matches
+        .free
+        .iter()
+        .try_for_each(|name| check_var_name(name))
+    {
+        let _ = writeln!(ctx.stderr, "{}", msg!("read.error", e));
+        return 2;
+    }
+
+    let line = match read_line(matches.opt_present("r")) {
+        Ok(Some(line)) => line,
+        Ok(None) => return 1,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("read.error", e));
+            return 1;
+        }
+    };
+    let ifs = ctx.state.ifs();
+    let mut fields = split_fields(&line, &ifs, matches.free.len()).into_iter();
+    for name in &matches.free {
+        let value = fields.next().unwrap_or("").to_owned();
+        ctx.state.set_var(
+            Key::Var(name),
+            Var::new(name.clone(), VarValue::Array(vec![value])),
+            false,
+        );
+    }
+    0
+}
@@ -0,0 +1,275 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::print_paged;
+use crate::msg;
+use crate::shell::{Config, Context};
+use crate::util::json_string;
+use std::io::Write;
+
+struct ShellOption {
+    name: &'static str,
+    short: Option<char>,
+    flag: fn(&mut Config) -> &mut bool,
+}
+
+// keep sorted!
+static OPTIONS: &'static [ShellOption] = &[
+    ShellOption {
+        name: "autocd",
+        short: None,
+        flag: |c| &mut c.autocd,
+    },
+    ShellOption {
+        name: "autols",
+        short: None,
+        flag: |c| &mut c.autols,
+    },
+    ShellOption {
+        name: "autopushd",
+        short: None,
+        flag: |c| &mut c.autopushd,
+    },
+    ShellOption {
+        name: "cdfuzzy",
+        short: None,
+        flag: |c| &mut c.cd_fuzzy,
+    },
+    ShellOption {
+        name: "debug",
+        short: None,
+        flag: |c| &mut c.debug,
+    },
+    ShellOption {
+        name: "errexit",
+        short: Some('e'),
+        flag: |c| &mut c.errexit,
+    },
+    ShellOption {
+        name: "errtrace",
+        short: Some('E'),
+        flag: |c| &mut c.errtrace,
+    },
+    ShellOption {
+        name: "guard",
+        short: None,
+        flag: |c| &mut c.guard,
+    },
+    ShellOption {
+        name: "nopager",
+        short: None,
+        flag: |c| &mut c.no_pager,
+    },
+    ShellOption {
+        name: "plain",
+        short: None,
+        flag: |c| &mut c.plain,
+    },
+    ShellOption {
+        name: "resolve-trace",
+        short: None,
+        flag: |c| &mut c.resolve_trace,
+    },
+    ShellOption {
+        name: "substtrunc",
+        short: None,
+        flag: |c| &mut c.subst_truncate,
+    },
+    ShellOption {
+        name: "xtrace",
+        short: Some('x'),
+        flag: |c| &mut c.xtrace,
+    },
+];
+
+/// A number bounding what the shell does, set with `set -o name=number` and
+/// removed with `set +o name`.
+struct ShellLimit {
+    name: &'static str,
+    limit: fn(&mut Config) -> &mut Option<usize>,
+}
+
+// keep sorted!
+static LIMITS: &[ShellLimit] = &[
+    ShellLimit {
+        name: "max-function-depth",
+        limit: |c| &mut c.max_function_depth,
+    },
+    ShellLimit {
+        name: "max-iterations",
+        limit: |c| &mut c.max_iterations,
+    },
+];
+
+fn get_limit(name: &str) -> Option<&'static ShellLimit> {
+    LIMITS
+        .binary_search_by(|probe| probe.name.cmp(name))
+        .ok()
+        .map(|i| &LIMITS[i])
+}
+
+/// Returns the names of the options and limits with their values, as text and as
+/// JSON, sorted by name.
+fn values(config: &mut Config) -> Vec<(&'static str, String, String)> {
+    let mut values = Vec::new();
+    for o in OPTIONS {
+        let on = *(o.flag)(config);
+        let text = if on { "on" } else { "off" };
+        values.push((o.name, text.to_owned(), on.to_string()));
+    }
+    for l in LIMITS {
+        values.push(match *(l.limit)(config) {
+            Some(n) => (l.name, n.to_string(), n.to_string()),
+            None => (l.name, "off".to_owned(), "null".to_owned()),
+        });
+    }
+    values.sort_by_key(|v| v.0);
+    values
+}
+
+fn get_option(name: &str) -> Option<&'static ShellOption> {
+    OPTIONS
+        .binary_search_by(|probe| probe.name.cmp(name))
+        .ok()
+        .map(|i| &OPTIONS[i])
+}
+
+/// Tells whether `name` is a shell option or limit that `set -o` knows.
+pub(super) fn is_option(name: &str) -> bool {
+    get_option(name).is_some() || get_limit(name).is_some()
+}
+
+fn get_short_option(short: char) -> Option<&'static ShellOption> {
+    OPTIONS.iter().find(|o| o.short == Some(short))
+}
+
+fn print_usage(ctx: &mut Context) {
+    let _ = writeln!(ctx.stderr, "{}", msg!("set.usage"));
+    for o in OPTIONS {
+        let _ = match o.short {
+            Some(short) => writeln!(ctx.stderr, "    -{}, -o {}", short, o.name),
+            None => writeln!(ctx.stderr, "        -o {}", o.name),
+        };
+    }
+    for l in LIMITS {
+        let _ = writeln!(ctx.stderr, "        -o {}=number", l.name);
+    }
+}
+
+fn print_options(ctx: &mut Context) {
+    let values = values(&mut ctx.state.config);
+    let width = values.iter().map(|v| v.0.len()).max().unwrap_or(0) + 2;
+    let mut listing = String::new();
+    for (name, value, _) in values {
+        listing += &format!("{:<width$}{}\n", name, value, width = width);
+    }
+    print_paged(ctx, &listing);
+}
+
+pub fn set(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    match args.get(1) {
+        None => {
+            print_options(ctx);
+            return 0;
+        }
+        Some(&"--porcelain") if args.len() == 2 => {
+            for (name, value, _) in values(&mut ctx.state.config) {
+                let _ = writeln!(ctx.stdout, "{}\t{}", name, value);
+            }
+            return 0;
+        }
+        Some(&"--json") if args.len() == 2 => {
+            let fields = values(&mut ctx.state.config)
+                .into_iter()
+                .map(|(name, _, json)| format!("{}:{}", json_string(name), json))
+                .collect::<Vec<_>>();
+            let _ = writeln!(ctx.stdout, "{{{}}}", fields.join(","));
+            return 0;
+        }
+        _ => {}
+    }
+
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i];
+        let enable = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("set.unexpected-argument", arg));
+                print_usage(ctx);
+                return 2;
+            }
+        };
+        if &arg[1..] == "o" {
+            i += 1;
+            let name = match args.get(i) {
+                Some(name) => name,
+                None => {
+                    print_options(ctx);
+                    return 0;
+                }
+            };
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (*name, None),
+            };
+            if let Some(l) = get_limit(name) {
+                let limit = match value {
+                    _ if !enable => None,
+                    Some(value) => match value.parse::<usize>() {
+                        Ok(0) => None,
+                        Ok(n) => Some(n),
+                        Err(_) => {
+                            let _ = writeln!(ctx.stderr, "{}", msg!("set.bad-limit", value));
+                            return 2;
+                        }
+                    },
+                    None => {
+                        let _ = writeln!(ctx.stderr, "{}", msg!("set.missing-limit", name));
+                        print_usage(ctx);
+                        return 2;
+                    }
+                };
+                *(l.limit)(&mut ctx.state.config) = limit;
+                i += 1;
+                continue;
+            }
+            match get_option(name).filter(|_| value.is_none()) {
+                Some(o) => *(o.flag)(&mut ctx.state.config) = enable,
+                None => {
+                    let _ = writeln!(ctx.stderr, "{}", msg!("set.unknown-option", args[i]));
+                    print_usage(ctx);
+                    return 2;
+                }
+            }
+        } else {
+            for c in arg.chars().skip(1) {
+                match get_short_option(c) {
+                    Some(o) => *(o.flag)(&mut ctx.state.config) = enable,
+                    None => {
+                        let _ = writeln!(ctx.stderr, "{}", msg!("set.unknown-short-option", c));
+                        print_usage(ctx);
+                        return 2;
+                    }
+                }
+            }
+        }
+        i += 1;
+    }
+    0
+}
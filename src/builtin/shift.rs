@@ -0,0 +1,42 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::Context;
+use std::io::Write;
+
+pub fn shift(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    if args.len() > 2 {
+        let _ = writeln!(ctx.stderr, "{}", msg!("shift.usage"));
+        return 2;
+    }
+    let n = match args.get(1).map(|n| n.parse::<usize>()) {
+        None => 1,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("shift.bad-count"));
+            return 2;
+        }
+    };
+    let available = ctx.state.args.len().saturating_sub(1);
+    if n > available {
+        let _ = writeln!(ctx.stderr, "{}", msg!("shift.too-many", n, available));
+        return 1;
+    }
+    ctx.state.args.drain(1..=n);
+    0
+}
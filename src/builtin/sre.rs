@@ -0,0 +1,131 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::Context;
+use crate::sre::{self, Buffer};
+use crate::sys;
+use crate::task::SRESequence;
+use std::fs::{self, File};
+use std::io::{self, BufRead, ErrorKind, Write};
+
+/// What the editor prints before reading a command, if the standard input is a terminal.
+const PROMPT: &str = "|> ";
+
+/// Edits a file with SRE commands read from the standard input, one per line, keeping
+/// the dot between them and showing it after each. An empty line steps to the next
+/// line, like `n`. `w [file]` writes the buffer, to the file it was read from by
+/// default, and `q` quits; unsaved changes need a second `q`.
+///
+/// `sre [file]`
+pub fn sre(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut path = match &args[1..] {
+        [] => None,
+        [path] => Some(path.to_string()),
+        [_, arg, ..] => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("sre.unexpected-argument", arg));
+            let _ = writeln!(ctx.stderr, "{}", msg!("sre.usage"));
+            return 2;
+        }
+    };
+    // a file that doesn't exist yet starts empty, like in `ed`
+    let buf = match &path {
+        Some(path) => match File::open(path) {
+            Ok(f) => Buffer::new(f),
+            Err(e) if e.kind() == ErrorKind::NotFound => Buffer::new(io::empty()),
+            Err(e) => Err(e),
+        },
+        None => Buffer::new(io::empty()),
+    };
+    let mut buf = match buf {
+        Ok(buf) => buf,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("sre.open-error", e));
+            return 1;
+        }
+    };
+    let mut saved = buf.contents().to_owned();
+    let mut dot = buf.new_address(0, 0).range();
+    let prompt = sys::isatty(0);
+    let mut warned = false;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if prompt {
+            let _ = write!(ctx.stderr, "{}", PROMPT);
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("sre.read-error", e));
+                return 1;
+            }
+            None if buf.contents() != saved => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("sre.error", msg!("sre.unsaved")));
+                return 1;
+            }
+            None => return 0,
+        };
+        let line = line.trim();
+        if line == "q" {
+            if buf.contents() == saved || warned {
+                return 0;
+            }
+            warned = true;
+            let _ = writeln!(ctx.stderr, "{}", msg!("sre.error", msg!("sre.unsaved")));
+            continue;
+        }
+        warned = false;
+        if line == "w" || line.starts_with("w ") {
+            let target = match line[1..].trim() {
+                "" => path.clone(),
+                target => Some(target.to_owned()),
+            };
+            let target = match target {
+                Some(target) => target,
+                None => {
+                    let _ = writeln!(ctx.stderr, "{}", msg!("sre.error", msg!("sre.no-file")));
+                    continue;
+                }
+            };
+            match fs::write(&target, buf.contents()) {
+                Ok(()) => {
+                    let _ = writeln!(ctx.stderr, "{}", buf.contents().len());
+                    saved = buf.contents().to_owned();
+                    path.get_or_insert(target);
+                }
+                Err(e) => {
+                    let _ = writeln!(ctx.stderr, "{}", msg!("sre.error", e));
+                }
+            }
+            continue;
+        }
+        let line = if line.is_empty() { "n" } else { line };
+        match SRESequence::run_line(ctx, &mut buf, dot, line) {
+            Ok(new_dot) => {
+                dot = new_dot;
+                let _ = sre::show_dot(&mut ctx.stdout, &buf, dot);
+            }
+            Err(e) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("sre.error", e));
+            }
+        }
+        if ctx.state.exit != -1 {
+            return 0;
+        }
+    }
+}
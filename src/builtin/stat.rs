@@ -0,0 +1,143 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{check_var_name, sorted_entries, Context, Key, Var, VarValue};
+use getopts::Options;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs::{self, FileType, Metadata};
+use std::io::Write;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+/// Returns the name of the user, or the number if it has no entry.
+fn user_name(uid: u32) -> String {
+    let p = unsafe { libc::getpwuid(uid) };
+    if p.is_null() {
+        return uid.to_string();
+    }
+    unsafe { CStr::from_ptr((*p).pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns the name of the group, or the number if it has no entry.
+fn group_name(gid: u32) -> String {
+    let g = unsafe { libc::getgrgid(gid) };
+    if g.is_null() {
+        return gid.to_string();
+    }
+    unsafe { CStr::from_ptr((*g).gr_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn type_name(t: FileType) -> &'static str {
+    if t.is_dir() {
+        "directory"
+    } else if t.is_symlink() {
+        "link"
+    } else if t.is_fifo() {
+        "fifo"
+    } else if t.is_socket() {
+        "socket"
+    } else if t.is_block_device() {
+        "block"
+    } else if t.is_char_device() {
+        "char"
+    } else {
+        "file"
+    }
+}
+
+/// Returns the fields of the map: the modification time is in seconds since the epoch,
+/// and the permissions are in octal, like `chmod` takes them.
+fn fields(m: &Metadata) -> HashMap<String, String> {
+    let fields = vec![
+        ("type", type_name(m.file_type()).to_owned()),
+        ("size", m.size().to_string()),
+        ("mtime", m.mtime().to_string()),
+        ("mode", format!("{:o}", m.mode() & 0o7777)),
+        ("owner", user_name(m.uid())),
+        ("group", group_name(m.gid())),
+    ];
+    fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect()
+}
+
+/// Stores the metadata of a file in a map, or prints its fields.
+///
+/// `stat [-L] [-v var] path`
+pub fn stat(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag("L", "", "follow symbolic links");
+    opts.optopt(
+        "v",
+        "",
+        "store the fields in a map variable instead of printing them",
+        "VAR",
+    );
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("stat.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("stat.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let path = match m.free.as_slice() {
+        [path] => path,
+        [] => usage!(msg!("stat.missing-path")),
+        [_, extra, ..] => usage!(msg!("stat.unexpected-argument", extra)),
+    };
+    let var = m.opt_str("v");
+    if let Some(Err(e)) = var.as_ref().map(|name| check_var_name(name)) {
+        usage!(e);
+    }
+    let metadata = if m.opt_present("L") {
+        fs::metadata(path)
+    } else {
+        fs::symlink_metadata(path)
+    };
+    let map = match metadata {
+        Ok(metadata) => fields(&metadata),
+        Err(e) => {
+            let _ = writeln!(
+                ctx.stderr,
+                "{}",
+                msg!("stat.error", format!("{}: {}", path, e))
+            );
+            return 1;
+        }
+    };
+    match var {
+        Some(name) => {
+            let var = Var::new(name.clone(), VarValue::Map(map));
+            ctx.state.set_var(Key::Var(&name), var, false);
+        }
+        None => {
+            for (k, v) in sorted_entries(&map) {
+                if writeln!(ctx.stdout, "{}={}", k, v).is_err() {
+                    return 1;
+                }
+            }
+        }
+    }
+    0
+}
@@ -0,0 +1,159 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{check_var_name, Context, Key, Var, VarValue};
+use crate::task::{split_fields, DEFAULT_IFS};
+use getopts::{Options, ParsingStyle};
+use regex::Regex;
+use std::io::{self, BufRead, Write};
+
+/// Returns the strings given as arguments, or the lines of the standard input if there are none.
+fn input(strings: &[String]) -> Result<Vec<String>, String> {
+    if !strings.is_empty() {
+        return Ok(strings.to_vec());
+    }
+    let stdin = io::stdin();
+    let lines = stdin.lock().lines().collect::<Result<Vec<_>, _>>();
+    lines.map_err(|e| msg!("str.read-error", e))
+}
+
+/// Runs a `str` command, returning its results.
+fn transform(cmd: &str, args: &[String]) -> Result<Vec<String>, String> {
+    let mut opts = Options::new();
+    // the strings may start with a dash
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    let params = match cmd {
+        "lower" | "trim" | "upper" => 0,
+        "join" | "split" => 1,
+        "replace" => 2,
+        _ => return Err(msg!("str.unknown-command", cmd)),
+    };
+    if cmd == "replace" {
+        opts.optflag("r", "", "the pattern is a regular expression");
+        opts.optflag("f", "", "only replace the first match");
+    } else if cmd == "split" {
+        opts.optopt("n", "", "split in at most this many fields", "COUNT");
+    }
+    let m = opts.parse(args).map_err(|e| e.to_string())?;
+    if m.free.len() < params {
+        return Err(msg!("str.missing-arguments", cmd));
+    }
+    let (params, strings) = m.free.split_at(params);
+    let strings = input(strings)?;
+    Ok(match cmd {
+        "join" => vec![strings.join(&params[0])],
+        "lower" => strings.iter().map(|s| s.to_lowercase()).collect(),
+        "upper" => strings.iter().map(|s| s.to_uppercase()).collect(),
+        "trim" => strings.iter().map(|s| s.trim().to_owned()).collect(),
+        "replace" => {
+            let (from, to) = (&params[0], params[1].as_str());
+            let limit = if m.opt_present("f") { 1 } else { 0 };
+            if m.opt_present("r") {
+                let re = Regex::new(from).map_err(|e| msg!("str.bad-regex", e))?;
+                strings
+                    .iter()
+                    .map(|s| re.replacen(s, limit, to).into_owned())
+                    .collect()
+            } else if limit == 0 {
+                strings
+                    .iter()
+                    .map(|s| s.replace(from.as_str(), to))
+                    .collect()
+            } else {
+                strings
+                    .iter()
+                    .map(|s| s.replacen(from.as_str(), to, 1))
+                    .collect()
+            }
+        }
+        _ => {
+            let max = match m.opt_str("n") {
+                None => usize::MAX,
+                Some(n) => match n.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(msg!("str.bad-count", n)),
+                },
+            };
+            let sep = &params[0];
+            let mut fields = Vec::new();
+            for s in &strings {
+                if sep.is_empty() {
+                    fields.extend(
+                        split_fields(s, DEFAULT_IFS, max)
+                            .into_iter()
+                            .map(String::from),
+                    );
+                } else {
+                    fields.extend(s.splitn(max, sep.as_str()).map(String::from));
+                }
+            }
+            fields
+        }
+    })
+}
+
+/// Transforms strings. Every result is printed on its own line,
+/// or stored as an element of an array variable.
+pub fn str(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optopt(
+        "v",
+        "",
+        "store the results in an array variable instead of printing them",
+        "VAR",
+    );
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    let usage = opts.usage(&msg!("str.usage"));
+    let result = opts
+        .parse(&args[1..])
+        .map_err(|e| e.to_string())
+        .and_then(|m| {
+            let (cmd, rest) = m
+                .free
+                .split_first()
+                .ok_or_else(|| msg!("str.missing-command"))?;
+            if let Some(name) = m.opt_str("v") {
+                check_var_name(&name)?;
+            }
+            Ok((m.opt_str("v"), transform(cmd, rest)?))
+        });
+    match result {
+        Ok((Some(name), results)) => {
+            ctx.state.set_var(
+                Key::Var(&name),
+                Var::new(name.clone(), VarValue::Array(results)),
+                false,
+            );
+            0
+        }
+        Ok((None, results)) => {
+            for s in results {
+                if let Err(e) = writeln!(ctx.stdout, "{}", s) {
+                    let _ = writeln!(ctx.stderr, "{}", msg!("str.error", e));
+                    return 1;
+                }
+            }
+            0
+        }
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("str.error", e));
+            let _ = write!(ctx.stderr, "{}", usage);
+            2
+        }
+    }
+}
@@ -0,0 +1,67 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::len::{unit_from_matches, unit_options};
+use crate::msg;
+use crate::shell::Context;
+use crate::util::TextUnit;
+use getopts::Options;
+use std::io::Write;
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    s.parse::<usize>()
+        .map_err(|_| msg!("substr.not-a-count", s))
+}
+
+/// Prints a part of a string. Positions are counted in grapheme clusters by default.
+pub fn substr(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    unit_options(&mut opts);
+    let usage = opts.usage(&msg!("substr.usage"));
+    let result = opts
+        .parse(&args[1..])
+        .map_err(|e| e.to_string())
+        .and_then(|m| unit_from_matches(&m).map(|u| (m, u)))
+        .and_then(|(m, unit)| {
+            let free = &m.free;
+            if free.len() < 2 || free.len() > 3 {
+                return Err(msg!("substr.wrong-arguments"));
+            }
+            let start = parse_count(&free[1])?;
+            let len = match free.get(2) {
+                Some(l) => Some(parse_count(l)?),
+                None => None,
+            };
+            Ok(unit
+                .unwrap_or(TextUnit::Graphemes)
+                .slice(&free[0], start, len))
+        });
+    match result {
+        Ok(s) => {
+            if let Err(e) = writeln!(ctx.stdout, "{}", s) {
+                let _ = writeln!(ctx.stderr, "{}", msg!("substr.error", e));
+                return 1;
+            }
+            0
+        }
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("substr.error", e));
+            let _ = write!(ctx.stderr, "{}", usage);
+            2
+        }
+    }
+}
@@ -0,0 +1,219 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::Context;
+use crate::sys;
+use crate::task::split_fields;
+use getopts::Options;
+use std::io::{self, BufRead, Write};
+
+/// The columns are this far apart.
+const GAP: &str = "  ";
+
+/// Returns the length of the escape sequence at the start of `s`, if it starts with one.
+fn escape_len(s: &str) -> Option<usize> {
+    let rest = s.strip_prefix("\x1b[")?;
+    let end = rest.find(|c: char| ('@'..='~').contains(&c))?;
+    Some(2 + end + 1)
+}
+
+/// Returns how many columns the text takes on the terminal, without its escape sequences.
+fn width(s: &str) -> usize {
+    let mut n = 0;
+    let mut i = 0;
+    while i < s.len() {
+        match escape_len(&s[i..]) {
+            Some(len) => i += len,
+            None => {
+                let c = s[i..].chars().next().unwrap();
+                i += c.len_utf8();
+                n += 1;
+            }
+        }
+    }
+    n
+}
+
+/// Cuts the text to `max` columns, ending it with `…` if it was longer.
+/// The escape sequences are kept, and the style is reset after a cut.
+fn truncate(s: &str, max: usize) -> String {
+    if width(s) <= max {
+        return s.to_owned();
+    }
+    let mut r = String::new();
+    let mut n = 0;
+    let mut styled = false;
+    let mut i = 0;
+    while i < s.len() && n + 1 < max {
+        match escape_len(&s[i..]) {
+            Some(len) => {
+                r.push_str(&s[i..i + len]);
+                styled = true;
+                i += len;
+            }
+            None => {
+                let c = s[i..].chars().next().unwrap();
+                r.push(c);
+                i += c.len_utf8();
+                n += 1;
+            }
+        }
+    }
+    if max > 0 {
+        r.push('…');
+    }
+    if styled {
+        r.push_str("\x1b[0m");
+    }
+    r
+}
+
+fn is_number(s: &str) -> bool {
+    s.parse::<f64>().is_ok()
+}
+
+/// Prints the lines of the standard input as a table, with the fields of each line
+/// in aligned columns. Columns of numbers are aligned to the right.
+///
+/// `table [-H] [-s separator] [-w width]`
+pub fn table(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag("H", "", "the first line is the header");
+    opts.optopt(
+        "s",
+        "",
+        "split the fields at this string instead of blanks",
+        "SEP",
+    );
+    opts.optopt("w", "", "cut the fields to this many columns", "WIDTH");
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("table.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("table.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    if !m.free.is_empty() {
+        usage!(msg!("table.unexpected-argument", m.free[0]));
+    }
+    let max = match m.opt_str("w").map(|w| w.parse::<usize>()) {
+        None => None,
+        Some(Ok(w)) if w > 0 => Some(w),
+        Some(_) => usage!(msg!("table.bad-width", m.opt_str("w").unwrap())),
+    };
+    let separator = m.opt_str("s").filter(|s| !s.is_empty());
+    let ifs = ctx.state.ifs();
+
+    let stdin = io::stdin();
+    let mut rows = Vec::new();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("table.error", e));
+                return 1;
+            }
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let fields = match &separator {
+            Some(sep) => line.split(sep.as_str()).collect::<Vec<_>>(),
+            None => split_fields(&line, &ifs, usize::MAX),
+        };
+        let fields = fields.into_iter().map(|f| match max {
+            Some(max) => truncate(f, max),
+            None => f.to_owned(),
+        });
+        rows.push(fields.collect::<Vec<_>>());
+    }
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let header = m.opt_present("H") && !rows.is_empty();
+    let body = if header { &rows[1..] } else { &rows[..] };
+    let widths = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|f| width(f))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let numeric = (0..columns)
+        .map(|i| {
+            let mut cells = body.iter().filter_map(|row| row.get(i));
+            cells.clone().next().is_some() && cells.all(|f| is_number(f))
+        })
+        .collect::<Vec<_>>();
+    // like ls, only a terminal gets colors
+    let style = if sys::isatty(ctx.stdout.0) {
+        ctx.state.style("table.header")
+    } else {
+        Default::default()
+    };
+
+    for (r, row) in rows.iter().enumerate() {
+        let mut line = String::new();
+        for (i, field) in row.iter().enumerate() {
+            let pad = " ".repeat(widths[i] - width(field));
+            let last = i + 1 == row.len();
+            let field = if header && r == 0 {
+                style.paint(field)
+            } else {
+                field.clone()
+            };
+            if numeric[i] {
+                line.push_str(&pad);
+                line.push_str(&field);
+            } else {
+                line.push_str(&field);
+                // no trailing blanks
+                if !last {
+                    line.push_str(&pad);
+                }
+            }
+            if !last {
+                line.push_str(GAP);
+            }
+        }
+        if writeln!(ctx.stdout, "{}", line).is_err() {
+            return 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{truncate, width};
+
+    #[test]
+    fn widths() {
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("\x1b[1;31mred\x1b[0m"), 3);
+        assert_eq!(width("€uro"), 4);
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("abcd", 4), "abcd");
+        assert_eq!(truncate("\x1b[31mabcdef\x1b[0m", 3), "\x1b[31mab…\x1b[0m");
+    }
+}
@@ -0,0 +1,93 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::Context;
+use std::fs;
+use std::io::Write;
+
+fn unary(op: &str, arg: &str) -> Option<bool> {
+    Some(match op {
+        "-d" => fs::metadata(arg).map(|m| m.is_dir()).unwrap_or(false),
+        "-e" => fs::metadata(arg).is_ok(),
+        "-f" => fs::metadata(arg).map(|m| m.is_file()).unwrap_or(false),
+        "-n" => !arg.is_empty(),
+        "-z" => arg.is_empty(),
+        _ => return None,
+    })
+}
+
+fn binary(left: &str, op: &str, right: &str) -> Option<Result<bool, String>> {
+    let int = |s: &str| {
+        s.trim()
+            .parse::<i64>()
+            .map_err(|_| msg!("test.not-integer", s))
+    };
+    let cmp = |f: fn(&i64, &i64) -> bool| Some(int(left).and_then(|l| Ok(f(&l, &int(right)?))));
+    match op {
+        "=" | "==" => Some(Ok(left == right)),
+        "!=" => Some(Ok(left != right)),
+        "-eq" => cmp(i64::eq),
+        "-ne" => cmp(i64::ne),
+        "-lt" => cmp(i64::lt),
+        "-le" => cmp(i64::le),
+        "-gt" => cmp(i64::gt),
+        "-ge" => cmp(i64::ge),
+        _ => None,
+    }
+}
+
+fn evaluate(args: &[&str]) -> Result<bool, String> {
+    match args {
+        [] => Ok(false),
+        [s] => Ok(!s.is_empty()),
+        // binary operators take precedence over negation
+        [left, op, right] => match binary(left, op, right) {
+            Some(r) => r,
+            None if *left == "!" => evaluate(&args[1..]).map(|b| !b),
+            None => Err(msg!("test.unknown-operator", op)),
+        },
+        ["!", rest @ ..] if rest.len() <= 3 => evaluate(rest).map(|b| !b),
+        [op, arg] => unary(op, arg).ok_or_else(|| msg!("test.unknown-operator", op)),
+        _ => Err(msg!("test.too-many-arguments")),
+    }
+}
+
+/// Evaluates a file or string predicate. Also available as `[`, in which case
+/// the last argument must be `]`.
+pub fn test(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut args = &args[..];
+    if args[0] == "[" {
+        if args.last() != Some(&"]") {
+            let _ = writeln!(
+                ctx.stderr,
+                "{}",
+                msg!("test.error", args[0], msg!("test.missing-bracket"))
+            );
+            return 2;
+        }
+        args = &args[..args.len() - 1];
+    }
+    match evaluate(&args[1..]) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("test.error", args[0], e));
+            2
+        }
+    }
+}
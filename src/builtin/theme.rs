@@ -0,0 +1,66 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::print_paged;
+use crate::msg;
+use crate::shell::theme::Style;
+use crate::shell::Context;
+use std::io::Write;
+
+/// Shows or changes the styles of the [theme](../shell/theme/index.html).
+///
+/// Without arguments, it lists every role with its style. With a role, it shows its style,
+/// or sets it to the words that follow. `-r` puts back the default style of the roles.
+pub fn theme(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let result = match args.get(1).cloned() {
+        None => {
+            let listing = ctx
+                .state
+                .theme
+                .iter()
+                .map(|(role, style)| format!("{} {}\n", role, style))
+                .collect::<String>();
+            print_paged(ctx, &listing);
+            Ok(())
+        }
+        Some("-r") if args.len() > 2 => args[2..]
+            .iter()
+            .try_for_each(|role| ctx.state.theme.reset(role)),
+        Some(role) if role.starts_with('-') => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("theme.usage"));
+            return 2;
+        }
+        Some(role) if args.len() == 2 => {
+            let style = ctx.state.theme.iter().find(|(r, _)| *r == role);
+            match style.map(|(_, style)| style.to_string()) {
+                Some(style) => {
+                    let _ = writeln!(ctx.stdout, "{}", style);
+                    Ok(())
+                }
+                None => Err(msg!("theme.unknown-role", role)),
+            }
+        }
+        Some(role) => Style::parse(&args[2..]).and_then(|style| ctx.state.theme.set(role, style)),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("theme.error", e));
+            1
+        }
+    }
+}
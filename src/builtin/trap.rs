@@ -0,0 +1,72 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::print_paged;
+use crate::msg;
+use crate::shell::trap::{condition_name, Trap};
+use crate::shell::{self, Context};
+use crate::util::sh_quote;
+use std::io::Write;
+
+pub fn trap(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    if args.len() == 1 {
+        let listing = ctx
+            .state
+            .traps
+            .iter()
+            .map(|(name, trap)| format!("trap {} {}\n", sh_quote(&trap.code), name))
+            .collect::<String>();
+        print_paged(ctx, &listing);
+        return 0;
+    }
+    if args.len() == 2 {
+        let _ = writeln!(ctx.stderr, "{}", msg!("trap.usage"));
+        return 2;
+    }
+
+    let mut names = Vec::new();
+    for arg in &args[2..] {
+        match condition_name(arg) {
+            Some(name) => names.push(name),
+            None => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("trap.bad-condition", arg));
+                return 1;
+            }
+        }
+    }
+    let trap = if args[1] == "-" {
+        None
+    } else {
+        match shell::parse_code(args[1]) {
+            Ok(body) => Some(Trap {
+                code: args[1].to_owned(),
+                body,
+            }),
+            Err(e) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("trap.error", e));
+                return 1;
+            }
+        }
+    };
+    for name in names {
+        if let Err(e) = ctx.state.set_trap(name, trap.clone()) {
+            let _ = writeln!(ctx.stderr, "{}", msg!("trap.error", e));
+            return 1;
+        }
+    }
+    0
+}
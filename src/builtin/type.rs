@@ -0,0 +1,96 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::get_builtin;
+use crate::msg;
+use crate::shell::Context;
+use getopts::Options;
+use std::io::Write;
+
+/// What a command name refers to.
+pub(super) enum Kind {
+    Alias(Vec<String>),
+    Function,
+    Builtin,
+    File(String),
+}
+
+pub(super) fn kind(ctx: &mut Context, name: &str) -> Option<Kind> {
+    if let Some(words) = ctx.state.aliases.get(name) {
+        Some(Kind::Alias(words.clone()))
+    } else if ctx.state.functions.contains_key(name) {
+        Some(Kind::Function)
+    } else if get_builtin(name).is_some() {
+        Some(Kind::Builtin)
+    } else {
+        ctx.state
+            .find_command(name)
+            .map(|p| Kind::File(p.to_string_lossy().into_owned()))
+    }
+}
+
+/// Tells what each name would run as the name of a command.
+pub fn r#type(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optflag("t", "", "print only alias, function, builtin or file");
+    opts.optflag("p", "", "print only the path of files");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("type.error", e));
+            let _ = write!(ctx.stderr, "{}", opts.usage(&msg!("type.usage")));
+            return 2;
+        }
+    };
+    if matches.free.is_empty() {
+        let _ = write!(ctx.stderr, "{}", opts.usage(&msg!("type.usage")));
+        return 2;
+    }
+    let terse = matches.opt_present("t");
+    let path_only = matches.opt_present("p");
+
+    let mut ret = 0;
+    for name in &matches.free {
+        let line = match kind(ctx, name) {
+            None => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("type.not-found", name));
+                ret = 1;
+                continue;
+            }
+            Some(Kind::Alias(_)) | Some(Kind::Function) | Some(Kind::Builtin) if path_only => {
+                continue
+            }
+            Some(Kind::File(path)) if path_only => path,
+            Some(Kind::Alias(_)) if terse => "alias".to_owned(),
+            Some(Kind::Function) if terse => "function".to_owned(),
+            Some(Kind::Builtin) if terse => "builtin".to_owned(),
+            Some(Kind::File(_)) if terse => "file".to_owned(),
+            Some(Kind::Alias(words)) => msg!("type.alias", name, words.join(" ")),
+            Some(Kind::Function) => msg!("type.function", name),
+            Some(Kind::Builtin) => msg!("type.builtin", name),
+            Some(Kind::File(path)) => msg!("type.file", name, path),
+        };
+        let _ = writeln!(ctx.stdout, "{}", line);
+    }
+    ret
+}
+
+/// Forgets the locations of the commands found in `$PATH`.
+pub fn rehash(ctx: &mut Context, _args: Vec<&str>) -> i32 {
+    ctx.state.command_cache.clear();
+    0
+}
@@ -0,0 +1,115 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::names;
+use crate::msg;
+use crate::msg::overrides_path;
+use crate::shell::pretty::ToJson;
+use crate::shell::{profile_path, Context};
+use crate::util::json_string;
+use std::env::consts::{ARCH, OS};
+use std::io::Write;
+
+/// The optional features of the crate that this shell was built with.
+pub(super) fn features() -> Vec<String> {
+    let all = [
+        ("ffi", cfg!(all(unix, feature = "ffi"))),
+        ("parallel", cfg!(feature = "parallel")),
+        ("rustyline", cfg!(all(unix, feature = "rustyline"))),
+    ];
+    all.iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Returns what there is to know about this build of the shell: only the version,
+/// or, if `verbose`, also the features, the platform, the files that it reads and
+/// the builtins, as `key: value` lines or as a JSON object.
+pub fn version_info(verbose: bool, json: bool) -> String {
+    let version = env!("CARGO_PKG_VERSION");
+    if !verbose && !json {
+        return format!("rwsh {}\n", version);
+    }
+    let path = |p: Option<std::path::PathBuf>| p.map(|p| p.display().to_string());
+    let builtins = names().map(str::to_owned).collect::<Vec<_>>();
+    let build = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    let fields = vec![
+        ("version", version.to_owned(), json_string(version)),
+        ("features", features().join(" "), features().to_json()),
+        (
+            "target",
+            format!("{}-{}", ARCH, OS),
+            json_string(&format!("{}-{}", ARCH, OS)),
+        ),
+        ("build", build.to_owned(), json_string(build)),
+        (
+            "profile",
+            path(profile_path()).unwrap_or_default(),
+            path(profile_path()).to_json(),
+        ),
+        (
+            "messages",
+            path(overrides_path()).unwrap_or_default(),
+            path(overrides_path()).to_json(),
+        ),
+        ("builtins", builtins.join(" "), builtins.to_json()),
+    ];
+    if json {
+        let fields = fields
+            .into_iter()
+            .map(|(k, _, v)| format!("{}:{}", json_string(k), v))
+            .collect::<Vec<_>>();
+        format!("{{{}}}\n", fields.join(","))
+    } else {
+        fields
+            .into_iter()
+            .map(|(k, v, _)| format!("{}: {}\n", k, v))
+            .collect()
+    }
+}
+
+/// Prints the version of the shell, and with `-v` or `--json`, what it was built with,
+/// so that scripts can check for a feature before using it.
+///
+/// `version [-v] [--json]`
+pub fn version(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let (mut verbose, mut json) = (false, false);
+    for arg in &args[1..] {
+        match *arg {
+            "-v" | "--verbose" => verbose = true,
+            "--json" => json = true,
+            _ => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("version.unexpected-argument", arg));
+                let _ = writeln!(ctx.stderr, "{}", msg!("version.usage"));
+                return 2;
+            }
+        }
+    }
+    if ctx
+        .stdout
+        .write_all(version_info(verbose, json).as_bytes())
+        .is_err()
+    {
+        return 1;
+    }
+    0
+}
@@ -0,0 +1,115 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{self, Context, Key, Var, VarValue};
+use crate::sys::{self, watch::Watcher};
+use getopts::{Options, ParsingStyle};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Runs the body every time one of the files changes, with the kind of change in
+/// `$event` and the changed file in `$file`. A directory is watched for changes
+/// to the files in it.
+///
+/// `watch [-n count] path... { body }`
+pub fn watch(ctx: &mut Context, args: Vec<&str>) -> i32 {
+    let mut opts = Options::new();
+    opts.optopt("n", "", "stop after this many changes", "COUNT");
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    macro_rules! usage {
+        ($e:expr) => {{
+            let _ = writeln!(ctx.stderr, "{}", msg!("watch.error", $e));
+            let _ = writeln!(ctx.stderr, "{}", msg!("watch.usage"));
+            return 2;
+        }};
+    }
+    let m = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => usage!(e),
+    };
+    let mut left = match m.opt_str("n").map(|n| n.parse::<usize>()) {
+        None => None,
+        Some(Ok(n)) if n > 0 => Some(n),
+        Some(_) => usage!(msg!("watch.bad-count", m.opt_str("n").unwrap())),
+    };
+    let (code, paths) = match m.free.split_last() {
+        Some((code, paths)) if !paths.is_empty() => (code, paths),
+        _ => usage!(msg!("watch.missing-args")),
+    };
+    let body = match shell::parse_code(code) {
+        Ok(body) => body,
+        Err(e) => usage!(e),
+    };
+    let paths = paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let mut watcher = match Watcher::new(&paths) {
+        Ok(w) => w,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", msg!("watch.error", e));
+            return 1;
+        }
+    };
+
+    let mut status = 0;
+    loop {
+        let events = match watcher.wait() {
+            Ok(Some(events)) => events,
+            // everything watched was deleted
+            Ok(None) => return status,
+            // a signal interrupts the wait, as in `every`
+            Err(ref e) if sys::is_interrupted(e) => {
+                ctx.state.run_pending_traps();
+                if ctx.state.exit != -1 {
+                    return status;
+                }
+                continue;
+            }
+            Err(e) => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("watch.error", e));
+                return 1;
+            }
+        };
+        for event in events {
+            let file = event.file.to_string_lossy().into_owned();
+            for (name, value) in &[("event", event.kind.to_owned()), ("file", file)] {
+                ctx.state.set_var(
+                    Key::Var(name),
+                    Var::new((*name).to_owned(), VarValue::Array(vec![value.clone()])),
+                    false,
+                );
+            }
+            for prog in &body {
+                status = match ctx.run_program(prog.clone()) {
+                    Ok(status) => status,
+                    Err(e) => {
+                        let _ = writeln!(ctx.stderr, "{}", msg!("watch.error", e));
+                        1
+                    }
+                };
+                if ctx.state.exit != -1 {
+                    return status;
+                }
+            }
+            if let Some(n) = left.as_mut() {
+                *n -= 1;
+                if *n == 0 {
+                    return status;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! A C interface for programs that embed the shell, built with the `ffi` feature.
+//! The shared library is built with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! Strings go in and out as NUL-terminated UTF-8. The strings returned by the
+//! functions belong to the caller, who frees them with [`rwsh_string_free`](fn.rwsh_string_free.html).
+use crate::parser::Parser;
+use crate::shell::pretty::PrettyPrint;
+use crate::shell::{Config, Shell};
+use crate::util::{BufReadChars, FileLineReader};
+use libc::{c_char, c_int};
+use std::ffi::{CStr, CString};
+use std::io::Cursor;
+use std::ptr;
+
+/// A shell with the error of its last evaluation.
+pub struct RwshShell {
+    shell: Shell,
+    error: Option<CString>,
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err("null string".to_owned());
+    }
+    CStr::from_ptr(s).to_str().map_err(|e| e.to_string())
+}
+
+fn to_c_string(s: String) -> *mut c_char {
+    // NUL bytes can't be in the variables of the shell, but may come from elsewhere
+    CString::new(s.replace('\0', ""))
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Creates a shell that runs only the code given to [`rwsh_eval`](fn.rwsh_eval.html).
+/// Returns null if the shell can't be created.
+#[no_mangle]
+pub extern "C" fn rwsh_new() -> *mut RwshShell {
+    match Shell::new_embedded(Config::default()) {
+        Ok(shell) => Box::into_raw(Box::new(RwshShell { shell, error: None })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a shell returned by [`rwsh_new`](fn.rwsh_new.html).
+///
+/// # Safety
+///
+/// `sh` must be null or a shell returned by `rwsh_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rwsh_free(sh: *mut RwshShell) {
+    if !sh.is_null() {
+        drop(Box::from_raw(sh));
+    }
+}
+
+/// Runs the code and returns the status of its last command.
+/// Returns -1 if the code can't be parsed or run, see [`rwsh_error`](fn.rwsh_error.html).
+///
+/// # Safety
+///
+/// `sh` must be null or a shell returned by `rwsh_new` and not freed yet.
+/// `code` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rwsh_eval(sh: *mut RwshShell, code: *const c_char) -> c_int {
+    let sh = match sh.as_mut() {
+        Some(sh) => sh,
+        None => return -1,
+    };
+    let r = to_str(code).and_then(|code| sh.shell.eval(code));
+    match r {
+        Ok(status) => {
+            sh.error = None;
+            status
+        }
+        Err(e) => {
+            sh.error = CString::new(e.replace('\0', "")).ok();
+            -1
+        }
+    }
+}
+
+/// Returns the error of the last call to [`rwsh_eval`](fn.rwsh_eval.html), or null if it succeeded.
+/// The string belongs to the shell and lives until the next evaluation.
+///
+/// # Safety
+///
+/// `sh` must be null or a shell returned by `rwsh_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rwsh_error(sh: *const RwshShell) -> *const c_char {
+    match sh.as_ref().and_then(|sh| sh.error.as_ref()) {
+        Some(e) => e.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Returns the value of a variable, like `name` or `name[1]`, or null if it isn't set.
+///
+/// # Safety
+///
+/// `sh` must be null or a shell returned by `rwsh_new` and not freed yet.
+/// `name` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rwsh_get_var(sh: *const RwshShell, name: *const c_char) -> *mut c_char {
+    let sh = match sh.as_ref() {
+        Some(sh) => sh,
+        None => return ptr::null_mut(),
+    };
+    match to_str(name).ok().and_then(|name| sh.shell.get_var(name)) {
+        Some(value) => to_c_string(value),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Returns the syntax tree of the code, as printed by `rwsh -n`, or null if it can't be parsed.
+///
+/// # Safety
+///
+/// `code` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rwsh_parse(code: *const c_char) -> *mut c_char {
+    let code = match to_str(code) {
+        Ok(code) => code,
+        Err(_) => return ptr::null_mut(),
+    };
+    let reader = FileLineReader::new(Cursor::new(format!("{}\n", code))).unwrap();
+    let parser = Parser::new(BufReadChars::new(Box::new(reader)));
+    let mut tree = String::new();
+    for p in parser {
+        match p {
+            Ok(p) => tree.push_str(&p.pretty_print().render()),
+            Err(_) => return ptr::null_mut(),
+        }
+    }
+    to_c_string(tree)
+}
+
+/// Frees a string returned by the shell.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by `rwsh_get_var` or `rwsh_parse` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rwsh_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    fn take(s: *mut c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let r = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_owned();
+        unsafe { rwsh_string_free(s) };
+        Some(r)
+    }
+
+    #[test]
+    fn eval_and_get_var() {
+        unsafe {
+            let sh = rwsh_new();
+            assert_eq!(rwsh_eval(sh, c("let x = [ a b c ]\nfalse").as_ptr()), 1);
+            assert!(rwsh_error(sh).is_null());
+            assert_eq!(
+                take(rwsh_get_var(sh, c("x[1]").as_ptr())),
+                Some("b".to_owned())
+            );
+            assert_eq!(take(rwsh_get_var(sh, c("unset").as_ptr())), None);
+            assert_eq!(rwsh_eval(sh, c("exit 3").as_ptr()), 3);
+            assert_eq!(rwsh_eval(sh, c("echo (").as_ptr()), -1);
+            assert!(!rwsh_error(sh).is_null());
+            rwsh_free(sh);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let tree = take(unsafe { rwsh_parse(c("a | b").as_ptr()) }).unwrap();
+        assert!(tree.contains("pipeline"));
+        assert!(unsafe { rwsh_parse(c("{ a").as_ptr()) }.is_null());
+    }
+}
@@ -16,9 +16,15 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 pub mod builtin;
+#[cfg(all(unix, feature = "ffi"))]
+pub mod ffi;
+pub mod msg;
 pub mod parser;
+#[cfg(unix)]
+pub mod pty;
 pub mod shell;
 pub mod sre;
+pub mod sys;
 pub mod task;
 pub mod tests;
 pub mod util;
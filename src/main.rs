@@ -15,55 +15,161 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
-use getopts::Options;
+use getopts::{Options, ParsingStyle};
 use nix::unistd;
+use rwsh::builtin::version_info;
+use rwsh::msg;
+use rwsh::parser::grammar;
+use rwsh::shell::pretty::AstFormat;
+use rwsh::shell::record::record;
+use rwsh::shell::theme::plain_output;
 use rwsh::shell::{Config, Shell};
+use rwsh::task::Tracer;
 use rwsh::util::FileLineReader;
 use std::env;
 use std::fs::File;
-use std::io::stdin;
+use std::io::{stdin, Cursor};
 use std::process::exit;
 
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!(
-        "rwsh v{}\nUsage: {} [options] [file]",
-        env!("CARGO_PKG_VERSION"),
-        program
-    );
+    let brief = msg!("rwsh.usage", env!("CARGO_PKG_VERSION"), program, program);
     eprint!("{}", opts.usage(&brief));
 }
 
 fn main() {
     let args = env::args().collect::<Vec<_>>();
     let mut opts = Options::new();
-    opts.optflag("n", "", "pretty print AST instead of executing");
+    // everything after the script's name belongs to the script
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+    opts.optflag("n", "", "same as --ast");
+    opts.optflagopt(
+        "",
+        "ast",
+        "print the syntax tree instead of running the code, as a tree or as JSON",
+        "tree|json",
+    );
     opts.optflag("h", "help", "print this help message");
-    let matches = match opts.parse(args.iter()) {
+    opts.optopt("c", "", "run the code instead of a file", "CODE");
+    opts.optflag("l", "login", "run as a login shell");
+    opts.optflagopt(
+        "",
+        "trace-tasks",
+        "log how tasks are polled to the standard error or to a file",
+        "FILE",
+    );
+    opts.optflag("", "dump-grammar", "print the grammar of the shell in EBNF");
+    opts.optflag("", "version", "print the version of the shell");
+    opts.optflag(
+        "",
+        "verbose",
+        "with --version, also print the features, build and configuration files",
+    );
+    opts.optopt(
+        "",
+        "record",
+        "record the interactive session into a file",
+        "FILE",
+    );
+    let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(e) => {
-            eprintln!("rwsh: {}", e);
+            eprintln!("{}", msg!("rwsh.error", e));
             print_usage(&args[0], opts);
             exit(2);
         }
     };
     if matches.opt_present("h") {
-        print_usage(&matches.free[0], opts);
+        print_usage(&args[0], opts);
+        return;
+    }
+    if matches.opt_present("dump-grammar") {
+        print!("{}", grammar::dump());
         return;
     }
+    if matches.opt_present("version") {
+        print!("{}", version_info(matches.opt_present("verbose"), false));
+        return;
+    }
+
+    let interactive =
+        matches.free.is_empty() && !matches.opt_present("c") && unistd::isatty(0).unwrap();
+    if matches.opt_present("record") && !interactive {
+        eprintln!("{}", msg!("rwsh.error", msg!("record.not-interactive")));
+        exit(2);
+    }
 
+    let ast = match matches.opt_str("ast").as_deref() {
+        _ if !matches.opt_present("ast") && !matches.opt_present("n") => None,
+        None | Some("tree") => Some(AstFormat::Tree),
+        Some("json") => Some(AstFormat::Json),
+        Some(format) => {
+            eprintln!(
+                "{}",
+                msg!("rwsh.error", msg!("rwsh.bad-ast-format", format))
+            );
+            exit(2);
+        }
+    };
     let cfg = Config {
-        pretty_print: matches.opt_present("n"),
+        ast,
+        plain: plain_output(),
+        ..Config::default()
     };
-    if let Some(input) = matches.free.get(1) {
+    let shell = if let Some(code) = matches.opt_str("c") {
+        Shell::new(
+            Box::new(FileLineReader::new(Cursor::new(code + "\n")).unwrap()),
+            cfg,
+            false,
+        )
+    } else if let Some(input) = matches.free.get(0) {
         Shell::new(
             Box::new(FileLineReader::new(File::open(input).unwrap()).unwrap()),
             cfg,
             false,
         )
-        .run();
-    } else if unistd::isatty(0).unwrap() {
-        Shell::new_interactive(cfg).run();
+    } else if interactive {
+        if let Some(path) = matches.opt_str("record") {
+            if let Err(e) = record(&path) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Shell::new_interactive(cfg)
     } else {
-        Shell::new(Box::new(FileLineReader::new(stdin()).unwrap()), cfg, false).run();
+        Shell::new(Box::new(FileLineReader::new(stdin()).unwrap()), cfg, false)
+    };
+    let mut shell = match shell {
+        Ok(shell) => shell,
+        Err(e) => {
+            eprintln!("{}", msg!("rwsh.error", e));
+            exit(1);
+        }
+    };
+    // the variable is read once, so that the trace doesn't change when it is set
+    let trace = if matches.opt_present("trace-tasks") {
+        Some(
+            matches
+                .opt_str("trace-tasks")
+                .unwrap_or_else(|| "-".to_owned()),
+        )
+    } else {
+        env::var("RWSH_TRACE").ok().filter(|dest| !dest.is_empty())
+    };
+    if let Some(dest) = trace {
+        match Tracer::open(&dest) {
+            Ok(tracer) => shell.set_tracer(tracer),
+            Err(e) => eprintln!("{}", msg!("rwsh.error", msg!("rwsh.trace-error", dest, e))),
+        }
+    }
+    // as with sh, the first argument after `-c code` becomes $0
+    if matches.free.is_empty() {
+        shell.set_args(vec![args[0].clone()]);
+    } else {
+        shell.set_args(matches.free.clone());
+    }
+    // login programs start the shell with a name beginning with a dash
+    if matches.opt_present("l") || args[0].starts_with('-') {
+        shell.login();
     }
+    shell.run();
 }
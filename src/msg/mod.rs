@@ -0,0 +1,570 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! The catalog of user-facing messages.
+//!
+//! Every message has an id and a default template. The templates can be overridden
+//! by the file pointed to by `$RWSH_MESSAGES`, or `~/.rwsh_messages` if it's not set.
+//! Each line of the file is of the form `id = template`. Empty lines and lines
+//! starting with `#` are ignored. In a template, every `{}` is replaced by the
+//! next argument and `\n`, `\t` and `\\` are the usual escapes.
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+
+/// Formats a message from the catalog.
+///
+/// # Example
+///
+/// `msg!("shift.too-many", n, available)`
+#[macro_export]
+macro_rules! msg {
+    ($id:expr) => {
+        $crate::msg::format($id, &[])
+    };
+    ($id:expr, $($arg:expr),+ $(,)?) => {
+        $crate::msg::format($id, &[$(&$arg),+])
+    };
+}
+
+// keep sorted!
+static DEFAULTS: &'static [(&'static str, &'static str)] = &[
+    ("[.summary", "test a condition"),
+    ("alias.cycle", "alias loop: {}"),
+    ("alias.summary", "define or list aliases"),
+    ("alias.usage", "Usage: alias [name word1 word2 ... wordN]"),
+    ("arith.bad-number", "'{}' is not a number"),
+    ("arith.bad-precision", "the precision must be a number of digits"),
+    ("arith.division-by-zero", "division by zero"),
+    ("arith.no-such-var", "variable '{}' doesn't exist"),
+    ("arith.not-a-number", "'{}' is not a number: '{}'"),
+    ("arith.overflow", "integer overflow"),
+    ("arith.unexpected-char", "unexpected character '{}'"),
+    ("arith.unexpected-end", "unexpected end of expression"),
+    ("arith.unexpected-token", "unexpected '{}'"),
+    ("base64.summary", "encode or decode base64"),
+    ("breakpoint.error", "breakpoint: {}"),
+    ("breakpoint.list-only", "-l takes no other arguments"),
+    ("breakpoint.not-found", "breakpoint: no breakpoint at {}"),
+    ("breakpoint.summary", "set, list or delete breakpoints"),
+    (
+        "breakpoint.usage",
+        "Usage: breakpoint [-d] [line | function]...\n       breakpoint -l",
+    ),
+    ("calc.error", "calc: {}"),
+    ("calc.summary", "evaluate an arithmetic expression"),
+    ("cd.error", "cd: {}"),
+    ("cd.no-oldpwd", "cd: OLDPWD not set"),
+    ("cd.summary", "change the working directory"),
+    ("command.not-found", "{}: command not found"),
+    ("complete.alias", "alias for `{}`"),
+    ("complete.directory", "directory"),
+    ("complete.executable", "in {}"),
+    ("complete.function", "function"),
+    ("complete.link", "link to {}"),
+    ("correct-last.no-error", "correct-last: no parse error to correct"),
+    ("correct-last.summary", "edit the line with the last parse error"),
+    ("date-add.error", "date-add: {}"),
+    ("date-add.missing-time", "missing time"),
+    ("date-add.summary", "add durations to a time"),
+    ("date-add.usage", "Usage: date-add [-u] [-f format] time [duration...]"),
+    ("date-diff.error", "date-diff: {}"),
+    ("date-diff.missing-time", "missing time"),
+    ("date-diff.summary", "print the time between two times"),
+    ("date-diff.unexpected-argument", "unexpected argument {}"),
+    ("date-diff.unknown-unit", "unknown unit `{}`, expected s, m, h, d or w"),
+    ("date-diff.usage", "Usage: date-diff [-u] [-i unit] from to"),
+    ("date.bad-duration", "bad duration `{}`, expected something like 1d12h or -2w"),
+    ("date.bad-format", "bad format `{}`"),
+    ("date.bad-time", "bad time `{}`, expected now, @seconds or a date like 2019-10-16 14:02"),
+    ("date.out-of-range", "the time is out of range"),
+    ("debug.aborted", "aborted in the debugger"),
+    ("debug.at", "line {}: {}"),
+    (
+        "debug.help",
+        "s, step      run the command and pause before the next one\nc, continue  run until the next breakpoint\na, abort     don't run the command\nh, help      show this help\nAnything else is run as shell code.",
+    ),
+    ("debug.paused", "debug: paused at {}"),
+    ("debug.prompt", "debug> "),
+    ("dirs.summary", "print the directory stack"),
+    ("echo.summary", "print the arguments"),
+    ("echo.write-error", "echo: write error: {}"),
+    ("editor.unavailable", "rwsh: line editor `{}` is not available, using the default one"),
+    ("else.without-if", "cannot use else without an if before it"),
+    ("encoding.bad-char", "unexpected character `{}`"),
+    ("encoding.error", "{}: {}"),
+    ("encoding.read-error", "cannot read the standard input: {}"),
+    ("encoding.truncated", "the input ends in the middle of a byte"),
+    ("encoding.usage", "Usage: {} [-d] [string...]"),
+    ("env-diff.error", "env-diff: {}"),
+    ("env-diff.missing-body", "missing body"),
+    ("env-diff.summary", "show how a block changes the exported variables"),
+    ("env-diff.unexpected-argument", "unexpected argument {}"),
+    ("env-diff.usage", "Usage: env-diff { body }"),
+    ("eval.capture-error", "cannot capture the output: {}"),
+    ("eval.summary", "run the arguments as code"),
+    ("every.bad-count", "`{}` is not a positive number"),
+    ("every.bad-duration", "`{}` is not a duration, such as 5s, 200ms, 1.5m or 1h"),
+    ("every.error", "every: {}"),
+    ("every.missing-args", "expected a duration and a body"),
+    ("every.summary", "run a block on an interval"),
+    ("every.usage", "Usage: every [-n count] duration { body }"),
+    ("exec.error", "{}: {}"),
+    ("exit.not-integer", "exit: exit code not an integer"),
+    ("exit.summary", "exit the shell"),
+    ("exit.usage", "exit: Usage:\nexit [code]"),
+    ("explain.alias", "{}: alias for `{}`"),
+    ("explain.assignment", "{}={}: assignment of `{}`"),
+    ("explain.assignment-substitution", "{}={}: assignment of a command substitution, not run"),
+    ("explain.builtin", "{}: builtin"),
+    ("explain.definition", "{}: function being defined"),
+    ("explain.error", "explain: {}"),
+    ("explain.expanded-name", "{} (expands to {})"),
+    ("explain.expands", "{}: expands to `{}`"),
+    ("explain.file", "{}: file {}"),
+    ("explain.function", "{}: function"),
+    ("explain.glob", "{}: pattern matching {}"),
+    ("explain.glob-none", "{}: pattern matching nothing, passed as is"),
+    ("explain.keyword", "{}: keyword"),
+    ("explain.literal", "{}: literal"),
+    ("explain.not-found", "{}: command not found"),
+    ("explain.pattern", "{}: switch or match pattern"),
+    ("explain.sre", "|>: structural regular expression program"),
+    ("explain.substitution", "{}: command substitution, not run"),
+    ("explain.summary", "show how a command line would be expanded"),
+    ("explain.usage", "Usage: explain command-line"),
+    ("false.summary", "do nothing, unsuccessfully"),
+    ("file.not-utf8", "{} is not UTF-8 text, read it with -e base64 or -e hex"),
+    ("file.unknown-encoding", "unknown encoding `{}`, expected utf8, base64 or hex"),
+    ("filter.summary", "keep the elements for which a block succeeds"),
+    ("fork.error", "couldn't fork: {}"),
+    ("guard.bad-limit", "invalid GUARD_MAX: {}"),
+    ("guard.cancelled", "{}: cancelled"),
+    ("guard.confirm", "{}: {}, run anyway? [y/N] "),
+    ("guard.glob", "a pattern matches {} files"),
+    ("guard.not-interactive", "{}: {}, not run without a user to confirm it"),
+    ("guard.read-error", "guard: {}"),
+    ("guard.root", "it names /"),
+    ("has.error", "has: {}"),
+    ("has.summary", "tell whether the shell has what is named"),
+    ("has.usage", "Usage: has [-b] [-c] [-f] [-k] [-o] name1 name2 ... nameN"),
+    ("hash.error", "hash: {}"),
+    ("hash.missing-algorithm", "missing algorithm"),
+    ("hash.summary", "print the digest of strings"),
+    ("hash.unknown-algorithm", "unknown algorithm `{}`, expected sha256 or md5"),
+    ("hash.usage", "Usage: hash sha256|md5 [string...]"),
+    ("hex.summary", "encode or decode hexadecimal"),
+    ("home.error", "couldn't get home dir: {}"),
+    ("home.no-such-user", "couldn't get home dir: no such user"),
+    ("len.error", "len: {}"),
+    ("len.summary", "print the length of a variable"),
+    ("len.too-many-units", "only one of -g, -c and -b can be given"),
+    ("len.usage", "Usage: len [-g | -c | -b] variable"),
+    ("let.array-on-bool", "cannot use array as a boolean"),
+    ("let.array-on-number", "cannot use array on number"),
+    ("let.array-open", "array literal left open"),
+    ("let.bool-operator", "--bool only works with the '=' operator"),
+    ("let.erase-whole", "can only erase whole vars"),
+    ("let.error", "let: {}"),
+    ("let.keys-values-mismatch", "number of keys doesn't match the number of values"),
+    ("let.listing-only", "--porcelain and --json can only be used when listing variables"),
+    ("let.missing-keys", "missing keys"),
+    ("let.missing-operator", "missing '=' operator"),
+    ("let.missing-values", "missing values"),
+    ("let.no-such-var", "variable '{}' doesn't exist"),
+    ("let.not-a-map", "'{}' is not a map"),
+    ("let.not-a-number", "'{}' is not a number"),
+    ("let.not-an-array", "'{}' is a map"),
+    ("let.not-enough-arguments", "not enough arguments"),
+    ("let.null-only", "--null takes only the names of the variables"),
+    ("let.odd-pairs", "keys and values must come in pairs"),
+    ("let.special-var", "let: cannot change special variable"),
+    ("let.summary", "assign variables, with arithmetic"),
+    ("let.typed", "'{}' is a boolean or null"),
+    (
+        "let.usage",
+        "Usage: {} [options] key1 key2 ... keyN = value1 value 2 ... valueN\n       {} [options] map <= [ key1 value1 ... ]\n       {} [options] map >= [ key1 ... ]\n       {} [options] -e key\n       {} [options] --null key\n       {} -t expression",
+    ),
+    ("let.use-whole", "can only use whole vars"),
+    ("limit.function-depth", "function `{}` called on line {} nests more than max-function-depth ({}) calls"),
+    ("limit.hook-depth", "hook `{}` nests more than max-function-depth ({}) calls"),
+    ("limit.iterations", "the while loop on line {} ran more than max-iterations ({}) times"),
+    ("map.error", "{}: {}"),
+    ("map.missing-body", "missing body"),
+    ("map.pipe-error", "couldn't create pipe: {}"),
+    ("map.summary", "run a block for every element"),
+    ("map.usage", "Usage: {} [-v var] element... { body }"),
+    ("match.open-error", "match: cannot open {}: {}"),
+    ("math.error", "math: {}"),
+    ("math.missing-function", "missing function"),
+    ("math.negative-sqrt", "cannot take the square root of a negative number"),
+    ("math.summary", "apply a math function to expressions"),
+    ("math.unknown-function", "unknown function '{}'"),
+    (
+        "math.usage",
+        "Usage: math [-p digits] function expression...\n\nFunctions: abs x, ceil x, floor x, pow x y, round x, sqrt x",
+    ),
+    ("math.wrong-arguments", "{} takes {} arguments"),
+    ("net.bad-count", "`{}` is not a positive number"),
+    ("net.bad-port", "`{}` is not a port"),
+    ("net.error", "net: {}"),
+    ("net.missing-args", "expected an address and a body"),
+    ("net.summary", "connect to servers and serve clients"),
+    ("net.unknown-command", "unknown command `{}`, expected connect or listen"),
+    ("net.usage", "Usage: net connect (host port | -u path) { body }\n       net listen [-n count] ([host] port | -u path) { body }"),
+    ("pager.more", "--More--({}%)"),
+    ("pager.not-found", "pattern not found: {}"),
+    ("parse.bad-redirection", "bad redirection '{}'"),
+    ("parse.expected-and-list", "expected an and list"),
+    ("parse.expected-char-eof", "expected character, got EOF"),
+    ("parse.expected-condition-symbol", "expected '{}' in {} condition, got {}"),
+    ("parse.expected-condition-symbol-eof", "expected '{}' in {} condition, got EOF"),
+    ("parse.expected-delimiter", "expected '{}', got {}"),
+    ("parse.expected-delimiter-eof", "expected '{}', got EOF"),
+    ("parse.expected-else-body-eof", "expected else body, got EOF"),
+    ("parse.expected-function-body", "expected function body in braces"),
+    ("parse.expected-function-name", "expected function name"),
+    ("parse.expected-function-name-eof", "expected function name, got EOF"),
+    ("parse.expected-if-body-eof", "expected if body, got EOF"),
+    ("parse.expected-if-condition-eof", "expected if condition, got EOF"),
+    ("parse.expected-match-pattern", "expected match pattern"),
+    ("parse.expected-match-pattern-eof", "expected match pattern, got EOF"),
+    ("parse.expected-match-source-eof", "expected match source, got EOF"),
+    ("parse.expected-negated-pipeline", "expected pipeline after !"),
+    ("parse.expected-or-list", "expected an or list"),
+    ("parse.expected-pattern-body-eof", "expected pattern body, got EOF"),
+    ("parse.expected-pipe", "expected pipe, pizza or newline"),
+    ("parse.expected-pipeline-eof", "expected pipeline, got EOF"),
+    ("parse.expected-switch-matchee-eof", "expected switch matchee, got EOF"),
+    ("parse.expected-switch-pattern", "expected switch pattern"),
+    ("parse.expected-switch-pattern-eof", "expected switch pattern, got EOF"),
+    ("parse.expected-time-pipeline", "expected pipeline after time"),
+    ("parse.expected-while-body-eof", "expected while body, got EOF"),
+    ("parse.expected-while-condition", "expected while condition"),
+    ("parse.expected-while-condition-eof", "expected while condition, got EOF"),
+    ("parse.sre-bad-address", "Eaddress"),
+    ("parse.sre-bad-matches", "bad match numbers '{}'"),
+    ("parse.sre-files", "expected a blank after the list of files"),
+    ("parse.sre-missing-command-line", "expected a command line after {}"),
+    ("parse.sre-missing-delimiter", "missing terminal '{}' in parameter"),
+    ("parse.sre-missing-file", "expected a file name after {}"),
+    ("parse.sre-unexpected-arg-char", "unexpected character '{}' when reading argument"),
+    ("parse.sre-unexpected-arg-eof", "unexpected EOF when reading argument"),
+    ("parse.sre-unexpected-command-char", "unexpected character '{}' when reading command name"),
+    ("parse.sre-unexpected-command-eof", "unexpected EOF when reading command"),
+    ("parse.unclosed-regex", "unclosed regex"),
+    ("parse.unexpected-char", "unexpected character '{}'"),
+    ("parse.unexpected-token", "unexpected token {}"),
+    ("pipe.error", "failed to pipe: {}"),
+    ("pipe.run-error", "error in pipe: {}"),
+    ("popd.empty", "popd: directory stack empty"),
+    ("popd.error", "popd: {}"),
+    ("popd.summary", "go back to a directory of the stack"),
+    ("printf.error", "printf: {}"),
+    ("printf.invalid-directive", "%{}: invalid directive"),
+    ("printf.invalid-number", "{}: invalid number"),
+    ("printf.missing-directive", "missing directive after %"),
+    ("printf.summary", "print formatted text"),
+    ("printf.usage", "Usage: printf format [arguments]"),
+    ("printf.write-error", "printf: write error: {}"),
+    ("process.lost", "the status of process {} was collected by someone else"),
+    ("progress.bad-size", "`{}` is not a size in bytes"),
+    ("progress.error", "progress: {}"),
+    ("progress.summary", "pass data through while showing how much went through"),
+    ("progress.unexpected-argument", "unexpected argument {}"),
+    ("progress.usage", "Usage: progress [-l label] [-s size]"),
+    ("pty.error", "pty: {}"),
+    ("pty.summary", "run a command on a new pseudoterminal"),
+    ("pty.usage", "Usage: pty command [arg...]"),
+    ("pushd.error", "pushd: {}"),
+    ("pushd.no-other-directory", "pushd: no other directory"),
+    ("pushd.summary", "change the working directory, remembering the old one"),
+    ("quote.summary", "quote the arguments for a POSIX shell"),
+    ("quote.write-error", "quote: write error: {}"),
+    ("read-file.error", "read-file: {}"),
+    ("read-file.missing-path", "missing path"),
+    ("read-file.summary", "read a file into a variable, or print it"),
+    ("read-file.unexpected-argument", "unexpected argument {}"),
+    ("read-file.usage", "Usage: read-file [-e utf8|base64|hex] [-o var] path"),
+    ("read.error", "read: {}"),
+    ("read.missing-vars", "read: missing variable names"),
+    ("read.summary", "read a line into variables"),
+    ("read.usage", "Usage: {} [options] var1 var2 ... varN"),
+    ("record.error", "record: {}"),
+    ("record.not-interactive", "only interactive sessions can be recorded"),
+    ("record.open-error", "record: cannot open {}: {}"),
+    ("redirect.error", "cannot redirect {}: {}"),
+    ("redirect.open-error", "cannot open {}: {}"),
+    ("regex.error", "regex error: {}"),
+    ("rehash.summary", "forget the remembered command paths"),
+    ("resolve.alias", "alias for {}"),
+    ("resolve.autocd", "directory, changing to it"),
+    ("resolve.builtin", "builtin"),
+    ("resolve.function", "function"),
+    ("resolve.hashed", "{} (hashed)"),
+    ("resolve.hook", "not found, calling {}"),
+    ("resolve.not-found", "not found"),
+    ("resolve.path", "{} (searched PATH)"),
+    ("resolve.trace", "resolve: {} -> {}"),
+    ("rwsh.bad-ast-format", "unknown syntax tree format `{}`, expected tree or json"),
+    ("rwsh.error", "rwsh: {}"),
+    ("rwsh.trace-error", "cannot trace to {}: {}"),
+    ("rwsh.usage", "rwsh v{}\nUsage: {} [options] [file [arg...]]\n       {} [options] -c code [name [arg...]]"),
+    ("set.bad-limit", "set: `{}` is not a number"),
+    ("set.missing-limit", "set: missing number for {}"),
+    ("set.summary", "change or list the shell options"),
+    ("set.unexpected-argument", "set: unexpected argument {}"),
+    ("set.unknown-option", "set: unknown option {}"),
+    ("set.unknown-short-option", "set: unknown option -{}"),
+    ("set.usage", "Usage: set [-+eEx] [-+o option]\n       set [--porcelain | --json]\nOptions:"),
+    ("set.xtrace", "+ {}"),
+    ("shell.internal-error", "internal error: {}\nthis is a bug, please report it at {}"),
+    ("shell.profile-error", "{}: {}"),
+    ("shift.bad-count", "shift: count not a positive integer"),
+    ("shift.summary", "shift the positional arguments"),
+    ("shift.too-many", "shift: can't shift {} out of {} parameters"),
+    ("shift.usage", "Usage: shift [n]"),
+    ("sre.already-interactive", "already in interactive mode"),
+    ("sre.command-error", "cannot run {}: {}"),
+    ("sre.error", "?{}"),
+    ("sre.file-error", "cannot read {}: {}"),
+    ("sre.interactive-capture", "cannot use the interactive mode in a command substitution"),
+    ("sre.interactive-dry-run", "cannot use the interactive mode in a dry run"),
+    ("sre.interactive-files", "cannot use the interactive mode on a list of files"),
+    ("sre.no-file", "no file name"),
+    ("sre.open-error", "sre: {}"),
+    ("sre.read-error", "cannot read the standard input: {}"),
+    ("sre.summary", "edit a file with SRE commands"),
+    ("sre.tty-error", "cannot use the terminal: {}"),
+    ("sre.unexpected-argument", "sre: unexpected argument {}"),
+    ("sre.unsaved", "the buffer has unsaved changes"),
+    ("sre.usage", "Usage: sre [file]"),
+    ("sre.write-error", "cannot write {}: {}"),
+    ("ssh-run.summary", "run a block on a remote host"),
+    ("ssh-run.usage", "Usage: ssh-run [ssh-option...] host { code }"),
+    ("stat.error", "stat: {}"),
+    ("stat.missing-path", "missing path"),
+    ("stat.summary", "show the metadata of a file, or store it in a map"),
+    ("stat.unexpected-argument", "unexpected argument {}"),
+    ("stat.usage", "Usage: stat [-L] [-v var] path"),
+    ("str.bad-count", "'{}' is not a positive integer"),
+    ("str.bad-regex", "invalid regular expression: {}"),
+    ("str.error", "str: {}"),
+    ("str.missing-arguments", "missing arguments for {}"),
+    ("str.missing-command", "missing command"),
+    ("str.read-error", "cannot read the standard input: {}"),
+    ("str.summary", "transform strings"),
+    ("str.unknown-command", "unknown command '{}'"),
+    (
+        "str.usage",
+        "Usage: str [-v var] command [option...] [argument...] [string...]\n\nCommands:\n    join separator\n    lower\n    replace [-r] [-f] pattern replacement\n    split [-n count] separator\n    trim\n    upper\n\nWithout strings, the lines of the standard input are used.",
+    ),
+    ("substitution.bad-limit", "SUBST_MAX is not a number of bytes: {}"),
+    ("substitution.error", "error while executing command for command substitution: {}"),
+    ("substitution.pipe-error", "couldn't pipe command for substitution: {}"),
+    ("substitution.read-error", "failed to read command output: {}"),
+    ("substitution.too-long", "command output is longer than {} bytes (see SUBST_MAX and set -o substtrunc)"),
+    ("substr.error", "substr: {}"),
+    ("substr.not-a-count", "'{}' is not a non-negative integer"),
+    ("substr.summary", "print a part of a string"),
+    ("substr.usage", "Usage: substr [-g | -c | -b] string start [length]"),
+    ("substr.wrong-arguments", "wrong number of arguments"),
+    ("table.bad-width", "`{}` is not a positive number"),
+    ("table.error", "table: {}"),
+    ("table.summary", "print lines as a table of aligned columns"),
+    ("table.unexpected-argument", "unexpected argument {}"),
+    ("table.usage", "Usage: table [-H] [-s separator] [-w width]"),
+    ("test.error", "{}: {}"),
+    ("test.missing-bracket", "missing ']'"),
+    ("test.not-integer", "integer expression expected: {}"),
+    ("test.summary", "test a condition"),
+    ("test.too-many-arguments", "too many arguments"),
+    ("test.unknown-operator", "unknown operator {}"),
+    ("theme.bad-style", "bad style `{}`, expected a color, bold, italic, underline, reverse or none"),
+    ("theme.error", "theme: {}"),
+    ("theme.summary", "show or change the colors"),
+    ("theme.unknown-role", "unknown role `{}`"),
+    (
+        "theme.usage",
+        "Usage: theme [role [style1 ... styleN]]\n       theme -r role1 ... roleN",
+    ),
+    ("time.report", "real\t{}\nuser\t{}\nsys\t{}"),
+    (
+        "trap.bad-condition",
+        "trap: {}: unknown condition, use EXIT, DEBUG, ERR, INT or TERM",
+    ),
+    ("trap.error", "trap: {}"),
+    ("trap.summary", "run code on signals and events"),
+    (
+        "trap.usage",
+        "Usage: trap [code condition1 ... conditionN]\n       trap - condition1 ... conditionN",
+    ),
+    ("true.summary", "do nothing, successfully"),
+    ("type.alias", "{} is an alias for {}"),
+    ("type.builtin", "{} is a shell builtin"),
+    ("type.error", "type: {}"),
+    ("type.file", "{} is {}"),
+    ("type.function", "{} is a function"),
+    ("type.not-found", "type: {}: not found"),
+    ("type.summary", "tell what kind of command a name is"),
+    ("type.usage", "Usage: type [-t | -p] name1 name2 ... nameN"),
+    ("unalias.not-found", "unalias: no such alias: {}"),
+    ("unalias.summary", "remove aliases"),
+    ("unalias.usage", "Usage: unalias name1 name2 ... nameN"),
+    ("var.append-to-map", "cannot append to '{}': it is a map"),
+    (
+        "var.bad-name",
+        "'{}' is not a valid variable name: names are made of letters, digits and underscores, and don't start with a digit",
+    ),
+    (
+        "var.bad-subscript",
+        "bad subscript in '{}': use name[index], name[-index], name[+] or name[key]",
+    ),
+    (
+        "var.index-out-of-range",
+        "{} is out of range: '{}' has {} elements",
+    ),
+    (
+        "version.summary",
+        "print the version, features and configuration files of the shell",
+    ),
+    (
+        "version.unexpected-argument",
+        "version: unexpected argument '{}'",
+    ),
+    ("version.usage", "Usage: version [-v | --verbose] [--json]"),
+    ("watch.bad-count", "`{}` is not a positive number"),
+    ("watch.error", "watch: {}"),
+    ("watch.missing-args", "missing paths or body"),
+    ("watch.summary", "run a block when files change"),
+    ("watch.usage", "Usage: watch [-n count] path... { body }"),
+    ("write-file.error", "write-file: {}"),
+    ("write-file.missing-path", "missing path"),
+    ("write-file.summary", "write strings or the standard input to a file"),
+    ("write-file.usage", "Usage: write-file [-a] [-e utf8|base64|hex] path [string...]"),
+];
+
+lazy_static! {
+    static ref OVERRIDES: HashMap<String, String> = load_overrides();
+}
+
+/// Returns the file whose messages replace the default ones: `$RWSH_MESSAGES`, or
+/// `~/.rwsh_messages`.
+pub fn overrides_path() -> Option<PathBuf> {
+    env::var_os("RWSH_MESSAGES")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".rwsh_messages")))
+}
+
+fn load_overrides() -> HashMap<String, String> {
+    overrides_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|s| parse_overrides(&s))
+        .unwrap_or_default()
+}
+
+fn unescape(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    let mut it = s.chars();
+    while let Some(c) = it.next() {
+        if c != '\\' {
+            r.push(c);
+            continue;
+        }
+        match it.next() {
+            Some('n') => r.push('\n'),
+            Some('t') => r.push('\t'),
+            Some(c) => r.push(c),
+            None => r.push('\\'),
+        }
+    }
+    r
+}
+
+/// Parses the contents of a message override file.
+pub fn parse_overrides(s: &str) -> HashMap<String, String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let eq = line.find('=')?;
+            Some((
+                line[..eq].trim().to_owned(),
+                unescape(line[eq + 1..].trim()),
+            ))
+        })
+        .collect()
+}
+
+/// Returns the template of a message, or the id itself if there's no such message.
+pub fn template(id: &str) -> &str {
+    if let Some(t) = OVERRIDES.get(id) {
+        return t;
+    }
+    DEFAULTS
+        .binary_search_by(|probe| probe.0.cmp(id))
+        .map(|i| DEFAULTS[i].1)
+        .unwrap_or(id)
+}
+
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut r = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut rest = template;
+    while let Some(i) = rest.find("{}") {
+        r.push_str(&rest[..i]);
+        if let Some(arg) = args.next() {
+            r.push_str(&arg.to_string());
+        }
+        rest = &rest[i + 2..];
+    }
+    r.push_str(rest);
+    r
+}
+
+/// Formats the message with the given id. Use the [`msg!`](../macro.msg.html) macro instead.
+pub fn format(id: &str, args: &[&dyn Display]) -> String {
+    fill(template(id), args)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn defaults_sorted() {
+        assert!(super::DEFAULTS.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn fill() {
+        assert_eq!(
+            super::fill("can't shift {} out of {}", &[&3, &"two"]),
+            "can't shift 3 out of two"
+        );
+        assert_eq!(super::fill("missing {}", &[]), "missing ");
+    }
+
+    #[test]
+    fn parse_overrides() {
+        let o =
+            super::parse_overrides("# comment\n\nshift.usage = Folosire: shift [n]\\n\nbad line\n");
+        assert_eq!(o.len(), 1);
+        assert_eq!(o["shift.usage"], "Folosire: shift [n]\n");
+    }
+}
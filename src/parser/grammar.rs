@@ -0,0 +1,198 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! The grammar of the shell in EBNF, as printed by `rwsh --dump-grammar`.
+//!
+//! The rules of the commands that start with keywords come from the table the parser
+//! picks them from, and the symbols and operators from the tables of the lexer.
+use super::lex::{OPERATORS, SYMBOLS};
+use super::KEYWORDS;
+
+/// The rules of everything but the commands that start with keywords, from the top.
+/// Rules written as `? ... ?` are described in words.
+///
+/// Each rule comes with code that uses it. The tests parse that code, so that no rule
+/// shows syntax the parser doesn't take.
+pub static RULES: &[(&str, &str, &str)] = &[
+    (
+        "program",
+        "command_list { ( ';' | newline ) command_list }",
+        "a; b\nc",
+    ),
+    (
+        "command_list",
+        "pipeline { ( '&&' | '||' ) pipeline }",
+        "a && b || c",
+    ),
+    ("pipeline", "command { '|' command }", "a | b | c"),
+    (
+        "command",
+        "simple_command | brace_group | sre_sequence | keyword_command",
+        "{ a }",
+    ),
+    (
+        "simple_command",
+        "{ assignment } word { word } [ brace_group ]",
+        "A=1 B=2 a b { c }",
+    ),
+    ("assignment", "name '=' word", "A=$b a"),
+    (
+        "brace_group",
+        "'{' program '}' { redirection }",
+        "{ a; b } >out",
+    ),
+    (
+        "redirection",
+        "[ digits ] ( '<' | '>' | '>>' ) word | [ digits ] '>&' digits",
+        "{ a } <in >out 2>>log 2>&1",
+    ),
+    (
+        "sre_sequence",
+        "( '|>' | '🍕' ) sre_command { ( '|>' | '🍕' ) sre_command }",
+        "a |> ,x/b/ p 🍕 ,p",
+    ),
+    ("word", "word_part { word_part }", "a b'c'\"d\"$e$(f)"),
+    (
+        "word_part",
+        "string | single_quoted | double_quoted | parameter | command_substitution",
+        "a b 'c' \"d\" $e $(f)",
+    ),
+    ("single_quoted", "\"'\" { character } \"'\"", "a 'b c'"),
+    (
+        "double_quoted",
+        "'\"' { character | parameter | command_substitution } '\"'",
+        "a \"b $c $(d)\"",
+    ),
+    (
+        "parameter",
+        "'$' name [ '[' subscript ']' ]",
+        "a $b $b[1] $b[key]",
+    ),
+    (
+        "command_substitution",
+        "'$' '(' program ')'",
+        "a $(b; c | d)",
+    ),
+    (
+        "pattern",
+        "? a regular expression, ending at an unescaped '/' ?",
+        "switch a\n/[a-z]+\\/?/ b\nend",
+    ),
+    (
+        "sre_command",
+        "? an address and a structural regular expression command, like ',x/re/ p' or ',x|re| p' ?",
+        "a |> ,x|re| p",
+    ),
+    (
+        "subscript",
+        "? an index, which may be negative, or a key ?",
+        "a $b[-1] $b[c]",
+    ),
+    (
+        "string",
+        "? characters other than blanks and symbols, or escaped ones ?",
+        "a\\ b c\\;",
+    ),
+    (
+        "character",
+        "? any character, with backslash escapes ?",
+        "a '\\' \"\\\"\"",
+    ),
+    (
+        "name",
+        "? a letter or '_', followed by letters, digits or '_' ?",
+        "_a1=b c",
+    ),
+    ("digits", "? one or more decimal digits ?", "{ a } 12>out"),
+    ("newline", "? a line break ?", "a\nb"),
+];
+
+/// Returns the grammar with the lists of keywords, symbols and operators.
+pub fn dump() -> String {
+    let mut s = String::from("(* the grammar of rwsh, made by rwsh --dump-grammar *)\n\n");
+    for (name, rule, _) in RULES {
+        s.push_str(&format!("{} ::= {} ;\n", name, rule));
+    }
+    let alternatives = KEYWORDS
+        .iter()
+        .map(|k| k.rule_name)
+        .collect::<Vec<_>>()
+        .join(" | ");
+    s.push_str(&format!("keyword_command ::= {} ;\n", alternatives));
+    for k in KEYWORDS {
+        s.push_str(&format!("{} ::= {} ;\n", k.rule_name, k.rule));
+    }
+
+    let keywords = KEYWORDS.iter().map(|k| k.name).collect::<Vec<_>>();
+    let symbols = SYMBOLS
+        .iter()
+        .map(|(c, kind)| format!("{} {:?}", c, kind))
+        .collect::<Vec<_>>();
+    let operators = OPERATORS
+        .iter()
+        .map(|(op, kind)| format!("{} {:?}", op, kind))
+        .collect::<Vec<_>>();
+    s.push_str(&format!("\n(* keywords: {} *)\n", keywords.join(" ")));
+    s.push_str(&format!("(* symbols: {} *)\n", symbols.join(", ")));
+    s.push_str(&format!("(* operators: {} *)\n", operators.join(", ")));
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KEYWORDS, RULES};
+    use crate::parser::Parser;
+    use crate::tests::common::new_dummy_buf;
+    use regex::Regex;
+
+    #[test]
+    fn examples_parse() {
+        for (name, _, example) in RULES {
+            let lines = Box::leak(example.to_string().into_boxed_str()).lines();
+            for program in Parser::new(new_dummy_buf(lines)) {
+                assert!(program.is_ok(), "the example of {} doesn't parse", name);
+            }
+        }
+    }
+
+    #[test]
+    fn rules_are_defined() {
+        let defined = RULES
+            .iter()
+            .map(|(name, _, _)| *name)
+            .chain(KEYWORDS.iter().map(|k| k.rule_name))
+            .chain(std::iter::once("keyword_command"))
+            .collect::<Vec<_>>();
+        // names outside quotes and special sequences
+        let names = Regex::new(r#"'[^']*'|"[^"]*"|\?[^?]*\?|([a-z_]+)"#).unwrap();
+        let rules = RULES
+            .iter()
+            .map(|(_, r, _)| *r)
+            .chain(KEYWORDS.iter().map(|k| k.rule));
+        for rule in rules {
+            for caps in names.captures_iter(rule) {
+                if let Some(name) = caps.get(1) {
+                    assert!(
+                        defined.contains(&name.as_str()),
+                        "{} isn't defined",
+                        name.as_str()
+                    );
+                }
+            }
+        }
+    }
+}
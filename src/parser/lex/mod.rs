@@ -19,6 +19,7 @@
 pub mod sre;
 
 use super::{escape, skip_whitespace};
+use crate::msg;
 use crate::util::{BufReadChars, NullReader, ParseError};
 use bitflags::bitflags;
 
@@ -49,7 +50,8 @@ pub enum TokenKind {
 
     /// The `end` keyword. Works only in [`END mode`](struct.LexMode.html#associatedconstant.END).
     End,
-    /// The slash (`/`) keyword. Works only in [`SLASH mode`](struct.LexMode.html#associatedconstant.SLASH).
+    /// The slash (`/`) keyword, or the [delimiter](struct.Lexer.html#structfield.delimiter) that replaces it.
+    /// Works only in [`SLASH mode`](struct.LexMode.html#associatedconstant.SLASH).
     Slash,
     /// '&'
     Ampersand,
@@ -69,6 +71,34 @@ impl TokenKind {
     }
 }
 
+/// Shows the token as it is written, quoted, or described if it can't be seen.
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            TokenKind::Nothing => return write!(f, "nothing"),
+            TokenKind::Space => return write!(f, "space"),
+            TokenKind::Newline => return write!(f, "newline"),
+            TokenKind::Word(w) => w,
+            TokenKind::Pipe => "|",
+            TokenKind::Pizza => "|>",
+            TokenKind::LBrace => "{",
+            TokenKind::RBrace => "}",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::Semicolon => ";",
+            TokenKind::DoubleQuote => "\"",
+            TokenKind::SingleQuote => "'",
+            TokenKind::Dollar => "$",
+            TokenKind::End => "end",
+            TokenKind::Slash => "/",
+            TokenKind::Ampersand => "&",
+            TokenKind::Or => "||",
+            TokenKind::And => "&&",
+        };
+        write!(f, "'{}'", s)
+    }
+}
+
 #[derive(Clone)]
 /// Structure representing a lexical token, together with its position in the file
 /// and its size.
@@ -109,6 +139,8 @@ bitflags! {
 pub struct Lexer {
     pub input: BufReadChars,
     pub mode: LexMode,
+    /// The character lexed as a [`Slash` token](enum.TokenKind.html#variant.Slash), `/` by default.
+    pub delimiter: char,
     pipe_follows: bool,
     errored: bool,
 
@@ -136,6 +168,7 @@ impl Lexer {
             pipe_follows: false,
             errored: false,
             mode: LexMode::empty(),
+            delimiter: '/',
 
             peeked: None,
         }
@@ -144,6 +177,7 @@ impl Lexer {
     /// Reset the lexer to clean state. Used after encountering an error in interractive mode.
     pub fn reload(&mut self) {
         self.mode = LexMode::empty();
+        self.delimiter = '/';
         self.pipe_follows = false;
         self.errored = false;
         self.peeked = None;
@@ -185,7 +219,8 @@ impl Lexer {
                 } else if c == '\\' {
                     escaping = true;
                 } else {
-                    if !is_clear_string_char(c) || (self.mode.contains(LexMode::SLASH) && c == '/')
+                    if !is_clear_string_char(c)
+                        || (self.mode.contains(LexMode::SLASH) && c == self.delimiter)
                     {
                         break;
                     }
@@ -195,22 +230,18 @@ impl Lexer {
             }
 
             if escaping {
-                Err(self
-                    .input
-                    .new_error("expected character, got EOF".to_owned()))
+                Err(self.input.new_error(msg!("parse.expected-char-eof")))
             } else {
                 Ok(s)
             }
         } else {
-            Err(self
-                .input
-                .new_error(format!("unexpected character '{}'", c)))
+            Err(self.input.new_error(msg!("parse.unexpected-char", c)))
         }
     }
 }
 
 // KEEP SORTED
-static SYMBOLS: &'static [(char, TokenKind)] = &[
+pub static SYMBOLS: &'static [(char, TokenKind)] = &[
     ('"', TokenKind::DoubleQuote),
     ('$', TokenKind::Dollar),
     ('\'', TokenKind::SingleQuote),
@@ -222,6 +253,22 @@ static SYMBOLS: &'static [(char, TokenKind)] = &[
     ('🍕', TokenKind::Pizza),
 ];
 
+/// The operators of one or two characters. The longest one that matches is used.
+pub static OPERATORS: &[(&str, TokenKind)] = &[
+    ("&", TokenKind::Ampersand),
+    ("&&", TokenKind::And),
+    ("|", TokenKind::Pipe),
+    ("|>", TokenKind::Pizza),
+    ("||", TokenKind::Or),
+];
+
+fn find_operator(op: &str) -> Option<TokenKind> {
+    OPERATORS
+        .iter()
+        .find(|(o, _)| *o == op)
+        .map(|(_, kind)| kind.clone())
+}
+
 fn find_symbol(c: char) -> Option<TokenKind> {
     SYMBOLS
         .binary_search_by(|probe| probe.0.cmp(&c))
@@ -244,17 +291,15 @@ impl Iterator for Lexer {
             if let Some('|') | Some('\n') | Some('}') | None = peek {
                 self.pipe_follows = false;
             } else if peek.is_some() && peek.unwrap().is_whitespace() {
-
             } else {
                 self.errored = true;
-                return Some(Err(self
-                    .input
-                    .new_error("expected pipe, pizza or newline".to_owned())));
+                return Some(Err(self.input.new_error(msg!("parse.expected-pipe"))));
             }
         }
         let r = if let Some(&the_c) = self.input.peek() {
             self.input.ps2_enter("".to_owned());
-            let c = if the_c == '#' {
+            let hash_delimits = self.mode.contains(LexMode::SLASH) && self.delimiter == '#';
+            let c = if the_c == '#' && !hash_delimits {
                 self.input.next();
                 while let Some(&some_c) = self.input.peek() {
                     if some_c == '\n' {
@@ -270,24 +315,20 @@ impl Iterator for Lexer {
             } else {
                 the_c
             };
-            if c == '|' {
+            if c == self.delimiter && self.mode.contains(LexMode::SLASH) {
                 self.input.next();
-                if let Some('>') = self.input.peek() {
-                    self.input.next();
-                    Some(Ok(tok!(TokenKind::Pizza, 2, self.input)))
-                } else if let Some('|') = self.input.peek() {
-                    self.input.next();
-                    Some(Ok(tok!(TokenKind::Or, 2, self.input)))
-                } else {
-                    Some(Ok(tok!(TokenKind::Pipe, 1, self.input)))
-                }
-            } else if c == '&' {
+                Some(Ok(tok!(TokenKind::Slash, 1, self.input)))
+            } else if let Some(kind) = find_operator(&c.to_string()) {
                 self.input.next();
-                if let Some('&') = self.input.peek() {
+                let longer = self
+                    .input
+                    .peek()
+                    .and_then(|&next| find_operator(&format!("{}{}", c, next)));
+                if let Some(kind) = longer {
                     self.input.next();
-                    Some(Ok(tok!(TokenKind::And, 2, self.input)))
+                    Some(Ok(tok!(kind, 2, self.input)))
                 } else {
-                    Some(Ok(tok!(TokenKind::Ampersand, 1, self.input)))
+                    Some(Ok(tok!(kind, 1, self.input)))
                 }
             } else if c == '\n' {
                 self.input.next();
@@ -295,9 +336,6 @@ impl Iterator for Lexer {
             } else if let Some(kind) = find_symbol(c) {
                 self.input.next();
                 Some(Ok(tok!(kind, 1, self.input)))
-            } else if c == '/' && self.mode.contains(LexMode::SLASH) {
-                self.input.next();
-                Some(Ok(tok!(TokenKind::Slash, 1, self.input)))
             } else if c.is_whitespace() {
                 let len = skip_whitespace(&mut self.input, true);
                 Some(Ok(tok!(TokenKind::Space, len, self.input)))
@@ -369,6 +407,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn display_kind() {
+        use super::TokenKind;
+        assert_eq!(TokenKind::RParen.to_string(), "')'");
+        assert_eq!(TokenKind::Word("end".to_owned()).to_string(), "'end'");
+        assert_eq!(TokenKind::Newline.to_string(), "newline");
+    }
+
     #[test]
     fn read_word_no_quotes() {
         let s = "hell_o nice \\-meme😀 test";
@@ -432,6 +478,13 @@ mod tests {
         assert_eq!(lex.next(), Some(Ok(tok!(Slash))));
         lex.mode.remove(LexMode::SLASH);
         assert_eq!(lex.next(), Some(Ok(tok!(Word("/".to_owned())))));
+
+        let mut lex = super::Lexer::new(new_dummy_buf("|a/b|".lines()));
+        lex.mode.insert(LexMode::SLASH);
+        lex.delimiter = '|';
+        assert_eq!(lex.next(), Some(Ok(tok!(Slash))));
+        assert_eq!(lex.next(), Some(Ok(tok!(Word("a/b".to_owned())))));
+        assert_eq!(lex.next(), Some(Ok(tok!(Slash))));
     }
 
     #[test]
@@ -15,6 +15,7 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use crate::msg;
 use crate::util::{BufReadChars, ParseError};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -30,6 +31,8 @@ pub enum Token {
     Comma,
     Semicolon,
     Dollar,
+    Mark,
+    PreviousDot,
 }
 
 pub fn lex_address(it: &mut BufReadChars) -> Result<(Vec<Token>, String), ParseError> {
@@ -67,6 +70,12 @@ pub fn lex_address(it: &mut BufReadChars) -> Result<(Vec<Token>, String), ParseE
         } else if c == '$' {
             v.push(Token::Dollar);
             it.next();
+        } else if c == '\'' {
+            v.push(Token::Mark);
+            it.next();
+        } else if c == '"' {
+            v.push(Token::PreviousDot);
+            it.next();
         } else {
             break;
         }
@@ -132,7 +141,7 @@ fn scan_regexp(it: &mut BufReadChars, reverse: bool) -> Result<Token, ParseError
             Ok(Token::Regexp(s))
         }
     } else {
-        Err(it.new_error("unclosed regex".to_owned()))
+        Err(it.new_error(msg!("parse.unclosed-regex")))
     }
 }
 
@@ -16,13 +16,16 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 //! The parsers and lexers of the `rwsh` scripting language and its SRE sublanguage.
+pub mod grammar;
 pub mod lex;
 pub mod misc;
 pub mod sre;
 
 use self::lex::{LexMode, Lexer, Token};
+use crate::msg;
 use crate::shell::pretty::*;
-use crate::shell::Var;
+use crate::shell::{check_var_name, Var};
+use crate::util::editor::complete::Completer;
 use crate::util::{BufReadChars, ParseError};
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -37,7 +40,8 @@ enum WordStringReadMode {
     // The `char` is the delimiter.
     DoubleQuoted(char),
     Parameter,
-    Pattern,
+    // The `char` is the delimiter.
+    Pattern(char),
 }
 
 fn skip_whitespace(it: &mut BufReadChars, break_at_newline: bool) -> usize {
@@ -61,14 +65,13 @@ fn check_condition_symbol(
 ) -> Result<(), ParseError> {
     match x {
         Some(Err(e)) => Err(e),
-        Some(Ok(ref tok @ Token { .. })) if tok.kind != kind => Err(tok.new_error(format!(
-            "expected '{}' in {} condition, got {:?}",
-            ch, construct, tok.kind
-        ))),
-        None => Err(kw_tok.new_error(format!(
-            "expected '{}' in {} condition, got EOF",
-            ch, construct
+        Some(Ok(ref tok @ Token { .. })) if tok.kind != kind => Err(tok.new_error(msg!(
+            "parse.expected-condition-symbol",
+            ch,
+            construct,
+            format!("{:?}", tok.kind)
         ))),
+        None => Err(kw_tok.new_error(msg!("parse.expected-condition-symbol-eof", ch, construct))),
         _ => Ok(()),
     }
 }
@@ -95,10 +98,13 @@ pub fn escape(c: char) -> char {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum WordParameterBracket {
     None,
-    Index(usize),
+    /// An index of an array. Negative indices count from the end.
+    Index(isize),
+    /// A key of an associative array.
+    Key(String),
 }
 
 /// An entity to be substituted in a string. Always starts with the dollar sign (`$`).
@@ -122,13 +128,20 @@ impl<'a> WordParameter<'a> {
         }
     }
 
-    pub fn with_index(name: &'a str, index: usize) -> WordParameter {
+    pub fn with_index(name: &'a str, index: isize) -> WordParameter {
         WordParameter {
             name,
             bracket: WordParameterBracket::Index(index),
         }
     }
 
+    pub fn with_key(name: &'a str, key: &str) -> WordParameter<'a> {
+        WordParameter {
+            name,
+            bracket: WordParameterBracket::Key(key.to_owned()),
+        }
+    }
+
     pub fn into_word(self) -> Word {
         RawWord::Parameter(OwnedWordParameter {
             name: self.name.to_owned(),
@@ -140,7 +153,7 @@ impl<'a> WordParameter<'a> {
     pub fn to_owned(&self) -> OwnedWordParameter {
         OwnedWordParameter {
             name: self.name.to_owned(),
-            bracket: self.bracket,
+            bracket: self.bracket.clone(),
         }
     }
 }
@@ -249,29 +262,129 @@ impl PrettyPrint for RawWord {
 }
 
 #[derive(Debug, PartialEq, Clone)]
-/// A command tuple is made of its name and its arguments.
-pub struct SimpleCommand(pub Word, pub Vec<Word>);
+/// A command tuple is made of its name, its arguments, the assignments before the name,
+/// like `LANG=C sort`, that are only given to the environment of the command,
+/// and the line it starts on.
+pub struct SimpleCommand(pub Word, pub Vec<Word>, pub Vec<(String, Word)>, pub usize);
 
 impl SimpleCommand {
     pub fn with_deep_copied_word(&self) -> SimpleCommand {
         SimpleCommand(
             deep_clone_word(&self.0),
             self.1.iter().map(deep_clone_word).collect(),
+            self.2
+                .iter()
+                .map(|(name, value)| (name.clone(), deep_clone_word(value)))
+                .collect(),
+            self.3,
         )
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+/// Where a file descriptor goes, like in `> out`, `2>> log`, `< in` or `2>&1`.
+pub struct Redirection(pub i32, pub RedirectionTarget);
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RedirectionTarget {
+    /// `<`, a file opened for reading.
+    Read(Word),
+    /// `>`, a file created or truncated.
+    Write(Word),
+    /// `>>`, a file created or appended to.
+    Append(Word),
+    /// `>&`, another file descriptor.
+    Duplicate(i32),
+}
+
+impl Redirection {
+    pub fn with_deep_copied_word(&self) -> Redirection {
+        let target = match &self.1 {
+            RedirectionTarget::Read(w) => RedirectionTarget::Read(deep_clone_word(w)),
+            RedirectionTarget::Write(w) => RedirectionTarget::Write(deep_clone_word(w)),
+            RedirectionTarget::Append(w) => RedirectionTarget::Append(deep_clone_word(w)),
+            RedirectionTarget::Duplicate(fd) => RedirectionTarget::Duplicate(*fd),
+        };
+        Redirection(self.0, target)
+    }
+}
+
+impl RedirectionTarget {
+    /// Returns the word naming the file, if the target is a file.
+    pub fn word(&self) -> Option<&Word> {
+        match self {
+            RedirectionTarget::Read(w)
+            | RedirectionTarget::Write(w)
+            | RedirectionTarget::Append(w) => Some(w),
+            RedirectionTarget::Duplicate(_) => None,
+        }
+    }
+}
+
+impl PrettyPrint for Redirection {
+    fn pretty_print(&self) -> PrettyTree {
+        let (op, children) = match &self.1 {
+            RedirectionTarget::Read(w) => ("<", vec![naked_word(w.clone()).pretty_print()]),
+            RedirectionTarget::Write(w) => (">", vec![naked_word(w.clone()).pretty_print()]),
+            RedirectionTarget::Append(w) => (">>", vec![naked_word(w.clone()).pretty_print()]),
+            RedirectionTarget::Duplicate(fd) => {
+                return PrettyTree {
+                    text: format!("redirection {}>&{}", self.0, fd),
+                    children: vec![],
+                }
+            }
+        };
+        PrettyTree {
+            text: format!("redirection {}{}", self.0, op),
+            children,
+        }
+    }
+}
+
+/// Splits a word like `NAME=value` in the name and the value.
+/// Only unquoted names of valid variables are recognized.
+fn split_assignment(w: &Word) -> Option<(String, Word)> {
+    let words = match &*w.borrow() {
+        RawWord::List(words, false) => words.clone(),
+        _ => return None,
+    };
+    let first = match &*words.first()?.borrow() {
+        RawWord::String(s, false) => s.clone(),
+        _ => return None,
+    };
+    let eq = first.find('=')?;
+    check_var_name(&first[..eq]).ok()?;
+    let mut value = vec![RawWord::String(first[eq + 1..].to_owned(), false).into()];
+    value.extend(words[1..].iter().cloned());
+    Some((first[..eq].to_owned(), RawWord::List(value, false).into()))
+}
+
 #[derive(Debug, PartialEq, Clone)]
 /// A chain of SRE commands.
 ///
-/// Something like `|> ,a/append/ |> 1,2p`.
-pub struct SRESequence(pub Vec<SRECommand>);
+/// Something like `|> ,a/append/ |> 1,2p`. The second field is set if one of the
+/// commands is written after `|>!`, and then the sequence only shows the difference
+/// it would make to its input. The third is the list of files written right after the
+/// first `|>`, like in `|>*.rs ,x/TODO/p`, which the sequence reads instead of its input.
+pub struct SRESequence(pub Vec<SRECommand>, pub bool, pub Option<Word>);
 
 impl PrettyPrint for SRESequence {
     fn pretty_print(&self) -> PrettyTree {
         PrettyTree {
-            text: "SRE sequence".to_owned(),
-            children: self.0.iter().map(|c| c.pretty_print()).collect(),
+            text: if self.1 {
+                "SRE sequence - dry run".to_owned()
+            } else {
+                "SRE sequence".to_owned()
+            },
+            children: self
+                .2
+                .iter()
+                .map(|w| PrettyTree {
+                    text: "files".to_owned(),
+                    children: vec![naked_word(w.clone()).pretty_print()],
+                })
+                .chain(self.0.iter().map(|c| c.pretty_print()))
+                .collect(),
         }
     }
 }
@@ -351,6 +464,97 @@ impl PrettyPrint for Program {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+/// Where a match construct reads its text from.
+pub enum MatchSource {
+    Stdin,
+    /// `match "some text"`
+    Text(Word),
+    /// `match <file`
+    File(Word),
+}
+
+impl PrettyPrint for MatchSource {
+    fn pretty_print(&self) -> PrettyTree {
+        match self {
+            MatchSource::Stdin => PrettyTree {
+                text: "stdin".to_owned(),
+                children: vec![],
+            },
+            MatchSource::Text(w) => PrettyTree {
+                text: "text".to_owned(),
+                children: vec![w.borrow().pretty_print()],
+            },
+            MatchSource::File(w) => PrettyTree {
+                text: "file".to_owned(),
+                children: vec![w.borrow().pretty_print()],
+            },
+        }
+    }
+}
+
+/// A word that starts a command other than a simple command.
+pub struct Keyword {
+    pub name: &'static str,
+    parse: fn(&mut Parser) -> Option<Result<Command, ParseError>>,
+    /// The name of the grammar rule of the command.
+    pub rule_name: &'static str,
+    /// The grammar rule of the command, in EBNF.
+    pub rule: &'static str,
+}
+
+/// The keywords, with how the commands they start are parsed.
+pub static KEYWORDS: &[Keyword] = &[
+    Keyword {
+        name: "if",
+        parse: Parser::parse_if,
+        rule_name: "if_construct",
+        rule: "'if' '(' program ')' program",
+    },
+    Keyword {
+        name: "else",
+        parse: Parser::parse_else,
+        rule_name: "else_construct",
+        rule: "'else' program",
+    },
+    Keyword {
+        name: "while",
+        parse: Parser::parse_while,
+        rule_name: "while_construct",
+        rule: "'while' '(' program ')' program",
+    },
+    Keyword {
+        name: "switch",
+        parse: Parser::parse_switch,
+        rule_name: "switch_construct",
+        rule: "'switch' word { '/' pattern '/' program } 'end'",
+    },
+    Keyword {
+        name: "match",
+        parse: Parser::parse_match,
+        rule_name: "match_construct",
+        rule: "'match' [ word | '<' word ] { '/' pattern '/' program } 'end'",
+    },
+    Keyword {
+        name: "!",
+        parse: Parser::parse_not,
+        rule_name: "not_construct",
+        rule: "'!' pipeline",
+    },
+    Keyword {
+        name: "time",
+        parse: Parser::parse_time,
+        rule_name: "time_construct",
+        rule: "'time' pipeline",
+    },
+    Keyword {
+        name: "fn",
+        parse: Parser::parse_function,
+        rule_name: "function_definition",
+        rule: "'fn' name brace_group",
+    },
+];
+
 #[derive(Debug, PartialEq, Clone)]
 /// A command can be a simple command, a brace group or a control structure.
 pub enum Command {
@@ -358,49 +562,66 @@ pub enum Command {
     SimpleCommand(SimpleCommand),
     /// A SRE program is code after the pizza operator.
     SREProgram(SRESequence),
-    /// A brace group is code enclosed in brackets.
-    BraceGroup(Vec<CommandList>),
+    /// A brace group is code enclosed in brackets, with the redirections that follow it.
+    BraceGroup(Vec<CommandList>, Vec<Redirection>),
     /// An if construct. First is the condition, second is the body.
     IfConstruct(Program, Program),
     /// An else construct. The tuple contains the body.
     ElseConstruct(Program),
     /// Like `IfConstruct`, first is the condition, second is the body.
-    WhileConstruct(Program, Program),
+    /// The third is the line of the `while` keyword.
+    WhileConstruct(Program, Program, usize),
     /// A switch construct, runs code based on the first pattern that matches.
     /// The first is the word to be matched, second is a list of patterns.
     /// A pattern has a `Word` that is the pattern, and a program, that is the code.
     SwitchConstruct(Word, Vec<(Word, Program)>),
     /// A match construct. It runs code *for each match* of *every pattern* in the text.
-    /// The text is given by `stdin`.
-    MatchConstruct(Vec<(Word, Program)>),
-    /// A negated expression.
-    NotConstruct(Program),
+    /// The text is given by the source.
+    MatchConstruct(MatchSource, Vec<(Word, Program)>),
+    /// A negated pipeline, like `! grep -q foo file`.
+    NotConstruct(Pipeline),
+    /// A pipeline run by `time`, which reports how long it took.
+    TimeConstruct(Pipeline),
+    /// A function definition. First is the name, second is the body.
+    FunctionDefinition(String, Program),
 }
 
 impl PrettyPrint for Command {
     fn pretty_print(&self) -> PrettyTree {
         match self {
-            Command::SimpleCommand(sc) => PrettyTree {
-                text: "simple command".to_owned(),
-                children: vec![
-                    PrettyTree {
-                        text: "name".to_owned(),
-                        children: vec![naked_word(sc.0.clone()).pretty_print()],
-                    },
-                    PrettyTree {
-                        text: "args".to_owned(),
-                        children: sc
-                            .1
-                            .iter()
-                            .map(|w| naked_word(w.clone()).pretty_print())
-                            .collect(),
-                    },
-                ],
-            },
+            Command::SimpleCommand(sc) => {
+                let mut children =
+                    sc.2.iter()
+                        .map(|(name, value)| PrettyTree {
+                            text: format!("assignment {}", name),
+                            children: vec![naked_word(value.clone()).pretty_print()],
+                        })
+                        .collect::<Vec<_>>();
+                children.push(PrettyTree {
+                    text: "name".to_owned(),
+                    children: vec![naked_word(sc.0.clone()).pretty_print()],
+                });
+                children.push(PrettyTree {
+                    text: "args".to_owned(),
+                    children: sc
+                        .1
+                        .iter()
+                        .map(|w| naked_word(w.clone()).pretty_print())
+                        .collect(),
+                });
+                PrettyTree {
+                    text: "simple command".to_owned(),
+                    children,
+                }
+            }
             Command::SREProgram(seq) => seq.pretty_print(),
-            Command::BraceGroup(cls) => PrettyTree {
+            Command::BraceGroup(cls, redirections) => PrettyTree {
                 text: "brace group".to_owned(),
-                children: cls.iter().map(|cl| cl.pretty_print()).collect(),
+                children: cls
+                    .iter()
+                    .map(|cl| cl.pretty_print())
+                    .chain(redirections.iter().map(|r| r.pretty_print()))
+                    .collect(),
             },
             Command::IfConstruct(condition, body) => PrettyTree {
                 text: "if construct".to_owned(),
@@ -419,7 +640,7 @@ impl PrettyPrint for Command {
                 text: "else construct - program".to_owned(),
                 children: body.pretty_print().children,
             },
-            Command::WhileConstruct(condition, body) => PrettyTree {
+            Command::WhileConstruct(condition, body, _) => PrettyTree {
                 text: "while construct".to_owned(),
                 children: vec![
                     PrettyTree {
@@ -460,29 +681,240 @@ impl PrettyPrint for Command {
                     },
                 ],
             },
-            Command::MatchConstruct(patterns) => PrettyTree {
+            Command::MatchConstruct(source, patterns) => PrettyTree {
                 text: "match construct".to_owned(),
-                children: patterns
-                    .iter()
-                    .map(|(w, prog)| PrettyTree {
-                        text: "item".to_owned(),
-                        children: vec![
-                            PrettyTree {
-                                text: "pattern".to_owned(),
-                                children: vec![w.borrow().pretty_print()],
-                            },
-                            PrettyTree {
-                                text: "body".to_owned(),
-                                children: prog.pretty_print().children,
-                            },
-                        ],
-                    })
-                    .collect(),
+                children: vec![
+                    PrettyTree {
+                        text: "source".to_owned(),
+                        children: vec![source.pretty_print()],
+                    },
+                    PrettyTree {
+                        text: "items".to_owned(),
+                        children: patterns
+                            .iter()
+                            .map(|(w, prog)| PrettyTree {
+                                text: "item".to_owned(),
+                                children: vec![
+                                    PrettyTree {
+                                        text: "pattern".to_owned(),
+                                        children: vec![w.borrow().pretty_print()],
+                                    },
+                                    PrettyTree {
+                                        text: "body".to_owned(),
+                                        children: prog.pretty_print().children,
+                                    },
+                                ],
+                            })
+                            .collect(),
+                    },
+                ],
             },
-            Command::NotConstruct(prog) => PrettyTree {
+            Command::NotConstruct(p) => PrettyTree {
                 text: "!".to_owned(),
-                children: vec![prog.pretty_print()],
+                children: vec![Node::Pipeline(p.clone()).pretty_print()],
+            },
+            Command::TimeConstruct(p) => PrettyTree {
+                text: "time".to_owned(),
+                children: vec![Node::Pipeline(p.clone()).pretty_print()],
             },
+            Command::FunctionDefinition(name, body) => PrettyTree {
+                text: format!("function {}", name),
+                children: body.pretty_print().children,
+            },
+        }
+    }
+}
+
+impl ToJson for RawWord {
+    fn to_json(&self) -> String {
+        match self {
+            RawWord::String(s, quoted) => json_object(
+                "string",
+                vec![("value", s.to_json()), ("quoted", quoted.to_string())],
+            ),
+            RawWord::Parameter(param) => {
+                let mut fields = vec![("name", param.name.to_json())];
+                match &param.bracket {
+                    WordParameterBracket::None => {}
+                    WordParameterBracket::Index(i) => fields.push(("index", i.to_string())),
+                    WordParameterBracket::Key(k) => fields.push(("key", k.to_json())),
+                }
+                json_object("parameter", fields)
+            }
+            RawWord::List(words, quoted) => json_object(
+                "list",
+                vec![("words", words.to_json()), ("quoted", quoted.to_string())],
+            ),
+            RawWord::Command(prog) => json_object("command", vec![("program", prog.to_json())]),
+            RawWord::Pattern(words) => json_object("pattern", vec![("words", words.to_json())]),
+            RawWord::Expansion(var) => {
+                json_object("expansion", vec![("value", var.to_string().to_json())])
+            }
+        }
+    }
+}
+
+impl ToJson for Word {
+    fn to_json(&self) -> String {
+        self.borrow().to_json()
+    }
+}
+
+impl ToJson for Redirection {
+    fn to_json(&self) -> String {
+        let (op, target) = match &self.1 {
+            RedirectionTarget::Read(w) => ("<", ("file", w.to_json())),
+            RedirectionTarget::Write(w) => (">", ("file", w.to_json())),
+            RedirectionTarget::Append(w) => (">>", ("file", w.to_json())),
+            RedirectionTarget::Duplicate(fd) => (">&", ("fd", fd.to_string())),
+        };
+        json_object(
+            "redirection",
+            vec![("fd", self.0.to_string()), ("op", op.to_json()), target],
+        )
+    }
+}
+
+impl ToJson for SRESequence {
+    fn to_json(&self) -> String {
+        json_object(
+            "sre_sequence",
+            vec![
+                ("commands", self.0.to_json()),
+                ("dry_run", self.1.to_string()),
+                ("files", self.2.to_json()),
+            ],
+        )
+    }
+}
+
+impl ToJson for Node {
+    fn to_json(&self) -> String {
+        match self {
+            Node::Pipeline(p) => json_object("pipeline", vec![("commands", p.0.to_json())]),
+            Node::BinOp(typ, left, right) => json_object(
+                "binop",
+                vec![
+                    (
+                        "op",
+                        if let BinOpType::And = typ { "&&" } else { "||" }.to_json(),
+                    ),
+                    ("left", left.to_json()),
+                    ("right", right.to_json()),
+                ],
+            ),
+        }
+    }
+}
+
+impl ToJson for CommandList {
+    fn to_json(&self) -> String {
+        self.0.to_json()
+    }
+}
+
+impl ToJson for Program {
+    fn to_json(&self) -> String {
+        json_object("program", vec![("command_lists", self.0.to_json())])
+    }
+}
+
+impl ToJson for MatchSource {
+    fn to_json(&self) -> String {
+        match self {
+            MatchSource::Stdin => json_object("stdin", vec![]),
+            MatchSource::Text(w) => json_object("text", vec![("word", w.to_json())]),
+            MatchSource::File(w) => json_object("file", vec![("word", w.to_json())]),
+        }
+    }
+}
+
+/// The items of switch and match constructs.
+fn items_to_json(items: &[(Word, Program)]) -> String {
+    let items = items
+        .iter()
+        .map(|(w, prog)| {
+            json_object(
+                "item",
+                vec![("pattern", w.to_json()), ("body", prog.to_json())],
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
+
+impl ToJson for Command {
+    fn to_json(&self) -> String {
+        match self {
+            Command::SimpleCommand(sc) => {
+                let assignments =
+                    sc.2.iter()
+                        .map(|(name, value)| {
+                            json_object(
+                                "assignment",
+                                vec![("name", name.to_json()), ("value", value.to_json())],
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                json_object(
+                    "simple_command",
+                    vec![
+                        ("assignments", format!("[{}]", assignments.join(","))),
+                        ("name", sc.0.to_json()),
+                        ("args", sc.1.to_json()),
+                        ("line", sc.3.to_string()),
+                    ],
+                )
+            }
+            Command::SREProgram(seq) => seq.to_json(),
+            Command::BraceGroup(cls, redirections) => json_object(
+                "brace_group",
+                vec![
+                    ("command_lists", cls.to_json()),
+                    ("redirections", redirections.to_json()),
+                ],
+            ),
+            Command::IfConstruct(condition, body) => json_object(
+                "if_construct",
+                vec![("condition", condition.to_json()), ("body", body.to_json())],
+            ),
+            Command::ElseConstruct(body) => {
+                json_object("else_construct", vec![("body", body.to_json())])
+            }
+            Command::WhileConstruct(condition, body, line) => json_object(
+                "while_construct",
+                vec![
+                    ("condition", condition.to_json()),
+                    ("body", body.to_json()),
+                    ("line", line.to_string()),
+                ],
+            ),
+            Command::SwitchConstruct(to_match, items) => json_object(
+                "switch_construct",
+                vec![
+                    ("word", to_match.to_json()),
+                    ("items", items_to_json(items)),
+                ],
+            ),
+            Command::MatchConstruct(source, items) => json_object(
+                "match_construct",
+                vec![
+                    ("source", source.to_json()),
+                    ("items", items_to_json(items)),
+                ],
+            ),
+            Command::NotConstruct(p) => json_object(
+                "not_construct",
+                vec![("pipeline", Node::Pipeline(p.clone()).to_json())],
+            ),
+            Command::TimeConstruct(p) => json_object(
+                "time_construct",
+                vec![("pipeline", Node::Pipeline(p.clone()).to_json())],
+            ),
+            Command::FunctionDefinition(name, body) => json_object(
+                "function_definition",
+                vec![("name", name.to_json()), ("body", body.to_json())],
+            ),
         }
     }
 }
@@ -492,6 +924,8 @@ pub struct Parser {
     lexer: RefCell<Lexer>,
     error: Option<String>,
     brace_group_level: u32,
+    /// How many brace groups an error left open.
+    unclosed: u32,
 }
 
 impl Parser {
@@ -507,15 +941,80 @@ impl Parser {
         self.lexer.borrow_mut().reload();
     }
 
+    /// Skips what is left of the construct that had a parse error, so that parsing
+    /// can go on after it: the rest of the line, and then everything up to the end of
+    /// the brace groups that were open.
+    pub fn recover(&mut self) {
+        let mut depth = std::mem::take(&mut self.unclosed);
+        self.reload();
+        while depth > 0 {
+            match self.next_tok() {
+                None => break,
+                Some(Ok(Token {
+                    kind: lex::TokenKind::LBrace,
+                    ..
+                })) => depth += 1,
+                Some(Ok(Token {
+                    kind: lex::TokenKind::RBrace,
+                    ..
+                })) => depth -= 1,
+                Some(Ok(_)) => {}
+                // a quote that isn't closed ends the line
+                Some(Err(_)) => self.lexer.borrow_mut().reload(),
+            }
+        }
+        self.lexer.borrow_mut().input.ps2_clear();
+    }
+
     /// Creates a new parser from a [`Lexer`](./lex/struct.Lexer.html).
     pub fn from_lexer(lexer: Lexer) -> Parser {
         Parser {
             lexer: RefCell::new(lexer),
             error: None,
             brace_group_level: 0,
+            unclosed: 0,
         }
     }
 
+    /// Parses the next top-level program, along with the code it was parsed from.
+    pub fn next_with_source(&mut self) -> Option<(Result<Program, ParseError>, String)> {
+        self.lexer.borrow_mut().input.start_recording();
+        let next = self.next();
+        let source = self.lexer.borrow_mut().input.stop_recording();
+        next.map(|p| (p, source.trim().to_owned()))
+    }
+
+    /// Returns the line that is being parsed.
+    pub fn current_line(&self) -> String {
+        self.lexer.borrow().input.current_line()
+    }
+
+    /// Pre-fill the next line read from an interactive input source.
+    pub fn set_initial_line(&self, line: String, cursor: usize) {
+        self.lexer.borrow_mut().input.set_initial(line, cursor);
+    }
+
+    /// Replace the primary prompt of an interactive input source, or restore the default one.
+    pub fn set_prompt(&self, prompt: Option<String>) {
+        self.lexer.borrow().input.set_prompt(prompt);
+    }
+
+    /// Replaces what the words of an interactive input source are completed with.
+    pub fn set_completer(&self, completer: Completer) {
+        self.lexer.borrow().input.set_completer(completer);
+    }
+
+    /// Sets the line the line editor puts back on Alt-E, with the cursor before
+    /// the `.1`-th character.
+    pub fn set_correction(&self, correction: Option<(String, usize)>) {
+        self.lexer.borrow().input.set_correction(correction);
+    }
+
+    /// Returns `true` if the input ended because the user gave up the pending construct.
+    pub fn take_cancelled(&self) -> bool {
+        self.lexer.borrow().input.take_cancelled()
+    }
+
     /// Switch the input source to null.
     pub fn blindfold(&mut self) {
         self.lexer.borrow_mut().blindfold();
@@ -580,7 +1079,9 @@ impl Parser {
                 lex::TokenKind::LBrace => {}
                 lex::TokenKind::Pizza => {}
                 _ if top_level => {
-                    return Some(Err(p.new_error(format!("unexpected token {:?}", p))))
+                    return Some(Err(
+                        p.new_error(msg!("parse.unexpected-token", format!("{:?}", p)))
+                    ))
                 }
                 _ => break,
             }
@@ -598,60 +1099,44 @@ impl Parser {
         }
     }
 
-    fn parse_and(&mut self) -> Option<Result<Node, ParseError>> {
-        let left = match self.parse_pipeline()? {
+    /// Parses pipelines joined by `&&` and `||`.
+    ///
+    /// Both operators have the same precedence and group to the left,
+    /// so `a && b || c` runs `c` if either `a` or `b` fails.
+    fn parse_and_or(&mut self) -> Option<Result<Node, ParseError>> {
+        let mut left = match self.parse_pipeline()? {
             Ok(p) => Node::Pipeline(p),
             Err(e) => return Some(Err(e)),
         };
-        self.skip_space(true);
-        match self.peek() {
-            Some(Ok(Token {
-                kind: lex::TokenKind::And,
-                ..
-            })) => self.next_tok(),
-            _ => return Some(Ok(left)),
-        };
-        self.skip_space(false);
-        let right = match self.parse_and() {
-            None => return Some(Err(self.new_error("expected an and list".to_owned()))),
-            Some(Err(e)) => return Some(Err(e)),
-            Some(Ok(n)) => n,
-        };
-        Some(Ok(Node::BinOp(
-            BinOpType::And,
-            Box::new(left),
-            Box::new(right),
-        )))
-    }
-
-    fn parse_or(&mut self) -> Option<Result<Node, ParseError>> {
-        let left = match self.parse_and()? {
-            Ok(a) => a,
-            Err(e) => return Some(Err(e)),
-        };
-        self.skip_space(true);
-        match self.peek() {
-            Some(Ok(Token {
-                kind: lex::TokenKind::Or,
-                ..
-            })) => self.next_tok(),
-            _ => return Some(Ok(left)),
-        };
-        self.skip_space(false);
-        let right = match self.parse_or() {
-            None => return Some(Err(self.new_error("expected an or list".to_owned()))),
-            Some(Err(e)) => return Some(Err(e)),
-            Some(Ok(n)) => n,
-        };
-        Some(Ok(Node::BinOp(
-            BinOpType::Or,
-            Box::new(left),
-            Box::new(right),
-        )))
+        loop {
+            self.skip_space(true);
+            let (typ, mode, error) = match self.peek() {
+                Some(Ok(Token {
+                    kind: lex::TokenKind::And,
+                    ..
+                })) => (BinOpType::And, "and", "parse.expected-and-list"),
+                Some(Ok(Token {
+                    kind: lex::TokenKind::Or,
+                    ..
+                })) => (BinOpType::Or, "or", "parse.expected-or-list"),
+                _ => return Some(Ok(left)),
+            };
+            self.next_tok();
+            self.lexer.borrow_mut().ps2_enter(mode.to_owned());
+            self.skip_space(false);
+            let right = self.parse_pipeline();
+            self.lexer.borrow_mut().ps2_exit();
+            let right = match right {
+                None => return Some(Err(self.new_error(msg!(error)))),
+                Some(Err(e)) => return Some(Err(e)),
+                Some(Ok(p)) => Node::Pipeline(p),
+            };
+            left = Node::BinOp(typ, Box::new(left), Box::new(right));
+        }
     }
 
     fn parse_command_list(&mut self) -> Option<Result<CommandList, ParseError>> {
-        match self.parse_or() {
+        match self.parse_and_or() {
             Some(Ok(n)) => Some(Ok(CommandList(n))),
             Some(Err(e)) => Some(Err(e.clone())),
             None => None,
@@ -671,7 +1156,6 @@ impl Parser {
         // the grammar is pipeline ::= command pipeline | command
         match self.parse_command() {
             Some(Ok(t)) => {
-                self.lexer.borrow_mut().ps2_enter("pipe".to_owned());
                 let mut v = vec![t];
                 self.skip_space(true);
                 match self.peek() {
@@ -700,29 +1184,29 @@ impl Parser {
                         if tok.kind == lex::TokenKind::Pipe {
                             self.next_tok();
                         }
-                        match self.parse_pipeline() {
-                            Some(Ok(Pipeline(ref mut new_v))) => {
-                                v.append(new_v);
+                        // the rest of the pipeline may be on the following lines
+                        self.lexer.borrow_mut().ps2_enter("pipe".to_owned());
+                        let rest = self.parse_pipeline();
+                        self.lexer.borrow_mut().ps2_exit();
+                        match rest {
+                            Some(Ok(Pipeline(mut new_v))) => {
+                                v.append(&mut new_v);
                             }
                             Some(Err(e)) => {
-                                self.lexer.borrow_mut().ps2_exit();
-                                return Some(Err(e.clone()));
+                                return Some(Err(e));
                             }
                             None => {
-                                self.lexer.borrow_mut().ps2_exit();
                                 return Some(Err(
-                                    tok.new_error("expected pipeline, got EOF".to_owned())
+                                    tok.new_error(msg!("parse.expected-pipeline-eof"))
                                 ));
                             }
                         }
                     }
                     Some(Err(e)) => {
-                        self.lexer.borrow_mut().ps2_exit();
-                        return Some(Err(e.clone()));
+                        return Some(Err(e));
                     }
                     _ => {}
                 }
-                self.lexer.borrow_mut().ps2_exit();
                 Some(Ok(Pipeline(v)))
             }
             Some(Err(e)) => Some(Err(e.clone())),
@@ -750,7 +1234,7 @@ impl Parser {
         let condition = match self.parse_program(false) {
             None => {
                 return Some(Err(
-                    lparen.new_error("expected if condition, got EOF".to_owned())
+                    lparen.new_error(msg!("parse.expected-if-condition-eof"))
                 ))
             }
             Some(Err(e)) => return Some(Err(e)),
@@ -771,7 +1255,7 @@ impl Parser {
         self.lexer.borrow_mut().ps2_enter("then".to_owned());
         self.skip_space(false);
         let body = match self.parse_program(false) {
-            None => return Some(Err(rparen.new_error("expected if body, got EOF".to_owned()))),
+            None => return Some(Err(rparen.new_error(msg!("parse.expected-if-body-eof")))),
             Some(Err(e)) => return Some(Err(e)),
             Some(Ok(b)) => b,
         };
@@ -785,11 +1269,7 @@ impl Parser {
         self.skip_space(false);
 
         let body = match self.parse_program(false) {
-            None => {
-                return Some(Err(
-                    else_tok.new_error("expected else body, got EOF".to_owned())
-                ))
-            }
+            None => return Some(Err(else_tok.new_error(msg!("parse.expected-else-body-eof")))),
             Some(Err(e)) => return Some(Err(e)),
             Some(Ok(b)) => b,
         };
@@ -799,6 +1279,7 @@ impl Parser {
 
     fn parse_while(&mut self) -> Option<Result<Command, ParseError>> {
         let while_tok = self.next_tok().unwrap().unwrap(); // while keyword
+        let line = while_tok.pos.0;
         self.lexer.borrow_mut().ps2_enter("while".to_owned());
 
         self.skip_space(false);
@@ -817,13 +1298,13 @@ impl Parser {
         let condition = match self.parse_program(false) {
             None => {
                 return Some(Err(
-                    lparen.new_error("expected while condition, got EOF".to_owned())
+                    lparen.new_error(msg!("parse.expected-while-condition-eof"))
                 ))
             }
             Some(Err(e)) => return Some(Err(e)),
             Some(Ok(p)) => {
                 if p.0.is_empty() {
-                    return Some(Err(lparen.new_error("expected while condition".to_owned())));
+                    return Some(Err(lparen.new_error(msg!("parse.expected-while-condition"))));
                 }
                 p
             }
@@ -844,16 +1325,12 @@ impl Parser {
         self.lexer.borrow_mut().ps2_enter("do".to_owned());
         self.skip_space(false);
         let body = match self.parse_program(false) {
-            None => {
-                return Some(Err(
-                    rparen.new_error("expected while body, got EOF".to_owned())
-                ))
-            }
+            None => return Some(Err(rparen.new_error(msg!("parse.expected-while-body-eof")))),
             Some(Err(e)) => return Some(Err(e)),
             Some(Ok(b)) => b,
         };
         self.lexer.borrow_mut().ps2_exit();
-        Some(Ok(Command::WhileConstruct(condition, body)))
+        Some(Ok(Command::WhileConstruct(condition, body, line)))
     }
 
     fn parse_switch(&mut self) -> Option<Result<Command, ParseError>> {
@@ -867,7 +1344,7 @@ impl Parser {
             Some(Ok(w)) => w,
             None => {
                 return Some(Err(
-                    switch_tok.new_error("expected switch matchee, got EOF".to_owned())
+                    switch_tok.new_error(msg!("parse.expected-switch-matchee-eof"))
                 ))
             }
         };
@@ -893,14 +1370,14 @@ impl Parser {
                         .lexer
                         .borrow()
                         .input
-                        .new_error("expected switch pattern".to_owned())))
+                        .new_error(msg!("parse.expected-switch-pattern"))))
                 }
                 None => {
                     return Some(Err(self
                         .lexer
                         .borrow()
                         .input
-                        .new_error("expected switch pattern, got EOF".to_owned())))
+                        .new_error(msg!("parse.expected-switch-pattern-eof"))))
                 }
             }
             self.lexer.borrow_mut().mode.remove(LexMode::END);
@@ -916,7 +1393,7 @@ impl Parser {
                         .lexer
                         .borrow()
                         .input
-                        .new_error("expected pattern body, got EOF".to_owned())))
+                        .new_error(msg!("parse.expected-pattern-body-eof"))))
                 }
                 Some(Err(e)) => return Some(Err(e)),
                 Some(Ok(p)) => {
@@ -933,11 +1410,12 @@ impl Parser {
     fn parse_word_pattern(&mut self, advance_closing_slash: bool) -> Result<Word, ParseError> {
         use crate::parser::lex::TokenKind;
         assert_eq!(self.next_tok().unwrap().unwrap().kind, TokenKind::Slash); // /
+        let delim = self.lexer.borrow().delimiter;
         let mut v = Vec::new();
         let mut closed = false;
 
         while let Some(c) = self.peek_char() {
-            if c == '/' {
+            if c == delim {
                 closed = true;
                 if advance_closing_slash {
                     self.lexer.borrow_mut().input.next();
@@ -947,7 +1425,8 @@ impl Parser {
             let w = if c == '$' {
                 self.parse_word_dollar()?
             } else {
-                self.parse_word_string(WordStringReadMode::Pattern)?.0
+                self.parse_word_string(WordStringReadMode::Pattern(delim))?
+                    .0
             };
             v.push(w);
         }
@@ -956,16 +1435,47 @@ impl Parser {
                 .lexer
                 .borrow_mut()
                 .input
-                .new_error("expected '/', got EOF".to_owned()))
+                .new_error(msg!("parse.expected-delimiter-eof", delim)))
         } else {
             Ok(RawWord::Pattern(v).into())
         }
     }
 
+    /// Parses what comes between `match` and the first pattern.
+    ///
+    /// This looks at characters instead of tokens because patterns need a different lexer mode.
+    fn parse_match_source(&mut self) -> Result<MatchSource, ParseError> {
+        while let Some(' ') | Some('\t') = self.peek_char() {
+            self.next_char();
+        }
+        let file = match self.peek_char() {
+            Some('/') | Some('\n') | None => return Ok(MatchSource::Stdin),
+            Some('<') => {
+                self.next_char();
+                true
+            }
+            Some(_) => false,
+        };
+        let word = match self.parse_word_list() {
+            Some(Ok(w)) => w,
+            Some(Err(e)) => return Err(e),
+            None => return Err(self.new_error(msg!("parse.expected-match-source-eof"))),
+        };
+        Ok(if file {
+            MatchSource::File(word)
+        } else {
+            MatchSource::Text(word)
+        })
+    }
+
     fn parse_match(&mut self) -> Option<Result<Command, ParseError>> {
         self.next_tok().unwrap().unwrap(); // match keyword
         let mut v = Vec::new();
         self.lexer.borrow_mut().ps2_enter("match".to_owned());
+        let source = match self.parse_match_source() {
+            Ok(s) => s,
+            Err(e) => return Some(Err(e)),
+        };
 
         loop {
             self.lexer
@@ -989,14 +1499,14 @@ impl Parser {
                         .lexer
                         .borrow()
                         .input
-                        .new_error("expected match pattern".to_owned())))
+                        .new_error(msg!("parse.expected-match-pattern"))))
                 }
                 None => {
                     return Some(Err(self
                         .lexer
                         .borrow()
                         .input
-                        .new_error("expected match pattern, got EOF".to_owned())))
+                        .new_error(msg!("parse.expected-match-pattern-eof"))))
                 }
             }
             self.lexer.borrow_mut().mode.remove(LexMode::END);
@@ -1012,7 +1522,7 @@ impl Parser {
                         .lexer
                         .borrow()
                         .input
-                        .new_error("expected pattern body, got EOF".to_owned())))
+                        .new_error(msg!("parse.expected-pattern-body-eof"))))
                 }
                 Some(Err(e)) => return Some(Err(e)),
                 Some(Ok(p)) => {
@@ -1023,19 +1533,69 @@ impl Parser {
             v.push((pattern, prog));
         }
         self.lexer.borrow_mut().ps2_exit();
-        Some(Ok(Command::MatchConstruct(v)))
+        Some(Ok(Command::MatchConstruct(source, v)))
     }
 
     fn parse_not(&mut self) -> Option<Result<Command, ParseError>> {
-        self.next_tok().unwrap().unwrap(); // !
+        let not_tok = self.next_tok().unwrap().unwrap(); // !
+        self.skip_space(true);
+        if let Some(Ok(Token {
+            kind: lex::TokenKind::Newline,
+            ..
+        })) = self.peek()
+        {
+            return Some(Err(
+                not_tok.new_error(msg!("parse.expected-negated-pipeline"))
+            ));
+        }
+        match self.parse_pipeline() {
+            Some(Ok(p)) => Some(Ok(Command::NotConstruct(p))),
+            Some(Err(e)) => Some(Err(e)),
+            None => Some(Err(not_tok.new_error(msg!("parse.expected-pipeline-eof")))),
+        }
+    }
+
+    fn parse_time(&mut self) -> Option<Result<Command, ParseError>> {
+        let time_tok = self.next_tok().unwrap().unwrap(); // time keyword
+        self.skip_space(true);
+        if let Some(Ok(Token {
+            kind: lex::TokenKind::Newline,
+            ..
+        })) = self.peek()
+        {
+            return Some(Err(time_tok.new_error(msg!("parse.expected-time-pipeline"))));
+        }
+        match self.parse_pipeline() {
+            Some(Ok(p)) => Some(Ok(Command::TimeConstruct(p))),
+            Some(Err(e)) => Some(Err(e)),
+            None => Some(Err(time_tok.new_error(msg!("parse.expected-pipeline-eof")))),
+        }
+    }
 
+    fn parse_function(&mut self) -> Option<Result<Command, ParseError>> {
+        let fn_tok = self.next_tok().unwrap().unwrap(); // fn keyword
         self.skip_space(false);
-        let prog = match self.parse_program(false) {
+        let name = match self.next_tok() {
+            Some(Ok(Token {
+                kind: lex::TokenKind::Word(name),
+                ..
+            })) => name,
+            Some(Ok(tok)) => return Some(Err(tok.new_error(msg!("parse.expected-function-name")))),
             Some(Err(e)) => return Some(Err(e)),
-            Some(Ok(p)) => p,
-            None => Program(Vec::new()),
+            None => {
+                return Some(Err(
+                    fn_tok.new_error(msg!("parse.expected-function-name-eof"))
+                ))
+            }
         };
-        Some(Ok(Command::NotConstruct(prog)))
+        self.skip_space(false);
+        match self.parse_command() {
+            Some(Ok(Command::BraceGroup(lists, ref redirections))) if redirections.is_empty() => {
+                Some(Ok(Command::FunctionDefinition(name, Program(lists))))
+            }
+            Some(Ok(_)) | None => Some(Err(fn_tok.new_error(msg!("parse.expected-function-body")))),
+            Some(Err(e)) => Some(Err(e)),
+        }
     }
 
     fn parse_command(&mut self) -> Option<Result<Command, ParseError>> {
@@ -1046,63 +1606,74 @@ impl Parser {
                 ..
             })) => {
                 let mut commands = Vec::<SRECommand>::new();
+                let mut dry_run = false;
+                let mut files = None;
                 while let Some(Ok(lex::Token {
                     kind: lex::TokenKind::Pizza,
                     ..
                 })) = self.peek()
                 {
                     self.next_tok();
-                    let sre = parse_sre_command(self, false).invert()?.unwrap();
-                    commands.push(sre);
+                    if self.peek_char() == Some('!') {
+                        self.next_char();
+                        dry_run = true;
+                    }
+                    // no SRE command starts with these, so they begin a file list;
+                    // the mark addresses ' and " are only read after a space
+                    if commands.is_empty()
+                        && matches!(
+                            self.peek_char(),
+                            Some('*') | Some('~') | Some('"') | Some('\'')
+                        )
+                    {
+                        match self.parse_word_list() {
+                            Some(Ok(w)) => files = Some(w),
+                            Some(Err(e)) => return Some(Err(e)),
+                            None => unreachable!(),
+                        }
+                        match self.next_tok() {
+                            Some(Ok(lex::Token {
+                                kind: lex::TokenKind::Space,
+                                ..
+                            })) => {}
+                            _ => return Some(Err(self.new_error(msg!("parse.sre-files")))),
+                        }
+                    }
+                    match parse_sre_command(self, false).invert()? {
+                        Ok(sre) => commands.push(sre),
+                        Err(e) => return Some(Err(e)),
+                    }
                     self.skip_space(true);
                 }
-                Some(Ok(Command::SREProgram(SRESequence(commands))))
+                Some(Ok(Command::SREProgram(SRESequence(
+                    commands, dry_run, files,
+                ))))
             }
             Some(Ok(lex::Token {
                 kind: lex::TokenKind::LBrace,
                 ..
             })) => {
-                let mut last = self.next_tok().unwrap().unwrap();
-                self.brace_group_level += 1;
-                self.lexer.borrow_mut().ps2_enter("brace".to_owned());
-                let mut lists = Vec::<CommandList>::new();
-                while let Some(Ok(tok)) = self.peek() {
-                    last = tok;
-                    match self.parse_command_list() {
-                        None => break,
-                        Some(Ok(cl)) => lists.push(cl),
-                        Some(Err(e)) => return Some(Err(e)),
-                    }
-                }
-                match self.next_tok() {
-                    Some(Ok(Token { .. })) => {}
-                    Some(Err(e)) => return Some(Err(e)),
-                    None => return Some(Err(last.new_error("expected '}', got EOF".to_owned()))),
-                }
-
-                self.lexer.borrow_mut().ps2_exit();
+                let lists = match self.parse_brace_group() {
+                    Ok(lists) => lists,
+                    Err(e) => return Some(Err(e)),
+                };
+                let redirections = match self.parse_redirections() {
+                    Ok(redirections) => redirections,
+                    Err(e) => return Some(Err(e)),
+                };
                 self.skip_space(true);
-                Some(Ok(Command::BraceGroup(lists)))
+                Some(Ok(Command::BraceGroup(lists, redirections)))
             }
             Some(Ok(lex::Token {
                 kind: lex::TokenKind::RBrace,
                 ..
-            })) if self.brace_group_level > 0 => {
-                self.brace_group_level -= 1;
-                None
-            }
+            })) if self.brace_group_level > 0 => None,
             Some(Ok(lex::Token {
                 kind: lex::TokenKind::Word(s),
                 ..
             })) => {
-                match s.as_ref() {
-                    "if" => return self.parse_if(),
-                    "else" => return self.parse_else(),
-                    "while" => return self.parse_while(),
-                    "switch" => return self.parse_switch(),
-                    "match" => return self.parse_match(),
-                    "!" => return self.parse_not(),
-                    _ => {}
+                if let Some(keyword) = KEYWORDS.iter().find(|k| k.name == s) {
+                    return (keyword.parse)(self);
                 }
                 self.parse_simple_command()
                     .map(|r| r.map(Command::SimpleCommand))
@@ -1112,14 +1683,170 @@ impl Parser {
                 .map(|r| r.map(Command::SimpleCommand)),
             None => None,
             Some(Err(e)) => Some(Err(e)),
-            Some(Ok(x)) => Some(Err(x.new_error(format!("unexpected token {:?}", x)))),
+            Some(Ok(x)) => Some(Err(
+                x.new_error(msg!("parse.unexpected-token", format!("{:?}", x)))
+            )),
+        }
+    }
+
+    /// Parses the command lists of a brace group, up to and including the closing brace.
+    fn parse_brace_group(&mut self) -> Result<Vec<CommandList>, ParseError> {
+        self.brace_group_level += 1;
+        self.lexer.borrow_mut().ps2_enter("brace".to_owned());
+        let lists = self.parse_brace_group_body();
+        if lists.is_err() {
+            self.unclosed = self.unclosed.max(self.brace_group_level);
+        }
+        self.lexer.borrow_mut().ps2_exit();
+        self.brace_group_level -= 1;
+        lists
+    }
+
+    fn parse_brace_group_body(&mut self) -> Result<Vec<CommandList>, ParseError> {
+        let mut last = self.next_tok().unwrap().unwrap();
+        let mut lists = Vec::<CommandList>::new();
+        while let Some(Ok(tok)) = self.peek() {
+            last = tok;
+            match self.parse_command_list() {
+                None => break,
+                Some(Ok(cl)) => lists.push(cl),
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        match self.next_tok() {
+            Some(Ok(Token {
+                kind: lex::TokenKind::RBrace,
+                ..
+            })) => Ok(lists),
+            Some(Ok(tok)) => Err(tok.new_error(msg!("parse.expected-delimiter", '}', tok.kind))),
+            Some(Err(e)) => Err(e),
+            None => Err(last.new_error(msg!("parse.expected-delimiter-eof", '}'))),
+        }
+    }
+
+    /// Parses the redirections after a brace group, like `2>&1 > log`.
+    fn parse_redirections(&mut self) -> Result<Vec<Redirection>, ParseError> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^(?P<fd>\d*)(?P<op>>>|>|<)(?P<rest>.*)$").unwrap();
+        }
+        let mut redirections = Vec::new();
+        loop {
+            self.skip_space(true);
+            let (tok, caps) = match self.peek() {
+                Some(Ok(
+                    tok @ Token {
+                        kind: lex::TokenKind::Word(_),
+                        ..
+                    },
+                )) => {
+                    let caps = match RE.captures(&tok.kind.clone().word()) {
+                        Some(caps) => caps
+                            .iter()
+                            .map(|c| c.unwrap().as_str().to_owned())
+                            .collect::<Vec<_>>(),
+                        None => break,
+                    };
+                    (tok, caps)
+                }
+                _ => break,
+            };
+            self.next_tok();
+            let (fd, op, rest) = (&caps[1], &caps[2], &caps[3]);
+            let fd = match fd.parse() {
+                Ok(fd) => fd,
+                Err(_) if fd.is_empty() => {
+                    if op == "<" {
+                        0
+                    } else {
+                        1
+                    }
+                }
+                Err(_) => return Err(tok.new_error(msg!("parse.bad-redirection", &caps[0]))),
+            };
+            let target = self.parse_redirection_target(&tok, op, rest)?;
+            redirections.push(Redirection(fd, target));
+        }
+        Ok(redirections)
+    }
+
+    fn parse_redirection_target(
+        &mut self,
+        tok: &Token,
+        op: &str,
+        rest: &str,
+    ) -> Result<RedirectionTarget, ParseError> {
+        let bad = || tok.new_error(msg!("parse.bad-redirection", tok.kind.clone().word()));
+        if op == ">" && rest.is_empty() {
+            if let Some(Ok(Token {
+                kind: lex::TokenKind::Ampersand,
+                ..
+            })) = self.peek()
+            {
+                self.next_tok();
+                return match self.next_tok() {
+                    Some(Ok(Token {
+                        kind: lex::TokenKind::Word(n),
+                        ..
+                    })) => n
+                        .parse()
+                        .map(RedirectionTarget::Duplicate)
+                        .map_err(|_| bad()),
+                    _ => Err(bad()),
+                };
+            }
+        }
+        let mut words = Vec::new();
+        if rest.is_empty() {
+            self.skip_space(true);
+        } else {
+            words.push(RawWord::String(rest.to_owned(), false).into());
+        }
+        match self.peek() {
+            Some(Ok(Token { ref kind, .. })) if can_start_word(kind) => {
+                match self.parse_word_list() {
+                    Some(Ok(w)) => words.push(w),
+                    Some(Err(e)) => return Err(e),
+                    None => {}
+                }
+            }
+            Some(Err(e)) => return Err(e),
+            _ => {}
         }
+        if words.is_empty() {
+            return Err(bad());
+        }
+        let word = RawWord::List(words, false).into();
+        Ok(match op {
+            "<" => RedirectionTarget::Read(word),
+            ">" => RedirectionTarget::Write(word),
+            _ => RedirectionTarget::Append(word),
+        })
+    }
+
+    /// Parses a brace group that follows the arguments of a simple command.
+    /// It is passed to the command as a last argument made of the code between
+    /// the braces, as it was written.
+    fn parse_block_argument(&mut self) -> Result<Word, ParseError> {
+        // the opening brace has already been read by the lexer
+        self.lexer.borrow_mut().input.start_recording();
+        let lists = self.parse_brace_group();
+        let code = self.lexer.borrow_mut().input.stop_recording();
+        lists?;
+        let code = &code[..code.rfind('}').unwrap_or(code.len())];
+        let code = RawWord::String(code.trim().to_owned(), true).into();
+        Ok(RawWord::List(vec![code], false).into())
     }
 
     /// Parses a command
     ///
-    /// A command is a chain of word lists (strings)
+    /// A command is a chain of word lists (strings),
+    /// optionally followed by a [block argument](#method.parse_block_argument).
     fn parse_simple_command(&mut self) -> Option<Result<SimpleCommand, ParseError>> {
+        self.skip_space(false);
+        let line = match self.peek() {
+            Some(Ok(tok)) => tok.pos.0,
+            _ => 0,
+        };
         match self.parse_word_list() {
             Some(Ok(name)) => {
                 let mut v: Vec<Word> = Vec::new();
@@ -1147,6 +1874,16 @@ impl Parser {
                             } => {
                                 break;
                             }
+                            lex::Token {
+                                kind: lex::TokenKind::LBrace,
+                                ..
+                            } => {
+                                match self.parse_block_argument() {
+                                    Ok(w) => v.push(w),
+                                    Err(e) => return Some(Err(e)),
+                                }
+                                break;
+                            }
                             _ => {
                                 break;
                             }
@@ -1156,7 +1893,18 @@ impl Parser {
                         }
                     }
                 }
-                Some(Ok(SimpleCommand(name, v)))
+                // assignments come first, but there must be something to run:
+                // words that are all assignments are a command, as `A=1` alone is
+                v.insert(0, name);
+                let mut assignments = Vec::new();
+                if v.iter().any(|w| split_assignment(w).is_none()) {
+                    while let Some(a) = split_assignment(&v[0]) {
+                        assignments.push(a);
+                        v.remove(0);
+                    }
+                }
+                let name = v.remove(0);
+                Some(Ok(SimpleCommand(name, v, assignments, line)))
             }
             Some(Err(e)) => Some(Err(e.clone())),
             None => None,
@@ -1242,11 +1990,11 @@ impl Parser {
                 s.push(escape(c));
                 escaping = false;
             } else if c == '\\' {
-                if let WordStringReadMode::Pattern = mode {
+                if let WordStringReadMode::Pattern(delim) = mode {
                     input.next();
                     match input.peek() {
                         Some('\\') => s.push_str("\\\\"),
-                        Some('/') => s.push('/'),
+                        Some(&x) if x == delim => s.push_str(&regex::escape(&x.to_string())),
                         Some(&x) => {
                             s.push('\\');
                             s.push(x);
@@ -1281,12 +2029,17 @@ impl Parser {
                         }
                     }
                     WordStringReadMode::Parameter => {
-                        if !lex::is_parameter_char(c) {
+                        let in_subscript = s.rfind('[') > s.rfind(']');
+                        // `]` closes a subscript, like in `[$a[1]]`,
+                        // and `-` starts negative indices, like in `$a[-1]`
+                        if (c == ']' && !in_subscript)
+                            || (!lex::is_parameter_char(c) && (!in_subscript || c != '-'))
+                        {
                             break;
                         }
                     }
-                    WordStringReadMode::Pattern => {
-                        if c == '/' {
+                    WordStringReadMode::Pattern(delim) => {
+                        if c == delim {
                             break;
                         }
                     }
@@ -1297,7 +2050,7 @@ impl Parser {
         }
 
         if escaping {
-            Err(input.new_error("expected character, got EOF".to_owned()))
+            Err(input.new_error(msg!("parse.expected-char-eof")))
         } else {
             let single_quote = if let WordStringReadMode::SingleQuoted = mode {
                 true
@@ -1338,7 +2091,7 @@ impl Parser {
                 .lexer
                 .borrow_mut()
                 .input
-                .new_error(format!("expected '{}', got EOF", delim)))
+                .new_error(msg!("parse.expected-delimiter-eof", delim)))
         } else {
             Ok(RawWord::List(v, true).into())
         }
@@ -1364,27 +2117,41 @@ impl Parser {
     pub fn get_word_parameter(s: &str) -> Option<WordParameter> {
         lazy_static! {
             static ref RE: Regex =
-                Regex::new(r"^(?P<name>[^\[\]]+)(\[(?P<index>\d+)\])?$").unwrap();
+                Regex::new(r"^(?P<name>[^\[\]]+)(\[((?P<index>-?\d+)|(?P<key>[^\[\]]+))\])?$")
+                    .unwrap();
         }
         let caps = RE.captures(s)?;
         let name = caps.name("name").unwrap().as_str();
         if let Some(index) = caps.name("index") {
             Some(WordParameter::with_index(
                 &name,
-                index.as_str().parse().unwrap(),
+                index.as_str().parse().ok()?,
             ))
+        } else if let Some(key) = caps.name("key") {
+            Some(WordParameter::with_key(name, key.as_str()))
         } else {
             Some(WordParameter::var(&name))
         }
     }
 
     fn parse_word_parameter(&mut self) -> Result<Word, ParseError> {
-        #[allow(clippy::single_match)]
         match self.peek_char() {
             Some('?') => {
                 self.next_char();
                 return Ok(WordParameter::var("?").into_word());
             }
+            Some('#') => {
+                self.next_char();
+                return Ok(WordParameter::var("#").into_word());
+            }
+            Some('@') => {
+                self.next_char();
+                return Ok(WordParameter::var("@").into_word());
+            }
+            Some('*') => {
+                self.next_char();
+                return Ok(WordParameter::var("*").into_word());
+            }
             _ => {}
         }
         let (w, len) = self.parse_word_string(WordStringReadMode::Parameter)?;
@@ -1392,7 +2159,10 @@ impl Parser {
             return Ok(WordParameter::var("").into_word());
         }
         let s = naked_word(w).string();
-        Ok(Self::get_word_parameter(&s).unwrap().into_word())
+        match Self::get_word_parameter(&s) {
+            Some(param) => Ok(param.into_word()),
+            None => Err(self.new_error(msg!("var.bad-subscript", s))),
+        }
     }
 
     fn parse_word_command(&mut self) -> Result<Word, ParseError> {
@@ -1440,13 +2210,54 @@ pub mod tests {
         let ok1: Option<Result<SimpleCommand, ParseError>> = Some(Ok(SimpleCommand(
             word!("echo".to_owned()),
             vec![word!("Hello, world!".to_owned(), true)],
+            vec![],
+            1,
         )));
         let ok2: Option<Result<SimpleCommand, ParseError>> = Some(Ok(SimpleCommand(
             word!("extra".to_owned()),
             vec![word!("command".to_owned())],
+            vec![],
+            2,
+        )));
+        assert_eq!(p.parse_simple_command(), ok1);
+        assert_eq!(p.parse_simple_command(), ok2);
+    }
+
+    #[test]
+    fn parse_assignment_prefix() {
+        let s = "A=1 env\nA=1\nA=1 B=2 env x\nA=1 B=2";
+        let mut p = super::Parser::new(new_dummy_buf(s.lines()));
+        let ok1: Option<Result<SimpleCommand, ParseError>> = Some(Ok(SimpleCommand(
+            word!("env".to_owned()),
+            vec![],
+            vec![("A".to_owned(), word!("1".to_owned()))],
+            1,
+        )));
+        let ok2: Option<Result<SimpleCommand, ParseError>> = Some(Ok(SimpleCommand(
+            word!("A=1".to_owned()),
+            vec![],
+            vec![],
+            2,
+        )));
+        let ok3: Option<Result<SimpleCommand, ParseError>> = Some(Ok(SimpleCommand(
+            word!("env".to_owned()),
+            vec![word!("x".to_owned())],
+            vec![
+                ("A".to_owned(), word!("1".to_owned())),
+                ("B".to_owned(), word!("2".to_owned())),
+            ],
+            3,
+        )));
+        let ok4: Option<Result<SimpleCommand, ParseError>> = Some(Ok(SimpleCommand(
+            word!("A=1".to_owned()),
+            vec![word!("B=2".to_owned())],
+            vec![],
+            4,
         )));
         assert_eq!(p.parse_simple_command(), ok1);
         assert_eq!(p.parse_simple_command(), ok2);
+        assert_eq!(p.parse_simple_command(), ok3);
+        assert_eq!(p.parse_simple_command(), ok4);
     }
 
     #[test]
@@ -1457,22 +2268,184 @@ pub mod tests {
             Command::SimpleCommand(SimpleCommand(
                 word!("dmesg".to_owned()),
                 vec![word!("--facility".to_owned()), word!("daemon".to_owned())],
+                vec![],
+                1,
             )),
-            Command::SimpleCommand(SimpleCommand(word!("lolcat".to_owned()), vec![])),
+            Command::SimpleCommand(SimpleCommand(word!("lolcat".to_owned()), vec![], vec![], 1)),
             Command::SimpleCommand(SimpleCommand(
                 word!("cat".to_owned()),
                 vec![word!("-v".to_owned())],
+                vec![],
+                1,
             )),
         ])));
         let ok2: Option<Result<Pipeline, ParseError>> =
             Some(Ok(Pipeline(vec![Command::SimpleCommand(SimpleCommand(
                 word!("meow".to_owned()),
                 vec![],
+                vec![],
+                3,
             ))])));
         assert_eq!(p.parse_pipeline(), ok1);
         assert_eq!(p.parse_pipeline(), ok2);
     }
 
+    #[test]
+    fn parse_brace_group_redirections() {
+        use super::{Redirection, RedirectionTarget};
+        let s = "{ a } 2>&1 >out >> $log <in\n{ b\n";
+        let mut p = super::Parser::new(new_dummy_buf(s.lines()));
+        match p.next() {
+            Some(Ok(prog)) => match &prog.0[0].0 {
+                super::Node::Pipeline(Pipeline(cmds)) => match &cmds[..] {
+                    [Command::BraceGroup(lists, redirections)] => {
+                        assert_eq!(lists.len(), 1);
+                        assert_eq!(
+                            redirections[0],
+                            Redirection(2, RedirectionTarget::Duplicate(1))
+                        );
+                        match &redirections[1..] {
+                            [Redirection(1, RedirectionTarget::Write(_)), Redirection(1, RedirectionTarget::Append(_)), Redirection(0, RedirectionTarget::Read(_))] =>
+                                {}
+                            r => panic!("wrong redirections: {:?}", r),
+                        }
+                    }
+                    _ => panic!("not a brace group: {:?}", cmds),
+                },
+                n => panic!("not a pipeline: {:?}", n),
+            },
+            r => panic!("{:?}", r),
+        }
+        assert!(p.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn parse_not() {
+        let s = "! a | b && c\n!\n";
+        let mut p = super::Parser::new(new_dummy_buf(s.lines()));
+        match p.next() {
+            Some(Ok(prog)) => match &prog.0[0].0 {
+                super::Node::BinOp(_, left, _) => match &**left {
+                    super::Node::Pipeline(Pipeline(cmds)) => match &cmds[..] {
+                        [Command::NotConstruct(Pipeline(negated))] => assert_eq!(negated.len(), 2),
+                        _ => panic!("not a negated pipeline: {:?}", cmds),
+                    },
+                    n => panic!("not a pipeline: {:?}", n),
+                },
+                n => panic!("not a binop: {:?}", n),
+            },
+            r => panic!("{:?}", r),
+        }
+        assert!(p.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn parse_and_or() {
+        use super::{BinOpType, Node};
+        let s = "a && b || c\n";
+        let mut p = super::Parser::new(new_dummy_buf(s.lines()));
+        match p.next() {
+            Some(Ok(prog)) => match &prog.0[0].0 {
+                Node::BinOp(BinOpType::Or, left, right) => {
+                    match &**left {
+                        Node::BinOp(BinOpType::And, _, _) => {}
+                        n => panic!("not an and: {:?}", n),
+                    }
+                    match &**right {
+                        Node::Pipeline(_) => {}
+                        n => panic!("not a pipeline: {:?}", n),
+                    }
+                }
+                n => panic!("not an or: {:?}", n),
+            },
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn parse_time() {
+        let s = "time a | b && c\ntime\n";
+        let mut p = super::Parser::new(new_dummy_buf(s.lines()));
+        match p.next() {
+            Some(Ok(prog)) => match &prog.0[0].0 {
+                super::Node::BinOp(_, left, _) => match &**left {
+                    super::Node::Pipeline(Pipeline(cmds)) => match &cmds[..] {
+                        [Command::TimeConstruct(Pipeline(timed))] => assert_eq!(timed.len(), 2),
+                        _ => panic!("not a timed pipeline: {:?}", cmds),
+                    },
+                    n => panic!("not a pipeline: {:?}", n),
+                },
+                n => panic!("not a binop: {:?}", n),
+            },
+            r => panic!("{:?}", r),
+        }
+        assert!(p.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn parse_multiline_lists() {
+        let s = "dmesg |\n\n  lolcat\ntrue &&\n  echo yes ||\n  echo no\nmeow\n";
+        let progs = super::Parser::new(new_dummy_buf(s.lines()))
+            .map(|p| p.unwrap().0.len())
+            .collect::<Vec<_>>();
+        assert_eq!(progs, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn parse_function() {
+        let s = "fn greet {\n  echo hello $1\n}\ngreet world\n";
+        let mut p = super::Parser::new(new_dummy_buf(s.lines()));
+        match p.next() {
+            Some(Ok(prog)) => {
+                let super::CommandList(node) = &prog.0[0];
+                if let super::Node::Pipeline(super::Pipeline(cmds)) = node {
+                    if let super::Command::FunctionDefinition(name, body) = &cmds[0] {
+                        assert_eq!(name, "greet");
+                        assert_eq!(body.0.len(), 1);
+                    } else {
+                        panic!("{:#?}", cmds);
+                    }
+                } else {
+                    panic!("{:#?}", node);
+                }
+            }
+            x => panic!("{:#?}", x),
+        }
+        assert!(p.next().unwrap().is_ok());
+
+        let mut p = super::Parser::new(new_dummy_buf("fn nobody\n".lines()));
+        assert!(p.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn parse_match_source() {
+        use super::{Command, MatchSource, Node};
+        let sources = [
+            "match /x/ a\nend\n",
+            "match $v /x/ a\nend\n",
+            "match <dir/f\n/x/ a\nend\n",
+        ];
+        let kinds = sources
+            .iter()
+            .map(|s| {
+                let prog = super::Parser::new(new_dummy_buf(s.lines()))
+                    .next()
+                    .unwrap()
+                    .unwrap();
+                match &(prog.0[0]).0 {
+                    Node::Pipeline(p) => match &p.0[0] {
+                        Command::MatchConstruct(MatchSource::Stdin, _) => "stdin",
+                        Command::MatchConstruct(MatchSource::Text(_), _) => "text",
+                        Command::MatchConstruct(MatchSource::File(_), _) => "file",
+                        c => panic!("{:#?}", c),
+                    },
+                    n => panic!("{:#?}", n),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec!["stdin", "text", "file"]);
+    }
+
     #[test]
     fn parse_sre_command() {
         let s = "|> 2,3a/something/    |> ,p";
@@ -1480,12 +2453,76 @@ pub mod tests {
         let task = p.parse_command();
         if let Some(Ok(super::Command::SREProgram(seq))) = task {
             assert_eq!(seq.0.len(), 2);
+            assert!(!seq.1);
         } else {
             println!("{:#?}", task);
             panic!();
         }
     }
 
+    #[test]
+    fn parse_sre_dry_run() {
+        let mut p = super::Parser::new(new_dummy_buf("|> ,x/a/ c/b/ |>! ,d".lines()));
+        match p.parse_command() {
+            Some(Ok(super::Command::SREProgram(seq))) => {
+                assert_eq!(seq.0.len(), 2);
+                assert_eq!(seq.0[1].name, 'd');
+                assert!(seq.1);
+            }
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn parse_sre_files() {
+        let mut p = super::Parser::new(new_dummy_buf("|>*.rs ,x/TODO/p |> ,p".lines()));
+        match p.parse_command() {
+            Some(Ok(super::Command::SREProgram(seq))) => {
+                assert_eq!(seq.0.len(), 2);
+                let files = RawWord::List(
+                    vec![RawWord::String("*.rs".to_owned(), false).into()],
+                    false,
+                );
+                assert_eq!(seq.2, Some(files.into()));
+            }
+            x => panic!("{:?}", x),
+        }
+        let mut p = super::Parser::new(new_dummy_buf("|>,x/TODO/p".lines()));
+        match p.parse_command() {
+            Some(Ok(super::Command::SREProgram(seq))) => assert!(seq.2.is_none()),
+            x => panic!("{:?}", x),
+        }
+        let mut p = super::Parser::new(new_dummy_buf("|>*.rs".lines()));
+        assert!(matches!(p.parse_command(), Some(Err(_))));
+        let mut p = super::Parser::new(new_dummy_buf("|>'/tmp/f' ,p".lines()));
+        match p.parse_command() {
+            Some(Ok(super::Command::SREProgram(seq))) => assert!(seq.2.is_some()),
+            x => panic!("{:?}", x),
+        }
+        let mut p = super::Parser::new(new_dummy_buf("|> ' p".lines()));
+        match p.parse_command() {
+            Some(Ok(super::Command::SREProgram(seq))) => assert!(seq.2.is_none()),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn parse_sre_interactive() {
+        let mut p = super::Parser::new(new_dummy_buf("|> ,x/a/ c/b/ |>i".lines()));
+        match p.parse_command() {
+            Some(Ok(super::Command::SREProgram(seq))) => {
+                assert_eq!(seq.0.len(), 2);
+                assert!(seq.0[1].is_interactive());
+            }
+            x => panic!("{:?}", x),
+        }
+        let mut p = super::Parser::new(new_dummy_buf("|>i/text/".lines()));
+        match p.parse_command() {
+            Some(Ok(super::Command::SREProgram(seq))) => assert!(!seq.0[0].is_interactive()),
+            x => panic!("{:?}", x),
+        }
+    }
+
     #[test]
     fn iterator() {
         let p = super::Parser::new(new_dummy_buf("dmesg |> 2,3p\necho 'All ok'\n".lines()));
@@ -1563,9 +2600,117 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn word_parameter_keys() {
+        use super::{Parser, WordParameter};
+        assert_eq!(
+            Parser::get_word_parameter("m"),
+            Some(WordParameter::var("m"))
+        );
+        assert_eq!(
+            Parser::get_word_parameter("m[1]"),
+            Some(WordParameter::with_index("m", 1))
+        );
+        assert_eq!(
+            Parser::get_word_parameter("m[name]"),
+            Some(WordParameter::with_key("m", "name"))
+        );
+        assert_eq!(
+            Parser::get_word_parameter("m[-2]"),
+            Some(WordParameter::with_index("m", -2))
+        );
+        assert_eq!(
+            Parser::get_word_parameter("m[+]"),
+            Some(WordParameter::with_key("m", "+"))
+        );
+        assert_eq!(Parser::get_word_parameter("m[a]b"), None);
+        assert_eq!(
+            Parser::get_word_parameter("m[99999999999999999999999]"),
+            None
+        );
+    }
+
+    #[test]
+    fn read_parameter_word_subscript() {
+        use super::{RawWord, WordParameter};
+        for (s, param) in &[
+            ("$a[-1]]", WordParameter::with_index("a", -1)),
+            ("$b]", WordParameter::var("b")),
+        ] {
+            let mut p = super::Parser::new(new_dummy_buf(s.lines()));
+            p.lexer.borrow_mut().next();
+            assert_eq!(
+                p.parse_word_parameter().unwrap(),
+                RawWord::Parameter(param.to_owned()).into(),
+            );
+        }
+    }
+
     #[test]
     fn read_word_error() {
         let mut p = super::Parser::new(new_dummy_buf("\"not finished".lines()));
         assert!(p.parse_word_list().unwrap().is_err());
     }
+
+    #[test]
+    fn bad_subscript_error() {
+        let mut p = super::Parser::new(new_dummy_buf("$a[] $b[".lines()));
+        assert!(p.parse_word_list().unwrap().is_err());
+    }
+
+    #[test]
+    fn program_source() {
+        let code = "echo a; echo b\n\n# comment\nif (true) {\n\techo c\n}\nls | wc -l";
+        let mut p = super::Parser::new(new_dummy_buf(code.lines()));
+        let mut sources = Vec::new();
+        while let Some((prog, source)) = p.next_with_source() {
+            if !prog.unwrap().0.is_empty() {
+                sources.push(source);
+            }
+        }
+        assert_eq!(
+            sources,
+            vec!["echo a; echo b", "if (true) {\n\techo c\n}", "ls | wc -l"]
+        );
+    }
+
+    #[test]
+    fn error_recovery() {
+        let code = "echo )\nfn f {\n\techo (\n\t{ echo }\n}\necho ok\nfoo | \n";
+        let mut p = super::Parser::new(new_dummy_buf(code.lines()));
+        let mut errors = Vec::new();
+        let mut programs = 0;
+        while let Some(prog) = p.next() {
+            match prog {
+                Ok(prog) => programs += prog.0.len(),
+                Err(e) => {
+                    errors.push((e.line, e.col));
+                    p.recover();
+                }
+            }
+        }
+        assert_eq!(errors, vec![(1, 6), (3, 7), (7, 6)]);
+        assert_eq!(programs, 1);
+    }
+
+    #[test]
+    fn json() {
+        use crate::shell::pretty::ToJson;
+        let mut p = super::Parser::new(new_dummy_buf("X=1 echo $a[2] |> 3d".lines()));
+        let json = p.next().unwrap().unwrap().to_json();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"type":"program","command_lists":[{"type":"pipeline","commands":["#,
+                r#"{"type":"simple_command","assignments":[{"type":"assignment","name":"X","#,
+                r#""value":{"type":"list","words":[{"type":"string","value":"1","quoted":false}],"#,
+                r#""quoted":false}}],"name":{"type":"list","words":[{"type":"string","#,
+                r#""value":"echo","quoted":false}],"quoted":false},"args":[{"type":"list","#,
+                r#""words":[{"type":"parameter","name":"a","index":2}],"quoted":false}],"line":1},"#,
+                r#"{"type":"sre_sequence","commands":[{"type":"sre_command","name":"d","#,
+                r#""address":{"type":"line","value":3,"left":null,"next":null},"#,
+                r#""original_address":"3","string_args":[],"command_args":[]}],"dry_run":false,"files":null}]}]}"#
+            )
+        );
+    }
 }
@@ -15,7 +15,9 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use crate::msg;
 use crate::parser::lex::sre::{lex_address, Token};
+use crate::shell::pretty::*;
 use crate::util::{BufReadChars, ParseError};
 use std::cell::RefCell;
 use std::iter::Peekable;
@@ -35,6 +37,10 @@ pub enum SimpleAddress {
     Comma,
     Semicolon,
     Dollar,
+    /// `'`, the range that the `k` command marked.
+    Mark,
+    /// `"`, the dot before the last command that moved it.
+    PreviousDot,
 }
 
 impl Default for SimpleAddress {
@@ -103,6 +109,68 @@ impl ComposedAddress {
     }
 }
 
+impl SimpleAddress {
+    /// The name of the kind of address, and its argument.
+    fn describe(&self) -> (&'static str, Option<String>) {
+        match self {
+            SimpleAddress::Nothing => ("nothing", None),
+            SimpleAddress::Char(n) => ("char", Some(n.to_string())),
+            SimpleAddress::Line(n) => ("line", Some(n.to_string())),
+            // the delimiter is kept in front of the regex
+            SimpleAddress::Regex(re, false) => ("regex", re.get(1..).map(|re| re.to_owned())),
+            SimpleAddress::Regex(re, true) => {
+                ("backwards_regex", re.get(1..).map(|re| re.to_owned()))
+            }
+            SimpleAddress::Dot => ("dot", None),
+            SimpleAddress::Plus => ("plus", None),
+            SimpleAddress::Minus => ("minus", None),
+            SimpleAddress::Comma => ("comma", None),
+            SimpleAddress::Semicolon => ("semicolon", None),
+            SimpleAddress::Dollar => ("dollar", None),
+            SimpleAddress::Mark => ("mark", None),
+            SimpleAddress::PreviousDot => ("previous_dot", None),
+        }
+    }
+}
+
+impl PrettyPrint for ComposedAddress {
+    fn pretty_print(&self) -> PrettyTree {
+        let (kind, arg) = self.simple.describe();
+        let mut children = Vec::new();
+        for (name, addr) in &[("left", &self.left), ("next", &self.next)] {
+            if let Some(addr) = addr {
+                let tree = addr.pretty_print();
+                children.push(PrettyTree {
+                    text: format!("{} - {}", name, tree.text),
+                    children: tree.children,
+                });
+            }
+        }
+        PrettyTree {
+            text: match arg {
+                Some(arg) => format!("address {} {}", kind, arg),
+                None => format!("address {}", kind),
+            },
+            children,
+        }
+    }
+}
+
+impl ToJson for ComposedAddress {
+    fn to_json(&self) -> String {
+        let (kind, arg) = self.simple.describe();
+        let mut fields = Vec::new();
+        match (&self.simple, arg) {
+            (SimpleAddress::Regex(..), Some(re)) => fields.push(("regex", re.to_json())),
+            (_, Some(n)) => fields.push(("value", n)),
+            (_, None) => {}
+        }
+        fields.push(("left", self.left.to_json()));
+        fields.push(("next", self.next.to_json()));
+        json_object(kind, fields)
+    }
+}
+
 impl AddressSet {
     fn new() -> AddressSet {
         AddressSet {
@@ -198,7 +266,7 @@ impl<I: Iterator<Item = Token>> Parser<I> {
             && is_low_precedence(&self.addr_set.get(addr.next.unwrap()).simple)
             && self.addr_set.get(addr.next.unwrap()).left.is_none()
         {
-            Err("Eaddress".to_owned())
+            Err(msg!("parse.sre-bad-address"))
         } else {
             Ok(Some(self.fill_defaults(self.addr_set.add(addr))))
         }
@@ -215,6 +283,8 @@ impl<I: Iterator<Item = Token>> Parser<I> {
                 Some(Token::BackwardsRegexp(re)) => SimpleAddress::Regex(re.clone(), true),
                 Some(Token::Dot) => SimpleAddress::Dot,
                 Some(Token::Dollar) => SimpleAddress::Dollar,
+                Some(Token::Mark) => SimpleAddress::Mark,
+                Some(Token::PreviousDot) => SimpleAddress::PreviousDot,
                 Some(Token::Plus) => SimpleAddress::Plus,
                 Some(Token::Minus) => SimpleAddress::Minus,
                 _ => return None,
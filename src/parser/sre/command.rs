@@ -16,8 +16,10 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 use super::{skip_whitespace, Command};
+use crate::msg;
 use crate::parser::lex::LexMode;
-use crate::parser::{Parser, Word};
+use crate::parser::{Parser, RawWord, Word};
+use crate::sre::commands::Matches;
 use crate::util::ParseError;
 
 fn arg_nr(name: char) -> i32 {
@@ -26,6 +28,10 @@ fn arg_nr(name: char) -> i32 {
 
         'a' | 'c' | 'i' => 1,
         'd' => 0,
+        'k' => 0,
+        'n' => 0,
+        'r' | 'w' => 1,
+        '|' | '<' | '>' => 1,
 
         'g' | 'v' => 1,
         'x' | 'y' => 1,
@@ -43,14 +49,36 @@ fn has_regex_argument(name: char) -> bool {
     s.binary_search(&name).is_ok()
 }
 
+/// The file of `r` and `w`, and the command line of `|`, `<` and `>`, are words like in
+/// a command line, so that they can end the line without a delimiter.
+fn has_word_argument(name: char) -> bool {
+    let s = ['<', '>', 'r', 'w', '|'];
+    s.binary_search(&name).is_ok()
+}
+
 fn has_command_argument(name: char) -> bool {
     let s = ['g', 'v', 'x', 'y'];
     s.binary_search(&name).is_ok()
 }
 
-fn read_arg(p: &mut Parser) -> Result<Word, ParseError> {
+/// Any punctuation except the backslash can delimit arguments, like `a|path/to/file|`.
+fn is_delimiter(c: char) -> bool {
+    c.is_ascii_punctuation() && c != '\\'
+}
+
+/// Tells whether only blanks are left on the line, skipping them.
+fn ends_line(p: &mut Parser) -> bool {
+    skip_whitespace(p, true);
+    if let Some('\n') | None = p.peek_char() {
+        true
+    } else {
+        false
+    }
+}
+
+fn read_arg(p: &mut Parser, delim: char) -> Result<Word, ParseError> {
     skip_whitespace(p, true);
-    p.parse_word_delimited('/')
+    p.parse_word_delimited(delim)
 }
 
 fn read_regex_arg(p: &mut Parser) -> Result<Word, ParseError> {
@@ -58,9 +86,37 @@ fn read_regex_arg(p: &mut Parser) -> Result<Word, ParseError> {
     p.parse_word_pattern(false)
 }
 
+fn read_number(p: &mut Parser) -> String {
+    let mut s = String::new();
+    while let Some(c) = p.peek_char().filter(char::is_ascii_digit) {
+        s.push(c);
+        p.next_char();
+    }
+    s
+}
+
+/// Reads the matches that `x` and `y` run their command on, written right after the
+/// regex: `2` is the second match, `2,4` the second to the fourth, and `2,` all from
+/// the second on. With a blank before it, a number is the address of the command instead.
+fn read_matches_arg(p: &mut Parser) -> Result<Option<Word>, ParseError> {
+    let mut s = read_number(p);
+    if s.is_empty() {
+        return Ok(None);
+    }
+    if p.peek_char() == Some(',') {
+        p.next_char();
+        s.push(',');
+        s.push_str(&read_number(p));
+    }
+    if Matches::parse(&s).is_none() {
+        return Err(p.new_error(msg!("parse.sre-bad-matches", s)));
+    }
+    Ok(Some(RawWord::String(s, true).into()))
+}
+
 #[derive(Debug, PartialEq)]
 /// A simple command is a command without any address.
-/// It has a list of slash-delimited arguments and an optional command argument, for commands such as `x` and `g`,
+/// It has a list of delimited arguments and an optional command argument, for commands such as `x` and `g`,
 /// that do an action (the command) based on a condition.
 pub struct SimpleCommand {
     pub name: char,
@@ -76,37 +132,61 @@ pub fn parse_command(p: &mut Parser, brace: bool) -> Result<Option<SimpleCommand
     let nr = chr.map_or(-1, arg_nr);
     let mut args = Vec::new();
     match chr {
+        Some('i') if !brace && ends_line(p) => Ok(Some(SimpleCommand {
+            name: 'i',
+            args,
+            command_args: Vec::new(),
+        })),
+        Some(name) if has_word_argument(name) => match p.parse_word_list() {
+            Some(Ok(arg)) => Ok(Some(SimpleCommand {
+                name,
+                args: vec![arg],
+                command_args: Vec::new(),
+            })),
+            Some(Err(e)) => Err(e),
+            None if name == 'r' || name == 'w' => {
+                Err(p.new_error(msg!("parse.sre-missing-file", name)))
+            }
+            None => Err(p.new_error(msg!("parse.sre-missing-command-line", name))),
+        },
         Some(name) if nr != -1 => {
+            if nr > 0 {
+                skip_whitespace(p, true);
+            }
+            // the first character after the command delimits its arguments
+            let delim = p.peek_char().filter(|&c| is_delimiter(c)).unwrap_or('/');
+            p.lexer.borrow_mut().delimiter = delim;
             p.lexer.borrow_mut().mode.insert(LexMode::SLASH);
             let mut i = 0;
-            while i < nr && p.peek_char() == Some('/') {
+            while i < nr && p.peek_char() == Some(delim) {
                 let arg = if i == 0 && has_regex_argument(name) {
                     read_regex_arg(p)?
                 } else {
-                    read_arg(p)?
+                    read_arg(p, delim)?
                 };
                 args.push(arg);
                 i += 1;
             }
             p.lexer.borrow_mut().mode.remove(LexMode::SLASH);
+            p.lexer.borrow_mut().delimiter = '/';
             if i < nr {
                 let peek = p.peek_char();
                 if peek.is_none() {
-                    Err(p.new_error("unexpected EOF when reading argument".to_owned()))
+                    Err(p.new_error(msg!("parse.sre-unexpected-arg-eof")))
                 } else {
-                    Err(p.new_error(format!(
-                        "unexpected character '{}' when reading argument",
-                        peek.unwrap()
-                    )))
+                    Err(p.new_error(msg!("parse.sre-unexpected-arg-char", peek.unwrap())))
                 }
             } else {
                 if nr > 0 {
-                    if let Some('/') = p.peek_char() {
+                    if p.peek_char() == Some(delim) {
                         p.next_char();
                     } else {
-                        return Err(p.new_error("missing terminal '/' in parameter".to_owned()));
+                        return Err(p.new_error(msg!("parse.sre-missing-delimiter", delim)));
                     }
                 }
+                if let 'x' | 'y' = name {
+                    args.extend(read_matches_arg(p)?);
+                }
                 let command_args = if has_command_argument(name) {
                     p.ps2_enter(format!("{}", name));
                     let r = vec![super::parse_command(p, false)?.unwrap()];
@@ -142,11 +222,8 @@ pub fn parse_command(p: &mut Parser, brace: bool) -> Result<Option<SimpleCommand
             }
         }
         Some('}') if brace => Ok(None),
-        Some(c) => Err(p.new_error(format!(
-            "unexpected character '{}' when reading command name",
-            c
-        ))),
-        None => Err(p.new_error("unexpected EOF when reading command".to_owned())),
+        Some(c) => Err(p.new_error(msg!("parse.sre-unexpected-command-char", c))),
+        None => Err(p.new_error(msg!("parse.sre-unexpected-command-eof"))),
     }
 }
 
@@ -154,6 +231,7 @@ pub fn parse_command(p: &mut Parser, brace: bool) -> Result<Option<SimpleCommand
 mod tests {
     use crate::parser::{Parser, RawWord};
     use crate::tests::common::new_dummy_buf;
+    use crate::util::ParseError;
 
     macro_rules! word {
         ($s:expr) => {
@@ -185,6 +263,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn other_delimiters() {
+        assert_eq!(
+            super::parse_command(
+                &mut Parser::new(new_dummy_buf("a|path/with/slashes|".lines())),
+                false
+            )
+            .unwrap()
+            .unwrap(),
+            super::SimpleCommand {
+                name: 'a',
+                args: vec![word!("path/with/slashes")],
+                command_args: vec![],
+            }
+        );
+        let v = super::parse_command(
+            &mut Parser::new(new_dummy_buf("x ,a/b\\,c, c#x#".lines())),
+            false,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            v.args,
+            vec![RawWord::Pattern(vec![RawWord::String("a/b,c".to_owned(), false).into()]).into()]
+        );
+        assert_eq!(v.command_args[0].string_args, vec![word!("x")]);
+        assert!(
+            super::parse_command(&mut Parser::new(new_dummy_buf("a|xd".lines())), false).is_err()
+        );
+    }
+
     #[test]
     fn command_arg() {
         let v = super::parse_command(
@@ -198,6 +307,46 @@ mod tests {
         assert!(v.command_args[0].command_args[0].command_args.is_empty());
     }
 
+    #[test]
+    fn matches_arg() {
+        fn parse(s: &'static str) -> Result<Option<super::SimpleCommand>, ParseError> {
+            super::parse_command(&mut Parser::new(new_dummy_buf(s.lines())), false)
+        }
+        let v = parse("x/a/2,3 p").unwrap().unwrap();
+        assert_eq!(v.args[1], RawWord::String("2,3".to_owned(), true).into());
+        assert_eq!(v.command_args[0].name, 'p');
+        // with a blank, the number is an address
+        let v = parse("x/a/ 2p").unwrap().unwrap();
+        assert_eq!(v.args.len(), 1);
+        assert!(parse("y/a/2, p").is_ok());
+        assert!(parse("x/a/0 p").is_err());
+        assert!(parse("x/a/3,2 p").is_err());
+    }
+
+    #[test]
+    fn word_arg() {
+        fn parse(s: &'static str) -> Result<Option<super::SimpleCommand>, ParseError> {
+            super::parse_command(&mut Parser::new(new_dummy_buf(s.lines())), false)
+        }
+        let v = parse("w /tmp/out.txt").unwrap().unwrap();
+        assert_eq!(v.name, 'w');
+        assert_eq!(
+            v.args,
+            vec![RawWord::List(
+                vec![RawWord::String("/tmp/out.txt".to_owned(), false).into()],
+                false
+            )
+            .into()]
+        );
+        assert_eq!(parse("r $file").unwrap().unwrap().args.len(), 1);
+        assert!(parse("w").is_err());
+        let v = parse("|\"tr a-z A-Z\"").unwrap().unwrap();
+        assert_eq!(v.name, '|');
+        assert_eq!(v.args.len(), 1);
+        assert_eq!(parse(">wc").unwrap().unwrap().name, '>');
+        assert!(parse("<").is_err());
+    }
+
     #[test]
     fn many_string_arguments() {
         use crate::parser::WordParameter;
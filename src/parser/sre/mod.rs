@@ -64,6 +64,9 @@ impl PrettyPrint for Command {
             })
             .collect::<Vec<_>>();
         let mut children = Vec::new();
+        if !self.original_address.is_empty() {
+            children.push(self.address.pretty_print());
+        }
         children.append(&mut string_args);
         children.append(&mut command_args);
         PrettyTree {
@@ -81,6 +84,21 @@ impl PrettyPrint for Command {
     }
 }
 
+impl ToJson for Command {
+    fn to_json(&self) -> String {
+        json_object(
+            "sre_command",
+            vec![
+                ("name", self.name.to_string().to_json()),
+                ("address", self.address.to_json()),
+                ("original_address", self.original_address.to_json()),
+                ("string_args", self.string_args.to_json()),
+                ("command_args", self.command_args.to_json()),
+            ],
+        )
+    }
+}
+
 impl PartialEq<Command> for Command {
     fn eq(&self, rhs: &Command) -> bool {
         self.address == rhs.address
@@ -106,6 +124,18 @@ impl Command {
             original_address,
         }
     }
+
+    /// An `i` without an argument, at the end of a line, starts the interactive mode.
+    pub fn is_interactive(&self) -> bool {
+        self.name == 'i' && self.string_args.is_empty()
+    }
+}
+
+impl CompleteCommand {
+    /// See [`Command::is_interactive`](struct.Command.html#method.is_interactive).
+    pub fn is_interactive(&self) -> bool {
+        self.name == 'i' && self.string_args.is_empty()
+    }
 }
 
 fn skip_whitespace(p: &mut Parser, break_at_newline: bool) {
@@ -0,0 +1,195 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Pseudoterminal management.
+//!
+//! A child can be run on a pseudoterminal, so that everything going to and from
+//! the terminal passes through the shell while the child still talks to a terminal.
+use crate::util::FdWriter;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::pty::forkpty;
+pub use nix::pty::Winsize;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg, Termios};
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{self, ForkResult, Pid};
+use std::io::Write;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set when the terminal changes size during a [`relay`](fn.relay.html).
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigwinch(_: libc::c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+/// A pseudoterminal with a child process on its slave side.
+pub struct Pty {
+    /// The master side, which the parent reads the output from and writes the input to.
+    pub master: RawFd,
+    pub child: Pid,
+}
+
+pub enum PtyFork {
+    Child,
+    Parent(Pty),
+}
+
+/// Forks a child running on a new pseudoterminal, which becomes its controlling terminal
+/// and its standard input, output and error.
+///
+/// The pseudoterminal gets the size and the attributes of the terminal `fd`, if it is one.
+pub fn fork(fd: RawFd) -> nix::Result<PtyFork> {
+    let size = window_size(fd).ok();
+    let attrs = tcgetattr(fd).ok();
+    let r = forkpty(size.as_ref(), attrs.as_ref())?;
+    Ok(match r.fork_result {
+        ForkResult::Child => PtyFork::Child,
+        ForkResult::Parent { child } => PtyFork::Parent(Pty {
+            master: r.master,
+            child,
+        }),
+    })
+}
+
+/// Returns the size of the terminal `fd`.
+pub fn window_size(fd: RawFd) -> nix::Result<Winsize> {
+    let mut size = Winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let res = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+    nix::errno::Errno::result(res).map(|_| size)
+}
+
+/// Changes the size of the terminal `fd`. The processes on it get a `SIGWINCH`.
+pub fn set_window_size(fd: RawFd, size: &Winsize) -> nix::Result<()> {
+    let res = unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, size) };
+    nix::errno::Errno::result(res).map(drop)
+}
+
+/// Keeps a terminal in raw mode, passing every byte through as it is typed.
+/// The attributes it had before are restored when dropped.
+pub struct RawMode {
+    fd: RawFd,
+    saved: Termios,
+}
+
+impl RawMode {
+    pub fn new(fd: RawFd) -> nix::Result<RawMode> {
+        let saved = tcgetattr(fd)?;
+        let mut raw = saved.clone();
+        cfmakeraw(&mut raw);
+        tcsetattr(fd, SetArg::TCSANOW, &raw)?;
+        Ok(RawMode { fd, saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tcsetattr(self.fd, SetArg::TCSANOW, &self.saved);
+    }
+}
+
+/// Which way data went through a pseudoterminal.
+#[derive(Clone, Copy)]
+pub enum Stream {
+    Input,
+    Output,
+}
+
+/// Copies `input` to the pseudoterminal and its output to `output` until the child is done
+/// with it, then returns the exit status of the child. `observe` is shown all the data copied.
+///
+/// If `input` is a terminal, it is put in raw mode meanwhile and changes of its size are
+/// passed on. When `input` ends, the child gets an end-of-file character.
+pub fn relay<F>(pty: &Pty, input: RawFd, output: RawFd, mut observe: F) -> nix::Result<i32>
+where
+    F: FnMut(Stream, &[u8]),
+{
+    let action = SigAction::new(
+        SigHandler::Handler(on_sigwinch),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    let old_action = unsafe { sigaction(Signal::SIGWINCH, &action)? };
+    let raw_mode = RawMode::new(input).ok();
+
+    let mut input_open = true;
+    let mut buf = [0u8; 4096];
+    let result = loop {
+        if RESIZED.swap(false, Ordering::Relaxed) {
+            if let Ok(size) = window_size(input) {
+                let _ = set_window_size(pty.master, &size);
+            }
+        }
+        let mut fds = vec![PollFd::new(pty.master, PollFlags::POLLIN)];
+        if input_open {
+            fds.push(PollFd::new(input, PollFlags::POLLIN));
+        }
+        match poll(&mut fds, -1) {
+            Ok(_) => {}
+            Err(nix::Error::Sys(Errno::EINTR)) => continue,
+            Err(e) => break Err(e),
+        }
+        let ready = |fd: &PollFd| !fd.revents().unwrap_or_else(PollFlags::empty).is_empty();
+
+        if ready(&fds[0]) {
+            match unistd::read(pty.master, &mut buf) {
+                // the slave side is closed once the child is done
+                Ok(0) | Err(nix::Error::Sys(Errno::EIO)) => break Ok(()),
+                Ok(n) => {
+                    let _ = FdWriter(output).write_all(&buf[..n]);
+                    observe(Stream::Output, &buf[..n]);
+                }
+                Err(nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => break Err(e),
+            }
+        }
+        if input_open && ready(&fds[1]) {
+            match unistd::read(input, &mut buf) {
+                Ok(0) => {
+                    input_open = false;
+                    // ^D, the usual end-of-file character
+                    let _ = FdWriter(pty.master).write_all(&[4]);
+                }
+                Ok(n) => {
+                    let _ = FdWriter(pty.master).write_all(&buf[..n]);
+                    observe(Stream::Input, &buf[..n]);
+                }
+                Err(nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => break Err(e),
+            }
+        }
+    };
+    drop(raw_mode);
+    let _ = unistd::close(pty.master);
+    unsafe {
+        sigaction(Signal::SIGWINCH, &old_action)?;
+    }
+    result?;
+
+    Ok(match waitpid(pty.child, None)? {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, sig, _) => 128 + sig as i32,
+        _ => 1,
+    })
+}
@@ -0,0 +1,123 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! A debugger that pauses before commands.
+//!
+//! It pauses before every command in step mode (`set -o debug`) and before the commands
+//! on the lines or with the names given to `breakpoint`. While paused, it reads
+//! commands from the terminal: debugger commands, or shell code to inspect and
+//! change variables.
+use super::{parse_code, Context};
+use crate::msg;
+use crate::util::{InteractiveLineReader, LineReader};
+use std::io::Write;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Breakpoint {
+    Line(usize),
+    /// Pauses before the commands with this name, such as calls of a function.
+    Function(String),
+}
+
+impl Breakpoint {
+    /// Returns a line breakpoint if `s` is a number, and a function breakpoint otherwise.
+    pub fn new(s: &str) -> Breakpoint {
+        match s.parse() {
+            Ok(line) => Breakpoint::Line(line),
+            Err(_) => Breakpoint::Function(s.to_owned()),
+        }
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Breakpoint::Line(line) => write!(f, "{}", line),
+            Breakpoint::Function(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Returns whether to pause before the command with this name, on this line.
+pub fn should_pause(ctx: &Context, name: &str, line: usize) -> bool {
+    let state = &ctx.state;
+    // the code run while paused isn't debugged itself
+    !state.debugging
+        && (state.config.debug
+            || state.breakpoints.contains(&Breakpoint::Line(line))
+            || state
+                .breakpoints
+                .contains(&Breakpoint::Function(name.to_owned())))
+}
+
+/// Reads debugger commands until told to go on.
+/// Lines that aren't debugger commands are run as shell code.
+/// Returns an error if the command must not run.
+pub fn pause(ctx: &mut Context, at: &str) -> Result<(), String> {
+    let _ = writeln!(ctx.stderr, "{}", msg!("debug.paused", at));
+    let mut reader = InteractiveLineReader::new();
+    reader.ps1 = msg!("debug.prompt");
+    ctx.state.debugging = true;
+    let result = loop {
+        let line = match reader.read_line() {
+            Ok(Some(line)) => line,
+            // like `continue`
+            Ok(None) => {
+                ctx.state.config.debug = false;
+                break Ok(());
+            }
+            Err(e) => break Err(e.to_string()),
+        };
+        match line.trim() {
+            "c" | "continue" => {
+                ctx.state.config.debug = false;
+                break Ok(());
+            }
+            "s" | "step" => {
+                ctx.state.config.debug = true;
+                break Ok(());
+            }
+            "a" | "abort" => break Err(msg!("debug.aborted")),
+            "h" | "help" => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("debug.help"));
+            }
+            "" => {}
+            code => run(ctx, code),
+        }
+        // `exit` was run
+        if ctx.state.exit != -1 {
+            break Ok(());
+        }
+    };
+    ctx.state.debugging = false;
+    result
+}
+
+fn run(ctx: &mut Context, code: &str) {
+    let progs = match parse_code(code) {
+        Ok(progs) => progs,
+        Err(e) => {
+            let _ = writeln!(ctx.stderr, "{}", e);
+            return;
+        }
+    };
+    for prog in progs {
+        if let Err(e) = ctx.run_program(prog) {
+            let _ = writeln!(ctx.stderr, "{}", e);
+        }
+    }
+}
@@ -15,17 +15,42 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+pub mod debug;
 pub mod pretty;
+mod process;
+mod prompt;
+#[cfg(unix)]
+pub mod record;
+pub mod theme;
+pub mod trap;
+use debug::Breakpoint;
+pub use process::{run_command, CpuTime, Process, Relay};
+use theme::{Style, Theme};
+pub use trap::Trap;
 
-use crate::parser::{Parser, Program, WordParameterBracket};
-use crate::task::{Task, TaskStatus};
-use crate::util::{BufReadChars, InteractiveLineReader, LineReader};
-use nix::sys::wait::WaitStatus;
-use nix::unistd::{self, ForkResult, Pid};
+use crate::builtin;
+use crate::msg;
+use crate::parser::{lex, Parser, Program, WordParameterBracket};
+use crate::sys::{self, ForkResult, Pid, Reaper};
+use crate::task::{FunctionCall, Task, Tracer, DEFAULT_IFS};
+use crate::util::editor::complete::{Candidate, Completer};
+use crate::util::{
+    BufReadChars, FdWriter, FileLineReader, InteractiveLineReader, LineReader, ParseError,
+};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::env;
 use std::error::Error;
+use std::fs;
+#[cfg(unix)]
+use std::fs::File;
+use std::io::{self, Cursor};
+#[cfg(unix)]
+use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::rc::Rc;
 
@@ -37,16 +62,47 @@ pub enum Fork {
 #[derive(Clone, Debug, PartialEq)]
 pub enum VarValue {
     Array(Vec<String>),
+    /// An associative array.
+    Map(HashMap<String, String>),
+    /// `true` or `false`, set with `let --bool`.
+    Bool(bool),
+    /// A variable that is set, but has no value, set with `let --null`.
+    Null,
 }
 
 impl VarValue {
-    pub fn array(&self) -> &Vec<String> {
+    /// Returns the elements. Those of a map are sorted by key. A boolean is
+    /// the word `true` or `false`, and null has no elements.
+    pub fn array(&self) -> Vec<String> {
         match self {
-            VarValue::Array(arr) => arr,
+            VarValue::Array(arr) => arr.clone(),
+            VarValue::Map(map) => sorted_entries(map).into_iter().map(|(_, v)| v).collect(),
+            VarValue::Bool(b) => vec![b.to_string()],
+            VarValue::Null => Vec::new(),
+        }
+    }
+
+    /// Returns the truth of the value: a boolean is itself and null is false.
+    /// Arrays and maps are true if they have an element that isn't empty.
+    pub fn is_true(&self) -> bool {
+        match self {
+            VarValue::Bool(b) => *b,
+            VarValue::Null => false,
+            _ => self.array().iter().any(|e| !e.is_empty()),
         }
     }
 }
 
+/// Returns the entries of a map, sorted by key.
+pub fn sorted_entries(map: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut entries = map
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Var {
     pub key: String,
@@ -75,6 +131,7 @@ impl std::fmt::Display for Var {
                 VarValue::Array(arr) => {
                     arr.join(if self.key.ends_with("PATH") { ":" } else { " " })
                 }
+                _ => self.value.array().join(" "),
             }
         )
     }
@@ -83,13 +140,50 @@ impl std::fmt::Display for Var {
 #[derive(Clone, Default)]
 /// The config options of the shell.
 pub struct Config {
-    pub pretty_print: bool,
+    /// Print the syntax tree of the code instead of running it.
+    pub ast: Option<pretty::AstFormat>,
+    /// Exit on the first failing command.
+    pub errexit: bool,
+    /// Print commands before executing them.
+    pub xtrace: bool,
+    /// Keep `errexit` in subshells, such as command substitutions and pipeline elements.
+    pub errtrace: bool,
+    /// Change to the directory given as the name of a command if no command is found.
+    pub autocd: bool,
+    /// List the contents of the new directory after each `cd`.
+    pub autols: bool,
+    /// Push the previous directory on the directory stack on each `cd`.
+    pub autopushd: bool,
+    /// Let `cd` take a prefix of a directory in the current one, if only one matches.
+    pub cd_fuzzy: bool,
+    /// Print how the name of each command is resolved.
+    pub resolve_trace: bool,
+    /// Cut the output of command substitutions at `$SUBST_MAX` bytes instead of failing.
+    pub subst_truncate: bool,
+    /// Ask before running commands that could remove too much, such as `rm -r /`.
+    pub guard: bool,
+    /// Pause in the debugger before each command.
+    pub debug: bool,
+    /// Write the long listings of builtins straight to the terminal instead of paging them.
+    pub no_pager: bool,
+    /// Don't use styles, the completion menu, the pager or progress bars. On from the
+    /// start on dumb terminals and when the output isn't a terminal.
+    pub plain: bool,
+    /// Stop a loop that runs its body more times than this, with status 1.
+    pub max_iterations: Option<usize>,
+    /// Fail a function call, with status 1, if it would nest more function calls than this.
+    pub max_function_depth: Option<usize>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Key<'a> {
     Var(&'a str),
-    Index(&'a str, usize),
+    /// An element of an array. Negative indices count from the end.
+    Index(&'a str, isize),
+    /// The element after the last one of an array, written `name[+]`.
+    Append(&'a str),
+    /// A key of an associative array.
+    Field(&'a str, &'a str),
 }
 
 impl<'a> Key<'a> {
@@ -97,15 +191,40 @@ impl<'a> Key<'a> {
         match self {
             Key::Var(name) => name,
             Key::Index(name, _) => name,
+            Key::Append(name) => name,
+            Key::Field(name, _) => name,
         }
     }
 
-    pub fn new(s: &'a str) -> Key<'a> {
-        let param = Parser::get_word_parameter(s).unwrap();
-        match param.bracket {
+    /// Parses a key like `name`, `name[2]`, `name[-1]`, `name[+]` or `name[field]`.
+    pub fn new(s: &'a str) -> Result<Key<'a>, String> {
+        let param = Parser::get_word_parameter(s).ok_or_else(|| msg!("var.bad-subscript", s))?;
+        check_var_name(&param.name)?;
+        Ok(match param.bracket {
             WordParameterBracket::None => Key::Var(&param.name),
             WordParameterBracket::Index(index) => Key::Index(&param.name, index),
-        }
+            WordParameterBracket::Key(_) => {
+                let open = param.name.len();
+                match &s[open + 1..s.len() - 1] {
+                    "+" => Key::Append(param.name),
+                    field => Key::Field(param.name, field),
+                }
+            }
+        })
+    }
+}
+
+/// Checks that the name can be given to a variable and then used as `$name`.
+pub fn check_var_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c != '[' && c != ']' && lex::is_parameter_char(c));
+    if valid {
+        Ok(())
+    } else {
+        Err(msg!("var.bad-name", name))
     }
 }
 
@@ -114,6 +233,8 @@ impl<'a> std::fmt::Display for Key<'a> {
         match self {
             Key::Var(name) => write!(f, "{}", name),
             Key::Index(name, index) => write!(f, "{}[{}]", name, index),
+            Key::Append(name) => write!(f, "{}[+]", name),
+            Key::Field(name, field) => write!(f, "{}[{}]", name, field),
         }
     }
 }
@@ -131,13 +252,51 @@ pub struct State {
     pub process: Option<Rc<RefCell<Process>>>,
     pub parser: Rc<RefCell<Parser>>,
 
-    pub exported_vars: HashMap<String, String>,
+    /// The variables given to child processes, sorted by name
+    /// so that their environment is always the same.
+    pub exported_vars: BTreeMap<String, String>,
+    /// The exported variables in `name=value` form.
     pub computed_exported_vars: Vec<String>,
+
+    /// The positional parameters. The first one is the name of the script.
+    pub args: Vec<String>,
+
+    /// How many conditions are being evaluated. `errexit` is ignored inside conditions.
+    pub condition_depth: u32,
+    /// How many function calls are running, each inside the previous one.
+    pub function_depth: usize,
+
+    /// Command names that stand for other words.
+    pub aliases: HashMap<String, Vec<String>>,
+    pub functions: HashMap<String, Program>,
+    /// The paths of the commands found in `$PATH`. Cleared when `$PATH` changes.
+    pub command_cache: HashMap<String, PathBuf>,
+    /// The directories left by `cd`, the most recent last.
+    pub dir_stack: Vec<PathBuf>,
+    pub theme: Theme,
+    /// The code to run on the conditions given to `trap`.
+    pub traps: BTreeMap<&'static str, Trap>,
+    /// Set while a `DEBUG` or `ERR` trap runs, so that its commands don't trigger traps.
+    pub in_trap: bool,
+    /// Where the debugger pauses, besides every command in step mode.
+    pub breakpoints: BTreeSet<Breakpoint>,
+    /// Set while the debugger is paused.
+    pub debugging: bool,
+    /// Logs how tasks are polled, if tracing is on.
+    pub tracer: Option<Tracer>,
+    /// Set if the commands are typed by a user, who can be asked questions.
+    pub interactive: bool,
+
+    reaper: Rc<Reaper>,
 }
 
 fn read_vars() -> HashMap<String, Var> {
     let mut v = HashMap::new();
     for (key, value) in env::vars() {
+        // like in other shells, an inherited IFS would change how every script works
+        if key == "IFS" {
+            continue;
+        }
         let var = if key.ends_with("PATH") {
             Var::new(
                 key,
@@ -152,7 +311,9 @@ fn read_vars() -> HashMap<String, Var> {
 }
 
 impl State {
-    pub fn new(config: Config, parser: Rc<RefCell<Parser>>) -> State {
+    /// Returns the state of a new shell, with the variables of the environment. Fails if
+    /// the pipe that wakes the shell when its children terminate can't be made.
+    pub fn new(config: Config, parser: Rc<RefCell<Parser>>) -> Result<State, sys::Error> {
         let vars = read_vars();
         let mut s = State {
             exit: -1,
@@ -173,36 +334,94 @@ impl State {
                 .map(|(k, v)| (k.clone(), v.to_string()))
                 .collect(),
             computed_exported_vars: Vec::new(),
+
+            args: Vec::new(),
+
+            condition_depth: 0,
+            function_depth: 0,
+
+            aliases: HashMap::new(),
+            functions: HashMap::new(),
+            command_cache: HashMap::new(),
+            dir_stack: Vec::new(),
+            theme: Theme::default(),
+            traps: BTreeMap::new(),
+            in_trap: false,
+            breakpoints: BTreeSet::new(),
+            debugging: false,
+            tracer: None,
+            interactive: false,
+
+            reaper: Rc::new(Reaper::new()?),
         };
         s.compute_exported_vars();
-        s
+        s.init_pwd();
+        Ok(s)
     }
 
-    pub fn new_process(&mut self, pid: Pid) -> Rc<RefCell<Process>> {
-        let p = Process {
-            pid,
-            terminated: false,
-            stat: WaitStatus::StillAlive,
+    /// Sets `PWD` to the working directory, unless it already names it, maybe through
+    /// symbolic links.
+    fn init_pwd(&mut self) {
+        let cwd = match env::current_dir() {
+            Ok(cwd) => cwd,
+            Err(_) => return,
         };
-        self.processes.push(Rc::new(RefCell::new(p)));
+        let valid = env::var_os("PWD").map_or(false, |pwd| {
+            let pwd = Path::new(&pwd);
+            pwd.is_absolute() && fs::canonicalize(pwd).ok() == fs::canonicalize(&cwd).ok()
+        });
+        if !valid {
+            self.set_dir_var("PWD", &cwd);
+        }
+    }
+
+    pub fn new_process(&mut self, pid: Pid) -> Rc<RefCell<Process>> {
+        self.processes
+            .push(Rc::new(RefCell::new(Process::new(pid))));
         self.processes.last().unwrap().clone()
     }
 
-    pub fn update_process(&mut self, pid: Pid, stat: WaitStatus) {
-        let p = self.processes.iter().find(|p| p.borrow().pid == pid);
-        if p.is_none() {
-            eprintln!("rwsh: warning: tried to update lost process {}", pid);
-            return; // poor process got lost
+    /// Collects the status of the terminated processes, without blocking.
+    /// Returns `true` if any process terminated.
+    pub fn reap_processes(&mut self) -> Result<bool, sys::Error> {
+        let mut reaped = false;
+        for p in &self.processes {
+            reaped = p.borrow_mut().reap()? || reaped;
         }
-        let p = p.unwrap();
+        self.forget_finished_processes();
+        Ok(reaped)
+    }
+
+    /// Drops the processes that terminated and whose status nobody holds anymore.
+    fn forget_finished_processes(&mut self) {
+        if self
+            .process
+            .as_ref()
+            .map_or(false, |p| p.borrow().terminated())
+        {
+            self.process = None;
+        }
+        self.processes
+            .retain(|p| !p.borrow().terminated() || Rc::strong_count(p) > 1);
+    }
+
+    /// Frees what the last command left behind, so that long sessions don't grow:
+    /// the processes whose status was used and the space of the collections that shrank.
+    pub fn cleanup(&mut self) {
+        self.forget_finished_processes();
+        self.processes.shrink_to_fit();
+        self.vars.shrink_to_fit();
+        self.command_cache.shrink_to_fit();
+    }
 
-        match stat {
-            WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => {
-                let mut p = p.borrow_mut();
-                p.terminated = true;
-                p.stat = stat;
+    /// Blocks until at least one of the running processes terminates.
+    /// Returns immediately if there are no running processes.
+    pub fn wait_for_processes(&mut self) -> Result<(), sys::Error> {
+        loop {
+            if self.reap_processes()? || self.processes.iter().all(|p| p.borrow().terminated()) {
+                return Ok(());
             }
-            _ => {}
+            self.reaper.wait()?;
         }
     }
 
@@ -220,6 +439,9 @@ impl State {
     /// as the exported variable does not change the exported variable's value.
     /// They are two, independent variables.
     pub fn export_var(&mut self, key: String, value: String) {
+        if key == "PATH" {
+            self.command_cache.clear();
+        }
         self.exported_vars.insert(key, value);
         self.compute_exported_vars();
     }
@@ -227,16 +449,82 @@ impl State {
     /// Removes a value from the exported set.
     /// If there's a normal variable with the same name, it will remain available.
     pub fn unexport_var(&mut self, key: &str) {
+        if key == "PATH" {
+            self.command_cache.clear();
+        }
         self.exported_vars.remove(key);
         self.compute_exported_vars()
     }
 
+    /// Changes the working directory and updates `$PWD` and `$OLDPWD` to match.
+    /// Returns the directory that was left, if it is known.
+    pub fn chdir(&mut self, dir: &Path) -> io::Result<Option<PathBuf>> {
+        let old = env::current_dir().ok();
+        env::set_current_dir(dir)?;
+        if let Some(old) = &old {
+            self.set_dir_var("OLDPWD", old);
+        }
+        if let Ok(new) = env::current_dir() {
+            self.set_dir_var("PWD", &new);
+        }
+        Ok(old)
+    }
+
+    /// Sets both the shell variable and the exported variable, so that commands see the change too.
+    fn set_dir_var(&mut self, name: &str, dir: &Path) {
+        let dir = dir.display().to_string();
+        let var = Var::new(name.to_owned(), VarValue::Array(vec![dir.clone()]));
+        self.set_var(Key::Var(name), var, false);
+        self.export_var(name.to_owned(), dir);
+    }
+
+    /// Resolves the key of an array element to set to a positive index.
+    /// Negative indices count from the end and `name[+]` is the element after the last one.
+    /// An index past that is an error, unless `fill` is true: the gap is then filled with empty elements.
+    /// Keys of maps and whole variables are returned unchanged.
+    pub fn resolve_key<'a>(&self, key: Key<'a>, fill: bool) -> Result<Key<'a>, String> {
+        let arr = match self.get_var(Key::Var(key.name())).map(|var| var.value) {
+            Some(VarValue::Map(_)) => match key {
+                Key::Append(name) => return Err(msg!("var.append-to-map", name)),
+                _ => return Ok(key),
+            },
+            Some(value) => value.array(),
+            None => Vec::new(),
+        };
+        let len = arr.len() as isize;
+        match key {
+            Key::Index(name, index) if index < 0 => {
+                if len + index < 0 {
+                    Err(msg!("var.index-out-of-range", key, name, len))
+                } else {
+                    Ok(Key::Index(name, len + index))
+                }
+            }
+            Key::Index(name, index) if index > len && !fill => {
+                Err(msg!("var.index-out-of-range", key, name, len))
+            }
+            Key::Append(name) => Ok(Key::Index(name, len)),
+            _ => Ok(key),
+        }
+    }
+
     /// Sets a variable's name. The variable is created in the current scope if it doesn't exit,
     /// or if `create_new` is true. If `create_new` is `false`, and the variable's scope is a parent
     /// of the current scope, the variable's value will be set to `value`.
     /// If `create_new` is `true` and the variable already exists in the scope, it will not be created again either.
     /// Only its value will change.
+    ///
+    /// The key is resolved with [`resolve_key`](State::resolve_key), filling gaps.
+    /// Keys that can't be resolved are ignored, so builtins setting keys given by the user
+    /// should resolve them first to report the error.
     pub fn set_var(&mut self, key: Key, mut value: Var, mut create_new: bool) {
+        let key = match self.resolve_key(key, true) {
+            Ok(key) => key,
+            Err(_) => return,
+        };
+        if key.name() == "PATH" {
+            self.command_cache.clear();
+        }
         if !self.vars.contains_key(key.name()) {
             self.vars.insert(key.name().to_owned(), Vec::new());
         }
@@ -248,28 +536,78 @@ impl State {
             create_new = false;
         }
         let current = if create_new {
-            Var::new(key.name().to_owned(), VarValue::Array(vec![]))
+            let empty = match key {
+                Key::Field(_, _) => VarValue::Map(HashMap::new()),
+                _ => VarValue::Array(vec![]),
+            };
+            Var::new(key.name().to_owned(), empty)
         } else {
             v.last().unwrap().clone().0
         };
-        match current.value {
-            VarValue::Array(arr) => {
-                if let Key::Index(_, index) = key {
-                    let mut new_value = arr.clone();
-                    if index >= new_value.len() {
-                        new_value.resize(index + 1, String::new());
-                    }
-                    new_value[index] = value.value.array().get(0).cloned().unwrap_or_default();
-
-                    value = Var::new(key.name().to_owned(), VarValue::Array(new_value))
-                };
-                if create_new {
-                    v.push((value, self.scope));
-                } else {
-                    let scope = v.last().unwrap().1;
-                    *v.last_mut().unwrap() = (value, scope);
+        // an element is set to the first element of the value
+        let elem = || value.value.array().into_iter().next().unwrap_or_default();
+        // the elements of booleans and null can be set like those of an array
+        let current = match current.value {
+            VarValue::Bool(_) | VarValue::Null => VarValue::Array(current.value.array()),
+            value => value,
+        };
+        let new_value = match (key, current) {
+            (Key::Var(_), _) => None,
+            (Key::Index(_, index), VarValue::Array(mut arr)) => {
+                let index = index as usize;
+                if index >= arr.len() {
+                    arr.resize(index + 1, String::new());
                 }
+                arr[index] = elem();
+                Some(VarValue::Array(arr))
+            }
+            (Key::Index(_, index), VarValue::Map(mut map)) => {
+                map.insert(index.to_string(), elem());
+                Some(VarValue::Map(map))
+            }
+            (Key::Index(_, _), _) | (Key::Append(_), _) => {
+                unreachable!("resolve_key turns appends into indices")
+            }
+            (Key::Field(_, field), VarValue::Map(mut map)) => {
+                map.insert(field.to_owned(), elem());
+                Some(VarValue::Map(map))
+            }
+            // an array becomes a map with the indices as keys
+            (Key::Field(_, field), value) => {
+                let mut map = value
+                    .array()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, v)| (i.to_string(), v))
+                    .collect::<HashMap<_, _>>();
+                map.insert(field.to_owned(), elem());
+                Some(VarValue::Map(map))
             }
+        };
+        if let Some(new_value) = new_value {
+            value = Var::new(key.name().to_owned(), new_value);
+        }
+        if create_new {
+            v.push((value, self.scope));
+        } else {
+            let scope = v.last().unwrap().1;
+            *v.last_mut().unwrap() = (value, scope);
+        }
+    }
+
+    /// Removes a key from an associative array.
+    /// Returns `false` if the variable is not an associative array.
+    pub fn remove_field(&mut self, name: &str, field: &str) -> bool {
+        match self.get_var(Key::Var(name)) {
+            Some(Var {
+                key,
+                value: VarValue::Map(mut map),
+            }) => {
+                map.remove(field);
+                self.set_var(Key::Var(name), Var::new(key, VarValue::Map(map)), false);
+                true
+            }
+            _ => false,
         }
     }
 
@@ -277,6 +615,9 @@ impl State {
     /// it is only masked: a new variable with the same name but in the current scope
     /// is created with a null value.
     pub fn remove_var(&mut self, key: &str) {
+        if key == "PATH" {
+            self.command_cache.clear();
+        }
         if self.scope == 0 {
             self.vars.remove(key);
         } else if self.vars.contains_key(key) {
@@ -300,24 +641,80 @@ impl State {
                     .cloned()
                     .map(|ex| Var::new(name.to_owned(), VarValue::Array(vec![ex])))
             }),
-            Key::Index(_, index) => match var {
-                Some(var) => match var.value {
-                    VarValue::Array(arr) => arr.get(index).cloned().map(|el| {
-                        Var::new(format!("{}[{}]", name, index), VarValue::Array(vec![el]))
-                    }),
-                },
-                None => None,
+            Key::Index(_, index) => {
+                let el = match var?.value {
+                    VarValue::Map(map) => map.get(&index.to_string()).cloned(),
+                    value => {
+                        let arr = value.array();
+                        let index = if index < 0 {
+                            arr.len() as isize + index
+                        } else {
+                            index
+                        };
+                        if index < 0 {
+                            None
+                        } else {
+                            arr.get(index as usize).cloned()
+                        }
+                    }
+                };
+                el.map(|el| Var::new(key.to_string(), VarValue::Array(vec![el])))
+            }
+            Key::Append(_) => None,
+            Key::Field(_, field) => match var?.value {
+                VarValue::Map(map) => map
+                    .get(field)
+                    .cloned()
+                    .map(|el| Var::new(key.to_string(), VarValue::Array(vec![el]))),
+                _ => None,
             },
         }
     }
 
+    /// Returns the characters that split fields: the value of `IFS`, or space, tab
+    /// and newline if it isn't set.
+    pub fn ifs(&self) -> String {
+        match self.get_var(Key::Var("IFS")) {
+            Some(var) => var.value.array().join(""),
+            None => DEFAULT_IFS.to_owned(),
+        }
+    }
+
+    /// Finds the executable that would run for the command name, searching `$PATH`
+    /// unless the name contains a slash.
+    pub fn find_command(&mut self, name: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            let p = PathBuf::from(name);
+            return if sys::is_executable(&p) {
+                Some(p)
+            } else {
+                None
+            };
+        }
+        if let Some(p) = self.command_cache.get(name) {
+            return Some(p.clone());
+        }
+        let dirs = match self.get_var(Key::Var("PATH")) {
+            Some(var) => var.value.array(),
+            None => return None,
+        };
+        let p = dirs
+            .iter()
+            .filter(|d| !d.is_empty())
+            .map(|d| Path::new(d).join(name))
+            .find(|p| sys::is_executable(p))?;
+        self.command_cache.insert(name.to_owned(), p.clone());
+        Some(p)
+    }
+
     pub fn fork(&mut self) -> Result<Fork, Box<Error>> {
-        let fr = unistd::fork()?;
+        let fr = sys::fork()?;
         match fr {
             ForkResult::Child => {
                 // Get rid of opened files.
                 // This should be only the current script, if any.
                 self.parser.borrow_mut().blindfold();
+                self.enter_subshell();
                 Ok(Fork::Child)
             }
             ForkResult::Parent { child: pid, .. } => {
@@ -327,6 +724,210 @@ impl State {
         }
     }
 
+    /// Adjusts the options of a forked child that keeps running shell code.
+    ///
+    /// `xtrace` is always inherited, while `errexit` is inherited only if `errtrace` is set.
+    pub fn enter_subshell(&mut self) {
+        if !self.config.errtrace {
+            self.config.errexit = false;
+        }
+        // the children and the traps of the parent aren't ours
+        for name in std::mem::take(&mut self.traps).keys() {
+            let _ = trap::handle(name, None);
+        }
+        self.processes.clear();
+        self.process = None;
+        self.reaper = Rc::new(Reaper::reinit().unwrap());
+    }
+
+    /// Makes the shell exit with `status` if `errexit` is set and the failing command
+    /// isn't part of a condition.
+    pub fn check_errexit(&mut self, status: i32) {
+        if status != 0 && self.config.errexit && self.condition_depth == 0 && self.exit == -1 {
+            self.exit = status;
+        }
+    }
+
+    /// Sets the trap of a condition, or removes it if `trap` is `None`.
+    pub fn set_trap(&mut self, name: &'static str, trap: Option<Trap>) -> Result<(), sys::Error> {
+        trap::handle(name, trap.as_ref())?;
+        match trap {
+            Some(trap) => self.traps.insert(name, trap),
+            None => self.traps.remove(name),
+        };
+        Ok(())
+    }
+
+    /// Runs the traps of the signals that arrived since the last call.
+    /// `$?` is left as it was.
+    pub fn run_pending_traps(&mut self) {
+        for name in trap::take_pending() {
+            if let Some(trap) = self.traps.get(name).cloned() {
+                let status = self.last_status;
+                self.run_trap(&trap);
+                self.last_status = status;
+            }
+        }
+    }
+
+    /// Runs the `EXIT` trap, once. `$?` is the status the shell exits with,
+    /// unless the trap calls `exit` itself.
+    pub fn run_exit_trap(&mut self) {
+        let trap = match self.traps.remove("EXIT") {
+            Some(trap) => trap,
+            None => return,
+        };
+        let status = self.exit;
+        // the trap wouldn't run past its first command otherwise
+        self.exit = -1;
+        self.last_status = status;
+        self.run_trap(&trap);
+        if self.exit == -1 {
+            self.exit = status;
+        }
+    }
+
+    /// Runs the `DEBUG` trap, before a command. `$?` is left as it was.
+    pub fn run_debug_trap(&mut self) {
+        let status = self.last_status;
+        self.run_command_trap("DEBUG");
+        self.last_status = status;
+    }
+
+    /// Runs the `ERR` trap after a command failed with `status`, unless the command
+    /// is part of a condition. `$?` is `status` inside the trap.
+    pub fn run_err_trap(&mut self, status: i32) {
+        if status == 0 || self.condition_depth != 0 {
+            return;
+        }
+        self.last_status = status;
+        self.run_command_trap("ERR");
+        self.last_status = status;
+    }
+
+    fn run_command_trap(&mut self, name: &str) {
+        if self.in_trap || self.exit != -1 {
+            return;
+        }
+        if let Some(trap) = self.traps.get(name).cloned() {
+            self.in_trap = true;
+            self.run_trap(&trap);
+            self.in_trap = false;
+        }
+    }
+
+    fn run_trap(&mut self, trap: &Trap) {
+        for prog in &trap.body {
+            if let Err(error) = run_program(prog.clone(), self) {
+                self.print_error(&error);
+            }
+            if self.exit != -1 {
+                break;
+            }
+        }
+    }
+
+    /// Calls the function `name` with `args`, if it is defined. `$?` is left as it was.
+    pub fn run_hook(&mut self, name: &str, args: Vec<String>) {
+        let body = match self.functions.get(name) {
+            Some(body) => body.clone(),
+            None => return,
+        };
+        let status = self.last_status;
+        let mut call_args = vec![name.to_owned()];
+        call_args.extend(args);
+        let mut task = Task::new(Box::new(FunctionCall::new(body, call_args, None)));
+        let mut ctx = Context {
+            state: self,
+            in_pipe: false,
+            stdout: FdWriter(1),
+            stderr: FdWriter(2),
+        };
+        if let Err(error) = task.run(&mut ctx) {
+            self.print_error(&error);
+        }
+        self.last_status = status;
+    }
+
+    /// Returns the style of `role` in the theme, or no style with the `plain` option.
+    pub fn style(&self, role: &str) -> Style {
+        if self.config.plain {
+            Style::default()
+        } else {
+            self.theme.get(role).clone()
+        }
+    }
+
+    /// Prints an error about a command, in the style of the `error` role of the theme
+    /// if the standard error is a terminal.
+    pub fn print_error(&self, error: &dyn std::fmt::Display) {
+        let error = error.to_string();
+        if sys::isatty(2) {
+            eprintln!("{}", self.style("error").paint(&error));
+        } else {
+            eprintln!("{}", error);
+        }
+    }
+
+    /// Returns a completer for the interactive line editor, with the commands and
+    /// variables that are defined now.
+    pub fn completer(&self) -> Completer {
+        const PREVIEW: usize = 40;
+        let mut commands = builtin::names()
+            .map(|name| {
+                Candidate::new(
+                    name.to_owned(),
+                    msg::format(&format!("{}.summary", name), &[]),
+                )
+            })
+            .collect::<Vec<_>>();
+        commands.extend(
+            self.functions
+                .keys()
+                .map(|name| Candidate::new(name.clone(), msg!("complete.function"))),
+        );
+        commands.extend(self.aliases.iter().map(|(name, words)| {
+            Candidate::new(name.clone(), msg!("complete.alias", words.join(" ")))
+        }));
+        let names = self
+            .vars
+            .keys()
+            .chain(self.exported_vars.keys())
+            .collect::<BTreeSet<_>>();
+        let vars = names
+            .into_iter()
+            .filter_map(|name| {
+                let value = self.get_var(Key::Var(name))?.to_string();
+                let mut preview = value.lines().next().unwrap_or_default().to_owned();
+                if preview.chars().count() > PREVIEW || preview.len() < value.len() {
+                    preview = preview.chars().take(PREVIEW).collect::<String>() + "…";
+                }
+                Some(Candidate::new(name.clone(), preview))
+            })
+            .collect();
+        let path = match self.get_var(Key::Var("PATH")) {
+            Some(var) => var.value.array(),
+            None => vec![],
+        };
+        let mut completer = Completer::new(commands, vars, path);
+        completer.description_style = self.theme.get("completion.description").clone();
+        completer.selected_style = self.theme.get("completion.selected").clone();
+        completer
+    }
+
+    /// Brings the state back to the top level after a command was stopped by a panic.
+    pub fn recover(&mut self) {
+        while self.scope > 0 {
+            self.end_scope();
+        }
+        self.condition_depth = 0;
+        self.function_depth = 0;
+        self.if_condition_ok = None;
+        self.in_trap = false;
+        self.debugging = false;
+        self.last_status = 1;
+    }
+
     pub fn begin_scope(&mut self) {
         self.scope += 1;
     }
@@ -350,34 +951,17 @@ impl State {
     }
 }
 
-#[derive(Clone)]
-pub struct Process {
-    pub pid: Pid,
-    pub terminated: bool,
-    pub stat: WaitStatus,
-}
-
-impl Process {
-    pub fn poll(&mut self) -> Result<TaskStatus, String> {
-        if !self.terminated {
-            Ok(TaskStatus::Wait)
-        } else {
-            match self.stat {
-                WaitStatus::Exited(_, code) => Ok(TaskStatus::Success(code)),
-                WaitStatus::Signaled(_, sig, _) => Ok(TaskStatus::Success(
-                    128 + unsafe { std::mem::transmute::<nix::sys::signal::Signal, i32>(sig) },
-                )),
-                _ => panic!(),
-            }
-        }
-    }
-}
-
 /// A context holds state information and per-job information.
 /// It is guaranteed to be shared across all members of a job.
 pub struct Context<'a> {
     pub state: &'a mut State,
+    /// Set while running in a forked pipeline element.
+    /// Builtins can't change the state of the shell from there.
     pub in_pipe: bool,
+    /// Where builtins write their output.
+    pub stdout: FdWriter,
+    /// Where builtins write their errors.
+    pub stderr: FdWriter,
 }
 
 impl<'a> Context<'a> {
@@ -391,9 +975,59 @@ impl<'a> Context<'a> {
                 "?".to_owned(),
                 VarValue::Array(vec![self.state.last_status.to_string()]),
             )),
+            "#" => Some(Var::new(
+                "#".to_owned(),
+                VarValue::Array(vec![self.state.args.len().saturating_sub(1).to_string()]),
+            )),
+            "@" | "*" => Some(Var::new(
+                key.name().to_owned(),
+                VarValue::Array(self.state.args.iter().skip(1).cloned().collect()),
+            )),
+            name if name.bytes().all(|b| b.is_ascii_digit()) => {
+                let i = name.parse::<usize>().ok()?;
+                self.state
+                    .args
+                    .get(i)
+                    .map(|arg| Var::new(name.to_owned(), VarValue::Array(vec![arg.clone()])))
+            }
             _ => self.state.get_var(key),
         }
     }
+
+    /// Runs a program in the state of the shell, writing to the same standard output
+    /// and error as the context. Builtins run their bodies with it.
+    pub fn run_program(&mut self, p: Program) -> Result<i32, Box<dyn Error>> {
+        let (status, _) = run_program_with(p, self.state, self.stdout, self.stderr)?;
+        Ok(status)
+    }
+
+    /// Makes the standard output and error of a forked child those of the context.
+    /// They are other files than the descriptors 1 and 2 when they were redirected,
+    /// or captured by [`Shell::eval_capture`](struct.Shell.html#method.eval_capture).
+    pub fn redirect_child(&mut self) -> Result<(), sys::Error> {
+        sys::set_outputs(self.stdout.0, self.stderr.0)?;
+        self.stdout = FdWriter(1);
+        self.stderr = FdWriter(2);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq)]
+/// What code run by [`Shell::eval_capture`](struct.Shell.html#method.eval_capture) did.
+pub struct EvalResult {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[cfg(unix)]
+fn read_capture(mut f: File) -> String {
+    let mut buf = Vec::new();
+    let _ = f
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| f.read_to_end(&mut buf));
+    String::from_utf8_lossy(&buf).into_owned()
 }
 
 /// The shell engine with its internal state.
@@ -402,64 +1036,298 @@ impl<'a> Context<'a> {
 pub struct Shell {
     p: Rc<RefCell<Parser>>,
     state: State,
-    interactive: bool,
+}
+
+/// Returns the file that login shells run at the start.
+pub fn profile_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".rwsh_profile"))
 }
 
 impl Shell {
     /// Create a new `Shell` with an [`InteractiveLineReader`](../util/struct.InteractiveLineReader.html).
-    pub fn new_interactive(config: Config) -> Shell {
+    pub fn new_interactive(config: Config) -> Result<Shell, sys::Error> {
         Self::new(Box::new(InteractiveLineReader::new()), config, true)
     }
 
     /// Returns a new `Shell` with the given [`LineReader`](../util/trait.LineReader.html).
-    pub fn new(r: Box<LineReader>, config: Config, interactive: bool) -> Shell {
+    pub fn new(r: Box<LineReader>, config: Config, interactive: bool) -> Result<Shell, sys::Error> {
         let buf = BufReadChars::new(r);
         let p = Rc::new(RefCell::new(Parser::new(buf)));
-        Shell {
-            p: p.clone(),
-            state: State::new(config, p.clone()),
-            interactive,
+        let mut state = State::new(config, p.clone())?;
+        state.interactive = interactive;
+        Ok(Shell { p, state })
+    }
+
+    /// Returns a `Shell` that reads no input and only runs the code given to
+    /// [`eval`](#method.eval), for programs that embed the shell.
+    pub fn new_embedded(config: Config) -> Result<Shell, sys::Error> {
+        let reader = FileLineReader::new(Cursor::new(String::new())).unwrap();
+        Self::new(Box::new(reader), config, false)
+    }
+
+    /// Runs the code and returns the status of its last command, or the status given
+    /// to `exit`. Unlike [`run`](#method.run), the process doesn't exit.
+    pub fn eval(&mut self, code: &str) -> Result<i32, String> {
+        self.eval_to(code, FdWriter(1), FdWriter(2))
+    }
+
+    /// Runs the code like [`eval`](#method.eval), writing to `stdout` and `stderr`.
+    fn eval_to(&mut self, code: &str, stdout: FdWriter, stderr: FdWriter) -> Result<i32, String> {
+        for p in parse_code(code)? {
+            if p.0.is_empty() {
+                continue;
+            }
+            run_program_with(p, &mut self.state, stdout, stderr).map_err(|e| e.to_string())?;
+            self.state.cleanup();
+            if self.state.exit != -1 {
+                return Ok(self.state.exit);
+            }
+        }
+        Ok(self.state.last_status)
+    }
+
+    /// Like [`eval`](#method.eval), but collects what the code writes to the standard
+    /// output and error, of builtins and processes alike. Errors are written to the
+    /// collected standard error, with the status 1.
+    #[cfg(unix)]
+    pub fn eval_capture(&mut self, code: &str) -> EvalResult {
+        let (out, err) = match (sys::temp_file(), sys::temp_file()) {
+            (Ok(out), Ok(err)) => (out, err),
+            (Err(e), _) | (_, Err(e)) => {
+                return EvalResult {
+                    status: 1,
+                    stdout: String::new(),
+                    stderr: msg!("eval.capture-error", e),
+                }
+            }
+        };
+        // only the children write to the descriptors 1 and 2, those of the shell are left alone
+        let (stdout, stderr) = (FdWriter(out.as_raw_fd()), FdWriter(err.as_raw_fd()));
+        let status = match self.eval_to(code, stdout, stderr) {
+            Ok(status) => status,
+            Err(e) => {
+                let _ = writeln!(FdWriter(err.as_raw_fd()), "{}", e);
+                1
+            }
+        };
+        EvalResult {
+            status,
+            stdout: read_capture(out),
+            stderr: read_capture(err),
+        }
+    }
+
+    /// Returns the value of a variable, like `$name`, or of an element, like `$name[1]`.
+    pub fn get_var(&self, name: &str) -> Option<String> {
+        let key = Key::new(name).ok()?;
+        self.state.get_var(key).map(|v| v.to_string())
+    }
+
+    /// Sets the positional parameters. The first one is the name of the script.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.state.args = args;
+    }
+
+    /// Logs how tasks are polled with the tracer.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.state.tracer = Some(tracer);
+    }
+
+    /// Sets the shell up as a login shell: `$SHELL` is exported as the path of rwsh
+    /// and `~/.rwsh_profile` is run, if it exists.
+    pub fn login(&mut self) {
+        if let Ok(exe) = env::current_exe() {
+            let exe = exe.display().to_string();
+            let var = Var::new("SHELL".to_owned(), VarValue::Array(vec![exe.clone()]));
+            self.state.set_var(Key::Var("SHELL"), var, false);
+            self.state.export_var("SHELL".to_owned(), exe);
+        }
+        let profile = match profile_path() {
+            Some(profile) => profile,
+            None => return,
+        };
+        let code = match fs::read_to_string(&profile) {
+            Ok(code) => code,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                eprintln!("{}", msg!("shell.profile-error", profile.display(), e));
+                return;
+            }
+        };
+        if let Err(e) = self.eval(&code) {
+            eprintln!("{}", msg!("shell.profile-error", profile.display(), e));
         }
     }
 
     /// Start the REPL.
+    ///
+    /// In interactive mode, the function `preexec`, if defined, is called before each
+    /// program with its source as it was typed, not expanded, and `precmd` is called
+    /// after it. A panic while reading or running a command is reported as an internal
+    /// error and the session goes on.
     pub fn run(&mut self) {
         self.install_signal_handlers();
+        let pid = sys::pid();
+        if self.state.interactive {
+            panic::set_hook(Box::new(|info| {
+                eprintln!(
+                    "{}",
+                    msg!("shell.internal-error", info, env!("CARGO_PKG_REPOSITORY"))
+                );
+            }));
+        }
         while self.state.exit == -1 {
-            let t = match self.p.borrow_mut().by_ref().next() {
-                None => {
-                    self.state.exit = self.state.last_status;
-                    break;
+            let go_on = if self.state.interactive {
+                match panic::catch_unwind(AssertUnwindSafe(|| self.step())) {
+                    Ok(go_on) => go_on,
+                    Err(_) => {
+                        // forked children must not go on as another shell
+                        if sys::pid() != pid {
+                            exit(1);
+                        }
+                        self.state.recover();
+                        self.p.borrow_mut().reload();
+                        true
+                    }
                 }
-                Some(t) => t,
+            } else {
+                self.step()
+            };
+            if !go_on {
+                break;
+            }
+        }
+        self.state.run_pending_traps();
+        self.state.run_exit_trap();
+        exit(self.state.exit);
+    }
+
+    /// Reads and runs one program. Returns `false` if the shell should stop.
+    fn step(&mut self) -> bool {
+        if self.state.interactive {
+            let ps1 = self.state.get_var(Key::Var("PS1"));
+            let prompt = ps1.map(|ps1| prompt::expand(&self.state, &ps1.to_string()));
+            self.p.borrow().set_prompt(prompt);
+            // without the menu, plain terminals don't complete at all
+            let completer = if self.state.config.plain {
+                Completer::default()
+            } else {
+                self.state.completer()
             };
-            if let Ok(p) = t {
-                if self.state.config.pretty_print {
-                    use pretty::PrettyPrint;
-                    p.pretty_print().print()
+            self.p.borrow().set_completer(completer);
+        }
+        let next = self.p.borrow_mut().next_with_source();
+        let (t, source) = match next {
+            None if self.state.interactive && self.p.borrow().take_cancelled() => {
+                self.p.borrow_mut().reload();
+                return true;
+            }
+            None => {
+                self.state.exit = self.state.last_status;
+                return false;
+            }
+            Some(t) => t,
+        };
+        match t {
+            Ok(p) => {
+                if let Some(format) = self.state.config.ast {
+                    use pretty::{AstFormat, PrettyPrint, ToJson};
+                    match format {
+                        AstFormat::Tree => p.pretty_print().print(),
+                        // blank lines and comments aren't worth an object
+                        AstFormat::Json if p.0.is_empty() => {}
+                        AstFormat::Json => println!("{}", p.to_json()),
+                    }
                 } else {
                     if p.0.is_empty() {
-                        continue;
+                        return true;
                     }
-                    match run_program(p, &mut self.state) {
-                        Ok(_status) => {
-                            // TODO: break on error
-                            // if (status.0 != 0 && break_on_error_flag) {
-                            //     self.state.exit = status.0
-                            // }
-                        }
-                        Err(error) => eprintln!("{}", error),
+                    if self.state.interactive {
+                        self.state.run_hook("preexec", vec![source]);
+                    }
+                    if let Err(error) = run_program(p, &mut self.state) {
+                        self.state.print_error(&error);
                     }
+                    // the error was seen by the command that followed it
+                    self.clear_parse_error();
+                    if self.state.interactive && self.state.exit == -1 {
+                        self.state.run_hook("precmd", Vec::new());
+                    }
+                    self.state.cleanup();
+                }
+            }
+            Err(e) => {
+                // a construct given up to be edited again isn't an error
+                if self.state.interactive && self.p.borrow().take_cancelled() {
+                    self.p.borrow_mut().reload();
+                    return true;
                 }
-            } else if let Err(e) = t {
-                eprintln!("{}", e);
-                if !self.interactive {
-                    exit(1);
+                self.state.print_error(&e);
+                if !self.state.interactive {
+                    // nothing runs after a parse error, but the rest of the script is
+                    // still checked, or printed with `-n`
+                    self.state.last_status = 1;
+                    self.p.borrow_mut().recover();
+                    if self.state.config.ast.is_some() {
+                        return true;
+                    }
+                    self.check_rest();
+                    self.state.exit = 1;
+                    return false;
                 }
+                self.set_parse_error(&e);
                 self.p.borrow_mut().reload();
             }
         }
-        exit(self.state.exit);
+        true
+    }
+
+    /// Parses the rest of the input without running it, reporting every parse error.
+    fn check_rest(&mut self) {
+        loop {
+            let next = self.p.borrow_mut().next();
+            match next {
+                None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    self.state.print_error(&e);
+                    self.p.borrow_mut().recover();
+                }
+            }
+        }
+    }
+
+    /// Exposes the parse error to the user through the `PARSE_ERROR*` variables,
+    /// and gives its line to the line editor for Alt-E.
+    fn set_parse_error(&mut self, e: &ParseError) {
+        let source = self.p.borrow().current_line();
+        let vars = [
+            ("PARSE_ERROR", e.message.clone()),
+            ("PARSE_ERROR_LINE", e.line.to_string()),
+            ("PARSE_ERROR_COL", e.col.to_string()),
+            ("PARSE_ERROR_SOURCE", source),
+        ];
+        for (key, value) in vars.iter() {
+            self.state.set_var(
+                Key::Var(key),
+                Var::new((*key).to_owned(), VarValue::Array(vec![value.clone()])),
+                false,
+            );
+        }
+        let correction = builtin::last_parse_error(&self.state);
+        self.p.borrow().set_correction(correction);
+    }
+
+    fn clear_parse_error(&mut self) {
+        for key in &[
+            "PARSE_ERROR",
+            "PARSE_ERROR_LINE",
+            "PARSE_ERROR_COL",
+            "PARSE_ERROR_SOURCE",
+        ] {
+            self.state.remove_var(key);
+        }
+        self.p.borrow().set_correction(None);
     }
 
     fn install_signal_handlers(&self) {
@@ -467,12 +1335,132 @@ impl Shell {
     }
 }
 
+/// Parses code given as a string, such as the body of a block argument.
+pub fn parse_code(code: &str) -> Result<Vec<Program>, String> {
+    let reader = FileLineReader::new(Cursor::new(format!("{}\n", code))).unwrap();
+    let parser = Parser::new(BufReadChars::new(Box::new(reader)));
+    parser
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
 pub fn run_program(p: Program, state: &mut State) -> Result<(i32, Context), Box<Error>> {
+    run_program_with(p, state, FdWriter(1), FdWriter(2))
+}
+
+/// Runs a program whose builtins write to `stdout` and `stderr`, which its processes
+/// get as their standard output and error.
+fn run_program_with(
+    p: Program,
+    state: &mut State,
+    stdout: FdWriter,
+    stderr: FdWriter,
+) -> Result<(i32, Context<'_>), Box<dyn Error>> {
     let mut task = Task::new_from_command_lists(p.0, false);
     let mut ctx = Context {
         state,
         in_pipe: false,
+        stdout,
+        stderr,
     };
     let r = task.run(&mut ctx)?;
     Ok((r, ctx))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, EvalResult, Key, Shell, Var, VarValue};
+    use crate::msg;
+    use std::collections::HashMap;
+
+    #[test]
+    fn truth_of_values() {
+        assert!(VarValue::Bool(true).is_true());
+        assert!(!VarValue::Bool(false).is_true());
+        assert!(!VarValue::Null.is_true());
+        assert!(!VarValue::Array(vec![String::new()]).is_true());
+        assert!(VarValue::Array(vec![String::new(), "a".to_owned()]).is_true());
+        assert_eq!(VarValue::Null.array(), Vec::<String>::new());
+        assert_eq!(VarValue::Bool(false).array(), vec!["false"]);
+    }
+
+    #[test]
+    fn resolve_keys() {
+        let mut shell = Shell::new_embedded(Config::default()).unwrap();
+        assert_eq!(shell.eval("let a = [ x y z ]"), Ok(0));
+        let mut map = HashMap::new();
+        map.insert("k".to_owned(), "v".to_owned());
+        let m = Var::new("m".to_owned(), VarValue::Map(map));
+        shell.state.set_var(Key::Var("m"), m, false);
+
+        // `a` has 3 elements
+        let out_of_range = |key: Key| Err(msg!("var.index-out-of-range", key, "a", 3));
+        let cases = vec![
+            (Key::Var("a"), false, Ok(Key::Var("a"))),
+            (Key::Index("a", 1), false, Ok(Key::Index("a", 1))),
+            (Key::Index("a", -1), false, Ok(Key::Index("a", 2))),
+            (Key::Index("a", -3), false, Ok(Key::Index("a", 0))),
+            (
+                Key::Index("a", -4),
+                false,
+                out_of_range(Key::Index("a", -4)),
+            ),
+            (Key::Index("a", -4), true, out_of_range(Key::Index("a", -4))),
+            // the element after the last one can always be set
+            (Key::Index("a", 3), false, Ok(Key::Index("a", 3))),
+            (Key::Index("a", 5), false, out_of_range(Key::Index("a", 5))),
+            (Key::Index("a", 5), true, Ok(Key::Index("a", 5))),
+            (Key::Append("a"), false, Ok(Key::Index("a", 3))),
+            (Key::Append("unset"), false, Ok(Key::Index("unset", 0))),
+            // maps have keys, not indices
+            (Key::Field("m", "k"), false, Ok(Key::Field("m", "k"))),
+            (Key::Field("m", "new"), false, Ok(Key::Field("m", "new"))),
+            (Key::Index("m", -1), false, Ok(Key::Index("m", -1))),
+            (Key::Append("m"), false, Err(msg!("var.append-to-map", "m"))),
+        ];
+        for (key, fill, expected) in cases {
+            assert_eq!(shell.state.resolve_key(key, fill), expected, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn finished_processes_are_dropped() {
+        let mut shell = Shell::new_embedded(Config::default()).unwrap();
+        assert_eq!(shell.eval("sh -c true | sh -c true; sh -c true"), Ok(0));
+        assert!(shell.state.processes.is_empty());
+    }
+
+    #[test]
+    fn eval_capture() {
+        let mut shell = Shell::new_embedded(Config::default()).unwrap();
+        assert_eq!(
+            shell.eval_capture("echo out; sh -c 'echo err >&2; echo process'; false"),
+            EvalResult {
+                status: 1,
+                stdout: "out\nprocess\n".to_owned(),
+                stderr: "err\n".to_owned(),
+            }
+        );
+        let r = shell.eval_capture("echo (");
+        assert_eq!((r.status, r.stdout.as_str()), (1, ""));
+        assert!(!r.stderr.is_empty());
+        // redirections, bodies of builtins and pipelines write to the captured files too
+        let r = shell.eval_capture(
+            "let f = $(mktemp); { echo to-file } > $f; cat $f; rm $f
+            { sh -c 'echo swapped >&2' } 2>&1; eval 'echo nested'; echo piped | cat",
+        );
+        assert_eq!(r.stdout, "to-file\nswapped\nnested\npiped\n");
+        assert_eq!(r.stderr, "");
+    }
+
+    #[test]
+    fn several_shells() {
+        // the children of every shell wake it up, not only those of the newest one
+        let mut first = Shell::new_embedded(Config::default()).unwrap();
+        let mut second = Shell::new_embedded(Config::default()).unwrap();
+        assert_eq!(first.eval("sh -c 'exit 3'"), Ok(3));
+        assert_eq!(second.eval("sh -c 'exit 4'"), Ok(4));
+        drop(second);
+        assert_eq!(first.eval("sh -c 'exit 5'"), Ok(5));
+    }
+}
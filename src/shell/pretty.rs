@@ -15,6 +15,17 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use crate::util::json_string;
+
+/// How `rwsh --ast` prints the syntax tree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AstFormat {
+    /// A [`PrettyTree`](struct.PrettyTree.html), for people.
+    Tree,
+    /// One JSON object per program, for editors and other tools.
+    Json,
+}
+
 /// A node in the pretty print tree.
 pub struct PrettyTree {
     pub text: String,
@@ -26,16 +37,78 @@ pub trait PrettyPrint {
     fn pretty_print(&self) -> PrettyTree;
 }
 
+/// Parse types that can be written as JSON. Nodes are objects with their kind in `type`.
+pub trait ToJson {
+    fn to_json(&self) -> String;
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> String {
+        let items = self.iter().map(|x| x.to_json()).collect::<Vec<_>>();
+        format!("[{}]", items.join(","))
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> String {
+        self[..].to_json()
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for Box<T> {
+    fn to_json(&self) -> String {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> String {
+        match self {
+            Some(x) => x.to_json(),
+            None => "null".to_owned(),
+        }
+    }
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> String {
+        json_string(self)
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> String {
+        json_string(self)
+    }
+}
+
+/// Returns a JSON object of the given type, with fields whose values are already JSON.
+pub fn json_object(kind: &str, fields: Vec<(&str, String)>) -> String {
+    let mut s = format!("{{\"type\":{}", json_string(kind));
+    for (name, value) in fields {
+        s.push_str(&format!(",{}:{}", json_string(name), value));
+    }
+    s.push('}');
+    s
+}
+
 impl PrettyTree {
     /// Pretty prints the tree.
     pub fn print(&self) {
-        self.print_tree("".to_owned(), true);
+        print!("{}", self.render());
+    }
+
+    /// Returns the tree as it would be printed.
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        self.render_tree(&mut s, "".to_owned(), true);
+        s
     }
 
-    fn print_tree(&self, prefix: String, last: bool) {
+    fn render_tree(&self, s: &mut String, prefix: String, last: bool) {
         let current_prefix = if last { "└─ " } else { "├─ " };
 
-        println!("{}{}{}", prefix, current_prefix, self.text);
+        s.push_str(&format!("{}{}{}\n", prefix, current_prefix, self.text));
 
         let child_prefix = if last { "   " } else { "│  " };
         let prefix = prefix + child_prefix;
@@ -44,7 +117,7 @@ impl PrettyTree {
             let last_child = self.children.len() - 1;
 
             for (i, child) in self.children.iter().enumerate() {
-                child.print_tree(prefix.to_owned(), i == last_child);
+                child.render_tree(s, prefix.to_owned(), i == last_child);
             }
         }
     }
@@ -0,0 +1,225 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Bookkeeping of child processes.
+//!
+//! Children are reaped only after the [`Reaper`](../../sys/struct.Reaper.html) tells
+//! that one of them changed state, and only the processes started by the shell are
+//! waited for, so processes owned by someone else are left alone.
+//!
+//! Builtins in the middle of a pipeline move the data between their neighbours with a
+//! [`Relay`](struct.Relay.html).
+use crate::msg;
+use crate::sys::{self, ChildStatus, Pid, RawFd};
+use crate::task::TaskStatus;
+use crate::util::{FdReader, FdWriter};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct Process {
+    pub pid: Pid,
+    pub status: ChildStatus,
+}
+
+impl Process {
+    pub fn new(pid: Pid) -> Process {
+        Process {
+            pid,
+            status: ChildStatus::Running,
+        }
+    }
+
+    pub fn terminated(&self) -> bool {
+        self.status != ChildStatus::Running
+    }
+
+    pub fn poll(&mut self) -> Result<TaskStatus, String> {
+        match self.status {
+            ChildStatus::Running => Ok(TaskStatus::Wait),
+            ChildStatus::Terminated(code) => Ok(TaskStatus::Success(code)),
+            ChildStatus::Lost => Err(msg!("process.lost", self.pid)),
+        }
+    }
+
+    /// Collects the status of the process if it terminated, without blocking.
+    /// Returns `true` if it did.
+    pub fn reap(&mut self) -> Result<bool, sys::Error> {
+        if self.terminated() {
+            return Ok(false);
+        }
+        self.status = sys::try_wait(self.pid)?;
+        Ok(self.terminated())
+    }
+}
+
+/// CPU time, split in the time spent running user code and running in the kernel.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuTime {
+    pub user: Duration,
+    pub sys: Duration,
+}
+
+impl CpuTime {
+    /// Returns the CPU time used so far by the shell and by the children it reaped.
+    pub fn now() -> CpuTime {
+        let (user, sys) = sys::cpu_time();
+        CpuTime { user, sys }
+    }
+
+    /// Returns the CPU time used since `earlier`.
+    pub fn since(&self, earlier: CpuTime) -> CpuTime {
+        CpuTime {
+            user: self.user - earlier.user,
+            sys: self.sys - earlier.sys,
+        }
+    }
+}
+
+/// How much a relay moves at once, and how big it asks its pipes to be.
+const CHUNK: usize = 1 << 20;
+
+/// Copies everything from one file descriptor to another.
+///
+/// If one of them is a pipe, the data is moved with `splice`, without going through
+/// the memory of the shell. Otherwise, or if the system can't do it, it is read and
+/// written like usual.
+pub struct Relay {
+    from: RawFd,
+    to: RawFd,
+    splice: bool,
+    buf: Vec<u8>,
+}
+
+impl Relay {
+    pub fn new(from: RawFd, to: RawFd) -> Relay {
+        sys::grow_pipe(from, CHUNK);
+        sys::grow_pipe(to, CHUNK);
+        Relay {
+            from,
+            to,
+            splice: true,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Moves the next chunk of data, and returns its size, or 0 at the end of the input.
+    pub fn step(&mut self) -> io::Result<usize> {
+        if self.splice {
+            match sys::splice(self.from, self.to, CHUNK) {
+                Some(r) => return r,
+                None => self.splice = false,
+            }
+        }
+        if self.buf.is_empty() {
+            self.buf = vec![0; 64 * 1024];
+        }
+        let n = FdReader(self.from).read(&mut self.buf)?;
+        FdWriter(self.to).write_all(&self.buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// Runs the command line with `sh -c`, giving it `input`, or nothing if there is none,
+/// and returns what it printed. What it prints on the standard error goes to the one
+/// of the shell.
+pub fn run_command(line: &str, input: Option<&[u8]>) -> io::Result<Vec<u8>> {
+    use std::process::{Command, Stdio};
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(line)
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .spawn()?;
+    // a command can print before it reads all of its input, so the input is written
+    // from another thread to keep both pipes moving
+    let writer = match (input, child.stdin.take()) {
+        (Some(input), Some(mut stdin)) => {
+            let input = input.to_vec();
+            Some(std::thread::spawn(move || {
+                let _ = stdin.write_all(&input);
+            }))
+        }
+        _ => None,
+    };
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Process, Relay};
+    use nix::unistd::{self, Pid};
+    use std::fs;
+    use std::os::unix::io::{AsRawFd, RawFd};
+
+    fn relay_all(from: RawFd, to: RawFd) -> usize {
+        let mut relay = Relay::new(from, to);
+        let mut total = 0;
+        loop {
+            match relay.step().unwrap() {
+                0 => return total,
+                n => total += n,
+            }
+        }
+    }
+
+    #[test]
+    fn relay_pipes() {
+        let (r1, w1) = unistd::pipe().unwrap();
+        let (r2, w2) = unistd::pipe().unwrap();
+        unistd::write(w1, b"through two pipes").unwrap();
+        unistd::close(w1).unwrap();
+        assert_eq!(relay_all(r1, w2), 17);
+        unistd::close(w2).unwrap();
+        let mut buf = [0; 32];
+        let n = unistd::read(r2, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"through two pipes");
+        unistd::close(r1).unwrap();
+        unistd::close(r2).unwrap();
+    }
+
+    #[test]
+    fn relay_files() {
+        // neither end is a pipe, so the data is read and written
+        let dir = std::env::temp_dir().join(format!("rwsh-relay-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("in"), "between two files").unwrap();
+        let from = fs::File::open(dir.join("in")).unwrap();
+        let to = fs::File::create(dir.join("out")).unwrap();
+        assert_eq!(relay_all(from.as_raw_fd(), to.as_raw_fd()), 17);
+        assert_eq!(
+            fs::read_to_string(dir.join("out")).unwrap(),
+            "between two files"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lost_process() {
+        // init is nobody's child, so there is no status to collect
+        let mut p = Process::new(Pid::from_raw(1));
+        assert_eq!(p.reap(), Ok(true));
+        assert!(p.poll().is_err());
+    }
+}
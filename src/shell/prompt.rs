@@ -0,0 +1,180 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! The primary prompt, taken from `PS1`.
+//!
+//! These escapes are replaced in it:
+//!
+//! - `%d`: the working directory, with the home directory as `~` and the directories
+//!   above the current one shortened to their first letter, like `~/s/rwsh`;
+//! - `%w`: the working directory, with the home directory as `~`;
+//! - `%c`: the name of the current directory;
+//! - `%?`: the status of the last command;
+//! - `%%`: a `%`.
+//!
+//! Other characters, and unknown escapes, are left as they are.
+//!
+//! The prompt is shown in the style of the `prompt` role of the [theme](../theme/index.html),
+//! the directories in the style of `prompt.dir`, and the status in the style of
+//! `prompt.status` if it isn't 0, unless the `plain` option is set.
+use super::theme::Style;
+use super::{Key, State};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Returns `ps1` with its escapes replaced. The working directory is the one in `PWD`.
+pub fn expand(state: &State, ps1: &str) -> String {
+    let pwd = state
+        .get_var(Key::Var("PWD"))
+        .map(|v| PathBuf::from(v.to_string()))
+        .or_else(|| env::current_dir().ok())
+        .unwrap_or_default();
+    let home = dirs::home_dir();
+    let base = &state.style("prompt");
+    let dir = &state.style("prompt.dir");
+    let mut s = base.start();
+    let mut it = ps1.chars();
+    while let Some(c) = it.next() {
+        if c != '%' {
+            s.push(c);
+            continue;
+        }
+        match it.next() {
+            Some('d') => {
+                let d = abbreviate(&tildify(&pwd, home.as_ref()));
+                push_styled(&mut s, &d, dir, base);
+            }
+            Some('w') => push_styled(&mut s, &tildify(&pwd, home.as_ref()), dir, base),
+            Some('c') => {
+                let c = current(&tildify(&pwd, home.as_ref()));
+                push_styled(&mut s, &c, dir, base);
+            }
+            Some('?') if state.last_status != 0 => {
+                let status = state.last_status.to_string();
+                push_styled(&mut s, &status, &state.style("prompt.status"), base);
+            }
+            Some('?') => s.push('0'),
+            Some('%') => s.push('%'),
+            Some(c) => {
+                s.push('%');
+                s.push(c);
+            }
+            None => s.push('%'),
+        }
+    }
+    if !base.start().is_empty() {
+        s.push_str("\x1b[0m");
+    }
+    s
+}
+
+/// Adds `text` in `style`, then goes back to the style of the prompt.
+fn push_styled(s: &mut String, text: &str, style: &Style, base: &Style) {
+    let painted = style.paint(text);
+    let changed = painted != text;
+    s.push_str(&painted);
+    if changed {
+        s.push_str(&base.start());
+    }
+}
+
+/// Writes `dir` with the home directory at its start as `~`.
+fn tildify(dir: &Path, home: Option<&PathBuf>) -> String {
+    match home.and_then(|home| dir.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_owned(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => dir.display().to_string(),
+    }
+}
+
+/// Shortens every directory but the last one to its first letter, or to its first two
+/// if it is hidden.
+fn abbreviate(dir: &str) -> String {
+    let last = dir.rfind('/').map_or(0, |i| i + 1);
+    let mut s = dir[..last]
+        .split('/')
+        .map(|name| {
+            let n = if name.starts_with('.') { 2 } else { 1 };
+            name.chars().take(n).collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    s.push_str(&dir[last..]);
+    s
+}
+
+/// Returns the last directory of `dir`.
+fn current(dir: &str) -> String {
+    match dir.rfind('/') {
+        Some(i) if i + 1 < dir.len() => dir[i + 1..].to_owned(),
+        _ => dir.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{abbreviate, current, expand, tildify};
+    use crate::shell::{Config, Shell};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn escapes() {
+        let mut shell = Shell::new_embedded(Config::default()).unwrap();
+        shell.eval("let PWD = /usr/local/bin; false").unwrap();
+        assert_eq!(
+            expand(&shell.state, "%c %?%% %x"),
+            "\x1b[1;34mbin\x1b[0m \x1b[31m1\x1b[0m% %x"
+        );
+        shell
+            .eval("theme prompt green; theme prompt.status none")
+            .unwrap();
+        assert_eq!(
+            expand(&shell.state, "%c %?%%"),
+            "\x1b[32m\x1b[1;34mbin\x1b[0m\x1b[32m 0%\x1b[0m"
+        );
+        shell.eval("set -o plain; false").unwrap();
+        assert_eq!(expand(&shell.state, "%c %?%%"), "bin 1%");
+    }
+
+    #[test]
+    fn directories() {
+        let home = PathBuf::from("/home/user");
+        for (dir, tilde, short, cur) in &[
+            ("/home/user", "~", "~", "~"),
+            ("/home/user/src/rwsh", "~/src/rwsh", "~/s/rwsh", "rwsh"),
+            (
+                "/home/user/.config/rwsh",
+                "~/.config/rwsh",
+                "~/.c/rwsh",
+                "rwsh",
+            ),
+            (
+                "/home/username",
+                "/home/username",
+                "/h/username",
+                "username",
+            ),
+            ("/usr/local/bin", "/usr/local/bin", "/u/l/bin", "bin"),
+            ("/", "/", "/", "/"),
+        ] {
+            let t = tildify(Path::new(dir), Some(&home));
+            assert_eq!(t, *tilde);
+            assert_eq!(abbreviate(&t), *short);
+            assert_eq!(current(&t), *cur);
+        }
+    }
+}
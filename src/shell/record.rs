@@ -0,0 +1,113 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Recording of interactive sessions.
+//!
+//! The session runs on a pseudoterminal, while the recording process relays the input
+//! and the output of the real terminal and writes them to a file in the
+//! [asciicast v2](https://docs.asciinema.org/manual/asciicast/v2/) format:
+//! a JSON header, then an event on each line, `[time, "i" or "o", data]`,
+//! with the time in seconds since the start of the session.
+use crate::msg;
+use crate::pty::{self, PtyFork, Stream, Winsize};
+use crate::util::{decode_utf8, json_string};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::exit;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Starts recording the session into the file at `path`.
+///
+/// Returns in a child on a pseudoterminal, which goes on to run the session.
+/// The recording process exits with the status of the child when the session is over.
+pub fn record(path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| msg!("record.open-error", path, e))?;
+    match pty::fork(0).map_err(|e| msg!("record.error", e))? {
+        PtyFork::Child => Ok(()),
+        PtyFork::Parent(pty) => {
+            let size = pty::window_size(0).ok();
+            let mut typescript =
+                Typescript::new(BufWriter::new(file), size).map_err(|e| msg!("record.error", e))?;
+            let code = pty::relay(&pty, 0, 1, |stream, data| {
+                let _ = typescript.event(stream, data);
+            })
+            .map_err(|e| msg!("record.error", e))?;
+            exit(code);
+        }
+    }
+}
+
+/// Writes the events of a session in the asciicast format.
+struct Typescript<W: Write> {
+    out: W,
+    start: Instant,
+    /// The bytes of characters cut between two reads, for the input and the output.
+    pending: [Vec<u8>; 2],
+}
+
+impl<W: Write> Typescript<W> {
+    /// Writes the header. Unknown sizes are recorded as 80x24.
+    fn new(mut out: W, size: Option<Winsize>) -> io::Result<Self> {
+        let (width, height) = size
+            .filter(|s| s.ws_col > 0 && s.ws_row > 0)
+            .map_or((80, 24), |s| (s.ws_col, s.ws_row));
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let var = |name| json_string(&env::var(name).unwrap_or_default());
+        writeln!(
+            out,
+            "{{\"version\":2,\"width\":{},\"height\":{},\"timestamp\":{},\"env\":{{\"SHELL\":{},\"TERM\":{}}}}}",
+            width,
+            height,
+            timestamp,
+            var("SHELL"),
+            var("TERM"),
+        )?;
+        out.flush()?;
+        Ok(Typescript {
+            out,
+            start: Instant::now(),
+            pending: [Vec::new(), Vec::new()],
+        })
+    }
+
+    fn event(&mut self, stream: Stream, data: &[u8]) -> io::Result<()> {
+        let pending = &mut self.pending[stream as usize];
+        pending.extend_from_slice(data);
+        let mut text = String::new();
+        decode_utf8(pending, &mut text);
+        if text.is_empty() {
+            return Ok(());
+        }
+        let code = match stream {
+            Stream::Input => "i",
+            Stream::Output => "o",
+        };
+        let time = self.start.elapsed();
+        writeln!(
+            self.out,
+            "[{}.{:06}, \"{}\", {}]",
+            time.as_secs(),
+            time.subsec_micros(),
+            code,
+            json_string(&text)
+        )?;
+        self.out.flush()
+    }
+}
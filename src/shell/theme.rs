@@ -0,0 +1,289 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Colors of the interactive interface.
+//!
+//! Each part of the interface has a role in the theme, with a style: a color and
+//! some attributes. The style is written as words, like `blue bold` or `#ff8800 underline`.
+//! Colors are either one of the eight terminal colors, optionally with `bright-` before
+//! them, or a true color in hexadecimal. `none` is the style without any color or attribute.
+use crate::msg;
+use crate::sys;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+
+/// The roles, with their default styles.
+pub const ROLES: &[(&str, &str)] = &[
+    // the descriptions of the candidates in the completion menu
+    ("completion.description", "bright-black"),
+    // the candidate selected in the completion menu
+    ("completion.selected", "reverse"),
+    // errors that the interactive shell prints about commands and parsing
+    ("error", "red"),
+    // the text of `PS1`
+    ("prompt", "none"),
+    // the working directory, in `PS1`
+    ("prompt.dir", "blue bold"),
+    // the status of the last command in `PS1`, if it failed
+    ("prompt.status", "red"),
+    // the header printed by `table -H`
+    ("table.header", "bold"),
+];
+
+const COLORS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    /// One of the 16 colors of the terminal. The bright ones are from 8 up.
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn parse(s: &str) -> Option<Color> {
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            let n = u32::from_str_radix(hex, 16).ok()?;
+            return Some(Color::Rgb((n >> 16) as u8, (n >> 8) as u8, n as u8));
+        }
+        let (name, bright) = match s.strip_prefix("bright-") {
+            Some(name) => (name, 8),
+            None => (s, 0),
+        };
+        let i = COLORS.iter().position(|c| *c == name)?;
+        Some(Color::Ansi(i as u8 + bright))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Color::Ansi(i) if i >= 8 => write!(f, "bright-{}", COLORS[i as usize - 8]),
+            Color::Ansi(i) => write!(f, "{}", COLORS[i as usize]),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    pub color: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Parses the words of a style, like `bright-green bold`.
+    pub fn parse(words: &[&str]) -> Result<Style, String> {
+        let mut style = Style::default();
+        for word in words {
+            match *word {
+                "none" => {}
+                "bold" => style.bold = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reverse" => style.reverse = true,
+                w => match Color::parse(w) {
+                    Some(c) => style.color = Some(c),
+                    None => return Err(msg!("theme.bad-style", w)),
+                },
+            }
+        }
+        Ok(style)
+    }
+
+    /// Returns the escape sequence that turns the style on, which is empty for no style.
+    pub fn start(&self) -> String {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_owned());
+        }
+        if self.italic {
+            codes.push("3".to_owned());
+        }
+        if self.underline {
+            codes.push("4".to_owned());
+        }
+        if self.reverse {
+            codes.push("7".to_owned());
+        }
+        match self.color {
+            Some(Color::Ansi(i)) if i >= 8 => codes.push((90 + i - 8).to_string()),
+            Some(Color::Ansi(i)) => codes.push((30 + i).to_string()),
+            Some(Color::Rgb(r, g, b)) => codes.push(format!("38;2;{};{};{}", r, g, b)),
+            None => {}
+        }
+        if codes.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", codes.join(";"))
+        }
+    }
+
+    /// Returns `s` in this style.
+    pub fn paint(&self, s: &str) -> String {
+        let start = self.start();
+        if start.is_empty() || s.is_empty() {
+            s.to_owned()
+        } else {
+            format!("{}{}\x1b[0m", start, s)
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut words = Vec::new();
+        if let Some(c) = self.color {
+            words.push(c.to_string());
+        }
+        for (on, name) in &[
+            (self.bold, "bold"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+            (self.reverse, "reverse"),
+        ] {
+            if *on {
+                words.push((*name).to_owned());
+            }
+        }
+        if words.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", words.join(" "))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The styles of all the [roles](constant.ROLES.html).
+pub struct Theme(BTreeMap<&'static str, Style>);
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme(
+            ROLES
+                .iter()
+                .map(|(role, style)| {
+                    let words = style.split(' ').collect::<Vec<_>>();
+                    (*role, Style::parse(&words).unwrap())
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Theme {
+    /// Returns the style of `role`, which must be one of the [roles](constant.ROLES.html).
+    pub fn get(&self, role: &str) -> &Style {
+        &self.0[role]
+    }
+
+    pub fn set(&mut self, role: &str, style: Style) -> Result<(), String> {
+        match self.0.get_mut(role) {
+            Some(s) => {
+                *s = style;
+                Ok(())
+            }
+            None => Err(msg!("theme.unknown-role", role)),
+        }
+    }
+
+    /// Puts back the default style of `role`.
+    pub fn reset(&mut self, role: &str) -> Result<(), String> {
+        let style = Theme::default().0.remove(role);
+        let style = style.ok_or_else(|| msg!("theme.unknown-role", role))?;
+        self.set(role, style)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Style)> {
+        self.0.iter().map(|(role, style)| (*role, style))
+    }
+}
+
+/// Tells whether the terminal can't take escape sequences, because `TERM` is `dumb`,
+/// like in the shells of editors, or isn't set at all.
+pub fn dumb_terminal() -> bool {
+    env::var("TERM").map_or(true, |term| term.is_empty() || term == "dumb")
+}
+
+/// Tells whether the shell starts with the `plain` option, without any styles: if
+/// `RWSH_PLAIN` is `1` or `0`, it decides, otherwise the option is on for dumb terminals
+/// and when the output isn't a terminal, like in CI logs.
+pub fn plain_output() -> bool {
+    match env::var("RWSH_PLAIN").ok().as_deref() {
+        Some("1") => true,
+        Some("0") => false,
+        _ => dumb_terminal() || !sys::isatty(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, Style, Theme};
+
+    #[test]
+    fn styles() {
+        for (spec, style, start) in &[
+            ("none", Style::default(), ""),
+            (
+                "bright-green bold",
+                Style {
+                    color: Some(Color::Ansi(10)),
+                    bold: true,
+                    ..Style::default()
+                },
+                "\x1b[1;92m",
+            ),
+            (
+                "#ff8800 underline",
+                Style {
+                    color: Some(Color::Rgb(255, 136, 0)),
+                    underline: true,
+                    ..Style::default()
+                },
+                "\x1b[4;38;2;255;136;0m",
+            ),
+        ] {
+            let words = spec.split(' ').collect::<Vec<_>>();
+            let parsed = Style::parse(&words).unwrap();
+            assert_eq!(&parsed, style);
+            assert_eq!(parsed.start(), *start);
+            assert_eq!(parsed.to_string(), *spec);
+        }
+        assert!(Style::parse(&["purple"]).is_err());
+        assert!(Style::parse(&["#12345"]).is_err());
+    }
+
+    #[test]
+    fn roles() {
+        let mut theme = Theme::default();
+        assert!(theme.set("prompt", Style::parse(&["red"]).unwrap()).is_ok());
+        assert_eq!(theme.get("prompt").paint("€ "), "\x1b[31m€ \x1b[0m");
+        assert!(theme.reset("prompt").is_ok());
+        assert_eq!(theme.get("prompt").paint("€ "), "€ ");
+        assert!(theme.set("menu", Style::default()).is_err());
+        assert!(theme.reset("menu").is_err());
+    }
+}
@@ -0,0 +1,86 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Code run when the shell exits, receives a signal, or runs a command.
+//!
+//! The signal handlers only take note of the signals. The traps run later, between commands,
+//! where any code can run safely.
+use crate::parser::Program;
+use crate::sys::{self, SignalAction};
+
+/// The conditions that can be trapped, with their signals.
+/// `EXIT` is the shell exiting, `DEBUG` is before every command and `ERR` is after
+/// every command that fails outside a condition.
+pub const CONDITIONS: &[(&str, Option<i32>)] = &[
+    ("EXIT", None),
+    ("DEBUG", None),
+    ("ERR", None),
+    ("INT", Some(sys::SIGINT)),
+    ("TERM", Some(sys::SIGTERM)),
+];
+
+#[derive(Clone)]
+pub struct Trap {
+    /// The code as given to `trap`.
+    pub code: String,
+    pub body: Vec<Program>,
+}
+
+/// Returns the name of a condition given like `INT`, `SIGINT`, `int` or `2`.
+pub fn condition_name(s: &str) -> Option<&'static str> {
+    let upper = s.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    let number = s.parse::<i32>().ok();
+    CONDITIONS
+        .iter()
+        .find(|(n, sig)| {
+            let n_number = match sig {
+                Some(sig) => Some(*sig),
+                None if *n == "EXIT" => Some(0),
+                None => None,
+            };
+            *n == name || (number.is_some() && number == n_number)
+        })
+        .map(|(n, _)| *n)
+}
+
+/// Sets what the signal of the condition does: nothing if the trap is empty,
+/// running the trap if there is one, and the default action otherwise.
+pub fn handle(name: &str, trap: Option<&Trap>) -> Result<(), sys::Error> {
+    let sig = match CONDITIONS.iter().find(|(n, _)| *n == name) {
+        Some((_, Some(sig))) => *sig,
+        _ => return Ok(()),
+    };
+    let action = match trap {
+        None => SignalAction::Default,
+        Some(trap) if trap.code.trim().is_empty() => SignalAction::Ignore,
+        Some(_) => SignalAction::Catch,
+    };
+    sys::set_signal_action(sig, action)
+}
+
+/// Returns the names of the conditions whose signals arrived since the last call.
+pub fn take_pending() -> Vec<&'static str> {
+    let pending = sys::take_caught_signals();
+    CONDITIONS
+        .iter()
+        .filter_map(|(name, sig)| match sig {
+            Some(sig) if pending & (1 << *sig) != 0 => Some(*name),
+            _ => None,
+        })
+        .collect()
+}
@@ -18,6 +18,7 @@
 use super::{Buffer, Range};
 use crate::parser::sre::address::{ComposedAddress, SimpleAddress};
 use crate::util::regex;
+use regex::Regex;
 
 #[derive(Debug)]
 pub enum AddressResolveError {
@@ -44,6 +45,49 @@ impl std::fmt::Display for AddressResolveError {
 
 impl std::error::Error for AddressResolveError {}
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+/// The way a regular expression address looks for a match.
+pub enum Direction {
+    /// `/re/`, from the end of the dot towards the end of the buffer.
+    Forward,
+    /// `?re?` or `-/re/`, from the start of the dot towards the start of the buffer.
+    Backward,
+}
+
+/// Looks for `re` in the buffer, starting from `dot` and going in the given direction.
+///
+/// Like in sam, the search wraps around the buffer: if nothing matches before the
+/// end (or the start, going backwards), it goes on from the other end, up to the
+/// dot. An empty match right where the search starts doesn't count, so that
+/// searching again moves on.
+pub fn search(buffer: &Buffer, re: &Regex, dot: Range, dir: Direction) -> Option<Range> {
+    let data = &buffer.data;
+    let found = |m: regex::Match| Range(m.start(), m.end());
+    match dir {
+        Direction::Forward => {
+            let start = dot.1;
+            let skip = |r: &Range| r.0 == r.1 && r.0 == start;
+            let from = |p| re.find_at(data, p).map(found);
+            from(start)
+                .filter(|r| !skip(r))
+                // step over a whole character, not into it
+                .or_else(|| buffer.forward(start, 1).and_then(from))
+                .or_else(|| re.find_iter(data).map(found).find(|r| !skip(r)))
+        }
+        Direction::Backward => {
+            let start = dot.0;
+            let skip = |r: &Range| r.0 == r.1 && r.0 == start;
+            let matches: Vec<Range> = re.find_iter(data).map(found).filter(|r| !skip(r)).collect();
+            matches
+                .iter()
+                .rev()
+                .find(|r| r.1 <= start)
+                .or_else(|| matches.last())
+                .copied()
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 /// An address is a chunk of a (../struct.Buffer.html).
 pub struct Address<'a> {
@@ -68,6 +112,11 @@ impl<'a> Address<'a> {
         self.r
     }
 
+    /// Returns the line after this address, like `+1`.
+    pub fn next_line(self) -> Result<Self, AddressResolveError> {
+        self.line_address(1, 1)
+    }
+
     fn line_address(self, line: usize, sign: i32) -> Result<Self, AddressResolveError> {
         let mut a = Address::new(self.buffer);
 
@@ -166,78 +215,39 @@ impl<'a> Address<'a> {
         Ok(a)
     }
 
+    /// Moves `pos` characters, which can be wider than a byte.
     fn char_address(mut self, pos: usize, sign: i32) -> Result<Self, AddressResolveError> {
-        if sign == 0 {
-            self.r = Range(pos, pos);
-        } else if sign < 0 {
-            if self.r.0 < pos {
-                return Err(AddressResolveError::OutOfRange);
-            }
-            self.r.0 -= pos;
-            self.r.1 -= pos;
+        let buf = self.buffer;
+        let r = if sign == 0 {
+            buf.forward(0, pos).map(|p| Range(p, p))
         } else {
-            self.r.0 += pos;
-            self.r.1 += pos;
-        }
-        if self.r.1 > self.buffer.data.len() {
-            Err(AddressResolveError::OutOfRange)
-        } else {
-            Ok(self)
-        }
+            let step = |p| {
+                if sign < 0 {
+                    buf.backward(p, pos)
+                } else {
+                    buf.forward(p, pos)
+                }
+            };
+            step(self.r.0).and_then(|l| step(self.r.1).map(|r| Range(l, r)))
+        };
+        self.r = r.ok_or(AddressResolveError::OutOfRange)?;
+        Ok(self)
     }
 
     fn regex_address(self, re: &str, sign: i32) -> Result<Self, AddressResolveError> {
-        let mut loc;
-        let mut l: usize;
-        let re = match regex(re) {
-            Ok(re) => re,
-            Err(e) => return Err(AddressResolveError::RegexError(e)),
-        };
-        if sign >= 0 {
-            l = self.r.1;
-            loc = match re.find(&self.buffer.data[l..]) {
-                Some(x) => (x.start() + l, x.end() + l),
-                None => (l, l),
-            };
-
-            if loc.0 == loc.1 && loc.0 == l {
-                l += 1;
-                if l > self.buffer.data.len() {
-                    l = 0;
-                }
-                loc = match re.find(&self.buffer.data[l..]) {
-                    Some(x) => (x.start() + l, x.end() + l),
-                    None => return Err(AddressResolveError::NoMatch),
-                }
-            }
+        let re = regex(re).map_err(AddressResolveError::RegexError)?;
+        let dir = if sign >= 0 {
+            Direction::Forward
         } else {
-            l = self.r.0;
-            let mut locs: Vec<_> = re
-                .find_iter(&self.buffer.data[..l])
-                .map(|m| (m.start(), m.end()))
-                .collect();
-            if locs.is_empty() {
-                locs.push((l, l));
-            }
-            loc = *locs.last().unwrap();
-            if loc.0 == loc.1 && loc.0 == l {
-                if l == 0 {
-                    l = self.buffer.data.len();
-                }
-                locs = re
-                    .find_iter(&self.buffer.data[..l])
-                    .map(|m| (m.start(), m.end()))
-                    .collect();
-                if locs.is_empty() {
-                    return Err(AddressResolveError::NoMatch);
-                }
-                loc = *locs.last().unwrap();
-            }
+            Direction::Backward
+        };
+        match search(self.buffer, &re, self.r, dir) {
+            Some(r) => Ok(Address {
+                r,
+                buffer: self.buffer,
+            }),
+            None => Err(AddressResolveError::NoMatch),
         }
-        Ok(Address {
-            r: Range(loc.0, loc.1),
-            buffer: self.buffer,
-        })
     }
 
     fn resolve_address(
@@ -253,6 +263,8 @@ impl<'a> Address<'a> {
                     self.r = Range(self.buffer.data.len(), self.buffer.data.len())
                 }
                 SimpleAddress::Dot => {}
+                SimpleAddress::Mark => self.r = self.buffer.mark,
+                SimpleAddress::PreviousDot => self.r = self.buffer.previous_dot,
                 SimpleAddress::Regex(re, true) => {
                     let sign = if sign == 0 { -1 } else { -sign };
                     self = self.regex_address(&re[1..], sign)?;
@@ -305,4 +317,179 @@ mod tests {
             .unwrap();
         assert_eq!(addr.r, Range(5, 15));
     }
+
+    fn resolve(
+        text: &'static str,
+        addr: &'static str,
+    ) -> Result<Range, super::AddressResolveError> {
+        let buf = new_buffer(text);
+        super::Address::new(&buf)
+            .address(new_composed_address(addr))
+            .map(|a| a.r)
+    }
+
+    #[test]
+    fn utf8_lines() {
+        assert_eq!(
+            resolve(
+                "ăâî
+șț
+",
+                "2"
+            )
+            .unwrap(),
+            Range(7, 12)
+        );
+        assert_eq!(
+            resolve(
+                "ăâî
+șț
+",
+                "2-1"
+            )
+            .unwrap(),
+            Range(0, 7)
+        );
+    }
+
+    #[test]
+    fn utf8_chars() {
+        // every letter is two bytes long
+        assert_eq!(
+            resolve(
+                "ăâî
+șț
+",
+                "#2"
+            )
+            .unwrap(),
+            Range(4, 4)
+        );
+        assert_eq!(
+            resolve(
+                "ăâî
+șț
+",
+                "#1,#3"
+            )
+            .unwrap(),
+            Range(2, 6)
+        );
+        assert_eq!(
+            resolve(
+                "ăâî
+șț
+",
+                "#5+#1"
+            )
+            .unwrap(),
+            Range(11, 11)
+        );
+        assert_eq!(
+            resolve(
+                "ăâî
+șț
+",
+                "#5-#2"
+            )
+            .unwrap(),
+            Range(6, 6)
+        );
+        assert!(resolve(
+            "ăâî
+șț
+",
+            "#8"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn utf8_regex() {
+        assert_eq!(resolve("aășb", "/ș/").unwrap(), Range(3, 5));
+        assert_eq!(resolve("aășb", "/ș/-/ă/").unwrap(), Range(1, 3));
+        // an empty match steps over the whole character
+        assert_eq!(resolve("ăăă", "/x*/").unwrap(), Range(2, 2));
+    }
+
+    fn search(text: &'static str, re: &str, dot: Range, dir: super::Direction) -> Option<Range> {
+        let buf = new_buffer(text);
+        super::search(&buf, &crate::util::regex(re).unwrap(), dot, dir)
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        use super::Direction::*;
+        // the next match after the dot, then the first one in the buffer
+        assert_eq!(
+            search("ab ab ab", "ab", Range(3, 5), Forward),
+            Some(Range(6, 8))
+        );
+        assert_eq!(
+            search("ab ab ab", "ab", Range(6, 8), Forward),
+            Some(Range(0, 2))
+        );
+        // the last match before the dot, then the last one in the buffer
+        assert_eq!(
+            search("ab ab ab", "ab", Range(3, 5), Backward),
+            Some(Range(0, 2))
+        );
+        assert_eq!(
+            search("ab ab ab", "ab", Range(0, 2), Backward),
+            Some(Range(6, 8))
+        );
+        // the only match is the dot itself
+        assert_eq!(
+            search("xx ab", "ab", Range(3, 5), Forward),
+            Some(Range(3, 5))
+        );
+        assert_eq!(
+            search("xx ab", "ab", Range(3, 5), Backward),
+            Some(Range(3, 5))
+        );
+        assert_eq!(search("xx ab", "cd", Range(0, 0), Backward), None);
+    }
+
+    #[test]
+    fn search_empty_matches() {
+        use super::Direction::*;
+        // an empty match where the search starts is skipped
+        assert_eq!(
+            search("a\nb\n", "^", Range(0, 0), Forward),
+            Some(Range(2, 2))
+        );
+        assert_eq!(
+            search("a\nb\n", "^", Range(2, 2), Backward),
+            Some(Range(0, 0))
+        );
+        assert_eq!(
+            search("a\nb\n", "^", Range(0, 0), Backward),
+            Some(Range(4, 4))
+        );
+        assert_eq!(resolve("a\nb\nc\n", "?^?").unwrap(), Range(6, 6));
+    }
+
+    #[test]
+    fn marks() {
+        let mut buf = new_buffer("one\ntwo\nthree\n");
+        buf.mark = Range(4, 8);
+        buf.previous_dot = Range(0, 4);
+        let addr = super::Address::new(&buf);
+        assert_eq!(
+            addr.address(new_composed_address("'")).unwrap().r,
+            Range(4, 8)
+        );
+        assert_eq!(
+            addr.address(new_composed_address("\"")).unwrap().r,
+            Range(0, 4)
+        );
+        assert_eq!(
+            addr.address(new_composed_address("',$")).unwrap().r,
+            Range(4, 14)
+        );
+        // changes before the mark move it
+        buf.change(Range(0, 4), false, "").unwrap();
+        buf.apply_changes(Range(0, 0));
+        assert_eq!(buf.mark, Range(0, 4));
+    }
 }
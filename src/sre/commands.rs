@@ -17,7 +17,10 @@
  */
 //! Implementations of SRE commands.
 use super::*;
+use crate::msg;
+use crate::shell;
 use crate::util::regex;
+use regex::Regex;
 use std::io::Write;
 use std::str::FromStr;
 
@@ -26,7 +29,16 @@ pub struct P;
 
 impl<'a> SimpleCommand<'a> for P {
     fn execute(&self, w: &mut Write, buffer: &mut Buffer, dot: Range) -> Result<Range, Box<Error>> {
-        write!(w, "{}", &buffer.data[dot.0..dot.1])?;
+        let text = &buffer.data[dot.0..dot.1];
+        match buffer.name() {
+            // every line starts with the name of the file, like in grep
+            Some(name) => {
+                for line in text.lines() {
+                    writeln!(w, "{}:{}", name, line)?;
+                }
+            }
+            None => write!(w, "{}", text)?,
+        }
 
         Ok(dot)
     }
@@ -45,7 +57,7 @@ impl<'a> SimpleCommand<'a> for A {
         buffer: &mut Buffer,
         dot: Range,
     ) -> Result<Range, Box<Error>> {
-        buffer.change(dot, true, &self.0);
+        buffer.change(dot, true, &self.0)?;
 
         Ok(Range(dot.1, dot.1 + self.0.len()))
     }
@@ -67,7 +79,7 @@ impl<'a> SimpleCommand<'a> for C {
         buffer: &mut Buffer,
         dot: Range,
     ) -> Result<Range, Box<Error>> {
-        buffer.change(dot, false, &self.0);
+        buffer.change(dot, false, &self.0)?;
 
         Ok(Range(dot.0, dot.0 + self.0.len()))
     }
@@ -91,7 +103,7 @@ impl<'a> SimpleCommand<'a> for I {
     ) -> Result<Range, Box<Error>> {
         let mut replacement = String::from_str(&self.0).unwrap();
         replacement.push_str(&buffer.data[dot.0..dot.1]);
-        buffer.change(dot, false, &replacement);
+        buffer.change(dot, false, &replacement)?;
 
         Ok(Range(dot.0, dot.0 + self.0.len()))
     }
@@ -113,7 +125,7 @@ impl<'a> SimpleCommand<'a> for D {
         buffer: &mut Buffer,
         dot: Range,
     ) -> Result<Range, Box<Error>> {
-        buffer.change(dot, false, "");
+        buffer.change(dot, false, "")?;
 
         Ok(Range(dot.0, dot.0))
     }
@@ -123,16 +135,55 @@ impl<'a> SimpleCommand<'a> for D {
     }
 }
 
+/// The matches of `x` or `y` to run the command on, counting from 1: the first and,
+/// if there is one, the last.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Matches(pub usize, pub Option<usize>);
+
+impl Matches {
+    /// All of them.
+    pub const ALL: Matches = Matches(1, None);
+
+    /// Parses the argument that follows the regex, like `2`, `2,4` or `2,`.
+    pub fn parse(s: &str) -> Option<Matches> {
+        let n = |s: &str| s.parse::<usize>().ok().filter(|&n| n > 0);
+        match s.split_once(',') {
+            Some((first, "")) => Some(Matches(n(first)?, None)),
+            Some((first, last)) => {
+                let (first, last) = (n(first)?, n(last)?);
+                Some(Matches(first, Some(last))).filter(|_| last >= first)
+            }
+            None => n(s).map(|n| Matches(n, Some(n))),
+        }
+    }
+
+    fn contains(&self, n: usize) -> bool {
+        n >= self.0 && self.1.is_none_or(|last| n <= last)
+    }
+}
+
+/// Returns where the regex matches the text. Big texts are searched with several
+/// threads if the shell was built with the `parallel` feature.
+fn find_matches(re: &Regex, text: &str) -> Vec<(usize, usize)> {
+    #[cfg(feature = "parallel")]
+    {
+        if text.len() >= super::parallel::THRESHOLD {
+            return super::parallel::find_all(re, text);
+        }
+    }
+    re.find_iter(text).map(|m| (m.start(), m.end())).collect()
+}
+
 #[derive(Debug)]
-pub struct X(pub String, pub SRECommand, pub bool);
+pub struct X(pub String, pub SRECommand, pub bool, pub Matches);
 
 impl<'a> SimpleCommand<'a> for X {
     fn execute(&self, w: &mut Write, buffer: &mut Buffer, dot: Range) -> Result<Range, Box<Error>> {
         let re = regex(&self.0)?;
         let mut addresses = Vec::new();
-        let mut last_match = 0;
-        for m in re.find_iter(&buffer.data[dot.0..dot.1]) {
-            let r = Range(m.start(), m.end());
+        let mut last_match = dot.0;
+        for (start, end) in find_matches(&re, &buffer.data[dot.0..dot.1]) {
+            let r = Range(dot.0 + start, dot.0 + end);
             if !self.2 {
                 addresses.push(r);
             } else if r.0 - last_match > 0 {
@@ -144,7 +195,11 @@ impl<'a> SimpleCommand<'a> for X {
             addresses.push(Range(last_match, dot.1));
         }
         let mut last: Option<Range> = None;
-        for addr in addresses {
+        for (_, addr) in addresses
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.3.contains(i + 1))
+        {
             let iv = Invocation::new(self.1.clone(), buffer, Some(addr))?;
             last = Some(iv.execute(w, buffer)?);
         }
@@ -202,18 +257,141 @@ impl<'a> SimpleCommand<'a> for Conditional {
 pub struct Equals;
 
 impl<'a> SimpleCommand<'a> for Equals {
+    fn execute(&self, w: &mut Write, buffer: &mut Buffer, dot: Range) -> Result<Range, Box<Error>> {
+        if let Some(name) = buffer.name() {
+            write!(w, "{}:", name)?;
+        }
+        writeln!(
+            w,
+            "#{},#{}",
+            buffer.char_offset(dot.0),
+            buffer.char_offset(dot.1)
+        )?;
+        Ok(dot)
+    }
+
+    fn to_tuple(&self) -> (char, LinkedList<String>) {
+        ('=', LinkedList::new())
+    }
+}
+
+#[derive(Debug)]
+/// Steps to the line after the dot.
+pub struct N;
+
+impl<'a> SimpleCommand<'a> for N {
     fn execute(
         &self,
-        w: &mut Write,
-        _buffer: &mut Buffer,
+        _w: &mut Write,
+        buffer: &mut Buffer,
         dot: Range,
     ) -> Result<Range, Box<Error>> {
-        writeln!(w, "#{},#{}", dot.0, dot.1)?;
+        Ok(Address::from_range(buffer, dot).next_line()?.range())
+    }
+
+    fn to_tuple(&self) -> (char, LinkedList<String>) {
+        ('n', LinkedList::new())
+    }
+}
+
+#[derive(Debug)]
+/// Marks the dot, so that `'` can address it later.
+pub struct K;
+
+impl<'a> SimpleCommand<'a> for K {
+    fn execute(
+        &self,
+        _w: &mut dyn Write,
+        buffer: &mut Buffer,
+        dot: Range,
+    ) -> Result<Range, Box<dyn Error>> {
+        buffer.mark = dot;
         Ok(dot)
     }
 
     fn to_tuple(&self) -> (char, LinkedList<String>) {
-        ('=', LinkedList::new())
+        ('k', LinkedList::new())
+    }
+}
+
+#[derive(Debug)]
+/// Replaces the dot with the contents of the file.
+pub struct R(pub String);
+
+impl<'a> SimpleCommand<'a> for R {
+    fn execute(
+        &self,
+        _w: &mut dyn Write,
+        buffer: &mut Buffer,
+        dot: Range,
+    ) -> Result<Range, Box<dyn Error>> {
+        let text =
+            std::fs::read_to_string(&self.0).map_err(|e| msg!("sre.file-error", self.0, e))?;
+        buffer.change(dot, false, &text)?;
+        Ok(Range(dot.0, dot.0 + text.len()))
+    }
+
+    fn to_tuple(&self) -> (char, LinkedList<String>) {
+        let mut list = LinkedList::new();
+        list.push_back(self.0.clone());
+        ('r', list)
+    }
+}
+
+#[derive(Debug)]
+/// Runs a command line, like in sam. `|cmd` replaces the dot with what the command
+/// prints when given the dot as input, `<cmd` replaces it with what the command prints
+/// without any input, and `>cmd` gives it the dot and prints what it prints.
+pub struct Pipe(pub char, pub String);
+
+impl<'a> SimpleCommand<'a> for Pipe {
+    fn execute(
+        &self,
+        w: &mut dyn Write,
+        buffer: &mut Buffer,
+        dot: Range,
+    ) -> Result<Range, Box<dyn Error>> {
+        let input = Some(buffer.text(dot).as_bytes()).filter(|_| self.0 != '<');
+        let out =
+            shell::run_command(&self.1, input).map_err(|e| msg!("sre.command-error", self.1, e))?;
+        if self.0 == '>' {
+            w.write_all(&out)?;
+            return Ok(dot);
+        }
+        let text = String::from_utf8_lossy(&out);
+        buffer.change(dot, false, &text)?;
+        Ok(Range(dot.0, dot.0 + text.len()))
+    }
+
+    fn to_tuple(&self) -> (char, LinkedList<String>) {
+        let mut list = LinkedList::new();
+        list.push_back(self.1.clone());
+        (self.0, list)
+    }
+}
+
+#[derive(Debug)]
+/// Writes the dot to the file, replacing what it had.
+pub struct W(pub String);
+
+impl<'a> SimpleCommand<'a> for W {
+    fn execute(
+        &self,
+        _w: &mut dyn Write,
+        buffer: &mut Buffer,
+        dot: Range,
+    ) -> Result<Range, Box<dyn Error>> {
+        if !buffer.dry_run {
+            std::fs::write(&self.0, buffer.text(dot))
+                .map_err(|e| msg!("sre.write-error", self.0, e))?;
+        }
+        Ok(dot)
+    }
+
+    fn to_tuple(&self) -> (char, LinkedList<String>) {
+        let mut list = LinkedList::new();
+        list.push_back(self.0.clone());
+        ('w', list)
     }
 }
 
@@ -229,4 +407,35 @@ mod tests {
         p.execute(&mut w, &mut b, addr).unwrap();
         assert_eq!(String::from_utf8_lossy(&w[..]), "xd");
     }
+
+    #[test]
+    fn next_line() {
+        let mut b = super::Buffer::new("one\ntwo\nthree\n".as_bytes()).unwrap();
+        let (first, second, last, end) = (
+            b.new_address(0, 4).range(),
+            b.new_address(4, 8).range(),
+            b.new_address(8, 14).range(),
+            b.new_address(14, 14).range(),
+        );
+        let mut w = Vec::new();
+        assert_eq!(super::N.execute(&mut w, &mut b, first).unwrap(), second);
+        let start = b.new_address(0, 0).range();
+        assert_eq!(super::N.execute(&mut w, &mut b, start).unwrap(), first);
+        // past the last line is the empty line at the end
+        assert_eq!(super::N.execute(&mut w, &mut b, last).unwrap(), end);
+    }
+
+    #[test]
+    fn matches() {
+        use super::Matches;
+        assert_eq!(Matches::parse("2"), Some(Matches(2, Some(2))));
+        assert_eq!(Matches::parse("2,4"), Some(Matches(2, Some(4))));
+        assert_eq!(Matches::parse("3,"), Some(Matches(3, None)));
+        assert_eq!(Matches::parse("0"), None);
+        assert_eq!(Matches::parse("4,2"), None);
+        assert_eq!(Matches::parse(",2"), None);
+        assert!(Matches(2, Some(4)).contains(3));
+        assert!(!Matches(2, Some(4)).contains(5));
+        assert!(Matches::ALL.contains(100));
+    }
 }
@@ -20,7 +20,10 @@
 //! We will use the "SRE" abbreviation from now on.
 pub mod address;
 pub mod commands;
+#[cfg(feature = "parallel")]
+mod parallel;
 
+use crate::parser::sre::address::ComposedAddress;
 use crate::parser::sre::CompleteCommand as SRECommand;
 use address::Address;
 use address::AddressResolveError;
@@ -60,18 +63,40 @@ impl PartialOrd for Change {
 }
 
 impl PartialEq for Change {
-    // a bit of a hack, in order to let the BTreeHash tell us
-    // if there are any intersecting changes,
-    // we will consider intersecting changes equal
+    // like the ordering, only the position counts: the set holds one change at each
+    // position, and `Buffer::change` looks for intersecting ones
     fn eq(&self, rhs: &Change) -> bool {
-        use std::cmp::{max, min};
-        let left = max(self.pos, rhs.pos);
-        let right = min(self.pos + self.deleted - 1, rhs.pos + rhs.deleted - 1);
+        self.pos == rhs.pos
+    }
+}
+
+impl Change {
+    /// Returns the range of the original text that the change replaces.
+    fn range(&self) -> Range {
+        Range(self.pos, self.pos + self.deleted)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// Two changes to the buffer that intersect, and so can't both be applied: the range
+/// of the change that was already there and the range of the new one.
+pub struct ChangeConflict {
+    pub existing: Range,
+    pub new: Range,
+}
 
-        left > right
+impl std::fmt::Display for ChangeConflict {
+    fn fmt(&self, w: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(
+            w,
+            "changes not in sequence: ({}, {}) and ({}, {}) intersect",
+            self.existing.0, self.existing.1, self.new.0, self.new.1
+        )
     }
 }
 
+impl Error for ChangeConflict {}
+
 #[derive(Debug)]
 /// The buffer holds the text that we are operating on.
 ///
@@ -79,6 +104,13 @@ impl PartialEq for Change {
 pub struct Buffer {
     data: String,
     changes: BTreeSet<Change>,
+    name: Option<String>,
+    /// The range marked by the `k` command, addressed by `'`.
+    pub mark: Range,
+    /// The dot before the last command that moved it, addressed by `"`.
+    pub previous_dot: Range,
+    /// In a dry run, `w` doesn't write anything.
+    pub dry_run: bool,
 }
 
 impl Buffer {
@@ -88,9 +120,25 @@ impl Buffer {
         Ok(Buffer {
             data: s,
             changes: BTreeSet::new(),
+            name: None,
+            mark: Range(0, 0),
+            previous_dot: Range(0, 0),
+            dry_run: false,
         })
     }
 
+    /// Names the buffer after the file it was read from. `p` and `=` start
+    /// what they print with the name, so that the output of several files can be told apart.
+    pub fn with_name(mut self, name: &str) -> Buffer {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Returns the name of the file, if the buffer was read from one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Returns a new address in this buffer.
     pub fn new_address(&self, l: usize, r: usize) -> Address {
         Address {
@@ -99,35 +147,47 @@ impl Buffer {
         }
     }
 
-    /// Returns `true` if the new change didn't intersect any
-    pub fn change(&mut self, dot: Range, append: bool, content: &str) -> bool {
-        if append {
-            self.changes.insert(Change {
-                pos: dot.1,
-                deleted: 0,
-                content: String::from_str(content).unwrap(),
-            })
+    /// Records a change of the dot, or, if `append`, an insertion after it, to be made
+    /// by `apply_changes`.
+    ///
+    /// Like in sam, the changes of a command must be in sequence: a change that
+    /// intersects one that was already made is refused.
+    pub fn change(
+        &mut self,
+        dot: Range,
+        append: bool,
+        content: &str,
+    ) -> Result<(), ChangeConflict> {
+        let change = if append {
+            Change::new(dot.1, 0, content)
         } else {
-            self.changes.insert(Change {
-                pos: dot.0,
-                deleted: dot.1 - dot.0,
-                content: String::from_str(content).unwrap(),
-            })
+            Change::new(dot.0, dot.1 - dot.0, content)
+        };
+        let before = self.changes.range(..=&change).next_back();
+        let after = self.changes.range(&change..).find(|c| c.pos > change.pos);
+        let conflict = before
+            .filter(|c| c.pos == change.pos || c.pos + c.deleted > change.pos)
+            .or_else(|| after.filter(|c| change.pos + change.deleted > c.pos));
+        if let Some(c) = conflict {
+            return Err(ChangeConflict {
+                existing: c.range(),
+                new: change.range(),
+            });
         }
+        self.changes.insert(change);
+        Ok(())
     }
 
     /// Applies the changes on the buffer.
     ///
     /// The changes must not be intersecting.
-    pub fn apply_changes(&mut self, mut dot: Range) -> Range {
+    pub fn apply_changes(&mut self, dot: Range) -> Range {
         let mut new_data = Vec::<u8>::new();
         let mut last_index: usize = 0;
-        let original = dot;
+        let dot = self.shifted(dot);
+        let mark = self.shifted(self.mark);
+        let previous_dot = self.shifted(self.previous_dot);
         for c in &self.changes {
-            if c.pos < original.0 {
-                dot.0 = dot.0 + c.content.len() - c.deleted;
-                dot.1 = dot.1 + c.content.len() - c.deleted;
-            }
             for i in last_index..c.pos {
                 new_data.push(self.data.as_bytes()[i]);
             }
@@ -141,8 +201,72 @@ impl Buffer {
         }
         self.data = String::from_utf8_lossy(&new_data).to_string();
         self.changes.clear();
+        // the changes could have eaten the marked text
+        self.mark = self.clamped(mark);
+        self.previous_dot = self.clamped(previous_dot);
         dot
     }
+
+    /// Moves the range over the text that the changes before it add or delete.
+    fn shifted(&self, mut r: Range) -> Range {
+        let original = r;
+        for c in self.changes.iter().take_while(|c| c.pos < original.0) {
+            r.0 = (r.0 + c.content.len()).saturating_sub(c.deleted);
+            r.1 = (r.1 + c.content.len()).saturating_sub(c.deleted);
+        }
+        r
+    }
+
+    /// Keeps the range inside the text and on character boundaries.
+    fn clamped(&self, r: Range) -> Range {
+        let fit = |mut p: usize| {
+            p = p.min(self.data.len());
+            while !self.data.is_char_boundary(p) {
+                p -= 1;
+            }
+            p
+        };
+        Range(fit(r.0), fit(r.1).max(fit(r.0)))
+    }
+
+    /// Returns the whole text.
+    pub fn contents(&self) -> &str {
+        &self.data
+    }
+
+    /// Returns the text in the range.
+    pub fn text(&self, r: Range) -> &str {
+        &self.data[r.0..r.1]
+    }
+
+    /// Returns the number of characters before the byte offset. Ranges hold byte
+    /// offsets, but the `#n` addresses count characters.
+    pub fn char_offset(&self, pos: usize) -> usize {
+        self.data[..pos].chars().count()
+    }
+
+    /// Returns the byte offset `n` characters after `pos`, or `None` if the text ends
+    /// before.
+    pub fn forward(&self, pos: usize, n: usize) -> Option<usize> {
+        if n == 0 {
+            return Some(pos);
+        }
+        let mut chars = self.data[pos..].char_indices().skip(n - 1);
+        chars.next().map(|(i, c)| pos + i + c.len_utf8())
+    }
+
+    /// Returns the byte offset `n` characters before `pos`, or `None` if the text
+    /// starts after.
+    pub fn backward(&self, pos: usize, n: usize) -> Option<usize> {
+        if n == 0 {
+            return Some(pos);
+        }
+        self.data[..pos]
+            .char_indices()
+            .rev()
+            .nth(n - 1)
+            .map(|(i, _)| i)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -170,13 +294,7 @@ impl<'a> Invocation<'a> {
         buf: &Buffer,
         address: Option<Range>,
     ) -> Result<Invocation<'a>, AddressResolveError> {
-        let address = match address {
-            Some(x) => x,
-            None => Address::new(buf).range(),
-        };
-        let address = Address::from_range(buf, address)
-            .address(parsed.address)?
-            .range();
+        let address = resolve(buf, parsed.address, address)?;
         if address.1 > buf.data.len() {
             panic!(
                 "bad range ({}, {}) out of buffer (0, {})",
@@ -185,6 +303,12 @@ impl<'a> Invocation<'a> {
                 buf.data.len()
             );
         }
+        let matches = match parsed.string_args.get(1) {
+            Some(s) if parsed.name == 'x' || parsed.name == 'y' => {
+                commands::Matches::parse(s).unwrap_or(commands::Matches::ALL)
+            }
+            _ => commands::Matches::ALL,
+        };
         Ok(Invocation {
             address,
             simple: match parsed.name {
@@ -198,11 +322,13 @@ impl<'a> Invocation<'a> {
                     parsed.string_args[0].clone(),
                     parsed.command_args[0].clone(),
                     false,
+                    matches,
                 )),
                 'y' => Box::new(commands::X(
                     parsed.string_args[0].clone(),
                     parsed.command_args[0].clone(),
                     true,
+                    matches,
                 )),
 
                 'g' => Box::new(commands::Conditional(
@@ -219,6 +345,13 @@ impl<'a> Invocation<'a> {
                 '{' => Box::new(commands::Brace(parsed.command_args)),
 
                 '=' => Box::new(commands::Equals),
+                'n' => Box::new(commands::N),
+                'k' => Box::new(commands::K),
+                'r' => Box::new(commands::R(parsed.string_args[0].clone())),
+                'w' => Box::new(commands::W(parsed.string_args[0].clone())),
+                '|' | '<' | '>' => {
+                    Box::new(commands::Pipe(parsed.name, parsed.string_args[0].clone()))
+                }
                 _ => unimplemented!(),
             },
         })
@@ -229,6 +362,43 @@ impl<'a> Invocation<'a> {
     }
 }
 
+/// Resolves an address starting from `dot`, or from the start of the buffer if there is no dot yet.
+pub fn resolve(
+    buf: &Buffer,
+    address: ComposedAddress,
+    dot: Option<Range>,
+) -> Result<Range, AddressResolveError> {
+    let dot = dot.unwrap_or_else(|| Address::new(buf).range());
+    Ok(Address::from_range(buf, dot).address(address)?.range())
+}
+
+/// Shows the text of the dot, or its position if it is empty.
+pub fn show_dot(w: &mut Write, buf: &Buffer, dot: Range) -> io::Result<()> {
+    let text = buf.text(dot);
+    if text.is_empty() {
+        writeln!(w, "#{}", buf.char_offset(dot.0))
+    } else if text.ends_with('\n') {
+        write!(w, "{}", text)
+    } else {
+        writeln!(w, "{}", text)
+    }
+}
+
+/// Runs a command on the buffer, starting from `dot`, and returns the dot after the changes.
+pub fn run_command(
+    cmd: SRECommand,
+    buf: &mut Buffer,
+    dot: Option<Range>,
+    w: &mut Write,
+) -> Result<Range, Box<Error>> {
+    let addr = Invocation::new(cmd, buf, dot)?.execute(w, buf)?;
+    w.flush()?;
+    if let Some(dot) = dot.filter(|&dot| dot != addr) {
+        buf.previous_dot = dot;
+    }
+    Ok(buf.apply_changes(addr))
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -236,4 +406,30 @@ mod tests {
         let b = super::Buffer::new("xd lol".as_bytes()).unwrap();
         assert_eq!(b.data, "xd lol");
     }
+
+    #[test]
+    fn change_conflicts() {
+        use super::{ChangeConflict, Range};
+        let mut b = super::Buffer::new("one two three".as_bytes()).unwrap();
+        b.change(Range(4, 7), false, "2").unwrap();
+        // next to the change is fine, on either side
+        b.change(Range(0, 4), false, "1 ").unwrap();
+        b.change(Range(4, 7), true, "!").unwrap();
+        assert_eq!(
+            b.change(Range(6, 9), false, ""),
+            Err(ChangeConflict {
+                existing: Range(4, 7),
+                new: Range(6, 9),
+            })
+        );
+        assert_eq!(
+            b.change(Range(2, 5), false, ""),
+            Err(ChangeConflict {
+                existing: Range(0, 4),
+                new: Range(2, 5),
+            })
+        );
+        b.apply_changes(Range(0, 0));
+        assert_eq!(b.data, "1 2! three");
+    }
 }
@@ -0,0 +1,150 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Finding the matches of `x` and `y` in big texts with several threads.
+//!
+//! The text is split in chunks at line boundaries, and every chunk is searched on its
+//! own. A match can go on past the end of its chunk, and then the next chunk is searched
+//! again from where it ends, so the matches are the same as those of `find_iter`.
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use regex::Regex;
+
+/// Texts shorter than this are searched by one thread, starting the others costs more.
+pub const THRESHOLD: usize = 1 << 20;
+
+/// The chunks are about this big.
+const CHUNK: usize = 256 * 1024;
+
+fn next_char(text: &str, pos: usize) -> usize {
+    pos + text[pos..].chars().next().map_or(1, char::len_utf8)
+}
+
+/// Finds the matches that start before `end`, searching from `pos` like `find_iter` does
+/// after a match that ended at `last`.
+fn matches_in(
+    re: &Regex,
+    text: &str,
+    mut pos: usize,
+    end: usize,
+    mut last: Option<usize>,
+) -> Vec<(usize, usize)> {
+    let mut v = Vec::new();
+    while pos <= text.len() {
+        let m = match re.find_at(text, pos) {
+            Some(m) if m.start() < end => m,
+            _ => break,
+        };
+        let empty = m.start() == m.end();
+        pos = if empty {
+            next_char(text, m.end())
+        } else {
+            m.end()
+        };
+        // an empty match right where the previous one ended doesn't count
+        if empty && Some(m.end()) == last {
+            continue;
+        }
+        last = Some(m.end());
+        v.push((m.start(), m.end()));
+    }
+    v
+}
+
+/// Returns where the chunks start: after the first newline that comes `chunk` bytes
+/// after the start of the previous one.
+fn boundaries(text: &str, chunk: usize) -> Vec<usize> {
+    let mut v = vec![0];
+    let mut p = chunk;
+    while p < text.len() {
+        match text.as_bytes()[p..].iter().position(|&b| b == b'\n') {
+            Some(i) if p + i + 1 < text.len() => {
+                v.push(p + i + 1);
+                p += i + 1 + chunk;
+            }
+            _ => break,
+        }
+    }
+    v
+}
+
+fn find_chunked(re: &Regex, text: &str, chunk: usize) -> Vec<(usize, usize)> {
+    let starts = boundaries(text, chunk);
+    // an empty match can be at the very end of the text
+    let end = |i: usize| starts.get(i + 1).copied().unwrap_or(text.len() + 1);
+    let search = || {
+        (0..starts.len())
+            .into_par_iter()
+            .map(|i| matches_in(re, text, starts[i], end(i), None))
+            .collect::<Vec<_>>()
+    };
+    // a new pool every time, because the threads of a global one would be missing in
+    // the processes that the shell forks after using it
+    let lists = match ThreadPoolBuilder::new().build() {
+        Ok(pool) => pool.install(search),
+        Err(_) => return matches_in(re, text, 0, text.len() + 1, None),
+    };
+    let mut all: Vec<(usize, usize)> = Vec::new();
+    for (i, list) in lists.into_iter().enumerate() {
+        let start = starts[i];
+        match all.last() {
+            // the last match goes on into this chunk, which is searched again after it
+            Some(&(_, e)) if e > start => all.extend(matches_in(re, text, e, end(i), Some(e))),
+            Some(&(_, e)) if e == start => {
+                all.extend(list.into_iter().filter(|&(s, e)| s != e || s != start))
+            }
+            _ => all.extend(list),
+        }
+    }
+    all
+}
+
+/// Returns the start and the end of every match in the text, in order.
+pub fn find_all(re: &Regex, text: &str) -> Vec<(usize, usize)> {
+    find_chunked(re, text, CHUNK)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::regex;
+
+    fn check(re: &str, text: &str) {
+        let re = regex(re).unwrap();
+        let serial: Vec<_> = re.find_iter(text).map(|m| (m.start(), m.end())).collect();
+        for chunk in 1..8 {
+            assert_eq!(
+                super::find_chunked(&re, text, chunk),
+                serial,
+                "chunk {}",
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn same_as_serial() {
+        let text = "one two\nthree\n\nfour five six\nseven\năâî\n";
+        check(r"\w+", text);
+        check("^", text);
+        check("$", text);
+        check("x*", text);
+        // matches that go past the end of their chunk
+        check(r"o[^x]*e", text);
+        check(r"\n\n", text);
+        check("(?s).*", text);
+    }
+}
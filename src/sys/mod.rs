@@ -0,0 +1,36 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! The parts of the shell that need the operating system: starting processes,
+//! plumbing their file descriptors, signals and terminals.
+//!
+//! The rest of the shell reaches the system only through here, so that the parser,
+//! the expansion of words, the SRE engine and the builtins that only compute also
+//! build for wasm32, where the functions that need processes fail with
+//! `UnsupportedOperation`. The builtins that talk to terminals, sockets and other
+//! processes on their own, such as `pty` and `net`, are only built on Unix.
+#[cfg(unix)]
+pub mod net;
+#[cfg(unix)]
+mod unix;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+pub mod watch;
+#[cfg(unix)]
+pub use self::unix::*;
+#[cfg(target_arch = "wasm32")]
+pub use self::wasm::*;
@@ -0,0 +1,179 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Connections over TCP and Unix sockets, for scripts that talk to the network.
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+/// Where to connect to or listen on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Address {
+    Tcp(String, u16),
+    Unix(PathBuf),
+}
+
+/// An open connection.
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn connect(address: &Address) -> io::Result<Stream> {
+        match address {
+            Address::Tcp(host, port) => TcpStream::connect((host.as_str(), *port)).map(Stream::Tcp),
+            Address::Unix(path) => UnixStream::connect(path).map(Stream::Unix),
+        }
+    }
+
+    /// The address of the other end, or an empty string if it has none, like the
+    /// unnamed clients of Unix sockets.
+    pub fn peer(&self) -> String {
+        match self {
+            Stream::Tcp(s) => s.peer_addr().map(|a| a.to_string()).unwrap_or_default(),
+            Stream::Unix(s) => s
+                .peer_addr()
+                .ok()
+                .and_then(|a| a.as_pathname().map(|p| p.display().to_string()))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Stream::Tcp(s) => s.as_raw_fd(),
+            Stream::Unix(s) => s.as_raw_fd(),
+        }
+    }
+}
+
+/// A socket waiting for connections. The file of a Unix socket is removed when
+/// the listener is dropped.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    pub fn bind(address: &Address) -> io::Result<Listener> {
+        match address {
+            Address::Tcp(host, port) => {
+                TcpListener::bind((host.as_str(), *port)).map(Listener::Tcp)
+            }
+            Address::Unix(path) => {
+                UnixListener::bind(path).map(|l| Listener::Unix(l, path.clone()))
+            }
+        }
+    }
+
+    /// Waits for the next connection.
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(l) => l.accept().map(|(s, _)| Stream::Tcp(s)),
+            Listener::Unix(l, _) => l.accept().map(|(s, _)| Stream::Unix(s)),
+        }
+    }
+
+    /// The address it listens on, with the port chosen by the system if 0 was asked for.
+    pub fn address(&self) -> io::Result<Address> {
+        match self {
+            Listener::Tcp(l) => l
+                .local_addr()
+                .map(|a| Address::Tcp(a.ip().to_string(), a.port())),
+            Listener::Unix(_, path) => Ok(Address::Unix(path.clone())),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Address, Listener, Stream};
+    use std::io::{Read, Write};
+    use std::thread;
+
+    fn echo_once(address: Address) {
+        let listener = Listener::bind(&address).unwrap();
+        let address = listener.address().unwrap();
+        let client = thread::spawn(move || {
+            let mut s = Stream::connect(&address).unwrap();
+            s.write_all(b"ping").unwrap();
+            let mut buf = [0; 4];
+            s.read_exact(&mut buf).unwrap();
+            buf
+        });
+        let mut s = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        s.read_exact(&mut buf).unwrap();
+        s.write_all(&buf).unwrap();
+        assert_eq!(&client.join().unwrap(), b"ping");
+    }
+
+    #[test]
+    fn tcp() {
+        echo_once(Address::Tcp("127.0.0.1".to_owned(), 0));
+    }
+
+    #[test]
+    fn unix() {
+        let dir = std::env::temp_dir().join(format!("rwsh-net-{}", std::process::id()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("sock");
+        echo_once(Address::Unix(path.clone()));
+        // the listener removed its socket
+        assert!(!path.exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,475 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::util::os2c;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{self, AccessFlags};
+use std::env;
+use std::ffi::{CStr, CString, OsStr};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::time::Duration;
+
+pub use nix::unistd::{ForkResult, Pid};
+pub use nix::Error;
+pub use std::os::unix::io::RawFd;
+
+/// The lowest file descriptor used to keep the redirected ones,
+/// out of the way of the file descriptors given in redirections.
+const SAVED_FD_BASE: RawFd = 10;
+
+fn c_strings(strings: &[String]) -> Vec<CString> {
+    strings.iter().map(|s| os2c(OsStr::new(s))).collect()
+}
+
+/// Makes a copy of the shell process, which keeps running shell code.
+pub fn fork() -> Result<ForkResult, Error> {
+    unistd::fork()
+}
+
+/// Starts the executable at `path` in a new process, with `outputs` as its standard
+/// output and error, and returns its pid.
+/// The child exits with 127 if the executable can't be run.
+pub fn spawn(
+    path: &Path,
+    args: &[String],
+    env: &[String],
+    outputs: (RawFd, RawFd),
+) -> Result<Pid, Error> {
+    match fork()? {
+        ForkResult::Child => {
+            let _ = set_outputs(outputs.0, outputs.1);
+            let e = unistd::execve(&os2c(path.as_os_str()), &c_strings(args), &c_strings(env))
+                .unwrap_err();
+            eprintln!("{}", msg!("exec.error", args[0], e));
+            std::process::exit(127);
+        }
+        ForkResult::Parent { child } => Ok(child),
+    }
+}
+
+/// Returns the home directory of `user`, or `None` if there is no such user.
+pub fn home_dir(user: &str) -> Result<Option<PathBuf>, Errno> {
+    let name = match CString::new(user) {
+        Ok(name) => name,
+        Err(_) => return Ok(None),
+    };
+    // getpwnam leaves errno alone when there is no such user
+    let p = unsafe {
+        Errno::clear();
+        libc::getpwnam(name.as_ptr())
+    };
+    if p.is_null() {
+        return match Errno::last() {
+            Errno::UnknownErrno => Ok(None),
+            e => Err(e),
+        };
+    }
+    let dir = unsafe { CStr::from_ptr((*p).pw_dir) };
+    Ok(Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes()))))
+}
+
+/// Blocks until the child terminates and returns its status, like `$?`.
+pub fn wait(pid: Pid) -> Result<i32, Error> {
+    match waitpid(pid, None)? {
+        WaitStatus::Exited(_, code) => Ok(code),
+        WaitStatus::Signaled(_, sig, _) => Ok(128 + sig as i32),
+        _ => Ok(1),
+    }
+}
+
+/// What became of a child process, as far as the shell can tell without waiting for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChildStatus {
+    Running,
+    /// It terminated with this status, like `$?`.
+    Terminated(i32),
+    /// Somebody else collected its status, or it wasn't a child of the shell,
+    /// so there is no status to tell.
+    Lost,
+}
+
+/// Collects the status of the child if it terminated, without blocking.
+pub fn try_wait(pid: Pid) -> Result<ChildStatus, Error> {
+    match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+        Ok(WaitStatus::Exited(_, code)) => Ok(ChildStatus::Terminated(code)),
+        Ok(WaitStatus::Signaled(_, sig, _)) => Ok(ChildStatus::Terminated(128 + sig as i32)),
+        Ok(_) => Ok(ChildStatus::Running),
+        Err(Error::Sys(Errno::ECHILD)) => Ok(ChildStatus::Lost),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the pid of the shell, which changes in forked children.
+pub fn pid() -> Pid {
+    unistd::getpid()
+}
+
+/// Makes `stdout` and `stderr` the descriptors 1 and 2 of a forked child.
+pub fn set_outputs(stdout: RawFd, mut stderr: RawFd) -> Result<(), Error> {
+    // `1>&2 2>&3` swaps them, and the first copy must not clobber the second
+    if stderr == 1 && stdout != 1 {
+        stderr = unistd::dup(stderr)?;
+    }
+    if stdout != 1 {
+        unistd::dup2(stdout, 1)?;
+    }
+    if stderr != 2 {
+        unistd::dup2(stderr, 2)?;
+    }
+    Ok(())
+}
+
+/// Returns the read and write ends of a new pipe, closed on exec.
+pub fn pipe() -> Result<(RawFd, RawFd), Error> {
+    unistd::pipe2(OFlag::O_CLOEXEC)
+}
+
+/// Makes `fd` refer to the file of `to`, and closes `to`.
+/// Used by forked children, which don't need the old file back.
+pub fn move_fd(to: RawFd, fd: RawFd) -> Result<(), Error> {
+    unistd::dup2(to, fd)?;
+    unistd::close(to)
+}
+
+pub fn close(fd: RawFd) -> Result<(), Error> {
+    unistd::close(fd)
+}
+
+/// Reads from `fd` without buffering.
+pub fn read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// Writes to `fd` without buffering.
+pub fn write(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    let n = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// Opens the file at `path` and returns its descriptor, which the caller closes.
+/// A new file can be read and written by everyone the umask allows.
+pub fn open(path: &Path, options: &mut OpenOptions) -> io::Result<RawFd> {
+    Ok(options.mode(0o666).open(path)?.into_raw_fd())
+}
+
+/// Returns whether `fd` is a terminal.
+pub fn isatty(fd: RawFd) -> bool {
+    unistd::isatty(fd).unwrap_or(false)
+}
+
+/// Returns whether the file at `path` is one that the shell may execute.
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file() && unistd::access(path, AccessFlags::X_OK).is_ok()
+}
+
+/// Returns whether the error is a system call interrupted by a signal.
+pub fn is_interrupted(e: &Error) -> bool {
+    *e == Error::Sys(Errno::EINTR)
+}
+
+/// Returns an unnamed temporary file. Unlike a pipe, it doesn't fill up
+/// while nothing reads it.
+pub fn temp_file() -> Result<File, Error> {
+    let (fd, path) = unistd::mkstemp(&env::temp_dir().join("rwsh.XXXXXX"))?;
+    let _ = unistd::unlink(&path);
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Keeps the file open for the rest of the shell's life, under a descriptor that
+/// redirections don't use, and returns the descriptor.
+pub fn keep_open(file: File) -> Result<RawFd, Error> {
+    fcntl(file.as_raw_fd(), FcntlArg::F_DUPFD_CLOEXEC(SAVED_FD_BASE))
+}
+
+/// Redirected file descriptors, with copies of what they were before, if they were open.
+#[derive(Default)]
+pub struct SavedFds(Vec<(RawFd, Option<RawFd>)>);
+
+impl SavedFds {
+    /// Makes `fd` refer to the file of `to`, keeping a copy of it to be restored.
+    pub fn redirect(&mut self, fd: RawFd, to: RawFd) -> Result<(), Error> {
+        let copy = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(SAVED_FD_BASE)).ok();
+        self.0.push((fd, copy));
+        unistd::dup2(to, fd)?;
+        Ok(())
+    }
+
+    /// Puts the file descriptors back, in the reverse order of their redirection.
+    pub fn restore(mut self) {
+        self.put_back();
+    }
+
+    fn put_back(&mut self) {
+        for (fd, copy) in self.0.drain(..).rev() {
+            match copy {
+                Some(copy) => {
+                    let _ = unistd::dup2(copy, fd);
+                    let _ = unistd::close(copy);
+                }
+                None => {
+                    let _ = unistd::close(fd);
+                }
+            }
+        }
+    }
+}
+
+/// The file descriptors are also put back when a command panics before restoring them.
+impl Drop for SavedFds {
+    fn drop(&mut self) {
+        self.put_back();
+    }
+}
+
+// Child processes are reaped only after they are known to have changed state.
+// The `SIGCHLD` handler writes a byte to the pipe of every shell of the process (the
+// self-pipe trick), which the shell waits on.
+
+/// How many [`Reaper`](struct.Reaper.html)s can be alive at once, one for each shell.
+const MAX_REAPERS: usize = 64;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// The write ends of the pipes of the live reapers. A child can belong to any of the
+/// shells of the process, so the `SIGCHLD` handler wakes them all.
+static SIGCHLD_FDS: [AtomicI32; MAX_REAPERS] = [NO_FD; MAX_REAPERS];
+
+/// How many `SIGCHLD` handlers are running, so that a reaper doesn't close its pipe
+/// while a handler is about to write to it.
+static IN_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_sigchld(_: libc::c_int) {
+    IN_HANDLER.fetch_add(1, Ordering::SeqCst);
+    let errno = unsafe { *libc::__errno_location() };
+    for slot in SIGCHLD_FDS.iter() {
+        let fd = slot.load(Ordering::SeqCst);
+        if fd >= 0 {
+            // write(2) is async-signal-safe. If the pipe is full, a wake-up is pending anyway.
+            unsafe {
+                libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1);
+            }
+        }
+    }
+    unsafe {
+        *libc::__errno_location() = errno;
+    }
+    IN_HANDLER.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Lets the shell sleep until a child process changes state.
+///
+/// Each shell has its own pipe, which is closed when the reaper is dropped.
+pub struct Reaper {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Reaper {
+    /// Creates the pipe, registers it with the `SIGCHLD` handler and installs the handler.
+    pub fn new() -> Result<Reaper, Error> {
+        let (read, write) = unistd::pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK)?;
+        let reaper = Reaper { read, write };
+        let registered = SIGCHLD_FDS.iter().any(|slot| {
+            slot.compare_exchange(-1, write, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        if !registered {
+            return Err(Error::Sys(Errno::EMFILE));
+        }
+        let action = SigAction::new(
+            SigHandler::Handler(on_sigchld),
+            SaFlags::SA_RESTART | SaFlags::SA_NOCLDSTOP,
+            SigSet::empty(),
+        );
+        unsafe {
+            sigaction(Signal::SIGCHLD, &action)?;
+        }
+        Ok(reaper)
+    }
+
+    /// Returns a new reaper after forgetting the pipes of all the others, which belong
+    /// to the shells of the parent. Forked children must use it instead of the reaper
+    /// of their parent, to avoid stealing its wake-ups.
+    pub fn reinit() -> Result<Reaper, Error> {
+        for slot in SIGCHLD_FDS.iter() {
+            slot.store(-1, Ordering::SeqCst);
+        }
+        Reaper::new()
+    }
+
+    /// Blocks until a `SIGCHLD` arrives, unless one arrived since the last call.
+    pub fn wait(&self) -> Result<(), Error> {
+        let mut fds = [PollFd::new(self.read, PollFlags::POLLIN)];
+        match poll(&mut fds, -1) {
+            Ok(_) | Err(Error::Sys(Errno::EINTR)) => {}
+            Err(e) => return Err(e),
+        }
+        let mut buf = [0u8; 64];
+        // the pipe is non-blocking, so this stops when it's empty
+        while let Ok(n) = unistd::read(self.read, &mut buf) {
+            if n == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Reaper {
+    fn drop(&mut self) {
+        for slot in SIGCHLD_FDS.iter() {
+            let _ = slot.compare_exchange(self.write, -1, Ordering::SeqCst, Ordering::SeqCst);
+        }
+        while IN_HANDLER.load(Ordering::SeqCst) != 0 {
+            std::hint::spin_loop();
+        }
+        let _ = unistd::close(self.read);
+        let _ = unistd::close(self.write);
+    }
+}
+
+/// The signals caught since the last call to `take_caught_signals`, one bit for each.
+static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    CAUGHT.fetch_or(1 << sig, Ordering::SeqCst);
+}
+
+pub const SIGINT: i32 = libc::SIGINT;
+pub const SIGTERM: i32 = libc::SIGTERM;
+
+/// What a signal does when it arrives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignalAction {
+    Default,
+    Ignore,
+    /// Take note of it, for [`take_caught_signals`](fn.take_caught_signals.html).
+    Catch,
+}
+
+/// Sets what the signal does. The system calls it interrupts are restarted.
+pub fn set_signal_action(sig: i32, action: SignalAction) -> Result<(), Error> {
+    let handler = match action {
+        SignalAction::Default => SigHandler::SigDfl,
+        SignalAction::Ignore => SigHandler::SigIgn,
+        SignalAction::Catch => SigHandler::Handler(on_signal),
+    };
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    unsafe {
+        sigaction(Signal::from_c_int(sig)?, &action)?;
+    }
+    Ok(())
+}
+
+/// Returns the signals caught since the last call, with the bit `1 << sig` set for each.
+pub fn take_caught_signals() -> usize {
+    CAUGHT.swap(0, Ordering::SeqCst)
+}
+
+/// Returns the CPU time used so far by the shell and by the children it reaped,
+/// running user code and running in the kernel.
+pub fn cpu_time() -> (Duration, Duration) {
+    let usage = |who| {
+        let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+        unsafe {
+            libc::getrusage(who, &mut usage);
+        }
+        (
+            timeval_to_duration(usage.ru_utime),
+            timeval_to_duration(usage.ru_stime),
+        )
+    };
+    let own = usage(libc::RUSAGE_SELF);
+    let children = usage(libc::RUSAGE_CHILDREN);
+    (own.0 + children.0, own.1 + children.1)
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
+
+/// Returns how big an unprivileged process may make a pipe.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn pipe_max_size() -> Option<usize> {
+    std::fs::read_to_string("/proc/sys/fs/pipe-max-size")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+}
+
+/// Makes the pipe hold up to `size` bytes, or as much as the system lets, so that
+/// the processes on its ends switch less often. Does nothing if `fd` is not a pipe or
+/// the user already has too much memory in pipes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn grow_pipe(fd: RawFd, size: usize) {
+    let size = size.min(pipe_max_size().unwrap_or(size)) as libc::c_int;
+    match fcntl(fd, FcntlArg::F_GETPIPE_SZ) {
+        Ok(current) if current < size => {
+            let _ = fcntl(fd, FcntlArg::F_SETPIPE_SZ(size));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn grow_pipe(_fd: RawFd, _size: usize) {}
+
+/// Moves up to `len` bytes from one descriptor to the other without going through
+/// the memory of the shell, and returns how many were moved, or 0 at the end of the
+/// input. Returns `None` if the system can't do that for these descriptors: one of
+/// them must be a pipe, and files open for appending don't work.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn splice(from: RawFd, to: RawFd, len: usize) -> Option<io::Result<usize>> {
+    use nix::fcntl::SpliceFFlags;
+    let flags = SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_MORE;
+    match nix::fcntl::splice(from, None, to, None, len, flags) {
+        Err(Error::Sys(Errno::EINVAL)) => None,
+        r => Some(r.map_err(to_io)),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn splice(_from: RawFd, _to: RawFd, _len: usize) -> Option<io::Result<usize>> {
+    None
+}
+
+fn to_io(e: Error) -> io::Error {
+    match e.as_errno() {
+        Some(errno) => io::Error::from_raw_os_error(errno as i32),
+        None => io::Error::other(e),
+    }
+}
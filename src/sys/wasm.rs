@@ -0,0 +1,227 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! The stand-ins for the system calls on wasm32, which has no processes, signals or
+//! file descriptors. Starting anything fails with `UnsupportedOperation`, while the
+//! standard input and outputs are those of the Rust standard library.
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub type RawFd = i32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Pid(i32);
+
+impl Pid {
+    pub fn from_raw(pid: i32) -> Pid {
+        Pid(pid)
+    }
+
+    pub fn as_raw(self) -> i32 {
+        self.0
+    }
+}
+
+impl fmt::Display for Pid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub enum ForkResult {
+    Parent { child: Pid },
+    Child,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    UnsupportedOperation,
+}
+
+impl Error {
+    pub fn desc(&self) -> &'static str {
+        "Unsupported Operation"
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.desc())
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, Error::UnsupportedOperation)
+}
+
+pub fn fork() -> Result<ForkResult, Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+pub fn spawn(
+    _path: &Path,
+    _args: &[String],
+    _env: &[String],
+    _outputs: (RawFd, RawFd),
+) -> Result<Pid, Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+/// There are no users to look up.
+pub fn home_dir(_user: &str) -> Result<Option<PathBuf>, Error> {
+    Ok(None)
+}
+
+pub fn wait(_pid: Pid) -> Result<i32, Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChildStatus {
+    Running,
+    Terminated(i32),
+    Lost,
+}
+
+pub fn try_wait(_pid: Pid) -> Result<ChildStatus, Error> {
+    Ok(ChildStatus::Lost)
+}
+
+pub fn pid() -> Pid {
+    Pid(0)
+}
+
+pub fn set_outputs(_stdout: RawFd, _stderr: RawFd) -> Result<(), Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+pub fn pipe() -> Result<(RawFd, RawFd), Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+pub fn move_fd(_to: RawFd, _fd: RawFd) -> Result<(), Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+pub fn close(_fd: RawFd) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reads from the standard input, which is the only descriptor open for reading.
+pub fn read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    match fd {
+        0 => io::stdin().read(buf),
+        _ => Err(unsupported()),
+    }
+}
+
+/// Writes to the standard output or error.
+pub fn write(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    match fd {
+        1 => io::stdout().write(buf),
+        2 => io::stderr().write(buf),
+        _ => Err(unsupported()),
+    }
+}
+
+pub fn open(_path: &Path, _options: &mut OpenOptions) -> io::Result<RawFd> {
+    Err(unsupported())
+}
+
+pub fn isatty(_fd: RawFd) -> bool {
+    false
+}
+
+/// Nothing can be executed.
+pub fn is_executable(_path: &Path) -> bool {
+    false
+}
+
+/// There are no signals to interrupt anything.
+pub fn is_interrupted(_e: &Error) -> bool {
+    false
+}
+
+pub fn temp_file() -> Result<File, Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+pub fn keep_open(_file: File) -> Result<RawFd, Error> {
+    Err(Error::UnsupportedOperation)
+}
+
+#[derive(Default)]
+pub struct SavedFds;
+
+impl SavedFds {
+    pub fn redirect(&mut self, _fd: RawFd, _to: RawFd) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation)
+    }
+
+    pub fn restore(self) {}
+}
+
+/// There are no children to wait for.
+pub struct Reaper;
+
+impl Reaper {
+    pub fn new() -> Result<Reaper, Error> {
+        Ok(Reaper)
+    }
+
+    pub fn reinit() -> Result<Reaper, Error> {
+        Ok(Reaper)
+    }
+
+    pub fn wait(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+pub const SIGINT: i32 = 2;
+pub const SIGTERM: i32 = 15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignalAction {
+    Default,
+    Ignore,
+    Catch,
+}
+
+/// Traps can be set, but no signal ever arrives.
+pub fn set_signal_action(_sig: i32, _action: SignalAction) -> Result<(), Error> {
+    Ok(())
+}
+
+pub fn take_caught_signals() -> usize {
+    0
+}
+
+pub fn cpu_time() -> (Duration, Duration) {
+    (Duration::default(), Duration::default())
+}
+
+pub fn grow_pipe(_fd: RawFd, _size: usize) {}
+
+pub fn splice(_from: RawFd, _to: RawFd, _len: usize) -> Option<io::Result<usize>> {
+    None
+}
@@ -0,0 +1,318 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Waiting for changes to files, with inotify on Linux and kqueue on the BSDs and macOS.
+use std::path::PathBuf;
+
+/// A change to a watched file or to a file in a watched directory.
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    /// What happened: `create`, `modify`, `delete`, `move` or `attrib`.
+    pub kind: &'static str,
+    pub file: PathBuf,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use self::inotify::Watcher;
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+))]
+pub use self::kqueue::Watcher;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod inotify {
+    use super::Event;
+    use nix::poll::{poll, PollFd, PollFlags};
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+    use nix::unistd;
+    use std::collections::HashMap;
+    use std::os::unix::io::AsRawFd;
+    use std::path::PathBuf;
+
+    /// Waits for changes to some files and directories.
+    pub struct Watcher {
+        inotify: Inotify,
+        paths: HashMap<WatchDescriptor, PathBuf>,
+    }
+
+    impl Watcher {
+        pub fn new(paths: &[PathBuf]) -> nix::Result<Watcher> {
+            let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+            let mut watcher = Watcher {
+                inotify,
+                paths: HashMap::new(),
+            };
+            let mask = AddWatchFlags::IN_CREATE
+                | AddWatchFlags::IN_MODIFY
+                | AddWatchFlags::IN_DELETE
+                | AddWatchFlags::IN_DELETE_SELF
+                | AddWatchFlags::IN_MOVE
+                | AddWatchFlags::IN_MOVE_SELF
+                | AddWatchFlags::IN_ATTRIB;
+            for path in paths {
+                let wd = watcher.inotify.add_watch(path, mask)?;
+                watcher.paths.insert(wd, path.clone());
+            }
+            Ok(watcher)
+        }
+
+        /// Blocks until something changes. Returns `None` when there's nothing left to watch,
+        /// and `EINTR` when a signal arrives.
+        pub fn wait(&mut self) -> nix::Result<Option<Vec<Event>>> {
+            let mut events = Vec::new();
+            while events.is_empty() {
+                if self.paths.is_empty() {
+                    return Ok(None);
+                }
+                // read() is restarted after the signals the shell traps, poll() isn't
+                let mut fds = [PollFd::new(self.inotify.as_raw_fd(), PollFlags::POLLIN)];
+                poll(&mut fds, -1)?;
+                for e in self.inotify.read_events()? {
+                    // the watch is gone, after its file was deleted
+                    if e.mask.contains(AddWatchFlags::IN_IGNORED) {
+                        self.paths.remove(&e.wd);
+                        continue;
+                    }
+                    let path = match self.paths.get(&e.wd) {
+                        Some(path) => path,
+                        None => continue,
+                    };
+                    let file = match e.name {
+                        Some(name) => path.join(name),
+                        None => path.clone(),
+                    };
+                    let kind = if e.mask.contains(AddWatchFlags::IN_CREATE) {
+                        "create"
+                    } else if e.mask.contains(AddWatchFlags::IN_MODIFY) {
+                        "modify"
+                    } else if e
+                        .mask
+                        .intersects(AddWatchFlags::IN_DELETE | AddWatchFlags::IN_DELETE_SELF)
+                    {
+                        "delete"
+                    } else if e
+                        .mask
+                        .intersects(AddWatchFlags::IN_MOVE | AddWatchFlags::IN_MOVE_SELF)
+                    {
+                        "move"
+                    } else if e.mask.contains(AddWatchFlags::IN_ATTRIB) {
+                        "attrib"
+                    } else {
+                        continue;
+                    };
+                    events.push(Event { kind, file });
+                }
+            }
+            Ok(Some(events))
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            let _ = unistd::close(self.inotify.as_raw_fd());
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+))]
+mod kqueue {
+    use super::Event;
+    use nix::fcntl::{open, OFlag};
+    use nix::sys::event::{kevent_ts, kqueue, EventFilter, EventFlag, FilterFlag, KEvent};
+    use nix::sys::stat::Mode;
+    use nix::unistd;
+    use std::collections::HashMap;
+    use std::os::unix::io::RawFd;
+    use std::path::PathBuf;
+
+    /// Waits for changes to some files and directories.
+    ///
+    /// kqueue doesn't tell which file of a directory changed, so the events of a
+    /// directory are about the directory itself.
+    pub struct Watcher {
+        kq: RawFd,
+        paths: HashMap<RawFd, PathBuf>,
+    }
+
+    fn flags() -> FilterFlag {
+        FilterFlag::NOTE_WRITE
+            | FilterFlag::NOTE_EXTEND
+            | FilterFlag::NOTE_DELETE
+            | FilterFlag::NOTE_RENAME
+            | FilterFlag::NOTE_ATTRIB
+    }
+
+    impl Watcher {
+        pub fn new(paths: &[PathBuf]) -> nix::Result<Watcher> {
+            let mut watcher = Watcher {
+                kq: kqueue()?,
+                paths: HashMap::new(),
+            };
+            let mut changes = Vec::new();
+            for path in paths {
+                let fd = open(
+                    path.as_path(),
+                    OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+                    Mode::empty(),
+                )?;
+                watcher.paths.insert(fd, path.clone());
+                changes.push(KEvent::new(
+                    fd as usize,
+                    EventFilter::EVFILT_VNODE,
+                    EventFlag::EV_ADD | EventFlag::EV_CLEAR,
+                    flags(),
+                    0,
+                    0,
+                ));
+            }
+            kevent_ts(watcher.kq, &changes, &mut [], None)?;
+            Ok(watcher)
+        }
+
+        /// Blocks until something changes. Returns `None` when there's nothing left to watch,
+        /// and `EINTR` when a signal arrives.
+        pub fn wait(&mut self) -> nix::Result<Option<Vec<Event>>> {
+            if self.paths.is_empty() {
+                return Ok(None);
+            }
+            let empty = KEvent::new(
+                0,
+                EventFilter::EVFILT_VNODE,
+                EventFlag::empty(),
+                FilterFlag::empty(),
+                0,
+                0,
+            );
+            let mut list = vec![empty; self.paths.len()];
+            let n = kevent_ts(self.kq, &[], &mut list, None)?;
+            let mut events = Vec::new();
+            for e in &list[..n] {
+                let fd = e.ident() as RawFd;
+                let file = match self.paths.get(&fd) {
+                    Some(path) => path.clone(),
+                    None => continue,
+                };
+                let fflags = e.fflags();
+                let kind = if fflags.contains(FilterFlag::NOTE_DELETE) {
+                    // the file is gone, and so is its watch
+                    self.paths.remove(&fd);
+                    let _ = unistd::close(fd);
+                    "delete"
+                } else if fflags.contains(FilterFlag::NOTE_RENAME) {
+                    "move"
+                } else if fflags.intersects(FilterFlag::NOTE_WRITE | FilterFlag::NOTE_EXTEND) {
+                    "modify"
+                } else {
+                    "attrib"
+                };
+                events.push(Event { kind, file });
+            }
+            Ok(Some(events))
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            for fd in self.paths.keys() {
+                let _ = unistd::close(*fd);
+            }
+            let _ = unistd::close(self.kq);
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+pub use self::unsupported::Watcher;
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+mod unsupported {
+    use super::Event;
+    use crate::sys::Error;
+    use std::path::PathBuf;
+
+    pub struct Watcher;
+
+    impl Watcher {
+        pub fn new(_paths: &[PathBuf]) -> Result<Watcher, Error> {
+            Err(Error::UnsupportedOperation)
+        }
+
+        pub fn wait(&mut self) -> Result<Option<Vec<Event>>, Error> {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod tests {
+    use super::{Event, Watcher};
+    use std::fs;
+
+    #[test]
+    fn events() {
+        let dir = std::env::temp_dir().join(format!("rwsh-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut w = Watcher::new(std::slice::from_ref(&dir)).unwrap();
+        fs::write(dir.join("a"), "").unwrap();
+        let events = w.wait().unwrap().unwrap();
+        assert_eq!(
+            events[0],
+            Event {
+                kind: "create",
+                file: dir.join("a"),
+            }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        let mut kinds = Vec::new();
+        while let Some(events) = w.wait().unwrap() {
+            kinds.extend(events.into_iter().map(|e| e.kind));
+        }
+        assert_eq!(kinds.last(), Some(&"delete"));
+    }
+}
@@ -33,10 +33,11 @@ impl BinOp {
 
 impl TaskImpl for BinOp {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
-        let left_status = match self.left.poll(ctx)? {
+        let left_status = match self.left.poll_condition(ctx)? {
             TaskStatus::Wait => return Ok(TaskStatus::Wait),
             TaskStatus::Success(i) => i,
         };
+        ctx.state.last_status = left_status;
 
         match self.typ {
             BinOpType::And => {
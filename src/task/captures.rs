@@ -0,0 +1,87 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::shell::{Key, State, Var, VarValue};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// The values of a variable in each scope, as kept by the state.
+type Scopes = Vec<(Var, u32)>;
+
+/// The capture groups of a match, as seen by the body of a `switch` or `match` item.
+///
+/// They are available in the `matches` array and in the `m` map:
+/// `$m[1]` is the first group and `$m[name]` is the group called `name`.
+/// The variables they replace get their values back after the body.
+pub struct Captures {
+    numbered: Vec<String>,
+    named: HashMap<String, String>,
+    /// The variables `matches` and `m` had before `set`, in every scope.
+    saved: Vec<(&'static str, Option<Scopes>)>,
+}
+
+impl Captures {
+    /// Collects the groups of `regex` from `text`. `get` returns the bounds of a group.
+    pub fn new<F>(regex: &Regex, text: &str, get: F) -> Self
+    where
+        F: Fn(usize) -> Option<(usize, usize)>,
+    {
+        let capture = |i| get(i).map_or(String::new(), |(s, e)| text[s..e].to_owned());
+        let numbered = (0..regex.captures_len()).map(capture).collect::<Vec<_>>();
+        let mut named = numbered
+            .iter()
+            .enumerate()
+            .map(|(i, val)| (i.to_string(), val.clone()))
+            .collect::<HashMap<_, _>>();
+        for (i, name) in regex.capture_names().enumerate() {
+            if let Some(name) = name {
+                named.insert(name.to_owned(), numbered[i].clone());
+            }
+        }
+        Captures {
+            numbered,
+            named,
+            saved: Vec::new(),
+        }
+    }
+
+    pub fn set(&mut self, state: &mut State) {
+        self.saved = ["matches", "m"]
+            .iter()
+            .map(|name| (*name, state.vars.get(*name).cloned()))
+            .collect();
+        state.set_var(
+            Key::Var("matches"),
+            Var::new("matches".to_owned(), VarValue::Array(self.numbered.clone())),
+            true,
+        );
+        state.set_var(
+            Key::Var("m"),
+            Var::new("m".to_owned(), VarValue::Map(self.named.clone())),
+            true,
+        );
+    }
+
+    pub fn unset(&mut self, state: &mut State) {
+        for (name, vars) in self.saved.drain(..) {
+            match vars {
+                Some(vars) => state.vars.insert(name.to_owned(), vars),
+                None => state.vars.remove(name),
+            };
+        }
+    }
+}
@@ -15,30 +15,50 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use super::fields::Fields;
+use super::guard;
 use super::word::word_to_str;
 use super::*;
 use crate::builtin;
+use crate::msg;
 use crate::parser;
-use crate::shell::{Context, Process};
+use crate::shell::{debug, Context, Process, Var, VarValue};
+use crate::sys::{self, Pid};
 use glob;
-use nix::unistd;
 use std::cell::RefCell;
-use std::ffi::{CString, OsStr};
+use std::io::Write;
 use std::ops::Deref;
-use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+/// The function that is called with a command that wasn't found.
+const COMMAND_NOT_FOUND: &str = "command_not_found";
+
 pub struct Command {
     cmd: parser::SimpleCommand,
     started: bool,
     args: Vec<String>,
     t: CommandType,
     process: Option<Rc<RefCell<Process>>>,
+    function: Option<Task>,
+    /// The most files matched by one of the patterns in the arguments.
+    glob_matches: usize,
+    /// The expanded assignments before the command name.
+    env: Vec<(String, String)>,
+    /// The exported values replaced by `env` while a builtin or a function runs.
+    saved_env: Vec<(String, Option<String>)>,
 }
 
 pub enum CommandType {
-    Process,
+    /// An executable, with its path.
+    Process(PathBuf),
     Builtin,
+    Function,
+    NotFound,
+    /// Not confirmed by the user when asked by the guard.
+    Cancelled,
+    /// A boolean or null variable alone, which succeeds if it's true.
+    Value(bool),
 }
 
 impl Command {
@@ -47,43 +67,99 @@ impl Command {
             cmd,
             started: false,
             args: Vec::new(),
-            t: CommandType::Process,
+            t: CommandType::NotFound,
             process: None,
+            function: None,
+            glob_matches: 0,
+            env: Vec::new(),
+            saved_env: Vec::new(),
         }
     }
 
-    fn process_start(&mut self, ctx: &mut Context) -> Result<(), String> {
-        match unistd::fork().map_err(|e| format!("failed to fork: {}", e))? {
-            unistd::ForkResult::Child => {
-                if let Err(e) = unistd::execvpe(
-                    &os2c(OsStr::new(&self.args[0].as_str())),
-                    self.args
-                        .iter()
-                        .map(|a| os2c(OsStr::new(&a)))
-                        .collect::<Vec<CString>>()
-                        .as_slice(),
-                    ctx.state
-                        .computed_exported_vars
-                        .iter()
-                        .map(|a| os2c(OsStr::new(&a)))
-                        .collect::<Vec<CString>>()
-                        .as_slice(),
-                ) {
-                    eprintln!("{}: {}", self.args[0], e);
-                    std::process::exit(127);
-                }
-                Ok(())
-            }
-            unistd::ForkResult::Parent { child: pid, .. } => {
-                self.process = Some(ctx.state.new_process(pid));
-                Ok(())
+    /// Returns the environment of a process running the command.
+    fn environment(&self, ctx: &Context) -> Vec<String> {
+        if self.env.is_empty() {
+            return ctx.state.computed_exported_vars.clone();
+        }
+        let mut vars = ctx.state.exported_vars.clone();
+        vars.extend(self.env.iter().cloned());
+        vars.iter().map(|(k, v)| format!("{}={}", k, v)).collect()
+    }
+
+    /// Exports the assignments until `restore_env` is called.
+    fn export_env(&mut self, ctx: &mut Context) {
+        for (name, value) in &self.env {
+            let old = ctx.state.exported_vars.get(name).cloned();
+            self.saved_env.push((name.clone(), old));
+            ctx.state.export_var(name.clone(), value.clone());
+        }
+    }
+
+    fn restore_env(&mut self, ctx: &mut Context) {
+        while let Some((name, old)) = self.saved_env.pop() {
+            match old {
+                Some(value) => ctx.state.export_var(name, value),
+                None => ctx.state.unexport_var(&name),
             }
         }
     }
 
-    fn process_poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+    /// Decides what runs the command.
+    /// Functions take precedence over builtins, which take precedence over executables.
+    fn resolve(&mut self, ctx: &mut Context) -> CommandType {
+        let name = self.args[0].clone();
+        let hashed = ctx.state.command_cache.contains_key(&name);
+        if let Some(body) = ctx.state.functions.get(&name) {
+            self.function = Some(Task::new(Box::new(FunctionCall::new(
+                body.clone(),
+                self.args.clone(),
+                Some(self.cmd.3),
+            ))));
+            trace_resolution(ctx, &name, msg!("resolve.function"));
+            CommandType::Function
+        } else if builtin::get_builtin(&name).is_some() {
+            trace_resolution(ctx, &name, msg!("resolve.builtin"));
+            CommandType::Builtin
+        } else if let Some(path) = ctx.state.find_command(&name) {
+            let step = if hashed {
+                msg!("resolve.hashed", path.display())
+            } else {
+                msg!("resolve.path", path.display())
+            };
+            trace_resolution(ctx, &name, step);
+            CommandType::Process(path)
+        } else if ctx.state.config.autocd && self.args.len() == 1 && Path::new(&name).is_dir() {
+            self.args.insert(0, "cd".to_owned());
+            trace_resolution(ctx, &name, msg!("resolve.autocd"));
+            CommandType::Builtin
+        } else if let Some(body) = ctx.state.functions.get(COMMAND_NOT_FOUND) {
+            // the hook gets the command as arguments
+            let mut args = vec![COMMAND_NOT_FOUND.to_owned()];
+            args.extend(self.args.iter().cloned());
+            self.function = Some(Task::new(Box::new(FunctionCall::new(
+                body.clone(),
+                args,
+                Some(self.cmd.3),
+            ))));
+            trace_resolution(ctx, &name, msg!("resolve.hook", COMMAND_NOT_FOUND));
+            CommandType::Function
+        } else {
+            trace_resolution(ctx, &name, msg!("resolve.not-found"));
+            CommandType::NotFound
+        }
+    }
+
+    fn process_start(&mut self, path: &Path, ctx: &mut Context) -> Result<(), String> {
+        let outputs = (ctx.stdout.0, ctx.stderr.0);
+        let pid = sys::spawn(path, &self.args, &self.environment(ctx), outputs)
+            .map_err(|e| msg!("fork.error", e))?;
+        self.process = Some(ctx.state.new_process(pid));
+        Ok(())
+    }
+
+    fn process_poll(&mut self, path: &Path, ctx: &mut Context) -> Result<TaskStatus, String> {
         if !self.started {
-            self.process_start(ctx)?;
+            self.process_start(path, ctx)?;
             self.started = true;
         }
 
@@ -99,79 +175,245 @@ impl Command {
         )))
     }
 
-    fn get_args(&mut self, _ctx: &Context) -> Result<(), String> {
-        self.args.push(word_to_str(self.cmd.0.clone()));
-        for word_list in &self.cmd.1 {
-            let words = if let parser::RawWord::List(words, false) = word_list.borrow().deref() {
-                words.clone()
-            } else {
-                panic!();
-            };
-            if words.len() == 1 {
-                if let parser::RawWord::Expansion(var) = words[0].borrow().deref() {
-                    self.args.extend(var.value.array().iter().cloned());
-                    continue;
-                }
+    fn get_args(&mut self, ctx: &Context) -> Result<(), String> {
+        for (name, value) in &self.cmd.2 {
+            self.env.push((name.clone(), word_to_str(value.clone())));
+        }
+        let ifs = ctx.state.ifs();
+        self.expand_word_list(&self.cmd.0.clone(), &ifs);
+        if self.args.is_empty() {
+            self.args.push(String::new());
+        }
+        for word_list in &self.cmd.1.clone() {
+            self.expand_word_list(word_list, &ifs);
+        }
+        Ok(())
+    }
+
+    /// Adds the fields of the word list to the arguments, and keeps count of the
+    /// files that globs matched.
+    fn expand_word_list(&mut self, word_list: &parser::Word, ifs: &str) {
+        let matches = expand_fields(word_list, ifs, &mut self.args);
+        self.glob_matches = self.glob_matches.max(matches);
+    }
+}
+
+/// Adds the arguments that a word list expands to: its fields, with the ones that
+/// have glob characters replaced by the files they match. Expansions are split at the
+/// characters of `ifs`. Returns the largest number of files that a glob matched.
+pub(super) fn expand_fields(word_list: &parser::Word, ifs: &str, args: &mut Vec<String>) -> usize {
+    let words = if let parser::RawWord::List(words, false) = word_list.borrow().deref() {
+        words.clone()
+    } else {
+        panic!();
+    };
+    if words.len() == 1 {
+        if let parser::RawWord::Expansion(var) = words[0].borrow().deref() {
+            // the elements of an array are fields already
+            if !is_scalar(var) {
+                args.extend(var.value.array());
+                return 0;
             }
-            let mut should_glob = false;
-            for word in &words {
-                if let parser::RawWord::String(s, false) = word.borrow().deref() {
-                    for c in s.chars() {
-                        should_glob = should_glob || (c == '*' || c == '?' || c == '[');
-                    }
-                }
+        }
+        // "$@" is the only quoted expansion that doesn't get joined
+        if let Some(var) = quoted_positional_params(&words[0]) {
+            args.extend(var.value.array());
+            return 0;
+        }
+    }
+    let mut glob_matches = 0;
+    let mut fields = Fields::new();
+    for word in &words {
+        match word.borrow().deref() {
+            parser::RawWord::String(s, false) => fields.push_literal(s),
+            parser::RawWord::Expansion(var) if is_scalar(var) => {
+                fields.push_split(&var.to_string(), ifs)
             }
-            if should_glob {
-                let mut original = String::new();
-                for word in &words {
-                    if let parser::RawWord::String(s, false) = word.borrow().deref() {
-                        original.push_str(&s);
-                    } else {
-                        original.push_str(&glob::Pattern::escape(&word_to_str(word.clone())));
-                    }
-                }
-                match glob::glob(&original) {
-                    Err(_) => self.args.push(word_to_str(word_list.clone())),
-                    Ok(g) => {
-                        let mut iter = g
-                            .filter_map(Result::ok)
-                            .map(|p| String::from(p.to_str().unwrap()))
-                            .peekable();
-                        if iter.peek().is_none() {
-                            self.args.push(word_to_str(word_list.clone()))
-                        } else {
-                            self.args.extend(iter)
-                        }
-                    }
+            _ => fields.push_quoted(&word_to_str(word.clone())),
+        }
+    }
+    for field in fields.finish() {
+        if !field.glob {
+            args.push(field.text);
+            continue;
+        }
+        match glob::glob(&field.pattern) {
+            Err(_) => args.push(field.text),
+            Ok(g) => {
+                let mut iter = g
+                    .filter_map(Result::ok)
+                    .map(|p| String::from(p.to_str().unwrap()))
+                    .peekable();
+                if iter.peek().is_none() {
+                    args.push(field.text)
+                } else {
+                    let len = args.len();
+                    args.extend(iter);
+                    glob_matches = glob_matches.max(args.len() - len);
                 }
-            } else {
-                self.args.push(word_to_str(word_list.clone()));
             }
         }
+    }
+    glob_matches
+}
 
-        Ok(())
+/// Returns `true` if the expansion is a single string, which is split into fields.
+fn is_scalar(var: &Var) -> bool {
+    match &var.value {
+        VarValue::Array(arr) => arr.len() == 1,
+        VarValue::Bool(_) => true,
+        VarValue::Map(_) | VarValue::Null => false,
     }
 }
 
+/// Prints a step taken to find what runs a command, if `resolve-trace` is set.
+fn trace_resolution(ctx: &mut Context, name: &str, step: String) {
+    if ctx.state.config.resolve_trace {
+        let _ = writeln!(ctx.stderr, "{}", msg!("resolve.trace", name, step));
+    }
+}
+
+/// Replaces the command name with its alias, as long as it names an alias.
+///
+/// Fails with the chain of aliases if it leads back to an alias that was already expanded.
+/// An alias starting with its own name is not expanded again.
+fn resolve_aliases(ctx: &mut Context, args: &mut Vec<String>) -> Result<(), String> {
+    let mut chain: Vec<String> = Vec::new();
+    while let Some(words) = ctx.state.aliases.get(&args[0]).cloned() {
+        let name = args[0].clone();
+        trace_resolution(ctx, &name, msg!("resolve.alias", words.join(" ")));
+        args.splice(0..1, words);
+        chain.push(name);
+        if args[0] == chain[chain.len() - 1] {
+            break;
+        }
+        if chain.contains(&args[0]) {
+            chain.push(args[0].clone());
+            return Err(msg!("alias.cycle", chain.join(" -> ")));
+        }
+    }
+    Ok(())
+}
+
 impl TaskImpl for Command {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
         ctx.state.if_condition_ok = None;
         if !self.started {
             self.get_args(ctx)?;
-            self.t = if builtin::get_builtin(&self.args[0]).is_some() {
-                CommandType::Builtin
+            if let Some(value) = typed_value(&self.cmd) {
+                self.t = CommandType::Value(value.is_true());
             } else {
-                CommandType::Process
-            };
+                resolve_aliases(ctx, &mut self.args)?;
+                self.t = self.resolve(ctx);
+            }
+            if let CommandType::Process(_) = self.t {
+                if !guard::allow(ctx, &self.args, self.glob_matches)? {
+                    self.t = CommandType::Cancelled;
+                }
+            }
+            if ctx.state.config.xtrace {
+                let mut words = self
+                    .env
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>();
+                words.extend(self.args.iter().cloned());
+                let _ = writeln!(ctx.stderr, "{}", msg!("set.xtrace", words.join(" ")));
+            }
+            ctx.state.run_debug_trap();
+            if ctx.state.exit != -1 {
+                return Ok(TaskStatus::Success(0));
+            }
+            if debug::should_pause(ctx, &self.args[0], self.cmd.3) {
+                debug::pause(ctx, &msg!("debug.at", self.cmd.3, self.args.join(" ")))?;
+                if ctx.state.exit != -1 {
+                    return Ok(TaskStatus::Success(0));
+                }
+            }
+        }
+
+        let status = match &self.t {
+            CommandType::Process(path) => {
+                let path = path.clone();
+                self.process_poll(&path, ctx)?
+            }
+            CommandType::Builtin => {
+                self.export_env(ctx);
+                let status = self.builtin_poll(ctx);
+                self.restore_env(ctx);
+                status?
+            }
+            CommandType::Function => {
+                if !self.started {
+                    self.export_env(ctx);
+                }
+                self.started = true;
+                let status = self.function.as_mut().unwrap().poll(ctx);
+                if !matches!(status, Ok(TaskStatus::Wait)) {
+                    self.restore_env(ctx);
+                }
+                status?
+            }
+            CommandType::NotFound => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("command.not-found", self.args[0]));
+                TaskStatus::Success(127)
+            }
+            CommandType::Cancelled => {
+                let _ = writeln!(ctx.stderr, "{}", msg!("guard.cancelled", self.args[0]));
+                TaskStatus::Success(1)
+            }
+            CommandType::Value(truth) => TaskStatus::Success(!truth as i32),
+        };
+        if let TaskStatus::Success(code) = status {
+            // a failing function already ran the trap for its failing command
+            if !matches!(self.t, CommandType::Function) {
+                ctx.state.run_err_trap(code);
+            }
+            ctx.state.check_errexit(code);
         }
+        Ok(status)
+    }
+
+    fn kind(&self) -> String {
+        match self.args.first() {
+            Some(name) => format!("Command {}", name),
+            None => "Command".to_owned(),
+        }
+    }
 
-        match self.t {
-            CommandType::Process => self.process_poll(ctx),
-            CommandType::Builtin => self.builtin_poll(ctx),
+    fn pids(&self) -> Vec<Pid> {
+        self.process.iter().map(|p| p.borrow().pid).collect()
+    }
+}
+
+/// Returns the value of the variable if the command is only the expansion of a boolean
+/// or null variable, like `$flag`, so that it can be used as a condition.
+fn typed_value(cmd: &parser::SimpleCommand) -> Option<VarValue> {
+    if !cmd.1.is_empty() || !cmd.2.is_empty() {
+        return None;
+    }
+    if let parser::RawWord::List(words, _) = cmd.0.borrow().deref() {
+        if let [word] = words.as_slice() {
+            if let parser::RawWord::Expansion(var) = word.borrow().deref() {
+                if let VarValue::Bool(_) | VarValue::Null = var.value {
+                    return Some(var.value.clone());
+                }
+            }
         }
     }
+    None
 }
 
-fn os2c(s: &OsStr) -> CString {
-    CString::new(s.as_bytes()).unwrap_or_else(|_e| CString::new("<string-with-nul>").unwrap())
+/// Returns the expanded positional parameters if the word is exactly `"$@"`.
+fn quoted_positional_params(word: &parser::Word) -> Option<Var> {
+    if let parser::RawWord::List(words, true) = word.borrow().deref() {
+        if words.len() == 1 {
+            if let parser::RawWord::Expansion(var) = words[0].borrow().deref() {
+                if var.key == "@" {
+                    return Some(var.clone());
+                }
+            }
+        }
+    }
+    None
 }
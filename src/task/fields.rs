@@ -0,0 +1,201 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Field splitting: the results of the expansions that aren't quoted are split
+//! into several arguments at the characters of `IFS`.
+//!
+//! Like in the POSIX shell, the whitespace of `IFS` is trimmed and its runs count as one
+//! separator, while every other character of `IFS` ends a field, even an empty one.
+//! Quoted text and the literal text of the word are never split, and the text that
+//! comes from expansions is never globbed.
+
+/// The separators used to split fields.
+pub const DEFAULT_IFS: &str = " \t\n";
+
+#[derive(Default, Debug, PartialEq)]
+/// An argument made of the pieces of a word.
+pub struct Field {
+    pub text: String,
+    /// The text as a glob pattern, where only the unquoted literal text isn't escaped.
+    pub pattern: String,
+    /// `true` if the unquoted literal text has glob characters.
+    pub glob: bool,
+    /// Set by any text, even an empty quoted string, which makes the field count.
+    content: bool,
+}
+
+#[derive(Default)]
+/// Builds the fields of a word from its pieces.
+pub struct Fields {
+    done: Vec<Field>,
+    current: Field,
+    /// Whitespace ended the last field, so the next separator doesn't start an empty one.
+    after_space: bool,
+}
+
+impl Fields {
+    pub fn new() -> Fields {
+        Fields::default()
+    }
+
+    /// Adds text that was written unquoted in the word.
+    pub fn push_literal(&mut self, s: &str) {
+        self.current.text.push_str(s);
+        self.current.pattern.push_str(s);
+        self.current.glob = self.current.glob || s.contains(&['*', '?', '['][..]);
+        self.current.content = true;
+        self.after_space = false;
+    }
+
+    /// Adds text that is never split nor globbed.
+    pub fn push_quoted(&mut self, s: &str) {
+        self.current.text.push_str(s);
+        self.current.pattern.push_str(&glob::Pattern::escape(s));
+        self.current.content = true;
+        self.after_space = false;
+    }
+
+    /// Adds the result of an expansion, splitting it at the characters of `ifs`.
+    pub fn push_split(&mut self, s: &str, ifs: &str) {
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            if !ifs.contains(c) {
+                continue;
+            }
+            if start < i {
+                self.push_quoted(&s[start..i]);
+            }
+            start = i + c.len_utf8();
+            if c.is_whitespace() {
+                if self.current.content {
+                    self.end_field();
+                    self.after_space = true;
+                }
+            } else {
+                if self.current.content || !self.after_space {
+                    self.end_field();
+                }
+                self.after_space = false;
+            }
+        }
+        if start < s.len() {
+            self.push_quoted(&s[start..]);
+        }
+    }
+
+    fn end_field(&mut self) {
+        self.done.push(std::mem::take(&mut self.current));
+    }
+
+    /// Returns the fields, without the last one if nothing went in it.
+    pub fn finish(mut self) -> Vec<Field> {
+        if self.current.content {
+            self.end_field();
+        }
+        self.done
+    }
+}
+
+/// Splits `s` in at most `n` fields, like `read` does. The last field gets the rest
+/// of the text, without the whitespace of `ifs` around it.
+pub fn split_fields<'a>(s: &'a str, ifs: &str, n: usize) -> Vec<&'a str> {
+    let is_space = |c: char| c.is_whitespace() && ifs.contains(c);
+    let mut fields = Vec::new();
+    let mut rest = s.trim_start_matches(is_space);
+    while !rest.is_empty() && fields.len() + 1 < n {
+        let end = match rest.find(|c| ifs.contains(c)) {
+            Some(end) => end,
+            None => break,
+        };
+        fields.push(&rest[..end]);
+        // one separator is whitespace, one other character, or both
+        rest = rest[end..].trim_start_matches(is_space);
+        if let Some(c) = rest.chars().next().filter(|c| ifs.contains(*c)) {
+            rest = rest[c.len_utf8()..].trim_start_matches(is_space);
+        }
+    }
+    let rest = rest.trim_end_matches(is_space);
+    if !rest.is_empty() && n > 0 {
+        fields.push(rest);
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_fields, Fields, DEFAULT_IFS};
+
+    fn split(s: &str, ifs: &str) -> Vec<String> {
+        let mut fields = Fields::new();
+        fields.push_split(s, ifs);
+        fields.finish().into_iter().map(|f| f.text).collect()
+    }
+
+    #[test]
+    fn splitting() {
+        for (s, ifs, fields) in &[
+            (
+                "  one  two\tthree\n",
+                DEFAULT_IFS,
+                vec!["one", "two", "three"],
+            ),
+            ("   ", DEFAULT_IFS, vec![]),
+            ("a,,b,", ",", vec!["a", "", "b"]),
+            (",a", ",", vec!["", "a"]),
+            ("a , b", " ,", vec!["a", "b"]),
+            ("a b", "", vec!["a b"]),
+        ] {
+            assert_eq!(&split(s, ifs), fields);
+        }
+    }
+
+    #[test]
+    fn at_most() {
+        for (s, ifs, n, fields) in &[
+            (
+                "  one two  three four ",
+                DEFAULT_IFS,
+                3,
+                vec!["one", "two", "three four"],
+            ),
+            ("one", DEFAULT_IFS, 3, vec!["one"]),
+            ("a:b:c", ":", 2, vec!["a", "b:c"]),
+            ("a::c", ":", 3, vec!["a", "", "c"]),
+            ("a : b", " :", 3, vec!["a", "b"]),
+            ("a:", ":", 3, vec!["a"]),
+            ("", ":", 3, vec![]),
+        ] {
+            assert_eq!(&split_fields(s, ifs, *n), fields);
+        }
+    }
+
+    #[test]
+    fn pieces() {
+        let mut f = Fields::new();
+        f.push_literal("x*");
+        f.push_split(" a b ", DEFAULT_IFS);
+        f.push_literal("y");
+        f.push_split("c ", DEFAULT_IFS);
+        f.push_quoted("");
+        let fields = f.finish();
+        let texts = fields.iter().map(|f| f.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["x*", "a", "b", "yc", ""]);
+        assert!(fields[0].glob);
+        assert_eq!(fields[0].pattern, "x*");
+        assert!(!fields[1].glob);
+    }
+}
@@ -0,0 +1,91 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::*;
+use crate::msg;
+use crate::parser::Program;
+use crate::shell::Context;
+use std::io::Write;
+
+/// Defines a function when run.
+pub struct FunctionDefinition {
+    name: String,
+    body: Program,
+}
+
+impl FunctionDefinition {
+    pub fn new(name: String, body: Program) -> FunctionDefinition {
+        FunctionDefinition { name, body }
+    }
+}
+
+impl TaskImpl for FunctionDefinition {
+    fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+        ctx.state
+            .functions
+            .insert(self.name.clone(), self.body.clone());
+        Ok(TaskStatus::Success(0))
+    }
+}
+
+/// Runs the body of a function, with the arguments as positional parameters.
+pub struct FunctionCall {
+    task: Task,
+    args: Vec<String>,
+    /// The line of the call, if the function is not run as a hook.
+    line: Option<usize>,
+}
+
+impl FunctionCall {
+    /// The first argument is the name of the function.
+    pub fn new(body: Program, args: Vec<String>, line: Option<usize>) -> FunctionCall {
+        FunctionCall {
+            task: Task::new_from_command_lists(body.0, true),
+            args,
+            line,
+        }
+    }
+}
+
+impl TaskImpl for FunctionCall {
+    fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+        // $0 stays the name of the script
+        let mut args = vec![ctx.state.args.first().cloned().unwrap_or_default()];
+        args.extend(self.args.iter().skip(1).cloned());
+        if !self.task.polled {
+            if let Some(max) = ctx.state.config.max_function_depth {
+                // only this call fails, so the callers can go on
+                if ctx.state.function_depth >= max {
+                    let error = match self.line {
+                        Some(line) => msg!("limit.function-depth", self.args[0], line, max),
+                        None => msg!("limit.hook-depth", self.args[0], max),
+                    };
+                    let _ = writeln!(ctx.stderr, "{}", error);
+                    return Ok(TaskStatus::Success(1));
+                }
+            }
+            ctx.state.function_depth += 1;
+        }
+        let outer_args = std::mem::replace(&mut ctx.state.args, args);
+        let status = self.task.poll(ctx);
+        ctx.state.args = outer_args;
+        if !matches!(status, Ok(TaskStatus::Wait)) {
+            ctx.state.function_depth -= 1;
+        }
+        status
+    }
+}
@@ -0,0 +1,107 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::msg;
+use crate::shell::{Context, Key};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+
+/// The commands checked when `$GUARD_COMMANDS` is not set.
+const DEFAULT_GUARD_COMMANDS: &[&str] = &["rm"];
+/// How many files a single pattern may match when `$GUARD_MAX` is not set.
+const DEFAULT_GUARD_MAX: usize = 20;
+
+/// Checks a command before it is executed, if the `guard` option is set.
+///
+/// Commands named in `$GUARD_COMMANDS` that get `/` as an argument, or a pattern matching
+/// more than `$GUARD_MAX` files, only run if the user confirms them on the terminal.
+/// A shell that isn't interactive has no one to ask, so it doesn't run them.
+/// `glob_matches` is the largest number of files matched by one of the command's patterns.
+///
+/// Returns whether the command may run.
+pub fn allow(ctx: &mut Context, args: &[String], glob_matches: usize) -> Result<bool, String> {
+    if !ctx.state.config.guard {
+        return Ok(true);
+    }
+    let name = Path::new(&args[0])
+        .file_name()
+        .map_or(String::new(), |n| n.to_string_lossy().into_owned());
+    let guarded = match ctx.state.get_var(Key::Var("GUARD_COMMANDS")) {
+        Some(var) => var.value.array().contains(&name),
+        None => DEFAULT_GUARD_COMMANDS.contains(&name.as_str()),
+    };
+    if !guarded {
+        return Ok(true);
+    }
+
+    let reason = if args[1..].iter().any(|a| is_root(a)) {
+        msg!("guard.root")
+    } else if glob_matches > guard_max(ctx)? {
+        msg!("guard.glob", glob_matches)
+    } else {
+        return Ok(true);
+    };
+    if !ctx.state.interactive {
+        let _ = writeln!(
+            ctx.stderr,
+            "{}",
+            msg!("guard.not-interactive", args.join(" "), reason)
+        );
+        return Ok(false);
+    }
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| msg!("guard.read-error", e))?;
+    let _ = write!(tty, "{}", msg!("guard.confirm", args.join(" "), reason));
+    let answer = read_answer(&mut tty).map_err(|e| msg!("guard.read-error", e))?;
+    let answer = answer.trim();
+    Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+}
+
+/// Reads a line one byte at a time, so that nothing after it is taken
+/// from the commands that read the terminal next.
+fn read_answer(tty: &mut File) -> std::io::Result<String> {
+    let mut answer = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        match tty.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => answer.push(byte[0]),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(String::from_utf8_lossy(&answer).into_owned())
+}
+
+fn guard_max(ctx: &Context) -> Result<usize, String> {
+    match ctx.state.get_var(Key::Var("GUARD_MAX")) {
+        Some(val) => {
+            let val = val.value.array().join("");
+            val.parse().map_err(|_| msg!("guard.bad-limit", val))
+        }
+        None => Ok(DEFAULT_GUARD_MAX),
+    }
+}
+
+fn is_root(arg: &str) -> bool {
+    !arg.starts_with('-') && Path::new(arg).canonicalize().ok().as_deref() == Some(Path::new("/"))
+}
@@ -16,6 +16,7 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 use super::*;
+use crate::msg;
 use crate::shell::Context;
 
 pub struct IfConstruct {
@@ -31,7 +32,7 @@ impl IfConstruct {
 
 impl TaskImpl for IfConstruct {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
-        let condition_status = self.condition.poll(ctx)?;
+        let condition_status = self.condition.poll_condition(ctx)?;
         match condition_status {
             TaskStatus::Success(0) => {
                 let r = self.body.poll(ctx);
@@ -64,7 +65,7 @@ impl ElseConstruct {
 impl TaskImpl for ElseConstruct {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
         if ctx.state.if_condition_ok.is_none() && !self.polled {
-            return Err("cannot use else without an if before it".to_owned());
+            return Err(msg!("else.without-if"));
         }
         if self.polled || !ctx.state.if_condition_ok.unwrap() {
             self.polled = true;
@@ -15,44 +15,46 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use super::captures::Captures;
 use super::word::word_to_str;
 use super::*;
+use crate::msg;
 use crate::parser;
-use crate::shell::{Key, Var, VarValue};
-use crate::util::regex;
-use regex::Regex;
-use std::collections::{HashMap, VecDeque};
-use std::io::{stdin, BufRead, BufReader, ErrorKind, Stdin};
-
-struct ExecContext {
-    int_captures: Vec<String>,
-    string_captures: HashMap<String, String>,
-}
+use crate::util::{decode_utf8, regex};
+use regex::{CaptureLocations, Regex};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, stdin, BufRead, BufReader, Cursor, ErrorKind};
 
 struct MatchItem {
     regex: Regex,
     offset: usize,
     task: Task,
-    to_exec: VecDeque<ExecContext>,
+    to_exec: VecDeque<Captures>,
     prog: parser::Program,
-    named_capture_groups: Vec<String>,
     started: bool,
 }
 
 pub struct MatchConstruct {
+    source: parser::MatchSource,
     ast: Vec<(parser::Word, parser::Program)>,
-    reader: Option<BufReader<Stdin>>,
+    /// Where the text comes from, if not from the standard input.
+    reader: Option<Box<dyn BufRead>>,
     items: Vec<MatchItem>,
 
     initialized: bool,
     finished: bool,
+    /// The text that may still be matched. Starts before the offset of every item.
     buf: String,
+    /// The bytes of a character split between two chunks.
+    pending: Vec<u8>,
     last_body_status: Result<TaskStatus, String>,
 }
 
 impl MatchConstruct {
-    pub fn new(items: Vec<(parser::Word, parser::Program)>) -> Self {
+    pub fn new(source: parser::MatchSource, items: Vec<(parser::Word, parser::Program)>) -> Self {
         MatchConstruct {
+            source,
             ast: items,
             items: Vec::new(),
             reader: None,
@@ -60,7 +62,8 @@ impl MatchConstruct {
             initialized: false,
             finished: false,
             buf: String::new(),
-            last_body_status: Ok(TaskStatus::Wait),
+            pending: Vec::new(),
+            last_body_status: Ok(TaskStatus::Success(0)),
         }
     }
 
@@ -73,26 +76,122 @@ impl MatchConstruct {
                 let pattern = word_to_str(pattern.clone());
                 let task = Task::new_from_command_lists(prog.0.clone(), false);
                 let regex = regex(&pattern).unwrap();
-                let named_capture_groups = regex
-                    .capture_names()
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().to_owned())
-                    .collect();
                 MatchItem {
                     regex,
                     offset: 0,
                     task,
                     to_exec: VecDeque::new(),
                     prog: self.ast[i].1.clone(),
-                    named_capture_groups,
                     started: false,
                 }
             })
             .collect();
-        self.reader = Some(BufReader::new(stdin()));
+        self.reader = match &self.source {
+            parser::MatchSource::Stdin => None,
+            parser::MatchSource::Text(w) => {
+                Some(Box::new(Cursor::new(word_to_str(w.clone()).into_bytes())))
+            }
+            parser::MatchSource::File(w) => {
+                let path = word_to_str(w.clone());
+                let f = File::open(&path).map_err(|e| msg!("match.open-error", path, e))?;
+                Some(Box::new(BufReader::new(f)))
+            }
+        };
         self.initialized = true;
         Ok(())
     }
+
+    /// Reads the next chunk of text. Returns an empty chunk at the end.
+    fn read_chunk(&mut self) -> io::Result<Vec<u8>> {
+        // the standard input is buffered globally, so what isn't consumed is left for others
+        let stdin = stdin();
+        let mut handle = stdin.lock();
+        let r: &mut dyn BufRead = match &mut self.reader {
+            Some(r) => r.as_mut(),
+            None => &mut handle,
+        };
+        let chunk = r.fill_buf()?.to_vec();
+        r.consume(chunk.len());
+        Ok(chunk)
+    }
+
+    /// Queues the new matches of every item and drops the text no item needs anymore.
+    fn scan(&mut self) {
+        for item in self.items.iter_mut() {
+            let (found, offset) = find_matches(&item.regex, &self.buf, item.offset, self.finished);
+            for locs in found.iter() {
+                item.to_exec
+                    .push_back(Captures::new(&item.regex, &self.buf, |i| locs.get(i)));
+            }
+            item.offset = offset;
+        }
+
+        let min = self
+            .items
+            .iter()
+            .map(|item| item.offset)
+            .min()
+            .unwrap_or(self.buf.len())
+            .min(self.buf.len());
+        // keep the previous character, so that anchors and word boundaries still see it
+        let keep = self.buf[..min]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i);
+        self.buf.drain(..keep);
+        for item in self.items.iter_mut() {
+            item.offset -= keep;
+        }
+    }
+}
+
+/// How far behind the end of the text a match can start and still be waited for.
+/// Text before that is never looked at again, so a match longer than this that is split
+/// between chunks can be missed or cut, but the text kept doesn't grow without a bound.
+const WINDOW: usize = 64 * 1024;
+
+/// Finds the matches of `regex` in `text`, starting from `offset`.
+///
+/// Unless `eof` is set, a match reaching the end of `text` is left for later,
+/// because the next chunk could extend it. In other words, a match is accepted
+/// as soon as some input after it has been read.
+///
+/// Returns the captures of each match and the offset to continue from,
+/// which is at most [`WINDOW`] bytes before the end of `text`.
+fn find_matches(
+    regex: &Regex,
+    text: &str,
+    mut offset: usize,
+    eof: bool,
+) -> (Vec<CaptureLocations>, usize) {
+    let mut found = Vec::new();
+    let mut locs = regex.capture_locations();
+    let mut last_end = None;
+    while offset <= text.len() {
+        let (start, end) = match regex.captures_read_at(&mut locs, text, offset) {
+            Some(m) => (m.start(), m.end()),
+            None => break,
+        };
+        if end == text.len() && !eof {
+            break;
+        }
+        if start == end {
+            // step over empty matches
+            offset = end + text[end..].chars().next().map_or(1, char::len_utf8);
+            if last_end == Some(end) {
+                continue;
+            }
+        } else {
+            offset = end;
+        }
+        last_end = Some(end);
+        found.push(locs.clone());
+    }
+    let mut restart = text.len().saturating_sub(WINDOW);
+    while !text.is_char_boundary(restart) {
+        restart += 1;
+    }
+    (found, offset.max(restart))
 }
 
 impl TaskImpl for MatchConstruct {
@@ -100,85 +199,34 @@ impl TaskImpl for MatchConstruct {
         if !self.initialized {
             self.initialize()?;
         }
-        let stdin = stdin();
         while ctx.state.exit == -1 {
             match self.items.iter_mut().find(|item| !item.to_exec.is_empty()) {
                 None => {
                     if self.finished {
                         return self.last_body_status.clone();
                     }
-                    let mut handle = stdin.lock();
-                    let available = match handle.fill_buf() {
-                        Ok(n) => n,
+                    let available = match self.read_chunk() {
+                        Ok(chunk) => chunk,
                         Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
                         Err(e) => return Err(format!("{}", e)),
                     };
                     if available.is_empty() {
                         self.finished = true;
-                        continue;
+                        self.buf.push_str(&String::from_utf8_lossy(&self.pending));
+                        self.pending.clear();
+                    } else {
+                        self.pending.extend_from_slice(&available);
+                        decode_utf8(&mut self.pending, &mut self.buf);
                     }
-                    let len = available.len();
-                    self.buf.push_str(&String::from_utf8_lossy(available));
-                    for item in self.items.iter_mut() {
-                        let s = &self.buf[item.offset..];
-                        let mut to_add = 0;
-                        for m in item.regex.captures_iter(&s) {
-                            item.to_exec.push_back(ExecContext {
-                                int_captures: m
-                                    .iter()
-                                    .map(|x| x.map_or(String::new(), |val| val.as_str().to_owned()))
-                                    .collect(),
-                                string_captures: item
-                                    .named_capture_groups
-                                    .iter()
-                                    .map(|name| {
-                                        (
-                                            name.clone(),
-                                            m.name(name).map_or(String::new(), |val| {
-                                                val.as_str().to_owned()
-                                            }),
-                                        )
-                                    })
-                                    .collect(),
-                            });
-                            to_add = m.get(0).unwrap().end();
-                        }
-                        item.offset += to_add;
+                    self.scan();
+                    if self.finished {
+                        continue;
                     }
-                    handle.consume(len);
                     return Ok(TaskStatus::Wait);
                 }
                 Some(item) => {
                     if !item.started {
-                        for (i, val) in item
-                            .to_exec
-                            .front()
-                            .as_ref()
-                            .unwrap()
-                            .int_captures
-                            .iter()
-                            .enumerate()
-                        {
-                            ctx.state.set_var(
-                                Key::Var(&i.to_string()),
-                                Var::new(i.to_string(), VarValue::Array(vec![val.clone()])),
-                                true,
-                            );
-                        }
-                        for (name, val) in item
-                            .to_exec
-                            .front()
-                            .as_ref()
-                            .unwrap()
-                            .string_captures
-                            .iter()
-                        {
-                            ctx.state.set_var(
-                                Key::Var(name),
-                                Var::new(name.clone(), VarValue::Array(vec![val.clone()])),
-                                true,
-                            );
-                        }
+                        item.to_exec.front_mut().unwrap().set(ctx.state);
                         item.started = true;
                     }
                     let body_status = item.task.poll(ctx)?;
@@ -187,19 +235,7 @@ impl TaskImpl for MatchConstruct {
                     } else {
                         self.last_body_status = Ok(body_status);
                         item.task = Task::new_from_command_lists(item.prog.0.clone(), false);
-                        for i in 0..item.to_exec.front().as_ref().unwrap().int_captures.len() {
-                            ctx.state.remove_var(&i.to_string());
-                        }
-                        for name in item
-                            .to_exec
-                            .front()
-                            .as_ref()
-                            .unwrap()
-                            .string_captures
-                            .keys()
-                        {
-                            ctx.state.remove_var(name);
-                        }
+                        item.to_exec.front_mut().unwrap().unset(ctx.state);
                         item.started = false;
                         item.to_exec.pop_front();
                     }
@@ -210,3 +246,59 @@ impl TaskImpl for MatchConstruct {
         Ok(TaskStatus::Success(ctx.state.exit))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `chunks` one by one, like `MatchConstruct` does, and returns the matched texts.
+    fn matches(pattern: &str, chunks: &[&[u8]]) -> Vec<String> {
+        let regex = regex(pattern).unwrap();
+        let mut pending = Vec::new();
+        let mut buf = String::new();
+        let mut offset = 0;
+        let mut result = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            pending.extend_from_slice(chunk);
+            decode_utf8(&mut pending, &mut buf);
+            let (found, new_offset) = find_matches(&regex, &buf, offset, i == chunks.len() - 1);
+            for locs in found {
+                let (start, end) = locs.get(0).unwrap();
+                result.push(buf[start..end].to_owned());
+            }
+            offset = new_offset;
+        }
+        result
+    }
+
+    #[test]
+    fn split_matches() {
+        assert_eq!(matches("bar", &[b"foo ba", b"r baz", b""]), vec!["bar"]);
+        assert_eq!(
+            matches(r"\w+", &[b"hel", b"lo wor", b"ld", b""]),
+            vec!["hello", "world"]
+        );
+        assert_eq!(
+            matches("^(.*)$", &[b"first li", b"ne\nsecond\n", b""]),
+            vec!["first line", "second", ""]
+        );
+        assert_eq!(matches("x*", &[b"ax", b"xb", b""]), vec!["", "xx", ""]);
+        assert_eq!(
+            matches("é+", &[b"a\xc3", b"\xa9\xc3\xa9b", b""]),
+            vec!["éé"]
+        );
+    }
+
+    #[test]
+    fn bounded_offset() {
+        let text = "é".repeat(WINDOW);
+        let regex = regex("x").unwrap();
+        let (found, offset) = find_matches(&regex, &text, 0, false);
+        assert!(found.is_empty());
+        assert_eq!(offset, text.len() - WINDOW);
+        // the offset stays on a character boundary
+        let text = format!("{}a", "é".repeat(WINDOW / 2));
+        let (_, offset) = find_matches(&regex, &text, 0, false);
+        assert_eq!(offset, 2);
+    }
+}
@@ -16,31 +16,43 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 mod binop;
+mod captures;
 mod command;
+mod fields;
+mod function;
+mod guard;
 mod if_construct;
 mod match_construct;
 mod not;
 mod pipeline;
+mod redirect;
 mod sresequence;
 mod switch_construct;
 mod tasklist;
+mod time;
+mod trace;
 mod while_construct;
 mod word;
 pub use binop::BinOp;
 pub use command::Command;
+pub use fields::{split_fields, DEFAULT_IFS};
+pub use function::{FunctionCall, FunctionDefinition};
 pub use if_construct::{ElseConstruct, IfConstruct};
 pub use match_construct::MatchConstruct;
 pub use not::Not;
 pub use pipeline::Pipeline;
+pub use redirect::Redirect;
 pub use sresequence::SRESequence;
 pub use switch_construct::SwitchConstruct;
 pub use tasklist::TaskList;
+pub use time::Time;
+pub use trace::Tracer;
 pub use while_construct::WhileConstruct;
-pub use word::Word;
+pub use word::{expand_tilde, Word};
 
 use crate::parser;
 use crate::shell::Context;
-use nix::sys::wait;
+use crate::sys::Pid;
 use std::error::Error;
 use std::ops::Deref;
 
@@ -59,6 +71,7 @@ pub enum TaskStatus {
 pub struct Task {
     ti: Box<TaskImpl>,
     status: Result<TaskStatus, String>,
+    polled: bool,
 }
 
 impl Task {
@@ -66,6 +79,7 @@ impl Task {
         Task {
             ti,
             status: Ok(TaskStatus::Wait),
+            polled: false,
         }
     }
 
@@ -74,20 +88,11 @@ impl Task {
             let p = self.poll(ctx)?;
             if let TaskStatus::Success(code) = p {
                 ctx.state.last_status = code;
+                ctx.state.run_pending_traps();
                 return Ok(code);
             }
-            match wait::waitpid(None, None) {
-                Err(e) => {
-                    if let Some(nix::errno::Errno::ECHILD) = e.as_errno() {
-                        ctx.state.last_status = 0;
-                        //return Ok(0);
-                        continue;
-                    } else {
-                        return Err(Box::new(e));
-                    }
-                }
-                Ok(stat) => ctx.state.update_process(stat.pid().unwrap(), stat),
-            }
+            ctx.state.wait_for_processes()?;
+            ctx.state.run_pending_traps();
         }
     }
 
@@ -95,11 +100,37 @@ impl Task {
     /// Returns the result without executing a second time if not waiting.
     pub fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
         if let Ok(TaskStatus::Wait) = self.status {
-            self.status = self.ti.poll(ctx);
+            self.status = if ctx.state.tracer.is_some() {
+                self.traced_poll(ctx)
+            } else {
+                self.ti.poll(ctx)
+            };
+            self.polled = true;
         }
         self.status.clone()
     }
 
+    /// Polls the implementation, logging it with the tracer of the shell.
+    fn traced_poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+        let first = !self.polled;
+        if let Some(tracer) = ctx.state.tracer.as_mut() {
+            tracer.enter(&self.ti.kind(), first);
+        }
+        let status = self.ti.poll(ctx);
+        if let Some(tracer) = ctx.state.tracer.as_mut() {
+            tracer.leave(&self.ti.kind(), &status, &self.ti.pids(), first);
+        }
+        status
+    }
+
+    /// Polls the task as a condition, where failures are expected and `errexit` doesn't apply.
+    pub fn poll_condition(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+        ctx.state.condition_depth += 1;
+        let r = self.poll(ctx);
+        ctx.state.condition_depth -= 1;
+        r
+    }
+
     pub fn new_from_word(word: parser::Word, expand_tilde: bool, is_pattern: bool) -> Self {
         if let parser::RawWord::List(children, double_quoted) = word.borrow().deref() {
             let mut tl = TaskList::new(false);
@@ -133,6 +164,10 @@ impl Task {
             tl.children
                 .push(Self::new_from_word(arg.clone(), true, false));
         }
+        for (_, value) in &sc.2 {
+            tl.children
+                .push(Self::new_from_word(value.clone(), true, false));
+        }
         tl.children.push(Task::new(Box::new(Command::new(sc))));
 
         Task::new(Box::new(tl))
@@ -140,6 +175,10 @@ impl Task {
 
     pub fn new_from_sre_sequence(seq: parser::SRESequence, top_level: bool) -> Self {
         let mut tl = TaskList::new(true);
+        if let Some(files) = &seq.2 {
+            tl.children
+                .push(Self::new_from_word(files.clone(), true, false));
+        }
         for sre in &seq.0 {
             tl.children.extend(sre.string_args.iter().map(|arg| {
                 if let parser::RawWord::Parameter(_) = arg.borrow().deref() {
@@ -150,7 +189,7 @@ impl Task {
             }));
             if !sre.command_args.is_empty() {
                 tl.children.push(Self::new_from_sre_sequence(
-                    parser::SRESequence(sre.command_args.clone()),
+                    parser::SRESequence(sre.command_args.clone(), false, None),
                     false,
                 ));
             }
@@ -175,8 +214,8 @@ impl Task {
         ))))
     }
 
-    pub fn new_from_while(condition: parser::Program, body: parser::Program) -> Self {
-        Task::new(Box::new(WhileConstruct::new(condition, body)))
+    pub fn new_from_while(condition: parser::Program, body: parser::Program, line: usize) -> Self {
+        Task::new(Box::new(WhileConstruct::new(condition, body, line)))
     }
 
     pub fn new_from_switch(
@@ -196,39 +235,74 @@ impl Task {
         Task::new(Box::new(tl))
     }
 
-    pub fn new_from_match(items: Vec<(parser::Word, parser::Program)>) -> Self {
+    pub fn new_from_match(
+        source: parser::MatchSource,
+        items: Vec<(parser::Word, parser::Program)>,
+    ) -> Self {
         let mut tl = TaskList::new(true);
+        if let parser::MatchSource::Text(w) | parser::MatchSource::File(w) = &source {
+            tl.children
+                .push(Self::new_from_word(w.clone(), true, false));
+        }
         for item in &items {
             tl.children
                 .push(Self::new_from_word(item.0.clone(), false, true));
         }
         tl.children
-            .push(Task::new(Box::new(MatchConstruct::new(items))));
+            .push(Task::new(Box::new(MatchConstruct::new(source, items))));
         Task::new(Box::new(tl))
     }
 
-    pub fn new_from_not(prog: parser::Program) -> Self {
-        Task::new(Box::new(Not::new(Self::new_from_command_lists(
-            prog.0, false,
-        ))))
+    pub fn new_from_not(p: parser::Pipeline) -> Self {
+        Task::new(Box::new(Not::new(Self::new_from_pipeline(p))))
     }
 
     pub fn new_from_command(pi: parser::Command) -> Self {
         match pi {
             parser::Command::SimpleCommand(c) => Self::new_from_simple_command(c),
             parser::Command::SREProgram(seq) => Self::new_from_sre_sequence(seq, true),
-            parser::Command::BraceGroup(arr) => Self::new_from_command_lists(arr, true),
+            parser::Command::BraceGroup(arr, redirections) => {
+                Self::new_from_brace_group(arr, redirections)
+            }
             parser::Command::IfConstruct(condition, body) => Self::new_from_if(condition, body),
             parser::Command::ElseConstruct(body) => Self::new_from_else(body),
-            parser::Command::WhileConstruct(condition, body) => {
-                Self::new_from_while(condition, body)
+            parser::Command::WhileConstruct(condition, body, line) => {
+                Self::new_from_while(condition, body, line)
             }
             parser::Command::SwitchConstruct(to_match, items) => {
                 Self::new_from_switch(to_match, items)
             }
-            parser::Command::MatchConstruct(items) => Self::new_from_match(items),
+            parser::Command::MatchConstruct(source, items) => Self::new_from_match(source, items),
             parser::Command::NotConstruct(prog) => Self::new_from_not(prog),
+            parser::Command::TimeConstruct(p) => {
+                Task::new(Box::new(Time::new(Self::new_from_pipeline(p))))
+            }
+            parser::Command::FunctionDefinition(name, body) => {
+                Task::new(Box::new(FunctionDefinition::new(name, body)))
+            }
+        }
+    }
+
+    pub fn new_from_brace_group(
+        lists: Vec<parser::CommandList>,
+        redirections: Vec<parser::Redirection>,
+    ) -> Self {
+        let group = Self::new_from_command_lists(lists, true);
+        if redirections.is_empty() {
+            return group;
+        }
+        let redirections = redirections
+            .iter()
+            .map(|r| r.with_deep_copied_word())
+            .collect::<Vec<_>>();
+        let mut tl = TaskList::new(false);
+        for w in redirections.iter().filter_map(|r| r.1.word()) {
+            tl.children
+                .push(Self::new_from_word(w.clone(), true, false));
         }
+        tl.children
+            .push(Task::new(Box::new(Redirect::new(redirections, group))));
+        Task::new(Box::new(tl))
     }
 
     pub fn new_from_pipeline(p: parser::Pipeline) -> Self {
@@ -261,6 +335,7 @@ impl Task {
 
     pub fn new_from_command_lists(v: Vec<parser::CommandList>, has_scope: bool) -> Self {
         let mut tl = TaskList::new(has_scope);
+        tl.sets_status = true;
 
         for cl in v {
             let child = Self::new_from_node(cl.0);
@@ -274,4 +349,15 @@ impl Task {
 /// Defines the behaviour of a [`Task`](struct.Task.html).
 pub trait TaskImpl {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String>;
+
+    /// What the task is, for the trace. The name of its type by default.
+    fn kind(&self) -> String {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name).to_owned()
+    }
+
+    /// The processes started by the task, for the trace.
+    fn pids(&self) -> Vec<Pid> {
+        Vec::new()
+    }
 }
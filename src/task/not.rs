@@ -30,7 +30,7 @@ impl Not {
 
 impl TaskImpl for Not {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
-        match self.task.poll(ctx)? {
+        match self.task.poll_condition(ctx)? {
             TaskStatus::Wait => Ok(TaskStatus::Wait),
             TaskStatus::Success(i) => Ok(TaskStatus::Success(if i == 0 { 1 } else { 0 })),
         }
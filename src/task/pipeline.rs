@@ -16,11 +16,10 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 use super::*;
+use crate::msg;
 use crate::shell::{Context, Fork, Process};
-use nix::unistd;
+use crate::sys::{self, Pid};
 use std::cell::RefCell;
-use std::io::{stdin, stdout};
-use std::os::unix::io::AsRawFd;
 use std::process::exit;
 use std::rc::Rc;
 
@@ -49,49 +48,49 @@ impl Pipeline {
         for (i, child) in self.children.iter_mut().enumerate() {
             write_pipe = -1;
             if i < len - 1 {
-                let (r, w) = unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).map_err(|e| {
+                let (r, w) = sys::pipe().map_err(|e| {
                     if last_stdout != -1 {
-                        unistd::close(last_stdout).unwrap();
+                        sys::close(last_stdout).unwrap();
                     }
-                    format!("failed to pipe: {}", e)
+                    msg!("pipe.error", e)
                 })?;
                 read_pipe = r;
                 write_pipe = w;
             }
             ctx.in_pipe = true;
-            match ctx
-                .state
-                .fork()
-                .map_err(|e| format!("couldn't fork: {}", e))?
-            {
+            match ctx.state.fork().map_err(|e| msg!("fork.error", e))? {
                 Fork::Child => {
+                    ctx.redirect_child().unwrap();
                     if write_pipe >= 0 {
-                        unistd::close(read_pipe).unwrap();
+                        sys::close(read_pipe).unwrap();
                     }
                     if last_stdout > 0 {
-                        unistd::dup2(last_stdout, stdin().as_raw_fd()).unwrap();
-                        unistd::close(last_stdout).unwrap();
+                        sys::move_fd(last_stdout, 0).unwrap();
                     }
                     if write_pipe > 1 {
-                        unistd::dup2(write_pipe, stdout().as_raw_fd()).unwrap();
-                        unistd::close(write_pipe).unwrap();
+                        sys::move_fd(write_pipe, 1).unwrap();
                     }
 
-                    match child.run(ctx) {
-                        Ok(x) => exit(x),
-                        Err(e) => eprintln!("error in pipe: {}", e),
-                    }
+                    // Builtins run in this child too, their output handles refer to the pipe now.
+                    let code = match child.run(ctx) {
+                        Ok(x) => x,
+                        Err(e) => {
+                            eprintln!("{}", msg!("pipe.run-error", e));
+                            1
+                        }
+                    };
+                    exit(code);
                 }
                 Fork::Parent(proc) => self.processes.push(proc),
             }
             ctx.in_pipe = false;
             // child.poll(ctx)?;
             if last_stdout >= 0 {
-                unistd::close(last_stdout).unwrap();
+                sys::close(last_stdout).unwrap();
             }
             last_stdout = read_pipe;
             if write_pipe != -1 {
-                unistd::close(write_pipe).unwrap();
+                sys::close(write_pipe).unwrap();
             }
         }
 
@@ -115,6 +114,13 @@ impl TaskImpl for Pipeline {
             }
         }
 
+        if let Ok(TaskStatus::Success(code)) = ret {
+            ctx.state.check_errexit(code);
+        }
         ret
     }
+
+    fn pids(&self) -> Vec<Pid> {
+        self.processes.iter().map(|p| p.borrow().pid).collect()
+    }
 }
@@ -0,0 +1,146 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::word::word_to_str;
+use super::*;
+use crate::msg;
+use crate::parser::{Redirection, RedirectionTarget};
+use crate::shell::Context;
+use crate::sys::{self, RawFd, SavedFds};
+use crate::util::FdWriter;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+/// Runs a task with some of its file descriptors redirected,
+/// and puts them back when it finishes.
+///
+/// The standard output and error are those of the context: builtins write to them, and
+/// forked children make them their descriptors 1 and 2. The other descriptors are
+/// redirected in the shell itself.
+pub struct Redirect {
+    redirections: Vec<Redirection>,
+    task: Task,
+    saved: Option<SavedFds>,
+    /// The standard output and error of the context for the task.
+    outputs: Option<(FdWriter, FdWriter)>,
+    /// The files opened for the standard output and error, closed after the task.
+    opened: Vec<RawFd>,
+}
+
+impl Redirect {
+    pub fn new(redirections: Vec<Redirection>, task: Task) -> Redirect {
+        Redirect {
+            redirections,
+            task,
+            saved: None,
+            outputs: None,
+            opened: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, saved: &mut SavedFds, ctx: &mut Context) -> Result<(), String> {
+        for Redirection(fd, target) in &self.redirections {
+            let mut options = OpenOptions::new();
+            let (path, options) = match target {
+                RedirectionTarget::Read(w) => (word_to_str(w.clone()), options.read(true)),
+                RedirectionTarget::Write(w) => (
+                    word_to_str(w.clone()),
+                    options.write(true).create(true).truncate(true),
+                ),
+                RedirectionTarget::Append(w) => {
+                    (word_to_str(w.clone()), options.append(true).create(true))
+                }
+                RedirectionTarget::Duplicate(from) => {
+                    let from = match *from {
+                        1 => ctx.stdout.0,
+                        2 => ctx.stderr.0,
+                        from => from,
+                    };
+                    match *fd {
+                        1 => ctx.stdout = FdWriter(from),
+                        2 => ctx.stderr = FdWriter(from),
+                        fd => saved
+                            .redirect(fd, from)
+                            .map_err(|e| msg!("redirect.error", fd, e))?,
+                    }
+                    continue;
+                }
+            };
+            let file = sys::open(Path::new(&path), options)
+                .map_err(|e| msg!("redirect.open-error", path, e))?;
+            match *fd {
+                1 | 2 => {
+                    self.opened.push(file);
+                    if *fd == 1 {
+                        ctx.stdout = FdWriter(file);
+                    } else {
+                        ctx.stderr = FdWriter(file);
+                    }
+                }
+                fd => {
+                    let r = saved.redirect(fd, file);
+                    let _ = sys::close(file);
+                    r.map_err(|e| msg!("redirect.error", fd, e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts back the descriptors, and closes the files opened for the outputs.
+    fn restore(&mut self, saved: SavedFds) {
+        saved.restore();
+        for fd in self.opened.drain(..) {
+            let _ = sys::close(fd);
+        }
+    }
+}
+
+impl TaskImpl for Redirect {
+    fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+        let (stdout, stderr) = (ctx.stdout, ctx.stderr);
+        match (self.saved.is_none(), self.outputs) {
+            (false, Some(outputs)) => {
+                ctx.stdout = outputs.0;
+                ctx.stderr = outputs.1;
+            }
+            _ => {
+                let mut saved = SavedFds::default();
+                if let Err(e) = self.apply(&mut saved, ctx) {
+                    self.restore(saved);
+                    ctx.stdout = stdout;
+                    ctx.stderr = stderr;
+                    let _ = writeln!(ctx.stderr, "{}", e);
+                    return Ok(TaskStatus::Success(1));
+                }
+                self.saved = Some(saved);
+                self.outputs = Some((ctx.stdout, ctx.stderr));
+            }
+        }
+        let status = self.task.poll(ctx);
+        // the outputs are only the task's while it is polled
+        ctx.stdout = stdout;
+        ctx.stderr = stderr;
+        if !matches!(status, Ok(TaskStatus::Wait)) {
+            if let Some(saved) = self.saved.take() {
+                self.restore(saved);
+            }
+        }
+        status
+    }
+}
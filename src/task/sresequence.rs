@@ -15,19 +15,27 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use super::command::expand_fields;
 use super::*;
-use crate::parser;
-use crate::parser::sre::{Command, CompleteCommand};
+use crate::msg;
+use crate::parser::sre::{parse_command as parse_sre_command, Command, CompleteCommand};
+use crate::parser::{self, Parser};
 use crate::shell::{Context, Process};
-use crate::sre::{Buffer, Invocation};
-use nix::unistd;
+use crate::sre::{self, Buffer, Range};
+use crate::sys::{self, ForkResult};
+use crate::util::{diff, BufReadChars, FileLineReader, LineReader};
 use std::cell::RefCell;
-use std::io::{stdin, stdout};
+use std::fs::{File, OpenOptions};
+use std::io::{self, stdin, stdout, Cursor, Write};
 use std::rc::Rc;
 
+/// What the interactive mode prints before reading a command.
+const PROMPT: &str = "|>i ";
+
 pub struct SRESequence {
     ast: parser::SRESequence,
     completed: Vec<CompleteCommand>,
+    files: Option<Vec<String>>,
     started: bool,
     process: Option<Rc<RefCell<Process>>>,
 }
@@ -37,33 +45,258 @@ impl SRESequence {
         SRESequence {
             ast,
             completed: Vec::new(),
+            files: None,
             started: false,
             process: None,
         }
     }
 
-    fn process_start(&mut self, ctx: &mut Context) -> Result<(), String> {
-        match unistd::fork().map_err(|e| format!("failed to fork: {}", e))? {
-            unistd::ForkResult::Child => {
-                let mut prev_address = None;
-                let mut buf = Buffer::new(stdin()).unwrap();
-                for prog in &self.completed {
-                    let inv = Invocation::new(prog.clone(), &buf, prev_address).unwrap();
-                    let mut out = stdout();
-                    let addr = inv.execute(&mut out, &mut buf).unwrap();
-                    use std::io::Write;
-                    out.flush().unwrap();
-                    prev_address = Some(buf.apply_changes(addr));
+    fn complete(&mut self, ifs: &str) {
+        self.completed
+            .extend(self.ast.0.iter().map(|c| Self::complete_command(c.clone())));
+        self.files = self.ast.2.as_ref().map(|w| {
+            let mut files = Vec::new();
+            expand_fields(w, ifs, &mut files);
+            files
+        });
+    }
+
+    /// Reads the buffers that the sequence runs on, one at a time: the standard input,
+    /// or every file of the list.
+    fn buffers(&self) -> Box<dyn Iterator<Item = Result<Buffer, String>> + '_> {
+        match &self.files {
+            None => Box::new(std::iter::once(
+                Buffer::new(stdin()).map_err(|e| msg!("sre.read-error", e)),
+            )),
+            Some(files) => Box::new(files.iter().map(|name| {
+                File::open(name)
+                    .and_then(Buffer::new)
+                    .map(|buf| buf.with_name(name))
+                    .map_err(|e| msg!("sre.file-error", name, e))
+            })),
+        }
+    }
+
+    /// Returns the commands to run on the buffer, with `%f` in their arguments
+    /// replaced by the name of its file.
+    fn commands_for(&self, buf: &Buffer) -> Vec<CompleteCommand> {
+        fn with_name(mut c: CompleteCommand, name: &str) -> CompleteCommand {
+            c.string_args = c
+                .string_args
+                .iter()
+                .map(|s| s.replace("%f", name))
+                .collect();
+            c.command_args = c
+                .command_args
+                .into_iter()
+                .map(|c| with_name(c, name))
+                .collect();
+            c
+        }
+        match buf.name() {
+            Some(name) => self
+                .completed
+                .iter()
+                .map(|c| with_name(c.clone(), name))
+                .collect(),
+            None => self.completed.clone(),
+        }
+    }
+
+    /// Runs the sequence on the standard input, or on the files of the list, in the
+    /// shell itself, and returns what it printed. Command substitutions made only of a
+    /// sequence use it, so that their output doesn't go through a process and a pipe.
+    /// The words of the commands must have been expanded already.
+    pub fn capture(mut self, ifs: &str) -> Result<Vec<u8>, String> {
+        self.complete(ifs);
+        let mut out = Vec::new();
+        for buf in self.buffers() {
+            let mut buf = buf?;
+            let commands = self.commands_for(&buf);
+            if self.ast.1 {
+                out.extend(self.preview(&mut buf, &commands)?.into_bytes());
+                continue;
+            }
+            let mut prev_address = None;
+            for prog in commands {
+                if prog.is_interactive() {
+                    return Err(msg!("sre.interactive-capture"));
                 }
-                std::process::exit(0);
+                let dot = sre::run_command(prog, &mut buf, prev_address, &mut out);
+                prev_address = Some(dot.map_err(|e| msg!("sre.error", e))?);
             }
-            unistd::ForkResult::Parent { child: pid, .. } => {
+        }
+        Ok(out)
+    }
+
+    /// Runs the commands on the buffer without showing what they print, and returns the
+    /// unified diff from the text they started with to the one they leave.
+    fn preview(&self, buf: &mut Buffer, commands: &[CompleteCommand]) -> Result<String, String> {
+        let old = buf.contents().to_owned();
+        buf.dry_run = true;
+        let mut prev_address = None;
+        for prog in commands {
+            if prog.is_interactive() {
+                return Err(msg!("sre.interactive-dry-run"));
+            }
+            let dot = sre::run_command(prog.clone(), buf, prev_address, &mut io::sink());
+            prev_address = Some(dot.map_err(|e| msg!("sre.error", e))?);
+        }
+        let (old_name, new_name) = match buf.name() {
+            Some(name) => (name, name),
+            None => ("input", "output"),
+        };
+        Ok(diff::unified(&old, buf.contents(), old_name, new_name, 3))
+    }
+
+    /// Runs the sequence on the standard input, or on every file of the list, and
+    /// returns its exit status.
+    fn exec(&self, ctx: &mut Context) -> i32 {
+        let mut status = 0;
+        for buf in self.buffers() {
+            let code = match buf {
+                Ok(mut buf) => self.exec_buffer(ctx, &mut buf),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            };
+            status = status.max(code);
+        }
+        status
+    }
+
+    fn exec_buffer(&self, ctx: &mut Context, buf: &mut Buffer) -> i32 {
+        let commands = self.commands_for(buf);
+        let mut out = stdout();
+        if self.ast.1 {
+            return match self.preview(buf, &commands) {
+                Ok(diff) => {
+                    let _ = out.write_all(diff.as_bytes()).and_then(|_| out.flush());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            };
+        }
+        let mut prev_address = None;
+        let mut status = 0;
+        for prog in commands {
+            if prog.is_interactive() {
+                let dot = if buf.name().is_some() {
+                    Err(msg!("sre.interactive-files"))
+                } else {
+                    sre::resolve(buf, prog.address.clone(), prev_address).map_err(|e| e.to_string())
+                };
+                if let Err(e) = dot.and_then(|dot| Self::interactive(ctx, buf, dot)) {
+                    eprintln!("{}", e);
+                    status = 1;
+                }
+                break;
+            }
+            match sre::run_command(prog, buf, prev_address, &mut out) {
+                Ok(dot) => prev_address = Some(dot),
+                Err(e) => {
+                    eprintln!("{}", msg!("sre.error", e));
+                    status = 1;
+                    break;
+                }
+            }
+        }
+        let _ = out.flush();
+        status
+    }
+
+    fn process_start(&mut self, ctx: &mut Context) -> Result<(), String> {
+        match sys::fork().map_err(|e| msg!("fork.error", e))? {
+            ForkResult::Child => {
+                ctx.redirect_child().unwrap();
+                std::process::exit(self.exec(ctx))
+            }
+            ForkResult::Parent { child: pid, .. } => {
                 self.process = Some(ctx.state.new_process(pid));
                 Ok(())
             }
         }
     }
 
+    /// Reads commands from the terminal and runs them on the buffer one at a time,
+    /// showing the dot after each. An empty line steps to the next line, like `n`,
+    /// and `q` or EOF ends the session.
+    fn interactive(ctx: &mut Context, buf: &mut Buffer, mut dot: Range) -> Result<(), String> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|e| msg!("sre.tty-error", e))?;
+        let mut term = tty.try_clone().map_err(|e| msg!("sre.tty-error", e))?;
+        let mut reader = FileLineReader::new(tty).map_err(|e| msg!("sre.tty-error", e))?;
+        let _ = sre::show_dot(&mut term, buf, dot);
+        loop {
+            let _ = write!(term, "{}", PROMPT);
+            let line = match reader.read_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(e) => return Err(msg!("sre.tty-error", e)),
+            };
+            let line = match line.trim() {
+                "q" => break,
+                "" => "n",
+                line => line,
+            };
+            match Self::run_line(ctx, buf, dot, line) {
+                Ok(new_dot) => {
+                    dot = new_dot;
+                    let _ = sre::show_dot(&mut term, buf, dot);
+                }
+                Err(e) => {
+                    let _ = writeln!(term, "{}", msg!("sre.error", e));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses, expands and runs one command typed in the interactive mode, or in the
+    /// `sre` builtin, and returns the new dot.
+    pub fn run_line(
+        ctx: &mut Context,
+        buf: &mut Buffer,
+        dot: Range,
+        line: &str,
+    ) -> Result<Range, String> {
+        let parse = |line: &str| {
+            let reader = FileLineReader::new(Cursor::new(format!("{}\n", line))).unwrap();
+            parse_sre_command(&mut Parser::new(BufReadChars::new(Box::new(reader))), false)
+        };
+        let cmd = match parse(line) {
+            Ok(Some(cmd)) if !cmd.is_interactive() => cmd,
+            Ok(_) => return Err(msg!("sre.already-interactive")),
+            Err(e) => {
+                // a line with only an address moves the dot, like in sam
+                return match parse(&format!("{}p", line)) {
+                    Ok(Some(cmd)) => {
+                        let new_dot =
+                            sre::resolve(buf, Self::complete_command(cmd).address, Some(dot))
+                                .map_err(|e| e.to_string())?;
+                        if new_dot != dot {
+                            buf.previous_dot = dot;
+                        }
+                        Ok(new_dot)
+                    }
+                    _ => Err(e.to_string()),
+                };
+            }
+        };
+        Task::new_from_sre_sequence(parser::SRESequence(vec![cmd.clone()], false, None), false)
+            .run(ctx)
+            .map_err(|e| e.to_string())?;
+        sre::run_command(Self::complete_command(cmd), buf, Some(dot), &mut stdout())
+            .map_err(|e| e.to_string())
+    }
+
     fn complete_command(c: Command) -> CompleteCommand {
         let string_args = c
             .string_args
@@ -92,12 +325,21 @@ impl TaskImpl for SRESequence {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
         ctx.state.if_condition_ok = None;
         if !self.started {
-            self.completed
-                .extend(self.ast.0.iter().map(|c| Self::complete_command(c.clone())));
+            self.complete(&ctx.state.ifs());
+            if ctx.in_pipe {
+                // the pipeline forked already, and exits with the status
+                let code = self.exec(ctx);
+                ctx.state.check_errexit(code);
+                return Ok(TaskStatus::Success(code));
+            }
             self.process_start(ctx)?;
             self.started = true;
         }
 
-        self.process.as_ref().unwrap().borrow_mut().poll()
+        let status = self.process.as_ref().unwrap().borrow_mut().poll()?;
+        if let TaskStatus::Success(code) = status {
+            ctx.state.check_errexit(code);
+        }
+        Ok(status)
     }
 }
@@ -15,17 +15,17 @@
  * You should have received a copy of the GNU General Public License
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
+use super::captures::Captures;
 use super::word::word_to_str;
 use super::*;
+use crate::msg;
 use crate::parser;
-use crate::shell::{Context, Key, Var, VarValue};
+use crate::shell::Context;
 use regex::{Regex, RegexSet};
-use std::collections::HashMap;
 
 struct ExecContext {
     index: usize,
-    int_captures: Vec<String>,
-    string_captures: HashMap<String, String>,
+    captures: Captures,
     started: bool,
     finished: bool,
 }
@@ -53,7 +53,6 @@ pub struct SwitchConstruct {
     patterns: Vec<String>,
     regex_set: Option<RegexSet>,
     regexes: Vec<Regex>,
-    named_capture_groups: Vec<Vec<String>>,
 
     initialized: bool,
     index: ItemIndex,
@@ -70,7 +69,6 @@ impl SwitchConstruct {
             index: ItemIndex::Unknown,
             regex_set: None,
             regexes: Vec::new(),
-            named_capture_groups: Vec::new(),
         }
     }
 
@@ -81,7 +79,6 @@ impl SwitchConstruct {
         self.items.reserve(len);
         self.patterns.reserve(len);
         self.regexes.reserve(len);
-        self.named_capture_groups.reserve(len);
 
         self.items.extend(
             self.ast
@@ -92,16 +89,9 @@ impl SwitchConstruct {
         self.patterns
             .extend(self.ast.1.iter().map(|(p, _)| word_to_str(p.clone())));
         self.regex_set =
-            Some(RegexSet::new(self.patterns.iter()).map_err(|e| format!("regex error: {}", e))?);
+            Some(RegexSet::new(self.patterns.iter()).map_err(|e| msg!("regex.error", e))?);
         self.regexes
             .extend(self.patterns.iter().map(|p| Regex::new(p).unwrap()));
-        self.named_capture_groups
-            .extend(self.regexes.iter().map(|re| {
-                re.capture_names()
-                    .filter(|v| v.is_some())
-                    .map(|v| v.unwrap().to_owned())
-                    .collect()
-            }));
         self.initialized = true;
         Ok(())
     }
@@ -120,20 +110,9 @@ impl TaskImpl for SwitchConstruct {
                     let cap = self.regexes[i].captures(&self.to_match).unwrap();
                     self.index = ItemIndex::Index(ExecContext {
                         index: i,
-                        int_captures: cap
-                            .iter()
-                            .map(|x| x.map_or(String::new(), |val| val.as_str().to_owned()))
-                            .collect(),
-                        string_captures: self.named_capture_groups[i]
-                            .iter()
-                            .map(|name| {
-                                (
-                                    name.clone(),
-                                    cap.name(name)
-                                        .map_or(String::new(), |val| val.as_str().to_owned()),
-                                )
-                            })
-                            .collect(),
+                        captures: Captures::new(&self.regexes[i], &self.to_match, |i| {
+                            cap.get(i).map(|m| (m.start(), m.end()))
+                        }),
                         started: false,
                         finished: false,
                     });
@@ -145,20 +124,7 @@ impl TaskImpl for SwitchConstruct {
         } else {
             let cur = self.index.index();
             if !cur.started {
-                for (i, val) in cur.int_captures.iter().enumerate() {
-                    ctx.state.set_var(
-                        Key::Var(&i.to_string()),
-                        Var::new(i.to_string(), VarValue::Array(vec![val.clone()])),
-                        true,
-                    );
-                }
-                for (name, val) in cur.string_captures.iter() {
-                    ctx.state.set_var(
-                        Key::Var(name),
-                        Var::new(name.clone(), VarValue::Array(vec![val.clone()])),
-                        true,
-                    );
-                }
+                cur.captures.set(ctx.state);
                 cur.started = true;
             }
             let body_status = self.items[cur.index].poll(ctx)?;
@@ -166,12 +132,7 @@ impl TaskImpl for SwitchConstruct {
                 Ok(TaskStatus::Wait)
             } else {
                 if !cur.finished {
-                    for i in 0..cur.int_captures.len() {
-                        ctx.state.remove_var(&i.to_string());
-                    }
-                    for name in cur.string_captures.keys() {
-                        ctx.state.remove_var(name);
-                    }
+                    cur.captures.unset(ctx.state);
                     cur.finished = true;
                 }
                 Ok(body_status)
@@ -23,6 +23,9 @@ pub struct TaskList {
     pub children: Vec<Task>,
     pub current: usize,
     pub has_scope: bool,
+    /// Set `$?` after each child, like in a list of commands.
+    /// The status of the list is the status of its last child.
+    pub sets_status: bool,
 
     started: bool,
     finished: bool,
@@ -34,6 +37,7 @@ impl TaskList {
             children: vec![],
             current: 0,
             has_scope,
+            sets_status: false,
 
             started: false,
             finished: false,
@@ -56,11 +60,19 @@ impl TaskImpl for TaskList {
 
             ret = child.poll(ctx);
             match ret {
-                Ok(TaskStatus::Success(_)) => {}
+                Ok(TaskStatus::Success(code)) => {
+                    if self.sets_status {
+                        ctx.state.last_status = code;
+                    }
+                }
                 _ => return ret,
             }
 
             self.current += 1;
+            if ctx.state.exit != -1 {
+                // don't run anything after `exit`
+                self.current = self.children.len();
+            }
         }
         if self.has_scope && !self.finished && self.current == self.children.len() {
             ctx.state.end_scope();
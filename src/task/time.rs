@@ -0,0 +1,67 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::*;
+use crate::msg;
+use crate::shell::{Context, CpuTime};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Runs a pipeline and reports the time it took.
+pub struct Time {
+    task: Task,
+    start: Option<(Instant, CpuTime)>,
+}
+
+impl Time {
+    pub fn new(task: Task) -> Time {
+        Time { task, start: None }
+    }
+}
+
+/// Formats a duration like `1m2.345s`.
+fn format_duration(d: Duration) -> String {
+    format!(
+        "{}m{}.{:03}s",
+        d.as_secs() / 60,
+        d.as_secs() % 60,
+        d.subsec_millis()
+    )
+}
+
+impl TaskImpl for Time {
+    fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+        let (real, cpu) = *self
+            .start
+            .get_or_insert_with(|| (Instant::now(), CpuTime::now()));
+        let status = self.task.poll(ctx)?;
+        if let TaskStatus::Success(_) = status {
+            let used = CpuTime::now().since(cpu);
+            let _ = writeln!(
+                ctx.stderr,
+                "{}",
+                msg!(
+                    "time.report",
+                    format_duration(real.elapsed()),
+                    format_duration(used.user),
+                    format_duration(used.sys)
+                )
+            );
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,124 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! A log of how tasks are polled, for finding where the shell hangs.
+//! It is turned on with `--trace-tasks[=file]`, or with `$RWSH_TRACE` set to `-`
+//! for the standard error or to the path of a file.
+use super::TaskStatus;
+use crate::sys::{self, Pid, RawFd};
+use crate::util::FdWriter;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+/// Where the trace is written, and how deep the task being polled is nested.
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    fd: RawFd,
+    depth: usize,
+}
+
+impl Tracer {
+    /// Traces to the standard error if `dest` is `-`, or appends to the file `dest`.
+    pub fn open(dest: &str) -> io::Result<Tracer> {
+        let fd = if dest == "-" {
+            2
+        } else {
+            let file = OpenOptions::new().create(true).append(true).open(dest)?;
+            sys::keep_open(file).map_err(io::Error::other)?
+        };
+        Ok(Tracer { fd, depth: 0 })
+    }
+
+    /// Called before a task is polled. The first poll is logged, and the tasks
+    /// polled meanwhile are nested in this one.
+    pub fn enter(&mut self, kind: &str, first: bool) {
+        if first {
+            self.write(&format!("{} start", kind));
+        }
+        self.depth += 1;
+    }
+
+    /// Called after a task is polled. What it returned is logged, along with the
+    /// processes it started, unless it's waiting again.
+    pub fn leave(
+        &mut self,
+        kind: &str,
+        status: &Result<TaskStatus, String>,
+        pids: &[Pid],
+        first: bool,
+    ) {
+        self.depth -= 1;
+        let status = match status {
+            Ok(TaskStatus::Wait) if !first => return,
+            Ok(TaskStatus::Wait) => "wait".to_owned(),
+            Ok(TaskStatus::Success(code)) => format!("success {}", code),
+            Err(e) => format!("error: {}", e),
+        };
+        let mut line = format!("{} {}", kind, status);
+        if !pids.is_empty() {
+            let pids = pids.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            line.push_str(&format!(" (pid {})", pids.join(" ")));
+        }
+        self.write(&line);
+    }
+
+    fn write(&self, line: &str) {
+        // forked pipeline elements trace to the same place
+        let line = format!("rwsh[{}] {}{}\n", sys::pid(), "  ".repeat(self.depth), line);
+        let _ = FdWriter(self.fd).write_all(line.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tracer;
+    use crate::task::TaskStatus;
+    use nix::unistd::{self, Pid};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn nesting() {
+        let path = env::temp_dir().join(format!("rwsh-trace-{}", unistd::getpid()));
+        let mut tracer = Tracer::open(path.to_str().unwrap()).unwrap();
+        tracer.enter("Pipeline", true);
+        tracer.enter("Command", true);
+        tracer.leave("Command", &Ok(TaskStatus::Wait), &[Pid::from_raw(42)], true);
+        tracer.leave("Pipeline", &Ok(TaskStatus::Wait), &[], true);
+        tracer.enter("Pipeline", false);
+        tracer.leave("Pipeline", &Ok(TaskStatus::Wait), &[], false);
+        tracer.enter("Pipeline", false);
+        tracer.leave("Pipeline", &Ok(TaskStatus::Success(1)), &[], false);
+        let log = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let prefix = format!("rwsh[{}] ", unistd::getpid());
+        let lines = log
+            .lines()
+            .map(|l| l.trim_start_matches(&prefix))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "Pipeline start",
+                "  Command start",
+                "  Command wait (pid 42)",
+                "Pipeline wait",
+                "Pipeline success 1",
+            ]
+        );
+    }
+}
@@ -16,19 +16,26 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 use super::*;
+use crate::msg;
 use crate::parser::Program;
 use crate::shell::Context;
+use std::io::Write;
 
 pub struct WhileConstruct {
     condition: Program,
     body: Program,
     condition_task: Task,
     body_task: Task,
+    /// The status of the loop, which is 0 if the body never ran.
     last_body_status: Result<TaskStatus, String>,
+    /// How many times the body ran.
+    iterations: usize,
+    /// The line of the `while` keyword.
+    line: usize,
 }
 
 impl WhileConstruct {
-    pub fn new(condition: Program, body: Program) -> WhileConstruct {
+    pub fn new(condition: Program, body: Program, line: usize) -> WhileConstruct {
         let c = condition.clone();
         let b = body.clone();
         WhileConstruct {
@@ -36,7 +43,9 @@ impl WhileConstruct {
             body,
             condition_task: Task::new_from_command_lists(c.0, false),
             body_task: Task::new_from_command_lists(b.0, false),
-            last_body_status: Ok(TaskStatus::Wait),
+            last_body_status: Ok(TaskStatus::Success(0)),
+            iterations: 0,
+            line,
         }
     }
 }
@@ -44,17 +53,28 @@ impl WhileConstruct {
 impl TaskImpl for WhileConstruct {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
         while ctx.state.exit == -1 {
-            let condition_status = self.condition_task.poll(ctx)?;
+            let condition_status = self.condition_task.poll_condition(ctx)?;
             match condition_status {
                 TaskStatus::Wait => return Ok(TaskStatus::Wait),
                 TaskStatus::Success(i) if i != 0 => return self.last_body_status.clone(),
                 _ => {}
             }
 
+            if !self.body_task.polled {
+                if let Some(max) = ctx.state.config.max_iterations {
+                    // only this loop stops, with an error status
+                    if self.iterations >= max {
+                        let _ =
+                            writeln!(ctx.stderr, "{}", msg!("limit.iterations", self.line, max));
+                        return Ok(TaskStatus::Success(1));
+                    }
+                }
+            }
             let body_status = self.body_task.poll(ctx)?;
             if let TaskStatus::Wait = body_status {
                 return Ok(TaskStatus::Wait);
             }
+            self.iterations += 1;
             self.last_body_status = Ok(body_status);
             self.condition_task = Task::new_from_command_lists(self.condition.clone().0, false);
             self.body_task = Task::new_from_command_lists(self.body.clone().0, false);
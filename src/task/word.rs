@@ -16,18 +16,32 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 use super::*;
+use crate::msg;
 use crate::parser;
-use crate::shell::{self, Context, Key, Process, Var};
-use nix::unistd;
+use crate::shell::{self, Context, Key, Process, Var, VarValue};
+use crate::sys::{self, ForkResult, RawFd};
+use crate::util::FdReader;
 use std::cell::RefCell;
-use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::stdout;
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::path::{Component, Path, PathBuf};
 use std::process::exit;
 use std::rc::Rc;
 
+/// How many bytes of output a command substitution may capture if `$SUBST_MAX` is not set.
+const DEFAULT_SUBST_MAX: u64 = 16 * 1024 * 1024;
+
+/// Returns the maximum size of the output of a command substitution, or `None` if unlimited.
+fn subst_max(ctx: &Context) -> Result<Option<u64>, String> {
+    let val = match ctx.state.get_var(Key::Var("SUBST_MAX")) {
+        Some(val) => val.value.array().join(""),
+        None => return Ok(Some(DEFAULT_SUBST_MAX)),
+    };
+    match val.parse::<u64>() {
+        Ok(0) => Ok(None),
+        Ok(n) => Ok(Some(n)),
+        Err(_) => Err(msg!("substitution.bad-limit", val)),
+    }
+}
+
 pub struct Word {
     word: parser::Word,
     expand_tilde: bool,
@@ -50,37 +64,63 @@ impl Word {
         }
     }
 
+    /// Replaces the word with the output of its command.
+    fn set_output(
+        &mut self,
+        mut buf: Vec<u8>,
+        limit: Option<u64>,
+        ctx: &Context,
+    ) -> Result<(), String> {
+        if let Some(limit) = limit {
+            if buf.len() as u64 > limit {
+                if !ctx.state.config.subst_truncate {
+                    return Err(msg!("substitution.too-long", limit));
+                }
+                buf.truncate(limit as usize);
+            }
+        }
+
+        // strip newlines
+        let mut s = String::from_utf8_lossy(&buf).into_owned();
+        while s.ends_with('\n') {
+            s.pop();
+        }
+
+        // unlike quoted text, the output is split into fields
+        *self.word.borrow_mut() = if self.is_pattern {
+            parser::RawWord::String(regex::escape(&s), true)
+        } else {
+            parser::RawWord::Expansion(Var::new(String::new(), VarValue::Array(vec![s])))
+        };
+        Ok(())
+    }
+
     fn start_command(&mut self, prog: parser::Program, ctx: &mut Context) -> Result<(), String> {
-        let (in_pipe, out_pipe) =
-            unistd::pipe().map_err(|e| format!("couldn't pipe command for substitution: {}", e))?;
+        let (in_pipe, out_pipe) = sys::pipe().map_err(|e| msg!("substitution.pipe-error", e))?;
 
-        let fork_result = match unistd::fork() {
+        let fork_result = match sys::fork() {
             Ok(x) => x,
             Err(e) => {
-                unistd::close(in_pipe).unwrap();
-                unistd::close(out_pipe).unwrap();
-                return Err(format!("couldn't fork: {}", e));
+                sys::close(in_pipe).unwrap();
+                sys::close(out_pipe).unwrap();
+                return Err(msg!("fork.error", e));
             }
         };
         match fork_result {
-            unistd::ForkResult::Child => {
-                unistd::close(in_pipe).unwrap();
-                unistd::dup2(out_pipe, stdout().as_raw_fd()).unwrap();
-                unistd::close(out_pipe).unwrap();
+            ForkResult::Child => {
+                ctx.redirect_child().unwrap();
+                sys::close(in_pipe).unwrap();
+                sys::move_fd(out_pipe, 1).unwrap();
+                ctx.state.enter_subshell();
 
                 exit(
                     shell::run_program(prog, ctx.state)
-                        .map_err(|e| {
-                            format!(
-                                "error while executing command for command substitution: {}",
-                                e
-                            )
-                        })?
+                        .map_err(|e| msg!("substitution.error", e))?
                         .0,
                 );
             }
-            unistd::ForkResult::Parent { child: pid, .. } => {
-                unistd::close(out_pipe).unwrap();
+            ForkResult::Parent { child: pid, .. } => {
+                sys::close(out_pipe).unwrap();
                 self.process = Some(ctx.state.new_process(pid));
                 self.fd = in_pipe;
                 Ok(())
@@ -89,29 +129,8 @@ impl Word {
     }
 }
 
-fn get_pw_dir(user: &str) -> Result<PathBuf, String> {
-    unsafe {
-        nix::errno::Errno::clear();
-        let p = libc::getpwnam(CString::new(user).unwrap().as_c_str().as_ptr());
-        if p.is_null() {
-            if nix::errno::errno() == 0 {
-                Err("couldn't get home dir: no such user".to_owned())
-            } else {
-                Err(format!(
-                    "couldn't get home dir: {}",
-                    nix::errno::Errno::last().desc(),
-                ))
-            }
-        } else {
-            let mut buf = PathBuf::new();
-            let dir = CStr::from_ptr((*p).pw_dir);
-            buf.push(dir.to_str().unwrap());
-            Ok(buf)
-        }
-    }
-}
-
-fn expand_tilde(s: &mut String) -> Result<(), String> {
+/// Replaces a leading `~` or `~user` with the home directory it names.
+pub fn expand_tilde(s: &mut String) -> Result<(), String> {
     if s.is_empty() || s.as_bytes()[0] != b'~' {
         return Ok(());
     }
@@ -123,7 +142,9 @@ fn expand_tilde(s: &mut String) -> Result<(), String> {
             if let Component::RootDir = p {
                 buf.push(dirs::home_dir().unwrap());
             } else {
-                buf.push(get_pw_dir(p.as_os_str().to_str().unwrap())?);
+                let user = p.as_os_str().to_str().unwrap();
+                let home = sys::home_dir(user).map_err(|e| msg!("home.error", e.desc()))?;
+                buf.push(home.ok_or_else(|| msg!("home.no-such-user"))?);
             }
             components.next();
         }
@@ -137,6 +158,10 @@ fn expand_tilde(s: &mut String) -> Result<(), String> {
 
 impl TaskImpl for Word {
     fn poll(&mut self, ctx: &mut Context) -> Result<TaskStatus, String> {
+        // the output of the substitution is in place already
+        if self.started {
+            return self.process.as_mut().unwrap().borrow_mut().poll();
+        }
         let mut program = None;
         let mut to_replace = None;
         use std::ops::DerefMut;
@@ -150,9 +175,10 @@ impl TaskImpl for Word {
         use crate::parser::WordParameterBracket;
         match self.word.borrow().deref() {
             parser::RawWord::Parameter(param) => {
-                let val = ctx.get_parameter_value(match param.bracket {
+                let val = ctx.get_parameter_value(match &param.bracket {
                     WordParameterBracket::None => Key::Var(&param.name),
-                    WordParameterBracket::Index(index) => Key::Index(&param.name, index),
+                    WordParameterBracket::Index(index) => Key::Index(&param.name, *index),
+                    WordParameterBracket::Key(field) => Key::Field(&param.name, field),
                 });
                 if self.is_pattern {
                     let s = match val {
@@ -175,28 +201,29 @@ impl TaskImpl for Word {
         }
         if let Some(prog) = program {
             if !self.started {
+                let limit = subst_max(ctx)?;
+                if let Some(seq) = sre_sequence(&prog) {
+                    // no need for another process, the sequence runs in the shell
+                    Task::new_from_sre_sequence(seq.clone(), false)
+                        .run(ctx)
+                        .map_err(|e| msg!("substitution.error", e))?;
+                    let buf = SRESequence::new(seq).capture(&ctx.state.ifs())?;
+                    self.set_output(buf, limit, ctx)?;
+                    return Ok(TaskStatus::Success(0));
+                }
                 self.start_command(prog, ctx)?;
                 self.started = true;
 
                 let mut buf = Vec::new();
-                {
-                    let mut f = unsafe { File::from_raw_fd(self.fd) };
-                    use std::io::Read;
-                    f.read_to_end(&mut buf)
-                        .map_err(|e| format!("failed to read command output: {}", e))?;
-                }
+                // Closing the pipe early makes the command die of SIGPIPE.
+                use std::io::Read;
+                let read = FdReader(self.fd)
+                    .take(limit.map_or(u64::MAX, |l| l + 1))
+                    .read_to_end(&mut buf);
+                let _ = sys::close(self.fd);
                 self.fd = -1;
-
-                // strip newlines
-                let mut s = String::from_utf8(buf).unwrap();
-                while s.ends_with('\n') {
-                    s.pop();
-                }
-
-                if self.is_pattern {
-                    s = regex::escape(&s);
-                }
-                *self.word.borrow_mut() = parser::RawWord::String(s, true);
+                read.map_err(|e| msg!("substitution.read-error", e))?;
+                self.set_output(buf, limit, ctx)?;
             }
 
             return self.process.as_mut().unwrap().borrow_mut().poll();
@@ -206,6 +233,19 @@ impl TaskImpl for Word {
     }
 }
 
+/// Returns the sequence if the program is made only of it, like `$(|> ,x/err/ p)`.
+fn sre_sequence(prog: &parser::Program) -> Option<parser::SRESequence> {
+    match prog.0.as_slice() {
+        [parser::CommandList(parser::Node::Pipeline(parser::Pipeline(cmds)))] => {
+            match cmds.as_slice() {
+                [parser::Command::SREProgram(seq)] => Some(seq.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 pub fn word_to_str(w: parser::Word) -> String {
     match w.borrow().deref() {
         parser::RawWord::String(s, _) => s.to_string(),
@@ -0,0 +1,432 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Arithmetic expressions.
+//!
+//! Expressions are made of numbers, variable names, parentheses and the
+//! following operators, from the loosest to the tightest binding:
+//!
+//! - `||`
+//! - `&&`
+//! - `==`, `!=`
+//! - `<`, `<=`, `>`, `>=`
+//! - `+`, `-`
+//! - `*`, `/`, `%`
+//! - `**`, which groups to the right
+//! - the unary `-`, `+` and `!`
+//!
+//! Numbers are 64-bit integers, or floating-point if they have a decimal point or
+//! an exponent, like `1.5` or `2e3`. An operation on an integer and a floating-point
+//! number gives a floating-point number, and so does raising an integer to a
+//! negative power. Dividing two integers gives an integer.
+//!
+//! Comparisons and logical operators give 1 for true and 0 for false.
+//! `&&` and `||` only evaluate their right side if needed.
+use crate::msg;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A number, either an integer or a floating-point one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    /// Parses a number, as written in a variable.
+    pub fn parse(s: &str) -> Option<Number> {
+        let s = s.trim();
+        if let Ok(i) = s.parse() {
+            return Some(Number::Int(i));
+        }
+        // don't accept `inf` and `NaN`
+        if !s.bytes().any(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok().map(Number::Float)
+    }
+
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    pub fn is_true(self) -> bool {
+        self.as_f64() != 0.0
+    }
+
+    /// Formats the number, with `precision` digits after the decimal point
+    /// if it is floating-point and `precision` is given.
+    pub fn format(self, precision: Option<usize>) -> String {
+        match (self, precision) {
+            (Number::Float(f), Some(p)) => format!("{:.*}", p, f),
+            _ => self.to_string(),
+        }
+    }
+
+    fn float(f: f64) -> Result<Number, String> {
+        if f.is_finite() {
+            Ok(Number::Float(f))
+        } else {
+            Err(msg!("arith.overflow"))
+        }
+    }
+
+    fn bool(b: bool) -> Number {
+        Number::Int(b as i64)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(i) => write!(f, "{}", i),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// Applies a binary operator other than `&&` and `||`.
+pub fn apply(op: &str, l: Number, r: Number) -> Result<Number, String> {
+    if let "==" | "!=" | "<" | "<=" | ">" | ">=" = op {
+        let ord = match (l, r) {
+            (Number::Int(l), Number::Int(r)) => l.cmp(&r),
+            // there are no NaNs
+            _ => l.as_f64().partial_cmp(&r.as_f64()).unwrap(),
+        };
+        return Ok(Number::bool(match op {
+            "==" => ord == Ordering::Equal,
+            "!=" => ord != Ordering::Equal,
+            "<" => ord == Ordering::Less,
+            "<=" => ord != Ordering::Greater,
+            ">" => ord == Ordering::Greater,
+            _ => ord != Ordering::Less,
+        }));
+    }
+    if (op == "/" || op == "%") && !r.is_true() {
+        return Err(msg!("arith.division-by-zero"));
+    }
+    match (l, r) {
+        (Number::Int(l), Number::Int(r)) => {
+            let checked = match op {
+                "+" => l.checked_add(r),
+                "-" => l.checked_sub(r),
+                "*" => l.checked_mul(r),
+                "/" => l.checked_div(r),
+                "%" => l.checked_rem(r),
+                "**" if r < 0 => return Number::float((l as f64).powf(r as f64)),
+                "**" => {
+                    if r > i64::from(u32::MAX) {
+                        None
+                    } else {
+                        l.checked_pow(r as u32)
+                    }
+                }
+                _ => unreachable!(),
+            };
+            checked
+                .map(Number::Int)
+                .ok_or_else(|| msg!("arith.overflow"))
+        }
+        (l, r) => {
+            let (l, r) = (l.as_f64(), r.as_f64());
+            Number::float(match op {
+                "+" => l + r,
+                "-" => l - r,
+                "*" => l * r,
+                "/" => l / r,
+                "%" => l % r,
+                "**" => l.powf(r),
+                _ => unreachable!(),
+            })
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Number(Number),
+    Name(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+/// The operators, longest first so that they are matched greedily.
+static OPERATORS: &[&str] = &[
+    "**", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!",
+];
+
+/// Returns the length of the number at the start of `s`, with its exponent if any.
+fn number_len(s: &str) -> usize {
+    let b = s.as_bytes();
+    let mut len = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    if len < b.len() && (b[len] == b'e' || b[len] == b'E') {
+        let mut exp = len + 1;
+        if exp < b.len() && (b[exp] == b'+' || b[exp] == b'-') {
+            exp += 1;
+        }
+        if exp < b.len() && b[exp].is_ascii_digit() {
+            len = exp
+                + s[exp..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(s.len() - exp);
+        }
+    }
+    len
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' {
+                Token::LParen
+            } else {
+                Token::RParen
+            });
+            rest = &rest[1..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let len = number_len(rest);
+            let n = &rest[..len];
+            let n = if n.contains(&['.', 'e', 'E'][..]) {
+                n.parse()
+                    .map(Number::Float)
+                    .map_err(|_| msg!("arith.bad-number", n))?
+            } else {
+                n.parse()
+                    .map(Number::Int)
+                    .map_err(|_| msg!("arith.overflow"))?
+            };
+            tokens.push(Token::Number(n));
+            rest = &rest[len..];
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_owned()));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(msg!("arith.unexpected-char", c));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(Number),
+    Var(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Returns the precedence of a binary operator and whether it groups to the right.
+fn binary_precedence(op: &str) -> Option<(u8, bool)> {
+    Some(match op {
+        "||" => (1, false),
+        "&&" => (2, false),
+        "==" | "!=" => (3, false),
+        "<" | "<=" | ">" | ">=" => (4, false),
+        "+" | "-" => (5, false),
+        "*" | "/" | "%" => (6, false),
+        "**" => (7, true),
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let t = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| msg!("arith.unexpected-end"))?;
+        self.pos += 1;
+        Ok(t)
+    }
+
+    /// Parses operators binding tighter than `min`, by precedence climbing.
+    fn expr(&mut self, min: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            let (prec, right_assoc) = match binary_precedence(op) {
+                Some(p) if p.0 >= min => p,
+                _ => break,
+            };
+            self.pos += 1;
+            let right = self.expr(if right_assoc { prec } else { prec + 1 })?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            Token::Op(op) if op == "-" || op == "+" || op == "!" => {
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Name(name) => Ok(Expr::Var(name)),
+            Token::LParen => {
+                let e = self.expr(0)?;
+                match self.next()? {
+                    Token::RParen => Ok(e),
+                    t => Err(unexpected(&t)),
+                }
+            }
+            t => Err(unexpected(&t)),
+        }
+    }
+}
+
+fn unexpected(t: &Token) -> String {
+    let s = match t {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => (*op).to_owned(),
+        Token::LParen => "(".to_owned(),
+        Token::RParen => ")".to_owned(),
+    };
+    msg!("arith.unexpected-token", s)
+}
+
+fn eval_expr(e: &Expr, lookup: &dyn Fn(&str) -> Option<String>) -> Result<Number, String> {
+    Ok(match e {
+        Expr::Number(n) => *n,
+        Expr::Var(name) => {
+            let val = lookup(name).ok_or_else(|| msg!("arith.no-such-var", name))?;
+            Number::parse(&val).ok_or_else(|| msg!("arith.not-a-number", name, val))?
+        }
+        Expr::Unary(op, e) => match (*op, eval_expr(e, lookup)?) {
+            ("-", Number::Int(i)) => {
+                Number::Int(i.checked_neg().ok_or_else(|| msg!("arith.overflow"))?)
+            }
+            ("-", Number::Float(f)) => Number::Float(-f),
+            ("!", v) => Number::bool(!v.is_true()),
+            (_, v) => v,
+        },
+        Expr::Binary("&&", l, r) => {
+            Number::bool(eval_expr(l, lookup)?.is_true() && eval_expr(r, lookup)?.is_true())
+        }
+        Expr::Binary("||", l, r) => {
+            Number::bool(eval_expr(l, lookup)?.is_true() || eval_expr(r, lookup)?.is_true())
+        }
+        Expr::Binary(op, l, r) => apply(op, eval_expr(l, lookup)?, eval_expr(r, lookup)?)?,
+    })
+}
+
+/// Evaluates an expression. `lookup` returns the value of a variable.
+pub fn eval(s: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<Number, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        pos: 0,
+    };
+    let e = parser.expr(0)?;
+    if let Some(t) = parser.peek() {
+        return Err(unexpected(t));
+    }
+    eval_expr(&e, lookup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{eval, Number};
+
+    fn calc(s: &str) -> Result<Number, String> {
+        eval(s, &|name| match name {
+            "x" => Some("6".to_owned()),
+            "f" => Some("0.5".to_owned()),
+            "s" => Some("text".to_owned()),
+            _ => None,
+        })
+    }
+
+    fn int(s: &str) -> Result<i64, String> {
+        calc(s).map(|n| match n {
+            Number::Int(i) => i,
+            Number::Float(f) => panic!("{} is floating-point: {}", s, f),
+        })
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(int("1 + 2 * 3"), Ok(7));
+        assert_eq!(int("(1 + 2) * x"), Ok(18));
+        assert_eq!(int("10 - 4 - 3"), Ok(3));
+        assert_eq!(int("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(int("-x % 4"), Ok(-2));
+        assert_eq!(int("7/2"), Ok(3));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(int("x > 5 && x <= 6"), Ok(1));
+        assert_eq!(int("1 + 1 == 3 || !0"), Ok(1));
+        assert_eq!(int("x != 6"), Ok(0));
+        // the right side isn't needed, so it isn't evaluated
+        assert_eq!(int("0 && 1 / 0"), Ok(0));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(calc("1.5 * 2"), Ok(Number::Float(3.0)));
+        assert_eq!(calc("x * f + .25"), Ok(Number::Float(3.25)));
+        assert_eq!(calc("2 ** -1"), Ok(Number::Float(0.5)));
+        assert_eq!(calc("1e3 - 2.5E-1"), Ok(Number::Float(999.75)));
+        assert_eq!(calc("-f < 0 && 1.0 == 1"), Ok(Number::Int(1)));
+        assert_eq!(Number::Float(2.0 / 3.0).format(Some(3)), "0.667");
+        assert_eq!(Number::Int(2).format(Some(3)), "2");
+        assert_eq!(Number::parse(" 2.5 "), Some(Number::Float(2.5)));
+        assert_eq!(Number::parse("inf"), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(calc("1 / 0").is_err());
+        assert!(calc("(1 + 2").is_err());
+        assert!(calc("1 2").is_err());
+        assert!(calc("y + 1").is_err());
+        assert!(calc("s + 1").is_err());
+        assert!(calc("9223372036854775807 + 1").is_err());
+        assert!(calc("1 $ 2").is_err());
+        assert!(calc("1.2.3").is_err());
+        assert!(calc("1.5 % 0").is_err());
+        assert!(calc("10.0 ** 400").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Line differences between two texts, shown as a unified diff.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Returns the shortest list of edits that turns the lines of `a` into those of `b`,
+/// found with Myers' algorithm.
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    // the furthest x reached on each diagonal k = x - y, indexed by k + offset
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+    'outer: for d in 0..=max as isize {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let i = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert);
+                y -= 1;
+            } else {
+                edits.push(Edit::Delete);
+                x -= 1;
+            }
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+/// Writes a line of a hunk, noting if it doesn't end in a newline.
+fn push_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// Returns the unified diff that turns `old` into `new`, with `context` unchanged
+/// lines around each change, or an empty string if they are the same.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> String {
+    let a = old.split_inclusive('\n').collect::<Vec<_>>();
+    let b = new.split_inclusive('\n').collect::<Vec<_>>();
+    let edits = edits(&a, &b);
+    // where each edit is in both texts
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for edit in &edits {
+        positions.push((i, j));
+        match edit {
+            Edit::Keep => {
+                i += 1;
+                j += 1;
+            }
+            Edit::Delete => i += 1,
+            Edit::Insert => j += 1,
+        }
+    }
+    positions.push((i, j));
+
+    let changes = (0..edits.len())
+        .filter(|&e| edits[e] != Edit::Keep)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return String::new();
+    }
+    // the ranges of edits shown together, merging the changes whose context overlaps
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &e in &changes {
+        let start = e.saturating_sub(context);
+        let end = (e + 1 + context).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        let (i, j) = positions[start];
+        let (old_len, new_len) = (positions[end].0 - i, positions[end].1 - j);
+        // an empty range is shown as starting at the line before it
+        let old_start = if old_len == 0 { i } else { i + 1 };
+        let new_start = if new_len == 0 { j } else { j + 1 };
+        out += &format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_len, new_start, new_len
+        );
+        for e in start..end {
+            let (i, j) = positions[e];
+            match edits[e] {
+                Edit::Keep => push_line(&mut out, ' ', a[i]),
+                Edit::Delete => push_line(&mut out, '-', a[i]),
+                Edit::Insert => push_line(&mut out, '+', b[j]),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unified;
+
+    #[test]
+    fn hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\n";
+        assert_eq!(
+            unified(old, new, "old", "new", 1),
+            "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n@@ -9,2 +9,1 @@\n i\n-j\n"
+        );
+        assert_eq!(
+            unified(old, new, "old", "new", 3),
+            "--- old\n+++ new\n@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n@@ -7,4 +7,3 @@\n g\n h\n i\n-j\n"
+        );
+        assert_eq!(unified(old, old, "old", "new", 3), "");
+    }
+
+    #[test]
+    fn edges() {
+        assert_eq!(
+            unified("", "x\n", "old", "new", 3),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+x\n"
+        );
+        assert_eq!(
+            unified("x\n", "x", "old", "new", 3),
+            "--- old\n+++ new\n@@ -1,1 +1,1 @@\n-x\n+x\n\\ No newline at end of file\n"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Completion of the word under the cursor.
+//!
+//! Words in the place of a command name are completed with the commands that the shell
+//! knows and the executables in `PATH`, words that start with `$` with the variables,
+//! and the other words with file names. Every candidate has a short description,
+//! shown next to it in the menu of the editor.
+use crate::msg;
+use crate::shell::theme::Style;
+use crate::sys;
+use crate::task::expand_tilde;
+use crate::util::human_size;
+use std::fs;
+use std::path::Path;
+
+/// Characters that end a word, besides whitespace.
+const SEPARATORS: &[char] = &['|', ';', '&', '(', ')', '{', '}'];
+/// Characters that are escaped with a `\` when a candidate is put in the line.
+const SPECIAL: &[char] = &[
+    '\\', '\'', '"', '$', '|', ';', '&', '(', ')', '{', '}', '<', '>', '*', '?', '[', ']', '#',
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub text: String,
+    pub description: String,
+}
+
+impl Candidate {
+    pub fn new(text: String, description: String) -> Candidate {
+        Candidate { text, description }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Finds the candidates for a word, from what the shell knew when it was created
+/// and from the file system.
+pub struct Completer {
+    commands: Vec<Candidate>,
+    vars: Vec<Candidate>,
+    /// The directories searched for executables.
+    path: Vec<String>,
+    /// The style of the descriptions in the menu.
+    pub description_style: Style,
+    /// The style of the candidate selected in the menu.
+    pub selected_style: Style,
+}
+
+impl Completer {
+    /// Returns a completer with the builtins, functions and aliases of the shell
+    /// in `commands`, its variables in `vars`, without the `$`, and the directories of `PATH`.
+    pub fn new(commands: Vec<Candidate>, vars: Vec<Candidate>, path: Vec<String>) -> Completer {
+        Completer {
+            commands,
+            vars,
+            path,
+            ..Completer::default()
+        }
+    }
+
+    /// Returns where the word before the cursor starts, counted in characters,
+    /// and the candidates that can replace it, sorted. The text of the candidates
+    /// is escaped, ready to be put in the line.
+    pub fn complete(&self, before: &[char]) -> (usize, Vec<Candidate>) {
+        let start = word_start(before);
+        let word = unescape(&before[start..]);
+        let command_position = match before[..start].iter().rev().find(|c| !c.is_whitespace()) {
+            Some(c) => SEPARATORS.contains(c),
+            None => true,
+        };
+        let mut candidates = if let Some(name) = word.strip_prefix('$') {
+            matching(&self.vars, name)
+                .map(|c| Candidate::new(format!("${}", c.text), c.description.clone()))
+                .collect()
+        } else if command_position && !word.contains('/') {
+            let mut candidates = matching(&self.commands, &word).cloned().collect::<Vec<_>>();
+            candidates.extend(executables(&self.path, &word));
+            escape_all(candidates)
+        } else {
+            escape_all(files(&word))
+        };
+        // the first one of the same name wins, like when the command is run
+        candidates.sort_by(|a, b| a.text.cmp(&b.text));
+        candidates.dedup_by(|a, b| a.text == b.text);
+        (start, candidates)
+    }
+}
+
+fn matching<'a>(
+    candidates: &'a [Candidate],
+    prefix: &'a str,
+) -> impl Iterator<Item = &'a Candidate> + 'a {
+    candidates
+        .iter()
+        .filter(move |c| c.text.starts_with(prefix))
+}
+
+/// Returns where the last word of `line` starts, after the last unescaped separator.
+fn word_start(line: &[char]) -> usize {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if *c == '\\' {
+            escaped = true;
+        } else if c.is_whitespace() || SEPARATORS.contains(c) {
+            start = i + 1;
+        }
+    }
+    start
+}
+
+fn unescape(word: &[char]) -> String {
+    let mut s = String::new();
+    let mut escaped = false;
+    for &c in word {
+        if c == '\\' && !escaped {
+            escaped = true;
+        } else {
+            s.push(c);
+            escaped = false;
+        }
+    }
+    s
+}
+
+/// Returns `s` with the characters that the parser would take specially escaped.
+fn escape(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_whitespace() || SPECIAL.contains(&c) {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    r
+}
+
+fn escape_all(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    candidates
+        .into_iter()
+        .map(|c| Candidate::new(escape(&c.text), c.description))
+        .collect()
+}
+
+/// Returns the longest prefix that all the candidates have.
+pub fn common_prefix(candidates: &[Candidate]) -> String {
+    let mut prefix = match candidates.first() {
+        Some(c) => c.text.as_str(),
+        None => return String::new(),
+    };
+    for c in &candidates[1..] {
+        let len = prefix
+            .char_indices()
+            .zip(c.text.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or_else(|| prefix.len().min(c.text.len()), |((i, _), _)| i);
+        prefix = &prefix[..len];
+    }
+    prefix.to_owned()
+}
+
+/// Returns the files whose path starts with `word`. Directories end in `/`.
+fn files(word: &str) -> Vec<Candidate> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let mut path = if dir.is_empty() {
+        ".".to_owned()
+    } else {
+        dir.to_owned()
+    };
+    if expand_tilde(&mut path).is_err() {
+        return vec![];
+    }
+    let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // hidden files only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let text = format!("{}{}", dir, name);
+            let link = entry.file_type().ok()?.is_symlink();
+            Some(match fs::metadata(entry.path()) {
+                Ok(m) if m.is_dir() => Candidate::new(text + "/", msg!("complete.directory")),
+                _ if link => {
+                    let target = fs::read_link(entry.path()).ok()?;
+                    Candidate::new(text, msg!("complete.link", target.display()))
+                }
+                Ok(m) => Candidate::new(text, human_size(m.len())),
+                Err(_) => Candidate::new(text, String::new()),
+            })
+        })
+        .collect()
+}
+
+/// Returns the executables in the directories of `path` whose name starts with `prefix`.
+fn executables(path: &[String], prefix: &str) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for dir in path.iter().filter(|d| !d.is_empty()) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = match entry.file_name().into_string() {
+                Ok(name) if name.starts_with(prefix) => name,
+                _ => continue,
+            };
+            if sys::is_executable(&entry.path()) {
+                let dir = Path::new(dir).display();
+                candidates.push(Candidate::new(name, msg!("complete.executable", dir)));
+            }
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{common_prefix, escape, human_size, Candidate, Completer};
+    use std::fs;
+
+    fn texts(candidates: &[Candidate]) -> Vec<&str> {
+        candidates.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    fn completer() -> Completer {
+        Completer::new(
+            vec![
+                Candidate::new("echo".to_owned(), "print the arguments".to_owned()),
+                Candidate::new("exit".to_owned(), "exit the shell".to_owned()),
+            ],
+            vec![
+                Candidate::new("HOME".to_owned(), "/home/me".to_owned()),
+                Candidate::new("HISTFILE".to_owned(), "~/.history".to_owned()),
+                Candidate::new("PATH".to_owned(), "/bin".to_owned()),
+            ],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn variables() {
+        let line = "echo $H".chars().collect::<Vec<_>>();
+        let (start, candidates) = completer().complete(&line);
+        assert_eq!(start, 5);
+        assert_eq!(texts(&candidates), vec!["$HISTFILE", "$HOME"]);
+        assert_eq!(candidates[1].description, "/home/me");
+    }
+
+    #[test]
+    fn commands() {
+        for line in &["ec", "true | ec", "true;  ec", "if { ec"] {
+            let line = line.chars().collect::<Vec<_>>();
+            let (start, candidates) = completer().complete(&line);
+            assert_eq!(start, line.len() - 2);
+            assert_eq!(candidates[0].text, "echo");
+            assert_eq!(candidates[0].description, "print the arguments");
+        }
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir().join(format!("rwsh-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub dir")).unwrap();
+        fs::write(dir.join("some file"), "12345").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        let prefix = format!("{}/", dir.display());
+
+        let line = format!("echo {}s", escape(&prefix))
+            .chars()
+            .collect::<Vec<_>>();
+        let (start, candidates) = completer().complete(&line);
+        assert_eq!(start, 5);
+        assert_eq!(
+            candidates,
+            vec![
+                Candidate::new(format!("{}some\\ file", prefix), "5 B".to_owned()),
+                Candidate::new(format!("{}sub\\ dir/", prefix), "directory".to_owned()),
+            ]
+        );
+        // an escaped space doesn't end the word
+        let line = format!("echo {}sub\\ ", escape(&prefix))
+            .chars()
+            .collect::<Vec<_>>();
+        assert_eq!(completer().complete(&line).1.len(), 1);
+        let line = format!("cat {}.", prefix).chars().collect::<Vec<_>>();
+        assert_eq!(
+            texts(&completer().complete(&line).1),
+            vec![format!("{}.hidden", prefix)]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prefix() {
+        let candidates = ["$HISTFILE", "$HOME", "$HOMEBREW"]
+            .iter()
+            .map(|t| Candidate::new((*t).to_owned(), String::new()))
+            .collect::<Vec<_>>();
+        assert_eq!(common_prefix(&candidates), "$H");
+        assert_eq!(common_prefix(&candidates[1..]), "$HOME");
+        assert_eq!(common_prefix(&[]), "");
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(human_size(10), "10 B");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(3 << 30), "3.0G");
+    }
+}
@@ -0,0 +1,197 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Line editors that the interactive shell reads its input with.
+//!
+//! [rustyline](https://github.com/kkawakam/rustyline) is used when the shell is built
+//! with the `rustyline` feature, which is on by default. Otherwise, or when the
+//! `RWSH_EDITOR` environment variable is `raw`, the small editor of the
+//! [`raw`](raw/index.html) module is used. On [dumb terminals](../../shell/theme/fn.dumb_terminal.html),
+//! or when `RWSH_EDITOR` is `plain`, lines are read without any editing.
+//!
+//! In both editors, Alt-E puts back the line with the last parse error, with the cursor
+//! where the error is, like the `correct-last` builtin does for the next line.
+use self::complete::Completer;
+use crate::msg;
+use crate::shell::theme::dumb_terminal;
+use std::env;
+use std::error::Error;
+use std::io;
+
+pub mod complete;
+pub mod raw;
+
+#[derive(Debug)]
+/// Why a line editor returned without a line.
+pub enum EditError {
+    /// The user pressed Ctrl-C.
+    Interrupted,
+    /// The user pressed Ctrl-D on an empty line, or the input ended.
+    Eof,
+    Other(Box<dyn Error>),
+}
+
+impl From<io::Error> for EditError {
+    fn from(e: io::Error) -> EditError {
+        EditError::Other(Box::new(e))
+    }
+}
+
+/// An editor that reads a line from the terminal, with a prompt and a history.
+pub trait LineEditor {
+    /// Shows `prompt` and reads a line, starting with the text `initial.0` before
+    /// the cursor and `initial.1` after it. The line doesn't end in '\n'.
+    ///
+    /// Lines that aren't empty are added to the history.
+    fn read_line(&mut self, prompt: &str, initial: (&str, &str)) -> Result<String, EditError>;
+
+    /// Replaces what the words are completed with. Editors without completion ignore it.
+    fn set_completer(&mut self, _completer: Completer) {}
+
+    /// Sets the line that Alt-E puts back and the character the cursor goes before,
+    /// or unbinds the key. Editors without key bindings ignore it.
+    fn set_correction(&mut self, _correction: Option<(String, usize)>) {}
+}
+
+/// Splits `line` before its `cursor`-th character.
+pub fn split_at_char(line: &str, cursor: usize) -> (&str, &str) {
+    let i = line
+        .char_indices()
+        .nth(cursor)
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| line.len());
+    line.split_at(i)
+}
+
+/// Returns the editor chosen by `RWSH_EDITOR`, which can be `rustyline`, `raw` or
+/// `plain`, or the best one available for the terminal.
+pub fn new() -> Box<dyn LineEditor> {
+    match env::var("RWSH_EDITOR").ok().as_deref() {
+        None | Some("") if dumb_terminal() => Box::new(raw::RawEditor::plain()),
+        None | Some("") => default(),
+        Some("raw") => Box::new(raw::RawEditor::new()),
+        Some("plain") => Box::new(raw::RawEditor::plain()),
+        #[cfg(all(unix, feature = "rustyline"))]
+        Some("rustyline") => Box::new(rl::RustylineEditor::new()),
+        Some(name) => {
+            eprintln!("{}", msg!("editor.unavailable", name));
+            default()
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "rustyline"))]
+fn default() -> Box<dyn LineEditor> {
+    Box::new(rl::RustylineEditor::new())
+}
+
+#[cfg(not(all(unix, feature = "rustyline")))]
+fn default() -> Box<dyn LineEditor> {
+    Box::new(raw::RawEditor::new())
+}
+
+#[cfg(all(unix, feature = "rustyline"))]
+mod rl {
+    use super::{split_at_char, EditError, LineEditor};
+    use rustyline::{config::Builder, error::ReadlineError};
+    use rustyline::{Cmd, ConditionalEventHandler, Editor, Event, EventContext, EventHandler};
+    use rustyline::{KeyEvent, RepeatCount};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    type Correction = Arc<Mutex<Option<(String, usize)>>>;
+
+    /// Handles Alt-E. Rustyline can't replace the line and move the cursor in one command,
+    /// so the line is given up and read again, starting with the correction.
+    struct CorrectLast {
+        correction: Correction,
+        pressed: Arc<AtomicBool>,
+    }
+
+    impl ConditionalEventHandler for CorrectLast {
+        fn handle(&self, _: &Event, _: RepeatCount, _: bool, _: &EventContext) -> Option<Cmd> {
+            if self.correction.lock().unwrap().is_some() {
+                self.pressed.store(true, Ordering::SeqCst);
+                Some(Cmd::Interrupt)
+            } else {
+                None
+            }
+        }
+    }
+
+    pub struct RustylineEditor {
+        editor: Editor<()>,
+        correction: Correction,
+        pressed: Arc<AtomicBool>,
+    }
+
+    impl RustylineEditor {
+        pub fn new() -> RustylineEditor {
+            let mut editor = Editor::with_config(Builder::new().auto_add_history(true).build());
+            let correction = Correction::default();
+            let pressed = Arc::new(AtomicBool::new(false));
+            let handler = CorrectLast {
+                correction: correction.clone(),
+                pressed: pressed.clone(),
+            };
+            editor.bind_sequence(
+                KeyEvent::alt('e'),
+                EventHandler::Conditional(Box::new(handler)),
+            );
+            RustylineEditor {
+                editor,
+                correction,
+                pressed,
+            }
+        }
+    }
+
+    impl LineEditor for RustylineEditor {
+        fn read_line(&mut self, prompt: &str, initial: (&str, &str)) -> Result<String, EditError> {
+            let mut initial = (initial.0.to_owned(), initial.1.to_owned());
+            loop {
+                let r = if initial.0.is_empty() && initial.1.is_empty() {
+                    self.editor.readline(prompt)
+                } else {
+                    self.editor
+                        .readline_with_initial(prompt, (&initial.0, &initial.1))
+                };
+                match r {
+                    Err(ReadlineError::Interrupted)
+                        if self.pressed.swap(false, Ordering::SeqCst) =>
+                    {
+                        if let Some((line, cursor)) = &*self.correction.lock().unwrap() {
+                            let (before, after) = split_at_char(line, *cursor);
+                            initial = (before.to_owned(), after.to_owned());
+                        }
+                    }
+                    r => {
+                        return r.map_err(|e| match e {
+                            ReadlineError::Interrupted => EditError::Interrupted,
+                            ReadlineError::Eof => EditError::Eof,
+                            e => EditError::Other(Box::new(e)),
+                        })
+                    }
+                }
+            }
+        }
+
+        fn set_correction(&mut self, correction: Option<(String, usize)>) {
+            *self.correction.lock().unwrap() = correction;
+        }
+    }
+}
@@ -0,0 +1,603 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! A small line editor that needs nothing but a terminal in raw mode.
+//!
+//! It knows these keys:
+//!
+//! - the arrows, Home and End, or Ctrl-B, Ctrl-F, Ctrl-A and Ctrl-E, to move the cursor;
+//! - Up and Down, or Ctrl-P and Ctrl-N, to go through the history;
+//! - Backspace and Delete;
+//! - Ctrl-U, Ctrl-K and Ctrl-W, to delete up to the start of the line, up to its end,
+//!   or the word before the cursor;
+//! - Ctrl-L, to clear the screen;
+//! - Ctrl-C, to give up the line, and Ctrl-D, to end the input if the line is empty;
+//! - Tab, to complete the word before the cursor;
+//! - Alt-E, to put back the line with the last parse error.
+//!
+//! When a word has more than one candidate, a menu with them and their descriptions
+//! is shown under the line. Tab and Shift-Tab go through it, typing narrows it down
+//! and Enter takes the selected candidate. Any other key closes it.
+//!
+//! Every character is taken to be one column wide.
+//!
+//! Without a terminal to put in raw mode, as on wasm32, it always reads lines as they come.
+#![cfg_attr(not(unix), allow(dead_code))]
+use super::complete::{self, Candidate, Completer};
+use super::{EditError, LineEditor};
+#[cfg(unix)]
+use crate::pty::{window_size, RawMode};
+use crate::util::FdReader;
+#[cfg(unix)]
+use crate::util::FdWriter;
+use std::io::{self, Read, Write};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    Interrupt,
+    Eof,
+    KillStart,
+    KillEnd,
+    KillWord,
+    Clear,
+    Tab,
+    BackTab,
+    Correct,
+    Other,
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut b = [0u8];
+    loop {
+        match input.read(&mut b) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(b[0])),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Reads the next key, or `None` at the end of the input.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let b = match read_byte(input)? {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+    Ok(Some(match b {
+        1 => Key::Home,
+        2 => Key::Left,
+        3 => Key::Interrupt,
+        4 => Key::Eof,
+        5 => Key::End,
+        6 => Key::Right,
+        8 | 127 => Key::Backspace,
+        b'\t' => Key::Tab,
+        b'\r' | b'\n' => Key::Enter,
+        11 => Key::KillEnd,
+        12 => Key::Clear,
+        14 => Key::Down,
+        16 => Key::Up,
+        21 => Key::KillStart,
+        23 => Key::KillWord,
+        27 => read_escape(input)?,
+        b if b < 32 => Key::Other,
+        b if b < 128 => Key::Char(b as char),
+        b => read_utf8(input, b)?,
+    }))
+}
+
+/// Reads the rest of an escape sequence, after the escape character.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    match read_byte(input)? {
+        Some(b'[') => {
+            let mut params = String::new();
+            loop {
+                match read_byte(input)? {
+                    Some(b) if (0x40..=0x7e).contains(&b) => {
+                        return Ok(match (params.as_str(), b) {
+                            ("", b'A') => Key::Up,
+                            ("", b'B') => Key::Down,
+                            ("", b'C') => Key::Right,
+                            ("", b'D') => Key::Left,
+                            ("", b'H') | ("1", b'~') | ("7", b'~') => Key::Home,
+                            ("", b'F') | ("4", b'~') | ("8", b'~') => Key::End,
+                            ("3", b'~') => Key::Delete,
+                            ("", b'Z') => Key::BackTab,
+                            _ => Key::Other,
+                        });
+                    }
+                    Some(b) => params.push(b as char),
+                    None => return Ok(Key::Other),
+                }
+            }
+        }
+        Some(b'e') => Ok(Key::Correct),
+        Some(b'O') => Ok(match read_byte(input)? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Other,
+        }),
+        _ => Ok(Key::Other),
+    }
+}
+
+/// Reads the rest of a character encoded in UTF-8 that starts with `first`.
+fn read_utf8(input: &mut impl Read, first: u8) -> io::Result<Key> {
+    let len = (!first).leading_zeros() as usize;
+    let mut bytes = vec![first];
+    for _ in 1..len.min(4) {
+        match read_byte(input)? {
+            Some(b) => bytes.push(b),
+            None => break,
+        }
+    }
+    Ok(std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map_or(Key::Other, Key::Char))
+}
+
+/// The candidates shown under the line.
+struct Menu {
+    /// Where the word being completed starts.
+    start: usize,
+    candidates: Vec<Candidate>,
+    selected: Option<usize>,
+}
+
+/// How many candidates the menu shows at once.
+const MENU_ROWS: usize = 10;
+
+impl Menu {
+    /// Selects the next candidate, or the previous one if `back`, and puts it in the line.
+    fn cycle(&mut self, back: bool, line: &mut Vec<char>, cursor: &mut usize) {
+        let n = self.candidates.len();
+        let i = match (self.selected, back) {
+            (None, false) => 0,
+            (None, true) => n - 1,
+            (Some(i), false) => (i + 1) % n,
+            (Some(i), true) => (i + n - 1) % n,
+        };
+        self.selected = Some(i);
+        replace_word(line, cursor, self.start, &self.candidates[i].text);
+    }
+
+    /// Returns the rows of the menu, at most `width` characters wide.
+    fn rows(&self, completer: &Completer, width: usize) -> Vec<String> {
+        let first = match self.selected {
+            Some(i) if i >= MENU_ROWS => i + 1 - MENU_ROWS,
+            _ => 0,
+        };
+        let shown = &self.candidates[first..self.candidates.len().min(first + MENU_ROWS)];
+        let text_width = shown
+            .iter()
+            .map(|c| c.text.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(width / 2);
+        let mut rows = shown
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let text = format!("{:w$}", c.text, w = text_width)
+                    .chars()
+                    .take(width)
+                    .collect::<String>();
+                let room = width.saturating_sub(text.chars().count() + 2);
+                let description = c.description.chars().take(room).collect::<String>();
+                let text = if self.selected == Some(first + i) {
+                    completer.selected_style.paint(&text)
+                } else {
+                    text
+                };
+                if description.is_empty() {
+                    text
+                } else {
+                    format!(
+                        "{}  {}",
+                        text,
+                        completer.description_style.paint(&description)
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        let hidden = self.candidates.len() - shown.len();
+        if hidden > 0 {
+            rows.push(format!("({} more)", hidden));
+        }
+        rows
+    }
+}
+
+/// Puts `text` in place of the line from `start` up to the cursor.
+fn replace_word(line: &mut Vec<char>, cursor: &mut usize, start: usize, text: &str) {
+    line.splice(start..*cursor, text.chars());
+    *cursor = start + text.chars().count();
+}
+
+/// Draws the line and the menu again and puts the cursor back in its place.
+fn refresh(
+    out: &mut impl Write,
+    prompt: &str,
+    line: &[char],
+    cursor: usize,
+    menu: &[String],
+) -> io::Result<()> {
+    let line = line.iter().collect::<String>();
+    write!(out, "\r{}{}\x1b[J", prompt, line)?;
+    if !menu.is_empty() {
+        for row in menu {
+            write!(out, "\r\n{}", row)?;
+        }
+        write!(out, "\x1b[{}A\r{}{}", menu.len(), prompt, line)?;
+    }
+    let after = line.chars().count() - cursor;
+    if after > 0 {
+        write!(out, "\x1b[{}D", after)?;
+    }
+    out.flush()
+}
+
+#[derive(Default)]
+/// A [`LineEditor`](../trait.LineEditor.html) that works on the standard input and output.
+///
+/// If the input isn't a terminal, it reads lines as they come.
+pub struct RawEditor {
+    history: Vec<String>,
+    completer: Completer,
+    /// How many columns the menu can take.
+    width: usize,
+    /// Read lines as they come even from a terminal, for those that can't be drawn on.
+    plain: bool,
+    /// The line Alt-E puts back and the character the cursor goes before.
+    correction: Option<(String, usize)>,
+}
+
+impl RawEditor {
+    pub fn new() -> RawEditor {
+        RawEditor {
+            width: 80,
+            ..RawEditor::default()
+        }
+    }
+
+    /// Returns an editor that only shows the prompt and reads lines, without moving
+    /// the cursor or drawing menus, for dumb terminals.
+    pub fn plain() -> RawEditor {
+        RawEditor {
+            plain: true,
+            ..RawEditor::new()
+        }
+    }
+
+    /// Completes the word before the cursor. If there are many candidates,
+    /// their common prefix is put in the line and a menu with them is returned.
+    fn complete(&self, line: &mut Vec<char>, cursor: &mut usize) -> Option<Menu> {
+        let (start, candidates) = self.completer.complete(&line[..*cursor]);
+        match candidates.len() {
+            0 => None,
+            1 => {
+                let mut text = candidates[0].text.clone();
+                if !text.ends_with('/') {
+                    text.push(' ');
+                }
+                replace_word(line, cursor, start, &text);
+                None
+            }
+            _ => {
+                let prefix = complete::common_prefix(&candidates);
+                if prefix.chars().count() > *cursor - start {
+                    replace_word(line, cursor, start, &prefix);
+                }
+                Some(Menu {
+                    start,
+                    candidates,
+                    selected: None,
+                })
+            }
+        }
+    }
+
+    /// Edits a line with the keys read from `input`, drawing it on `out`.
+    fn edit(
+        &mut self,
+        prompt: &str,
+        initial: (&str, &str),
+        input: &mut impl Read,
+        out: &mut impl Write,
+    ) -> Result<String, EditError> {
+        let mut line = initial
+            .0
+            .chars()
+            .chain(initial.1.chars())
+            .collect::<Vec<_>>();
+        let mut cursor = initial.0.chars().count();
+        // the line being written is one past the end of the history
+        let mut index = self.history.len();
+        let mut draft = Vec::new();
+        let mut menu: Option<Menu> = None;
+        refresh(out, prompt, &line, cursor, &[])?;
+        loop {
+            let key = read_key(input)?;
+            let mut narrow = None;
+            if let Some(mut m) = menu.take() {
+                match key {
+                    // takes the selected candidate
+                    Some(Key::Enter) if m.selected.is_some() => {
+                        refresh(out, prompt, &line, cursor, &[])?;
+                        continue;
+                    }
+                    Some(Key::Tab) | Some(Key::BackTab) => {
+                        m.cycle(key == Some(Key::BackTab), &mut line, &mut cursor);
+                        let rows = m.rows(&self.completer, self.width);
+                        refresh(out, prompt, &line, cursor, &rows)?;
+                        menu = Some(m);
+                        continue;
+                    }
+                    Some(Key::Char(_)) | Some(Key::Backspace) => narrow = Some(m.start),
+                    _ => {}
+                }
+            }
+            match key {
+                Some(Key::Enter) => break,
+                None | Some(Key::Eof) if line.is_empty() => {
+                    write!(out, "\r\n")?;
+                    return Err(EditError::Eof);
+                }
+                None => break,
+                Some(Key::Interrupt) => {
+                    write!(out, "^C\r\n")?;
+                    return Err(EditError::Interrupted);
+                }
+                Some(Key::Char(c)) => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                Some(Key::Backspace) if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                Some(Key::Delete) | Some(Key::Eof) if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Some(Key::Left) => cursor = cursor.saturating_sub(1),
+                Some(Key::Right) => cursor = (cursor + 1).min(line.len()),
+                Some(Key::Home) => cursor = 0,
+                Some(Key::End) => cursor = line.len(),
+                Some(Key::KillStart) => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                Some(Key::KillEnd) => line.truncate(cursor),
+                Some(Key::KillWord) => {
+                    let mut start = cursor;
+                    while start > 0 && line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    while start > 0 && !line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    line.drain(start..cursor);
+                    cursor = start;
+                }
+                Some(Key::Up) if index > 0 => {
+                    if index == self.history.len() {
+                        draft = line;
+                    }
+                    index -= 1;
+                    line = self.history[index].chars().collect();
+                    cursor = line.len();
+                }
+                Some(Key::Down) if index < self.history.len() => {
+                    index += 1;
+                    line = match self.history.get(index) {
+                        Some(s) => s.chars().collect(),
+                        None => draft.clone(),
+                    };
+                    cursor = line.len();
+                }
+                Some(Key::Clear) => write!(out, "\x1b[H\x1b[2J")?,
+                Some(Key::Correct) => {
+                    if let Some((text, at)) = &self.correction {
+                        line = text.chars().collect();
+                        cursor = (*at).min(line.len());
+                    }
+                }
+                Some(Key::Tab) => {
+                    let len = line.len();
+                    menu = self.complete(&mut line, &mut cursor);
+                    if menu.is_none() && line.len() == len {
+                        write!(out, "\x07")?;
+                    }
+                }
+                Some(_) => {}
+            }
+            // the menu follows the word as it is typed
+            if let Some(start) = narrow {
+                let (new_start, candidates) = self.completer.complete(&line[..cursor]);
+                if new_start == start && cursor > start && !candidates.is_empty() {
+                    menu = Some(Menu {
+                        start,
+                        candidates,
+                        selected: None,
+                    });
+                }
+            }
+            let rows = match &menu {
+                Some(m) => m.rows(&self.completer, self.width),
+                None => vec![],
+            };
+            refresh(out, prompt, &line, cursor, &rows)?;
+        }
+        write!(out, "\x1b[J\r\n")?;
+        out.flush()?;
+        let line = line.into_iter().collect::<String>();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        Ok(line)
+    }
+
+    /// Reads a line without editing it, for when the input isn't a terminal.
+    fn read_plain(&mut self, prompt: &str, input: &mut impl Read) -> Result<String, EditError> {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let mut bytes = Vec::new();
+        loop {
+            match read_byte(input)? {
+                Some(b'\n') => break,
+                Some(b) => bytes.push(b),
+                None if bytes.is_empty() => return Err(EditError::Eof),
+                None => break,
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl LineEditor for RawEditor {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn read_line(&mut self, prompt: &str, initial: (&str, &str)) -> Result<String, EditError> {
+        let _ = io::stdout().flush();
+        if self.plain {
+            return self.read_plain(prompt, &mut FdReader(0));
+        }
+        #[cfg(unix)]
+        {
+            match window_size(1) {
+                Ok(size) if size.ws_col > 0 => self.width = size.ws_col as usize,
+                _ => {}
+            }
+            if let Ok(_raw) = RawMode::new(0) {
+                return self.edit(prompt, initial, &mut FdReader(0), &mut FdWriter(1));
+            }
+        }
+        self.read_plain(prompt, &mut FdReader(0))
+    }
+
+    fn set_completer(&mut self, completer: Completer) {
+        self.completer = completer;
+    }
+
+    fn set_correction(&mut self, correction: Option<(String, usize)>) {
+        self.correction = correction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Candidate, Completer, EditError, RawEditor};
+
+    fn edit(
+        editor: &mut RawEditor,
+        initial: (&str, &str),
+        keys: &str,
+    ) -> Result<String, EditError> {
+        editor.edit("> ", initial, &mut keys.as_bytes(), &mut Vec::new())
+    }
+
+    #[test]
+    fn editing() {
+        let mut e = RawEditor::new();
+        for (initial, keys, line) in &[
+            (("", ""), "ac\x1b[Db\r", "abc"),
+            (("", ""), "bc\x01a\x05d\r", "abcd"),
+            (("", ""), "abx\x7fc\r", "abc"),
+            (("", ""), "abc\x1b[D\x1b[D\x1b[3~\r", "ac"),
+            (("", ""), "echo one two\x17\x17three\r", "echo three"),
+            (("", ""), "one two\x02\x02\x02\x15\x0b\r", ""),
+            (("ec", "o"), "h\r", "echo"),
+            (("", ""), "€ é\x1bOD\x1bOD\x04\r", "€é"),
+        ] {
+            assert_eq!(edit(&mut e, *initial, keys).unwrap(), *line);
+        }
+    }
+
+    #[test]
+    fn history() {
+        let mut e = RawEditor::new();
+        for line in &["one\r", "two\r", "two\r", " \r"] {
+            edit(&mut e, ("", ""), line).unwrap();
+        }
+        assert_eq!(e.history, vec!["one", "two"]);
+        assert_eq!(edit(&mut e, ("", ""), "\x1b[A\x1b[A\r").unwrap(), "one");
+        assert_eq!(
+            edit(&mut e, ("", ""), "new\x10\x10\x10\x0e\x0e\x0e\x0e\r").unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn ending() {
+        let mut e = RawEditor::new();
+        assert!(matches!(
+            edit(&mut e, ("", ""), "abc\x03"),
+            Err(EditError::Interrupted)
+        ));
+        assert!(matches!(
+            edit(&mut e, ("", ""), "\x04"),
+            Err(EditError::Eof)
+        ));
+        assert!(matches!(edit(&mut e, ("", ""), ""), Err(EditError::Eof)));
+        assert_eq!(edit(&mut e, ("", ""), "unfinished").unwrap(), "unfinished");
+    }
+
+    #[test]
+    fn correction() {
+        let mut e = RawEditor::new();
+        assert_eq!(edit(&mut e, ("", ""), "ab\x1be\r").unwrap(), "ab");
+        e.correction = Some(("echo (a b".to_owned(), 5));
+        assert_eq!(edit(&mut e, ("", ""), "ab\x1bex\r").unwrap(), "echo x(a b");
+    }
+
+    #[test]
+    fn completion() {
+        let mut e = RawEditor::new();
+        let commands = ["echo", "eval", "exit"]
+            .iter()
+            .map(|c| Candidate::new((*c).to_owned(), String::new()))
+            .collect();
+        e.completer = Completer::new(commands, vec![], vec![]);
+        for (keys, line) in &[
+            ("ech\t\r", "echo "),
+            ("e\t\t\t\r\r", "eval"),
+            ("e\t\x1b[Z\r\r", "exit"),
+            ("e\t\t\t\t\t\r\r", "echo"),
+            // typing narrows the menu down
+            ("e\tx\t\r\r", "exit"),
+            ("e\tv\x7f\x7fq\t\r", "q"),
+            ("x\t\r", "x"),
+        ] {
+            assert_eq!(edit(&mut e, ("", ""), keys).unwrap(), *line);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Text encodings of binary data, for the `base64` and `hex` builtins.
+use crate::msg;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes the data in base64, with padding.
+pub fn base64(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+/// Decodes base64, ignoring whitespace. Padding is optional.
+pub fn base64_decode(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0);
+    let mut padding = false;
+    for c in s.chars().filter(|c| !c.is_whitespace()) {
+        if c == '=' {
+            padding = true;
+            continue;
+        }
+        let value = match BASE64.iter().position(|&b| b as char == c) {
+            Some(value) if !padding => value as u32,
+            _ => return Err(msg!("encoding.bad-char", c)),
+        };
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    // a single character left over can't hold a byte
+    if bits >= 6 {
+        return Err(msg!("encoding.truncated"));
+    }
+    Ok(out)
+}
+
+/// Encodes the data as lowercase hexadecimal digits.
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes hexadecimal digits of any case, ignoring whitespace.
+pub fn hex_decode(s: &str) -> Result<Vec<u8>, String> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).ok_or_else(|| msg!("encoding.bad-char", c)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if digits.len() % 2 != 0 {
+        return Err(msg!("encoding.truncated"));
+    }
+    Ok(digits.chunks(2).map(|d| (d[0] << 4 | d[1]) as u8).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, base64_decode, hex, hex_decode};
+
+    #[test]
+    fn base64_round_trip() {
+        for (data, encoded) in &[
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("hello, world", "aGVsbG8sIHdvcmxk"),
+        ] {
+            assert_eq!(&base64(data.as_bytes()), encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), data.as_bytes());
+        }
+        assert_eq!(base64_decode("Zm9v\nYg").unwrap(), b"foob");
+        assert!(base64_decode("Zm9vY").is_err());
+        assert!(base64_decode("Zm=9").is_err());
+        assert!(base64_decode("Zm9*").is_err());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(hex(b"\x00\xffAz"), "00ff417a");
+        assert_eq!(hex_decode("00FF 417a").unwrap(), b"\x00\xffAz");
+        assert!(hex_decode("abc").is_err());
+        assert!(hex_decode("zz").is_err());
+    }
+}
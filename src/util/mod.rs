@@ -16,12 +16,26 @@
  * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
  */
 //! Provides functions and types that are used throughout the codebase.
-use rustyline::{config::Builder, error::ReadlineError, Editor};
-use std::cell::RefCell;
+use crate::sys::{self, RawFd};
+use editor::complete::Completer;
+use editor::{EditError, LineEditor};
+use std::cell::{Cell, RefCell};
 use std::error::Error;
+#[cfg(unix)]
+use std::ffi::{CString, OsStr};
 use std::fmt;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::iter::Iterator;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub mod arith;
+pub mod diff;
+pub mod digest;
+pub mod editor;
+pub mod encoding;
+pub mod pager;
 
 #[derive(Debug, Clone)]
 /// ParseError is a kind of error that appears while parsing.
@@ -79,6 +93,25 @@ pub trait LineReader {
     fn ps2_exit(&self) {}
 
     fn ps2_clear(&self) {}
+
+    /// Pre-fill the next line with `line`, placing the cursor before the `cursor`-th character.
+    fn set_initial(&self, _line: String, _cursor: usize) {}
+
+    /// Replace the primary prompt, or go back to the default one if `prompt` is `None`.
+    fn set_prompt(&self, _prompt: Option<String>) {}
+
+    /// Replaces what the words of the next lines are completed with, if they are edited.
+    fn set_completer(&self, _completer: Completer) {}
+
+    /// Sets the line that the correct-last key of the editor puts back, with the cursor
+    /// before the `.1`-th character, or unbinds the key if `correction` is `None`.
+    fn set_correction(&self, _correction: Option<(String, usize)>) {}
+
+    /// Returns `true` once after the user gave up the construct being read,
+    /// which ends the input early.
+    fn take_cancelled(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Default)]
@@ -118,22 +151,45 @@ impl<R: Read> LineReader for FileLineReader<R> {
 }
 
 /// A [`LineReader`](trait.LineReader.html) that reads from `stdin` and prints a prompt.
+///
+/// The lines are edited with a [`LineEditor`](editor/trait.LineEditor.html).
 pub struct InteractiveLineReader {
     pub ps1: String,
     pub ps2: String,
 
     ps2_stack: RefCell<Vec<String>>,
-    rl: Editor<()>,
+    /// Replaces `ps1` if set.
+    prompt: RefCell<Option<String>>,
+    initial: RefCell<Option<(String, usize)>>,
+    /// Given to the editor before the next line is read.
+    completer: RefCell<Option<Completer>>,
+    /// Given to the editor before every line is read.
+    correction: RefCell<Option<(String, usize)>>,
+    /// The lines read since the last primary prompt, which belong to the pending construct.
+    pending: RefCell<Vec<String>>,
+    cancelled: Cell<bool>,
+    editor: Box<dyn LineEditor>,
 }
 
 impl InteractiveLineReader {
+    /// Returns a reader with the editor chosen by [`editor::new`](editor/fn.new.html).
     pub fn new() -> InteractiveLineReader {
+        Self::with_editor(editor::new())
+    }
+
+    pub fn with_editor(editor: Box<dyn LineEditor>) -> InteractiveLineReader {
         InteractiveLineReader {
             ps1: "€ ".to_owned(), // get it? it's like the dollar sign!
             ps2: "> ".to_owned(),
 
             ps2_stack: RefCell::new(vec![]),
-            rl: Editor::with_config(Builder::new().auto_add_history(true).build()),
+            prompt: RefCell::new(None),
+            initial: RefCell::new(None),
+            completer: RefCell::new(None),
+            correction: RefCell::new(None),
+            pending: RefCell::new(vec![]),
+            cancelled: Cell::new(false),
+            editor,
         }
     }
 }
@@ -146,8 +202,13 @@ impl Default for InteractiveLineReader {
 
 impl LineReader for InteractiveLineReader {
     fn read_line(&mut self) -> Result<Option<String>, Box<Error>> {
-        let ps = if self.ps2_stack.borrow().is_empty() {
-            self.ps1.clone()
+        let continued = !self.ps2_stack.borrow().is_empty();
+        let ps = if !continued {
+            self.pending.borrow_mut().clear();
+            self.prompt
+                .borrow()
+                .clone()
+                .unwrap_or_else(|| self.ps1.clone())
         } else {
             format!(
                 "{}{}",
@@ -161,17 +222,36 @@ impl LineReader for InteractiveLineReader {
                 self.ps2
             )
         };
-        let readline = self.rl.readline(&ps);
+        if let Some(completer) = self.completer.borrow_mut().take() {
+            self.editor.set_completer(completer);
+        }
+        self.editor.set_correction(self.correction.borrow().clone());
+        let readline = match self.initial.borrow_mut().take() {
+            Some((line, cursor)) => self
+                .editor
+                .read_line(&ps, editor::split_at_char(&line, cursor)),
+            None => self.editor.read_line(&ps, ("", "")),
+        };
         match readline {
             Ok(mut s) => {
                 if s.chars().last().unwrap_or_default() != '\n' {
                     s.push('\n');
                 }
+                self.pending.borrow_mut().push(s.clone());
                 Ok(Some(s))
             }
-            Err(ReadlineError::Interrupted) => Ok(Some("\n".to_owned())),
-            Err(ReadlineError::Eof) => Ok(None),
-            Err(err) => Err(Box::new(err)),
+            // the construct comes back in one line, to be edited as a whole
+            Err(EditError::Interrupted) if continued => {
+                let construct = self.pending.borrow_mut().drain(..).collect::<String>();
+                let construct = construct.trim_end_matches('\n').to_owned();
+                let cursor = construct.chars().count();
+                self.set_initial(construct, cursor);
+                self.cancelled.set(true);
+                Ok(None)
+            }
+            Err(EditError::Interrupted) => Ok(Some("\n".to_owned())),
+            Err(EditError::Eof) => Ok(None),
+            Err(EditError::Other(err)) => Err(err),
         }
     }
     fn ps2_enter(&self, s: String) {
@@ -185,6 +265,26 @@ impl LineReader for InteractiveLineReader {
     fn ps2_clear(&self) {
         self.ps2_stack.borrow_mut().clear();
     }
+
+    fn set_initial(&self, line: String, cursor: usize) {
+        *self.initial.borrow_mut() = Some((line, cursor));
+    }
+
+    fn set_prompt(&self, prompt: Option<String>) {
+        *self.prompt.borrow_mut() = prompt;
+    }
+
+    fn set_completer(&self, completer: Completer) {
+        *self.completer.borrow_mut() = Some(completer);
+    }
+
+    fn set_correction(&self, correction: Option<(String, usize)>) {
+        *self.correction.borrow_mut() = correction;
+    }
+
+    fn take_cancelled(&self) -> bool {
+        self.cancelled.replace(false)
+    }
 }
 
 /// A char iterator for UTF-8 texts.
@@ -198,6 +298,7 @@ pub struct BufReadChars {
     col: usize,
     #[allow(clippy::option_option)]
     peeked: Option<Option<char>>,
+    recordings: Vec<String>,
 }
 
 impl BufReadChars {
@@ -211,6 +312,7 @@ impl BufReadChars {
             line: 0,
             col: 0,
             peeked: None,
+            recordings: Vec::new(),
         }
     }
 
@@ -219,6 +321,9 @@ impl BufReadChars {
         match self.r.read_line().unwrap() {
             Some(line) => {
                 self.chars = line.chars().collect();
+                // the input may go on after a cancelled construct
+                self.finished = false;
+                self.peeked = None;
                 self.i = 0;
                 self.initialized = true;
                 self.line += 1;
@@ -256,11 +361,43 @@ impl BufReadChars {
     /// Returns the current character without advancing.
     pub fn peek(&mut self) -> Option<&<Self as Iterator>::Item> {
         if self.peeked.is_none() {
-            self.peeked = Some(self.next());
+            self.peeked = Some(self.read());
         }
         self.peeked.as_ref().unwrap().as_ref()
     }
 
+    /// Starts saving the characters that are read from now on.
+    /// Recordings can be nested.
+    pub fn start_recording(&mut self) {
+        self.recordings.push(String::new());
+    }
+
+    /// Returns the characters read since the matching call to `start_recording`.
+    pub fn stop_recording(&mut self) -> String {
+        self.recordings.pop().unwrap_or_default()
+    }
+
+    fn read(&mut self) -> Option<char> {
+        if self.finished {
+            return None;
+        }
+        if !self.initialized {
+            self.refresh();
+            return self.read();
+        }
+        match self.next_char() {
+            Some(c) => {
+                self.col += 1;
+                Some(c)
+            }
+            None => {
+                self.refresh();
+
+                self.read()
+            }
+        }
+    }
+
     pub fn ps2_enter(&mut self, s: String) {
         self.r.ps2_enter(s);
     }
@@ -272,35 +409,208 @@ impl BufReadChars {
     pub fn ps2_clear(&mut self) {
         self.r.ps2_clear();
     }
+
+    /// Returns the line that is being read, without the trailing newline.
+    pub fn current_line(&self) -> String {
+        let line = self.chars.iter().collect::<String>();
+        line.trim_end_matches('\n').to_owned()
+    }
+
+    pub fn set_initial(&mut self, line: String, cursor: usize) {
+        self.r.set_initial(line, cursor);
+    }
+
+    pub fn set_prompt(&self, prompt: Option<String>) {
+        self.r.set_prompt(prompt);
+    }
+
+    pub fn set_completer(&self, completer: Completer) {
+        self.r.set_completer(completer);
+    }
+
+    pub fn set_correction(&self, correction: Option<(String, usize)>) {
+        self.r.set_correction(correction);
+    }
+
+    pub fn take_cancelled(&self) -> bool {
+        self.r.take_cancelled()
+    }
 }
 
 impl Iterator for BufReadChars {
     type Item = char;
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(v) = self.peeked.take() {
-            return v;
+        let c = match self.peeked.take() {
+            Some(v) => v,
+            None => self.read(),
+        };
+        if let Some(c) = c {
+            for rec in &mut self.recordings {
+                rec.push(c);
+            }
         }
-        if self.finished {
-            return None;
+        c
+    }
+}
+
+/// Reads from a file descriptor without buffering.
+///
+/// Nothing past what is asked for is taken from the descriptor, so what's left
+/// is still there for the next reader, such as a child process.
+#[derive(Debug, Clone, Copy)]
+pub struct FdReader(pub RawFd);
+
+impl Read for FdReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        sys::read(self.0, buf)
+    }
+}
+
+/// Writes to a file descriptor without buffering.
+///
+/// Since nothing is buffered, the output is never lost when a forked child exits
+/// and is never duplicated in the children of a fork.
+#[derive(Debug, Clone, Copy)]
+pub struct FdWriter(pub RawFd);
+
+impl Write for FdWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        sys::write(self.0, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Escapes backslashes, tabs and newlines so that the string
+/// can be used as a tab-separated field in porcelain output.
+pub fn porcelain_escape(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => r.push_str("\\\\"),
+            '\t' => r.push_str("\\t"),
+            '\n' => r.push_str("\\n"),
+            _ => r.push(c),
         }
-        if !self.initialized {
-            self.refresh();
-            return self.next();
+    }
+    r
+}
+
+/// Returns the string as a quoted JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => r.push(c),
         }
-        match self.next_char() {
-            Some(c) => {
-                self.col += 1;
-                Some(c)
+    }
+    r.push('"');
+    r
+}
+
+/// Quotes the string so that a POSIX shell reads it back as a single word.
+/// Strings that need no quoting are left alone.
+pub fn sh_quote(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        s.to_owned()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// The unit in which the length of a string is measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextUnit {
+    /// Extended grapheme clusters, what the user perceives as characters.
+    Graphemes,
+    /// Unicode scalar values.
+    Chars,
+    Bytes,
+}
+
+impl TextUnit {
+    /// Returns the length of the string in this unit.
+    pub fn count(self, s: &str) -> usize {
+        match self {
+            TextUnit::Graphemes => s.graphemes(true).count(),
+            TextUnit::Chars => s.chars().count(),
+            TextUnit::Bytes => s.len(),
+        }
+    }
+
+    /// Returns at most `len` units of the string, starting with the unit at `start`.
+    /// Cutting through a character in `Bytes` mode leaves replacement characters.
+    pub fn slice(self, s: &str, start: usize, len: Option<usize>) -> String {
+        let len = len.unwrap_or(usize::MAX);
+        match self {
+            TextUnit::Graphemes => s.graphemes(true).skip(start).take(len).collect(),
+            TextUnit::Chars => s.chars().skip(start).take(len).collect(),
+            TextUnit::Bytes => {
+                let bytes = s.as_bytes().iter().skip(start).take(len).cloned();
+                String::from_utf8_lossy(&bytes.collect::<Vec<u8>>()).into_owned()
             }
-            None => {
-                self.refresh();
+        }
+    }
+}
 
-                self.next()
+/// Moves the text in `pending` to `buf`, except for a character cut at the end.
+pub fn decode_utf8(pending: &mut Vec<u8>, buf: &mut String) {
+    loop {
+        match std::str::from_utf8(pending) {
+            Ok(s) => {
+                buf.push_str(s);
+                pending.clear();
+                return;
+            }
+            Err(e) => {
+                let valid = e.valid_up_to();
+                buf.push_str(&String::from_utf8_lossy(&pending[..valid]));
+                match e.error_len() {
+                    None => {
+                        pending.drain(..valid);
+                        return;
+                    }
+                    Some(len) => {
+                        buf.push(std::char::REPLACEMENT_CHARACTER);
+                        pending.drain(..valid + len);
+                    }
+                }
             }
         }
     }
 }
 
+/// Converts a string for a system call. Strings with a nul byte can't be passed.
+#[cfg(unix)]
+pub fn os2c(s: &OsStr) -> CString {
+    CString::new(s.as_bytes()).unwrap_or_else(|_e| CString::new("<string-with-nul>").unwrap())
+}
+
+/// Returns a size in bytes the way `ls -h` does.
+pub fn human_size(size: u64) -> String {
+    const UNITS: &[&str] = &["K", "M", "G", "T"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::tests::common::DummyLineReader;
@@ -316,6 +626,59 @@ pub mod tests {
 
         assert_eq!(buf.collect::<Vec<char>>(), correct);
     }
+
+    #[test]
+    fn current_line() {
+        let dlr = DummyLineReader("ab\ncd".lines());
+        let mut buf = super::BufReadChars::new(Box::new(dlr));
+        buf.next();
+        assert_eq!(buf.current_line(), "ab");
+        buf.nth(2);
+        assert_eq!(buf.current_line(), "cd");
+    }
+
+    #[test]
+    fn text_units() {
+        use super::TextUnit;
+        // 'e' followed by a combining acute accent, then a family emoji
+        let s = "caf\u{65}\u{301} \u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}!";
+        assert_eq!(TextUnit::Graphemes.count(s), 7);
+        assert_eq!(TextUnit::Chars.count(s), 12);
+        assert_eq!(TextUnit::Bytes.count(s), 26);
+        assert_eq!(TextUnit::Graphemes.slice(s, 3, Some(1)), "\u{65}\u{301}");
+        assert_eq!(
+            TextUnit::Graphemes.slice(s, 5, None),
+            "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}!"
+        );
+        assert_eq!(TextUnit::Chars.slice(s, 3, Some(1)), "e");
+        assert_eq!(TextUnit::Bytes.slice(s, 0, Some(3)), "caf");
+        assert_eq!(TextUnit::Graphemes.slice(s, 20, Some(1)), "");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(super::porcelain_escape("a\tb\\c\n"), "a\\tb\\\\c\\n");
+        assert_eq!(
+            super::json_string("say \"hi\"\n\x01"),
+            "\"say \\\"hi\\\"\\n\\u0001\""
+        );
+        assert_eq!(super::sh_quote("a-b/c.d"), "a-b/c.d");
+        assert_eq!(super::sh_quote(""), "''");
+        assert_eq!(super::sh_quote("it's $HOME"), "'it'\\''s $HOME'");
+    }
+
+    #[test]
+    fn decode_utf8_chunks() {
+        let mut pending = b"a\xc3".to_vec();
+        let mut buf = String::new();
+        super::decode_utf8(&mut pending, &mut buf);
+        assert_eq!(buf, "a");
+        assert_eq!(pending, b"\xc3");
+        pending.extend_from_slice(b"\xa9\xffb");
+        super::decode_utf8(&mut pending, &mut buf);
+        assert_eq!(buf, "aé\u{fffd}b");
+        assert!(pending.is_empty());
+    }
 }
 
 pub fn regex(r: &str) -> Result<regex::Regex, regex::Error> {
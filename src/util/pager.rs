@@ -0,0 +1,273 @@
+/* Copyright (C) 2019 Tudor-Ioan Roman
+ *
+ * This file is part of the Really Weird Shell, also known as RWSH.
+ *
+ * RWSH is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * RWSH is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with RWSH. If not, see <http://www.gnu.org/licenses/>.
+ */
+//! A small pager for the listings of builtins that don't fit in the terminal.
+//!
+//! It shows a screenful of lines and waits for a key:
+//!
+//! - Space or `f`, for the next screenful;
+//! - Enter or `j`, for the next line;
+//! - `/`, to search for a text and go on from the next line that has it, and `n`,
+//!   to search for it again;
+//! - `q` or Ctrl-C, to stop.
+//!
+//! Without terminals, as on wasm32, the text is always written as it is.
+#![cfg_attr(not(unix), allow(dead_code))]
+use crate::msg;
+#[cfg(unix)]
+use crate::pty::{window_size, RawMode};
+use crate::sys;
+use crate::util::FdWriter;
+#[cfg(unix)]
+use std::fs::File;
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+/// Writes `text` to `out`, through the pager if `out` is a terminal that is too short for it.
+pub fn page_or_write(out: &mut FdWriter, text: &str) -> io::Result<()> {
+    if sys::isatty(out.0) {
+        if let Some(r) = page(out, text) {
+            return r;
+        }
+    }
+    out.write_all(text.as_bytes())
+}
+
+/// Shows the text in the pager, or returns `None` if it fits in the terminal or the
+/// keys can't be read one by one.
+#[cfg(unix)]
+fn page(out: &mut FdWriter, text: &str) -> Option<io::Result<()>> {
+    let (rows, cols) = terminal_size(out.0)?;
+    if rows_taken(text, cols) < rows {
+        return None;
+    }
+    // keys come from the terminal even if the standard input was redirected
+    let tty = File::open("/dev/tty").ok()?;
+    let _raw = RawMode::new(tty.as_raw_fd()).ok()?;
+    Some(Pager::new(text, rows, cols).run(&mut &tty, out))
+}
+
+#[cfg(not(unix))]
+fn page(_out: &mut FdWriter, _text: &str) -> Option<io::Result<()>> {
+    None
+}
+
+#[cfg(unix)]
+fn terminal_size(fd: sys::RawFd) -> Option<(usize, usize)> {
+    match window_size(fd) {
+        Ok(size) if size.ws_row > 1 && size.ws_col > 0 => {
+            Some((size.ws_row as usize, size.ws_col as usize))
+        }
+        _ => None,
+    }
+}
+
+/// Returns how many rows a line takes when it wraps at `cols` columns.
+fn rows_of(line: &str, cols: usize) -> usize {
+    (line.chars().count() + cols - 1).max(cols) / cols
+}
+
+fn rows_taken(text: &str, cols: usize) -> usize {
+    text.lines().map(|l| rows_of(l, cols)).sum()
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut b = [0u8];
+    loop {
+        match input.read(&mut b) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(b[0])),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+struct Pager<'a> {
+    lines: Vec<&'a str>,
+    rows: usize,
+    cols: usize,
+    /// The first line that wasn't shown yet.
+    next: usize,
+    search: String,
+}
+
+impl<'a> Pager<'a> {
+    fn new(text: &'a str, rows: usize, cols: usize) -> Pager<'a> {
+        Pager {
+            lines: text.lines().collect(),
+            rows,
+            cols,
+            next: 0,
+            search: String::new(),
+        }
+    }
+
+    /// Shows lines until they fill `rows` rows or run out. A line taller than `rows`
+    /// is shown whole.
+    fn show(&mut self, out: &mut impl Write, rows: usize) -> io::Result<()> {
+        let mut used = 0;
+        while let Some(line) = self.lines.get(self.next) {
+            if used >= rows || (used > 0 && used + rows_of(line, self.cols) > rows) {
+                break;
+            }
+            used += rows_of(line, self.cols);
+            write!(out, "{}\r\n", line)?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    /// Shows the lines, a screenful at a time, with the keys read from `input`.
+    fn run(&mut self, input: &mut impl Read, out: &mut impl Write) -> io::Result<()> {
+        let page = self.rows - 1;
+        self.show(out, page)?;
+        let mut status = None;
+        while self.next < self.lines.len() {
+            let percent = self.next * 100 / self.lines.len();
+            let text = status.take().unwrap_or_else(|| msg!("pager.more", percent));
+            write!(out, "\x1b[7m{}\x1b[0m", text)?;
+            out.flush()?;
+            let rows = match read_byte(input)? {
+                Some(b' ') | Some(b'f') => page,
+                Some(b'\r') | Some(b'\n') | Some(b'j') => 1,
+                None | Some(b'q') | Some(b'Q') | Some(3) => break,
+                Some(b'/') => match self.read_search(input, out)? {
+                    Some(search) => {
+                        self.search = search;
+                        self.find(&mut status)
+                    }
+                    None => 0,
+                },
+                Some(b'n') if !self.search.is_empty() => self.find(&mut status),
+                Some(_) => 0,
+            };
+            write!(out, "\r\x1b[K")?;
+            self.show(out, rows)?;
+        }
+        write!(out, "\r\x1b[K")?;
+        out.flush()
+    }
+
+    /// Reads the text to search for on the status line, or `None` if it was given up.
+    fn read_search(
+        &self,
+        input: &mut impl Read,
+        out: &mut impl Write,
+    ) -> io::Result<Option<String>> {
+        let mut bytes = Vec::new();
+        loop {
+            write!(
+                out,
+                "\r\x1b[K/{}",
+                String::from_utf8_lossy(&bytes).trim_end_matches('\u{fffd}')
+            )?;
+            out.flush()?;
+            match read_byte(input)? {
+                Some(b'\r') | Some(b'\n') => break,
+                None | Some(3) | Some(27) => return Ok(None),
+                Some(8) | Some(127) => {
+                    let s = String::from_utf8_lossy(&bytes).into_owned();
+                    let mut s = s.trim_end_matches('\u{fffd}').to_owned();
+                    s.pop();
+                    bytes = s.into_bytes();
+                }
+                Some(b) => bytes.push(b),
+            }
+        }
+        let search = String::from_utf8_lossy(&bytes).into_owned();
+        Ok(if search.is_empty() && !self.search.is_empty() {
+            Some(self.search.clone())
+        } else if search.is_empty() {
+            None
+        } else {
+            Some(search)
+        })
+    }
+
+    /// Skips to the next line that has the searched text and returns how many
+    /// rows to show, or sets `status` to the reason nothing can be shown.
+    fn find(&mut self, status: &mut Option<String>) -> usize {
+        match self.lines[self.next..]
+            .iter()
+            .position(|l| l.contains(self.search.as_str()))
+        {
+            Some(i) => {
+                self.next += i;
+                self.rows - 1
+            }
+            None => {
+                *status = Some(msg!("pager.not-found", self.search));
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pager;
+
+    fn run(text: &str, keys: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        Pager::new(text, 4, 10)
+            .run(&mut keys.as_bytes(), &mut out)
+            .unwrap();
+        // only the lines, without the status line
+        String::from_utf8(out)
+            .unwrap()
+            .split("\r\n")
+            .map(|l| l.rsplit("\x1b[K").next().unwrap().to_owned())
+            .filter(|l| !l.is_empty() && !l.contains('\x1b'))
+            .collect()
+    }
+
+    #[test]
+    fn paging() {
+        let text = (1..=10).map(|i| format!("{}\n", i)).collect::<String>();
+        assert_eq!(run(&text, "q"), vec!["1", "2", "3"]);
+        assert_eq!(run(&text, "\rq"), vec!["1", "2", "3", "4"]);
+        assert_eq!(run(&text, " q"), vec!["1", "2", "3", "4", "5", "6"]);
+        assert_eq!(
+            run(&text, "   "),
+            (1..=10).map(|i| i.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(run(&text, ""), vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn searching() {
+        let text = "a\nb\nc\nd\nmatch 1\ne\nf\nmatch 2\ng\n";
+        assert_eq!(
+            run(text, "/match\rq"),
+            vec!["a", "b", "c", "match 1", "e", "f"]
+        );
+        assert_eq!(
+            run(text, "/match\rnq"),
+            vec!["a", "b", "c", "match 1", "e", "f", "match 2", "g"]
+        );
+        assert_eq!(run(text, "/none\rq"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn wrapping() {
+        let text = "0123456789abcdefghij\nshort\nnext\n";
+        assert_eq!(run(text, "q"), vec!["0123456789abcdefghij", "short"]);
+        assert_eq!(super::rows_taken(text, 10), 4);
+    }
+}